@@ -0,0 +1,196 @@
+#version 330 core
+
+in VsOut {
+    vec2 texCoords;
+    vec3 normal;
+    vec3 fragPos;
+    vec4 tangent;
+} vsOut;
+
+layout (std140) uniform Material {
+    uniform vec4 texBaseColorFactor;
+    uniform float normalScale;
+    uniform int hasNormalTexture;
+    uniform float occlusionStrength;
+    uniform float alphaCutoff;
+    uniform vec3 emissiveFactor;
+    uniform int alphaMode;
+    uniform int unlit;
+    uniform mat3 baseColorUvTransform;
+};
+
+layout (std140) uniform Settings {
+    int doSkinning;
+    int srgb;
+    int eightInfluences;
+    int dualQuaternions;
+    int instanced;
+    int outline;
+    float outlineWidth;
+    vec2 viewportSize;
+    vec3 outlineColor;
+    float jointSize;
+};
+
+#define MAX_LIGHTS 8
+
+struct Light {
+    vec3 position;
+    float intensity;
+    vec3 direction;
+    int lightType;
+    vec3 color;
+};
+
+layout (std140) uniform Lighting {
+    uniform Light lights[MAX_LIGHTS];
+    uniform vec3 ambientColor;
+    uniform int lightCount;
+    uniform vec3 cameraPos;
+    // The ambient light and reflections come from the environment map
+    uniform int useEnvironment;
+    uniform float environmentIntensity;
+};
+
+// A 1x1 white texture is bound for primitives without a base color texture.
+// The texture units are set by the renderer (GLSL 3.30 doesn't have layout(binding)).
+uniform sampler2D baseColorTexture;
+uniform sampler2D normalTexture;
+uniform sampler2D emissiveTexture;
+uniform sampler2D occlusionTexture;
+uniform samplerCube environmentMap;
+uniform samplerCube irradianceMap;
+
+out vec4 FragColor;
+
+const int LIGHT_DIRECTIONAL = 0;
+
+const int ALPHA_OPAQUE = 0;
+const int ALPHA_MASK = 1;
+const float SHININESS = 32.0;
+const float SPECULAR_STRENGTH = 0.3;
+const float GAMMA = 2.2;
+// Mip level of the environment map used for the (glossy) reflections
+const float REFLECTION_LOD = 4.0;
+
+vec3 blinnPhong(Light light, vec3 albedo, vec3 norm, vec3 toCamera) {
+    vec3 toLight;
+    if (light.lightType == LIGHT_DIRECTIONAL) {
+        toLight = normalize(-light.direction);
+    } else {
+        toLight = normalize(light.position - vsOut.fragPos);
+    }
+
+    vec3 halfway = normalize(toLight + toCamera);
+
+    float diffuseK = max(dot(norm, toLight), 0.0);
+    vec3 diffuse = diffuseK * albedo;
+
+    float specularK = 0.0;
+    if (diffuseK > 0.0) {
+        specularK = pow(max(dot(norm, halfway), 0.0), SHININESS) * SPECULAR_STRENGTH;
+    }
+    vec3 specular = vec3(specularK);
+
+    return (diffuse + specular) * light.color * light.intensity;
+}
+
+// Applies the alpha mode of the material, discards the fragment if needed
+float applyAlphaMode(float alpha) {
+    if (alphaMode == ALPHA_OPAQUE) {
+        return 1.0;
+    } else if (alphaMode == ALPHA_MASK) {
+        if (alpha < alphaCutoff) {
+            discard;
+        }
+        return 1.0;
+    }
+
+    return alpha;
+}
+
+vec3 getNormal() {
+    vec3 norm = normalize(vsOut.normal);
+
+    if (hasNormalTexture != 0) {
+        // Re-orthogonalize the interpolated tangent
+        vec3 tangent = normalize(vsOut.tangent.xyz - dot(vsOut.tangent.xyz, norm) * norm);
+        vec3 bitangent = cross(norm, tangent) * vsOut.tangent.w;
+        mat3 tbn = mat3(tangent, bitangent, norm);
+
+        vec3 texNormal = texture(normalTexture, vsOut.texCoords).rgb * 2.0 - 1.0;
+        texNormal.xy *= normalScale;
+
+        norm = normalize(tbn * texNormal);
+    }
+
+    // Back faces of double-sided materials have to be lit from the other side
+    return gl_FrontFacing ? norm : -norm;
+}
+
+vec3 shade(vec3 albedo, vec3 norm) {
+    vec3 toCamera = normalize(cameraPos - vsOut.fragPos);
+
+    // Occlusion only affects the ambient (indirect) light
+    float occlusion = texture(occlusionTexture, vsOut.texCoords).r;
+    occlusion = 1.0 + occlusionStrength * (occlusion - 1.0);
+
+    vec3 ambient = ambientColor;
+    if (useEnvironment != 0) {
+        ambient = texture(irradianceMap, norm).rgb * environmentIntensity;
+    }
+
+    vec3 color = ambient * albedo * occlusion;
+
+    if (useEnvironment != 0) {
+        vec3 reflected = reflect(-toCamera, norm);
+        vec3 reflection = textureLod(environmentMap, reflected, REFLECTION_LOD).rgb * environmentIntensity;
+        color += reflection * SPECULAR_STRENGTH * occlusion;
+    }
+
+    for (int i = 0; i < lightCount; i++) {
+        color += blinnPhong(lights[i], albedo, norm, toCamera);
+    }
+
+    vec3 emissive = texture(emissiveTexture, vsOut.texCoords).rgb;
+    if (srgb == 0) {
+        // Emulate the texture being uploaded as linear
+        emissive = pow(emissive, vec3(1.0 / GAMMA));
+    }
+    emissive *= emissiveFactor;
+
+    return color + emissive;
+}
+
+void main() {
+    if (outline != 0) {
+        FragColor = vec4(outlineColor, 1.0);
+        return;
+    }
+
+    vec2 baseColorUv = (baseColorUvTransform * vec3(vsOut.texCoords, 1.0)).xy;
+    vec4 baseColor = texture(baseColorTexture, baseColorUv);
+    if (srgb == 0) {
+        // Emulate the texture being uploaded as linear
+        baseColor.rgb = pow(baseColor.rgb, vec3(1.0 / GAMMA));
+    }
+    baseColor *= texBaseColorFactor;
+
+    // The skeleton overlay doesn't have normals, draw it unlit
+    if (dot(vsOut.normal, vsOut.normal) == 0.0) {
+        FragColor = baseColor;
+        return;
+    }
+
+    baseColor.a = applyAlphaMode(baseColor.a);
+
+    // KHR_materials_unlit - only the base color is used
+    if (unlit != 0) {
+        FragColor = baseColor;
+        return;
+    }
+
+    vec3 norm = getNormal();
+    // The output is linear, the tone mapping pass encodes it
+    FragColor = vec4(shade(baseColor.rgb, norm), baseColor.a);
+}
@@ -0,0 +1,11 @@
+#version 330 core
+
+out vec2 texCoords;
+
+// A triangle covering the whole screen, generated from the vertex ids
+void main() {
+    vec2 pos = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+    texCoords = pos * 0.5 + 0.5;
+
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 330 core
+
+// Index of the node + 1 (0 is the background)
+uniform uint objectId;
+
+out uint FragId;
+
+void main() {
+    FragId = objectId;
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec3 direction;
+
+uniform samplerCube skybox;
+uniform float intensity;
+
+out vec4 FragColor;
+
+void main() {
+    vec3 color = texture(skybox, direction).rgb * intensity;
+
+    FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+// Inverse of (projection * view) without the camera translation
+uniform mat4 invViewProjection;
+
+out vec3 direction;
+
+// A triangle covering the whole screen, generated from the vertex ids
+void main() {
+    vec2 pos = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+
+    vec4 world = invViewProjection * vec4(pos, 1.0, 1.0);
+    direction = world.xyz / world.w;
+
+    gl_Position = vec4(pos, 1.0, 1.0);
+}
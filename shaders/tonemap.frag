@@ -0,0 +1,43 @@
+#version 330 core
+
+in vec2 texCoords;
+
+// The scene rendered into the HDR framebuffer
+uniform sampler2D hdrColor;
+uniform float exposure;
+// 0 - clamp, 1 - Reinhard, 2 - ACES
+uniform int toneMapping;
+uniform int srgb;
+
+out vec4 FragColor;
+
+const float GAMMA = 2.2;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+
+    return (x * (a * x + b)) / (x * (c * x + d) + e);
+}
+
+void main() {
+    vec3 color = texture(hdrColor, texCoords).rgb * exposure;
+
+    if (toneMapping == 1) {
+        color = color / (color + 1.0);
+    } else if (toneMapping == 2) {
+        color = aces(color);
+    }
+
+    color = clamp(color, 0.0, 1.0);
+
+    if (srgb != 0) {
+        color = pow(color, vec3(1.0 / GAMMA));
+    }
+
+    FragColor = vec4(color, 1.0);
+}
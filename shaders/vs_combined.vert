@@ -1,31 +1,120 @@
-#version 420 core
+#version 330 core
 
 layout(location = 0) in vec3 inPos;
 layout(location = 1) in vec2 inTexcoords;
 layout(location = 2) in vec3 inNormal;
 layout(location = 3) in uvec4 inJoints;
 layout(location = 4) in vec4 inWeights;
+layout(location = 5) in vec4 inTangent;
+layout(location = 6) in uvec4 inJoints1;
+layout(location = 7) in vec4 inWeights1;
+// Per-instance (EXT_mesh_gpu_instancing), occupies locations 8 - 11
+layout(location = 8) in mat4 inInstanceTransform;
 
-layout (std140, binding = 1) uniform Transforms {
+layout (std140) uniform Transforms {
     mat4 projection;
     mat4 view;
     mat4 model;
 };
 
-layout (std140, binding = 2) uniform JointMatrices {
-    mat4 jointMatrices[256];
+// Injected by the renderer when a skin has more joints
+#ifndef MAX_JOINTS
+#define MAX_JOINTS 256
+#endif
+
+// Size limits of the skeleton overlay's joint points in pixels
+#define MIN_JOINT_PIXELS 3.0
+#define MAX_JOINT_PIXELS 24.0
+
+layout (std140) uniform JointTransforms {
+    mat4 jointMatrices[MAX_JOINTS];
+};
+
+// The real part of joint i at index 2i, the dual part at 2i + 1
+layout (std140) uniform JointDualQuats {
+    vec4 jointDualQuats[MAX_JOINTS * 2];
 };
 
-layout (std140, binding = 3) uniform Settings {
+layout (std140) uniform Settings {
     int doSkinning;
+    int srgb;
+    int eightInfluences;
+    int dualQuaternions;
+    int instanced;
+    int outline;
+    float outlineWidth;
+    vec2 viewportSize;
+    vec3 outlineColor;
+    float jointSize;
 };
 
 out VsOut {
     vec2 texCoords;
     vec3 normal;
     vec3 fragPos;
+    vec4 tangent;
 } vsOut;
 
+struct DualQuat {
+    vec4 real;
+    vec4 dual;
+    // Uniform scale, blended linearly (dual quaternions can't represent scale)
+    float scale;
+};
+
+// Adds a weighted joint to the blend, flipping quaternions that are in the opposite
+// hemisphere of the first one (q and -q are the same rotation)
+void addJoint(inout DualQuat blend, vec4 pivot, uint joint, float weight) {
+    vec4 real = jointDualQuats[2 * int(joint)];
+    vec4 dual = jointDualQuats[2 * int(joint) + 1];
+
+    if (dot(real, pivot) < 0.0) {
+        weight = -weight;
+    }
+
+    blend.real += weight * real;
+    blend.dual += weight * dual;
+    blend.scale += abs(weight) * length(jointMatrices[int(joint)][0].xyz);
+}
+
+// Dual quaternion linear blending (Kavan et al.).
+// Non-uniform joint scale isn't supported, the length of the joint's X axis is used.
+mat4 dualQuatSkinTransform() {
+    vec4 pivot = jointDualQuats[2 * int(inJoints.x)];
+    DualQuat blend = DualQuat(vec4(0.0), vec4(0.0), 0.0);
+
+    addJoint(blend, pivot, inJoints.x, inWeights.x);
+    addJoint(blend, pivot, inJoints.y, inWeights.y);
+    addJoint(blend, pivot, inJoints.z, inWeights.z);
+    addJoint(blend, pivot, inJoints.w, inWeights.w);
+
+    if (eightInfluences == 1) {
+        addJoint(blend, pivot, inJoints1.x, inWeights1.x);
+        addJoint(blend, pivot, inJoints1.y, inWeights1.y);
+        addJoint(blend, pivot, inJoints1.z, inWeights1.z);
+        addJoint(blend, pivot, inJoints1.w, inWeights1.w);
+    }
+
+    float len = length(blend.real);
+    vec4 r = blend.real / len;
+    vec4 d = blend.dual / len;
+
+    vec3 translation = 2.0 * (r.w * d.xyz - d.w * r.xyz + cross(r.xyz, d.xyz));
+
+    mat3 rotation = mat3(
+        1.0 - 2.0 * (r.y * r.y + r.z * r.z), 2.0 * (r.x * r.y + r.w * r.z), 2.0 * (r.x * r.z - r.w * r.y),
+        2.0 * (r.x * r.y - r.w * r.z), 1.0 - 2.0 * (r.x * r.x + r.z * r.z), 2.0 * (r.y * r.z + r.w * r.x),
+        2.0 * (r.x * r.z + r.w * r.y), 2.0 * (r.y * r.z - r.w * r.x), 1.0 - 2.0 * (r.x * r.x + r.y * r.y)
+    ) * blend.scale;
+
+    return mat4(
+        vec4(rotation[0], 0.0),
+        vec4(rotation[1], 0.0),
+        vec4(rotation[2], 0.0),
+        vec4(translation, 1.0)
+    );
+}
+
 void main() {
     // https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#joint-hierarchy
     // "Only the joint transforms are applied to the skinned mesh; the transform of the
@@ -33,12 +122,25 @@ void main() {
 
     mat4 modelTransform;
 
-    if (doSkinning == 1) {
+    if (doSkinning == 1 && dualQuaternions == 1) {
+        modelTransform = dualQuatSkinTransform();
+    } else if (doSkinning == 1) {
         modelTransform =
             (inWeights.x * jointMatrices[int(inJoints.x)]) +
             (inWeights.y * jointMatrices[int(inJoints.y)]) +
             (inWeights.z * jointMatrices[int(inJoints.z)]) +
             (inWeights.w * jointMatrices[int(inJoints.w)]);
+
+        // The second set of influences (JOINTS_1 / WEIGHTS_1)
+        if (eightInfluences == 1) {
+            modelTransform +=
+                (inWeights1.x * jointMatrices[int(inJoints1.x)]) +
+                (inWeights1.y * jointMatrices[int(inJoints1.y)]) +
+                (inWeights1.z * jointMatrices[int(inJoints1.z)]) +
+                (inWeights1.w * jointMatrices[int(inJoints1.w)]);
+        }
+    } else if (instanced == 1) {
+        modelTransform = model * inInstanceTransform;
     } else {
         modelTransform = model;
     }
@@ -47,5 +149,23 @@ void main() {
 
     vsOut.texCoords = inTexcoords;
     vsOut.normal = mat3(transpose(inverse(modelTransform))) * inNormal;
+
+    // Extrude the vertex along the normal in screen space, so the outline is equally wide everywhere
+    if (outline == 1) {
+        vec2 screenNormal = (mat3(projection * view) * vsOut.normal).xy;
+        if (dot(screenNormal, screenNormal) > 0.0) {
+            vec2 offset = normalize(screenNormal) / viewportSize * outlineWidth * 2.0;
+            gl_Position.xy += offset * gl_Position.w;
+        }
+    }
+
+    // Joints of the skeleton overlay keep their size relative to the skeleton, but stay readable
+    // when the camera is far away or very close
+    if (jointSize > 0.0) {
+        float pixels = jointSize * projection[1][1] * viewportSize.y / (2.0 * gl_Position.w);
+        gl_PointSize = clamp(pixels, MIN_JOINT_PIXELS, MAX_JOINT_PIXELS);
+    }
+
     vsOut.fragPos = vec3(modelTransform * vec4(inPos, 1.0));
+    vsOut.tangent = vec4(mat3(modelTransform) * inTangent.xyz, inTangent.w);
 }
@@ -0,0 +1,57 @@
+use std::{fs, path::PathBuf};
+
+use eyre::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraPose;
+
+/// A named camera view
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: CameraPose,
+}
+
+/// List of saved camera views, persisted to 'camera_bookmarks.json' next to the executable
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+    /// Path of the JSON file
+    path: PathBuf,
+}
+
+impl Bookmarks {
+    /// Loads the bookmarks from the JSON file.
+    ///
+    /// A missing or invalid file isn't fatal, the list just starts empty.
+    pub fn load() -> Self {
+        let path = Self::path();
+
+        let list = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Couldn't parse '{}': {e}", path.display());
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        Self { list, path }
+    }
+
+    /// Writes the bookmarks to the JSON file
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.list)?;
+        fs::write(&self.path, json)?;
+        Ok(())
+    }
+
+    /// 'camera_bookmarks.json' in the directory of the executable (or the working directory)
+    fn path() -> PathBuf {
+        let dir = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+            .unwrap_or_default();
+
+        dir.join("camera_bookmarks.json")
+    }
+}
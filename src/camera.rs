@@ -1,6 +1,75 @@
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// How the camera is controlled
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CameraMode {
+    /// Free-fly camera moved with WASD
+    Free,
+    /// Camera orbiting around a target point
+    Orbit,
+}
+
+/// Parameters of the projection transformation
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Projection {
+    /// Vertical field of view in degrees (perspective projection)
+    pub fov: f32,
+    /// Distance of the near clipping plane
+    pub near: f32,
+    /// Distance of the far clipping plane
+    pub far: f32,
+    /// Use an orthographic projection instead of a perspective one
+    pub orthographic: bool,
+    /// Half of the height of the view volume (orthographic projection)
+    pub ortho_size: f32,
+}
+
+impl Projection {
+    pub fn new() -> Self {
+        Self {
+            fov: 60.,
+            near: 0.1,
+            far: 3000.,
+            orthographic: false,
+            ortho_size: 5.,
+        }
+    }
+
+    /// Returns the projection matrix for the aspect ratio (width / height)
+    pub fn matrix(&self, aspect: f32) -> Mat4 {
+        // The near plane must be in front of the camera and the far plane behind it
+        let near = self.near.max(0.001);
+        let far = self.far.max(near * 2.);
+
+        if self.orthographic {
+            let h = self.ortho_size;
+            let w = h * aspect;
+            Mat4::orthographic_rh(-w, w, -h, h, near, far)
+        } else {
+            Mat4::perspective_rh(self.fov.to_radians(), aspect, near, far)
+        }
+    }
+}
+
+/// The orbit camera can't get closer to the target than this
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+
+/// A snapshot of the camera view that can be restored later
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub pos: [f32; 3],
+    pub azimuth: f32,
+    pub zenith: f32,
+    pub mode: CameraMode,
+    /// Orbit target (only used in the orbit mode)
+    pub target: [f32; 3],
+    /// Distance from the orbit target (only used in the orbit mode)
+    pub distance: f32,
+}
 
 /// A component encapsulating the camera transformations
+#[derive(Clone)]
 pub struct Camera {
     /// Position of the camera
     pos: Vec3,
@@ -8,10 +77,20 @@ pub struct Camera {
     dir: Vec3,
     /// The 'up' vector
     up: Vec3,
-    /// Move speed
+    /// Maximum move speed in units per second
     pub move_speed: f32,
-    /// Look sensitivity
+    /// How quickly the camera reaches the move speed (1 / seconds)
+    pub acceleration: f32,
+    /// How quickly the camera stops when no key is pressed (1 / seconds)
+    pub damping: f32,
+    /// Look sensitivity in degrees per pixel
     pub look_sensitivity: f32,
+    /// Time constant of the mouse look smoothing in seconds (0 disables smoothing)
+    pub look_smoothing: f32,
+    /// Current velocity of the camera (free mode)
+    velocity: Vec3,
+    /// Requested direction of the movement in this frame, set by the move_* methods
+    move_intent: Vec3,
     /// Last x position of the mouse
     current_x: f32,
     /// Last y position of the mouse
@@ -20,10 +99,22 @@ pub struct Camera {
     azimuth: f32,
     /// Vertical angle from center
     zenith: f32,
+    /// The azimuth the (smoothed) mouse look is heading to
+    target_azimuth: f32,
+    /// The zenith the (smoothed) mouse look is heading to
+    target_zenith: f32,
+    /// Free / orbit mode
+    mode: CameraMode,
+    /// The point the camera orbits around (orbit mode)
+    target: Vec3,
+    /// Distance from the target (orbit mode)
+    distance: f32,
     /// Signals that the view transformation needs to be recomputed
     changed: bool,
     /// Cache of the view matrix
     view_matrix: Mat4,
+    /// Parameters of the projection
+    pub projection: Projection,
 }
 
 impl Camera {
@@ -40,13 +131,71 @@ impl Camera {
             dir: Vec3::new(0., 0., -1.),
             up: Vec3::new(0., 1., 0.),
             move_speed,
+            acceleration: 10.,
+            damping: 8.,
             look_sensitivity,
+            look_smoothing: 0.,
+            velocity: Vec3::ZERO,
+            move_intent: Vec3::ZERO,
             current_x: window_width as f32 / 2.,
             current_y: window_height as f32 / 2.,
             azimuth: 0.,
             zenith: 0.,
+            target_azimuth: 0.,
+            target_zenith: 0.,
+            mode: CameraMode::Free,
+            target: Vec3::ZERO,
+            distance: 1.,
             changed: true,
             view_matrix: Mat4::IDENTITY,
+            projection: Projection::new(),
+        }
+    }
+
+    /// Applies the movement requested in this frame, should be called once per frame.
+    ///
+    /// The velocity accelerates towards the requested direction and is exponentially damped
+    /// when no movement is requested. 'dt' is the duration of the frame in seconds.
+    pub fn update(&mut self, dt: f32) {
+        let intent = self.move_intent.clamp_length_max(1.) * self.move_speed;
+        self.move_intent = Vec3::ZERO;
+
+        if self.mode == CameraMode::Free {
+            if intent != Vec3::ZERO {
+                let t = 1. - (-self.acceleration * dt).exp();
+                self.velocity += (intent - self.velocity) * t;
+            } else {
+                self.velocity *= (-self.damping * dt).exp();
+            }
+
+            if self.velocity.length_squared() < 1e-6 {
+                self.velocity = Vec3::ZERO;
+            } else {
+                self.pos += self.velocity * dt;
+                self.changed = true;
+            }
+        } else {
+            self.velocity = Vec3::ZERO;
+        }
+
+        if self.azimuth != self.target_azimuth || self.zenith != self.target_zenith {
+            let t = if self.look_smoothing > 0. {
+                1. - (-dt / self.look_smoothing).exp()
+            } else {
+                1.
+            };
+
+            self.azimuth += (self.target_azimuth - self.azimuth) * t;
+            self.zenith += (self.target_zenith - self.zenith) * t;
+
+            if (self.target_azimuth - self.azimuth).abs() < 1e-3
+                && (self.target_zenith - self.zenith).abs() < 1e-3
+            {
+                self.azimuth = self.target_azimuth;
+                self.zenith = self.target_zenith;
+            }
+
+            self.adjust_dir();
         }
     }
 
@@ -60,31 +209,199 @@ impl Camera {
         self.view_matrix
     }
 
-    /// Sets the position of the camera
+    /// Returns the current view
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            pos: self.pos.to_array(),
+            azimuth: self.target_azimuth,
+            zenith: self.target_zenith,
+            mode: self.mode,
+            target: self.target.to_array(),
+            distance: self.distance,
+        }
+    }
+
+    /// Restores a view saved with `pose`
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.pos = Vec3::from(pose.pos);
+        self.mode = pose.mode;
+        self.target = Vec3::from(pose.target);
+        self.distance = pose.distance.max(MIN_ORBIT_DISTANCE);
+        self.velocity = Vec3::ZERO;
+
+        self.azimuth = pose.azimuth;
+        self.zenith = pose.zenith.clamp(-89., 89.);
+        self.target_azimuth = self.azimuth;
+        self.target_zenith = self.zenith;
+
+        self.adjust_dir();
+    }
+
+    /// Returns the projection matrix for the aspect ratio (width / height)
+    pub fn projection_mat(&self, aspect: f32) -> Mat4 {
+        self.projection.matrix(aspect)
+    }
+
+    /// Projects a world position into the viewport of the size in pixels.
+    ///
+    /// The pixel coordinates are from the top-left corner (the same as the clicked pixels for
+    /// picking). Returns None for positions behind the camera or outside of the depth range.
+    pub fn world_to_pixel(&mut self, pos: Vec3, (width, height): (u32, u32)) -> Option<Vec2> {
+        let aspect = width as f32 / height.max(1) as f32;
+        let clip = self.projection_mat(aspect) * self.view_mat() * pos.extend(1.);
+        if clip.w <= 0. {
+            return None;
+        }
+
+        let ndc = clip.truncate() / clip.w;
+        if !(-1.0..=1.0).contains(&ndc.z) {
+            return None;
+        }
+
+        Some(Vec2::new(
+            (ndc.x + 1.) * 0.5 * width as f32,
+            (1. - ndc.y) * 0.5 * height as f32,
+        ))
+    }
+
+    /// Returns the position of the camera
+    pub fn pos(&self) -> Vec3 {
+        self.pos
+    }
+
+    /// Sets the position of the camera.
+    ///
+    /// In the orbit mode the camera keeps looking at the target.
     pub fn set_pos(&mut self, pos: Vec3) {
         self.pos = pos;
         self.changed = true;
+
+        if self.mode == CameraMode::Orbit {
+            self.look_at(self.target);
+        }
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// Switches the camera mode while keeping the current view.
+    ///
+    /// When switching to the orbit mode, the orbit target is the point on the view ray
+    /// that is closest to 'focus' (usually the center of the selected model).
+    pub fn set_mode(&mut self, mode: CameraMode, focus: Vec3) {
+        if mode == self.mode {
+            return;
+        }
+
+        if mode == CameraMode::Orbit {
+            self.distance = (focus - self.pos).dot(self.dir).max(MIN_ORBIT_DISTANCE);
+            self.target = self.pos + self.dir * self.distance;
+        }
+
+        self.mode = mode;
+    }
+
+    /// Positions the camera so the bounding sphere fills ~70% of the vertical field of view.
+    ///
+    /// The view direction doesn't change. In the orbit mode the center becomes the new target.
+    pub fn frame_bounds(&mut self, center: Vec3, radius: f32) {
+        // The sphere covers an angle of 2 * asin(radius / distance)
+        let half_angle = (self.projection.fov * 0.7 * 0.5).to_radians();
+        let distance = (radius / half_angle.sin()).max(MIN_ORBIT_DISTANCE);
+
+        if self.projection.orthographic {
+            self.projection.ortho_size = radius / 0.7;
+        }
+
+        self.move_to(center - self.dir * distance, center);
+    }
+
+    /// Moves the camera to a new position, keeping the view direction.
+    ///
+    /// The transition is instant for now, animating it only requires changing this method.
+    fn move_to(&mut self, pos: Vec3, target: Vec3) {
+        match self.mode {
+            CameraMode::Free => {
+                self.pos = pos;
+                self.changed = true;
+            }
+            CameraMode::Orbit => {
+                self.target = target;
+                self.distance = (target - pos).length().max(MIN_ORBIT_DISTANCE);
+                self.adjust_dir();
+            }
+        }
+    }
+
+    /// Moves the orbit target while keeping the orientation and distance
+    pub fn set_target(&mut self, target: Vec3) {
+        if self.mode == CameraMode::Orbit {
+            self.target = target;
+            self.adjust_dir();
+        }
+    }
+
+    /// Turns the camera towards the target.
+    ///
+    /// In the orbit mode the target also becomes the new orbit target.
+    pub fn look_at(&mut self, target: Vec3) {
+        let dir = (target - self.pos).normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return;
+        }
+
+        if self.mode == CameraMode::Orbit {
+            self.target = target;
+            self.distance = (target - self.pos).length();
+        }
+
+        // Inverse of 'adjust_dir'
+        self.zenith = dir.y.asin().to_degrees().clamp(-89., 89.);
+        self.azimuth = dir.z.atan2(dir.x).to_degrees() - 270.;
+        self.target_zenith = self.zenith;
+        self.target_azimuth = self.azimuth;
+
+        self.adjust_dir();
+    }
+
+    /// If the camera stopped moving and turning (`update` wouldn't change it)
+    pub fn is_settled(&self) -> bool {
+        self.velocity == Vec3::ZERO
+            && self.move_intent == Vec3::ZERO
+            && self.azimuth == self.target_azimuth
+            && self.zenith == self.target_zenith
     }
 
-    /// Moves the camera forward
+    /// Requests moving the camera forward in this frame.
+    ///
+    /// The movement itself is applied by `update`.
     pub fn move_forward(&mut self, d: f32) {
-        self.pos += self.dir * d * self.move_speed;
-        self.changed = true;
+        self.move_intent += self.dir * d;
     }
 
-    /// Moves the camera backward
+    /// Requests moving the camera backward in this frame
     pub fn move_backward(&mut self, d: f32) {
         self.move_forward(-d);
     }
 
-    /// Moves the camera sideways to the right
+    /// Requests moving the camera up along the world Y axis in this frame
+    pub fn move_up(&mut self, d: f32) {
+        self.move_intent.y += d;
+    }
+
+    /// Requests moving the camera down along the world Y axis in this frame
+    pub fn move_down(&mut self, d: f32) {
+        self.move_up(-d);
+    }
+
+    /// Requests moving the camera sideways to the right in this frame
     pub fn strafe_right(&mut self, d: f32) {
         let dir = self.dir.cross(self.up);
-        self.pos += dir * d * self.move_speed;
-        self.changed = true;
+        self.move_intent += dir * d;
     }
 
-    /// Moves the camera sideways to the left
+    /// Requests moving the camera sideways to the left in this frame
     pub fn strafe_left(&mut self, d: f32) {
         self.strafe_right(-d);
     }
@@ -95,23 +412,65 @@ impl Camera {
         self.current_y = new_y;
     }
 
-    /// Update the (x, y) mouse position and update the azimuth and zenith
-    pub fn adjust_look(&mut self, new_x: f32, new_y: f32) {
+    /// Update the azimuth and zenith by a relative mouse movement (in pixels)
+    pub fn look_delta(&mut self, dx: f32, dy: f32) {
+        let x_offset = dx * self.look_sensitivity;
+        let y_offset = -dy * self.look_sensitivity;
+
+        self.target_azimuth += x_offset;
+        self.target_zenith += y_offset;
+
+        self.target_zenith = self.target_zenith.clamp(-89., 89.);
+
+        // Without smoothing the look is applied immediately, otherwise in `update`
+        if self.look_smoothing <= 0. {
+            self.azimuth = self.target_azimuth;
+            self.zenith = self.target_zenith;
+            self.adjust_dir();
+        }
+    }
+
+    /// Dollies the camera along its direction (free mode) or changes the distance
+    /// from the target (orbit mode).
+    ///
+    /// 'scene_distance' is the distance to the scene. Zooming is faster when the scene is further
+    /// away, so it's usable for both small and large models.
+    pub fn zoom(&mut self, amount: f32, scene_distance: f32) {
+        // Moving the camera doesn't change the size of the orthographic view
+        if self.projection.orthographic {
+            self.projection.ortho_size *= 1. - amount * 0.1;
+            self.projection.ortho_size = self.projection.ortho_size.max(0.01);
+        }
+
+        match self.mode {
+            CameraMode::Free => {
+                self.pos += self.dir * amount * self.move_speed * scene_distance.max(1.) * 0.02;
+                self.changed = true;
+            }
+            CameraMode::Orbit => {
+                self.distance = (self.distance * (1. - amount * 0.1)).max(MIN_ORBIT_DISTANCE);
+                self.adjust_dir();
+            }
+        }
+    }
+
+    /// Update the (x, y) mouse position and move the orbit target in the view plane
+    pub fn pan(&mut self, new_x: f32, new_y: f32) {
         let dx = new_x - self.current_x;
-        let dy = self.current_y - new_y;
+        let dy = new_y - self.current_y;
 
         self.current_x = new_x;
         self.current_y = new_y;
 
-        let x_offset = dx * self.look_sensitivity;
-        let y_offset = dy * self.look_sensitivity;
-
-        self.azimuth += x_offset;
-        self.zenith += y_offset;
-
-        self.zenith = self.zenith.clamp(-89., 89.);
+        if self.mode == CameraMode::Orbit {
+            let right = self.dir.cross(self.up).normalize();
+            let up = right.cross(self.dir);
 
-        self.adjust_dir();
+            // Pan faster when further away, so the target follows the cursor more or less
+            let scale = self.distance * self.look_sensitivity * 0.02;
+            self.target += (up * dy - right * dx) * scale;
+            self.adjust_dir();
+        }
     }
 
     /// Update the azimuth and zenith
@@ -125,5 +484,9 @@ impl Camera {
 
         self.dir = Vec3::new(x as f32, y as f32, z as f32).normalize();
         self.changed = true;
+
+        if self.mode == CameraMode::Orbit {
+            self.pos = self.target - self.dir * self.distance;
+        }
     }
 }
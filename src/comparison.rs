@@ -0,0 +1,98 @@
+use glam::{Mat4, Vec3};
+
+use crate::model::Model;
+
+/// Sync group of the selected and the compared model
+const COMPARE_SYNC_GROUP: usize = 0;
+
+/// A model compared with the selected one
+struct ComparedModel {
+    model: usize,
+    /// The transform of the model before it was moved next to the selected model
+    transform: Mat4,
+}
+
+/// Compares a model with the selected one.
+///
+/// The compared model is moved next to the selected model (along the X axis) and both of them
+/// are put into a sync group, so the compared model plays the selected model's animation.
+pub struct Comparison {
+    compared: Option<ComparedModel>,
+}
+
+impl Comparison {
+    pub fn new() -> Self {
+        Self { compared: None }
+    }
+
+    /// Starts or ends the comparison when the compared model ('compare_model') changes
+    pub fn update(&mut self, scene: &mut [Model], selected: usize, compare_model: Option<usize>) {
+        let wanted = compare_model.filter(|&i| i != selected && i < scene.len());
+
+        if self.compared.as_ref().map(|c| c.model) == wanted {
+            return;
+        }
+
+        if let Some(old) = self.compared.take() {
+            if let Some(model) = scene.get_mut(old.model) {
+                model.transform = old.transform;
+            }
+
+            for model in scene.iter_mut() {
+                model.animations.sync_group = None;
+            }
+        }
+
+        let (index, selected_bounds) = match (wanted, scene.get(selected)) {
+            (Some(index), Some(selected)) => (index, selected.bounds()),
+            _ => return,
+        };
+
+        let model = &mut scene[index];
+        let bounds = model.bounds();
+
+        // A small gap proportional to the sizes of the models
+        let gap =
+            0.1 * (selected_bounds.max.x - selected_bounds.min.x).max(bounds.max.x - bounds.min.x);
+        let offset = selected_bounds.max.x - bounds.min.x + gap;
+
+        self.compared = Some(ComparedModel {
+            model: index,
+            transform: model.transform,
+        });
+
+        model.transform = Mat4::from_translation(Vec3::X * offset) * model.transform;
+        model.animations.sync_group = Some(COMPARE_SYNC_GROUP);
+        scene[selected].animations.sync_group = Some(COMPARE_SYNC_GROUP);
+    }
+
+    /// Forgets the compared model without restoring its transform (the scene was replaced)
+    pub fn clear(&mut self) {
+        self.compared = None;
+    }
+}
+
+/// The models in the sync group of the selected model play its active animation
+/// at the same time
+pub fn sync_animations(scene: &mut [Model], selected: usize) {
+    if selected >= scene.len() {
+        return;
+    }
+
+    let (before, rest) = scene.split_at_mut(selected);
+    let (leader, after) = match rest.split_first_mut() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let group = match leader.animations.sync_group {
+        Some(group) => group,
+        None => return,
+    };
+
+    for model in before.iter_mut().chain(after) {
+        if model.animations.sync_group == Some(group) {
+            model.animations.follow(&leader.animations);
+        }
+    }
+}
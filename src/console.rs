@@ -0,0 +1,92 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Maximum number of kept messages, the oldest ones are dropped
+const MAX_ENTRIES: usize = 2000;
+
+/// A single logged message
+#[derive(Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    /// Seconds since the console was initialized
+    pub time: f32,
+    pub message: String,
+}
+
+/// Logger of the `log` crate that keeps the messages for the in-app console
+/// (and prints them to stderr)
+struct Console {
+    entries: Mutex<Vec<LogEntry>>,
+    start: OnceLock<Instant>,
+}
+
+static CONSOLE: Console = Console {
+    entries: Mutex::new(Vec::new()),
+    start: OnceLock::new(),
+};
+
+impl Log for Console {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        eprintln!("[{}] {message}", record.level());
+
+        let time = self.start.get_or_init(Instant::now).elapsed().as_secs_f32();
+
+        // A panic while holding the lock doesn't make the messages invalid
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_ENTRIES {
+            entries.remove(0);
+        }
+
+        entries.push(LogEntry {
+            level: record.level(),
+            time,
+            message,
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the console as the logger, messages up to the debug level are kept.
+///
+/// Messages logged before this is called are dropped.
+pub fn init() {
+    CONSOLE.start.get_or_init(Instant::now);
+
+    if log::set_logger(&CONSOLE).is_ok() {
+        log::set_max_level(LevelFilter::Debug);
+    }
+}
+
+/// Copy of the logged messages (oldest first).
+///
+/// A copy, so messages can be logged while the caller works with them.
+pub fn entries() -> Vec<LogEntry> {
+    CONSOLE
+        .entries
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Removes all of the logged messages
+pub fn clear() {
+    CONSOLE
+        .entries
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+}
@@ -0,0 +1,242 @@
+use std::{
+    f32::consts::TAU,
+    fs::{self, File},
+    io::BufWriter,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{eyre, Result};
+use glam::Vec3;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame,
+};
+
+use crate::{
+    camera::Camera,
+    model::{AnimationControl, Model, TimeSource},
+    renderer::{RenderOptions, Renderer},
+};
+
+/// Output format of the export
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// A single animated GIF
+    Gif,
+    /// A directory with a PNG image for each frame
+    Png,
+}
+
+/// Settings of the export (edited in the "Export" window)
+#[derive(Clone, Copy)]
+pub struct ExportSettings {
+    /// Length of the exported animation in seconds
+    pub duration: f32,
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+    /// If the camera should orbit around the model (one full turn)
+    pub turntable: bool,
+    pub format: ExportFormat,
+}
+
+impl ExportSettings {
+    pub fn new() -> Self {
+        Self {
+            duration: 3.,
+            fps: 30,
+            width: 640,
+            height: 480,
+            turntable: true,
+            format: ExportFormat::Gif,
+        }
+    }
+}
+
+/// Where the rendered frames go
+enum ExportOutput {
+    Gif(GifEncoder<BufWriter<File>>),
+    /// Path of the output directory
+    Png(String),
+}
+
+/// An export in progress.
+///
+/// Only a single frame is rendered per `render_frame` call, so the render loop (and the GUI)
+/// keeps running during the export.
+pub struct Export {
+    settings: ExportSettings,
+    output: ExportOutput,
+    /// Index of the exported model
+    model: usize,
+    /// Render settings at the start of the export (the exported model is selected)
+    options: RenderOptions,
+    /// Animation state of the model before the export (restored afterwards)
+    saved_control: AnimationControl,
+    saved_time: f32,
+    /// Camera used when the turntable mode is off
+    camera: Camera,
+    frame: u32,
+    frame_count: u32,
+}
+
+impl Export {
+    /// Starts exporting the model selected by the render options
+    pub fn new(
+        settings: ExportSettings,
+        scene: &[Model],
+        camera: &Camera,
+        options: RenderOptions,
+    ) -> Result<Self> {
+        let model = scene
+            .get(options.selected_model)
+            .ok_or_else(|| eyre!("There is no model to export"))?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let name = format!("exports/{}_{timestamp}", model.name);
+
+        let output = match settings.format {
+            ExportFormat::Gif => {
+                fs::create_dir_all("exports")?;
+
+                let file = File::create(format!("{name}.gif"))?;
+                let mut encoder = GifEncoder::new(BufWriter::new(file));
+                encoder.set_repeat(Repeat::Infinite)?;
+
+                ExportOutput::Gif(encoder)
+            }
+            ExportFormat::Png => {
+                fs::create_dir_all(&name)?;
+                ExportOutput::Png(name)
+            }
+        };
+
+        let (saved_control, saved_time) = match model.animations.active_animation() {
+            Some(i) => (
+                model.animations.animation_control,
+                model.animations.animations[i].current_time,
+            ),
+            None => (model.animations.animation_control, 0.),
+        };
+
+        let frame_count = ((settings.duration * settings.fps as f32).round() as u32).max(1);
+
+        Ok(Self {
+            settings,
+            output,
+            model: options.selected_model,
+            options,
+            saved_control,
+            saved_time,
+            camera: camera.clone(),
+            frame: 0,
+            frame_count,
+        })
+    }
+
+    /// The other models of the scene advance by the time of an exported frame,
+    /// so they don't depend on how long the rendering takes
+    pub fn time_source(&self) -> TimeSource {
+        TimeSource::FixedStep(1. / self.settings.fps as f32)
+    }
+
+    /// Progress of the export in the range [0, 1]
+    pub fn progress(&self) -> f32 {
+        self.frame as f32 / self.frame_count as f32
+    }
+
+    /// Renders and saves the next frame. Returns true when the export is finished.
+    pub fn render_frame(&mut self, renderer: &mut Renderer, scene: &mut [Model]) -> Result<bool> {
+        let model = &mut scene[self.model];
+
+        // The time of the frame doesn't depend on how long it takes to render it
+        let time = self.frame as f32 / self.settings.fps as f32;
+        let animation = match model.animations.active_animation() {
+            Some(i) => Some(i),
+            None if !model.animations.animations.is_empty() => Some(0),
+            None => None,
+        };
+
+        if let Some(i) = animation {
+            let anim = &mut model.animations.animations[i];
+            anim.current_time = if anim.end_time > 0. {
+                time % anim.end_time
+            } else {
+                0.
+            };
+
+            model.animations.animation_control = AnimationControl::Controllable {
+                active_animation: i,
+            };
+        }
+
+        let mut camera = if self.settings.turntable {
+            self.turntable_camera(model)
+        } else {
+            self.camera.clone()
+        };
+
+        let image = renderer.render_to_image(
+            scene,
+            &mut camera,
+            &self.options,
+            self.settings.width,
+            self.settings.height,
+        )?;
+
+        match &mut self.output {
+            ExportOutput::Gif(encoder) => {
+                let delay = Delay::from_numer_denom_ms(1000, self.settings.fps);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+            ExportOutput::Png(dir) => {
+                image.save(format!("{dir}/frame_{:04}.png", self.frame))?;
+            }
+        }
+
+        self.frame += 1;
+
+        let finished = self.frame >= self.frame_count;
+        if finished {
+            self.restore_animation(&mut scene[self.model]);
+        }
+
+        Ok(finished)
+    }
+
+    /// Puts the animation of the model back to the state before the export
+    fn restore_animation(&self, model: &mut Model) {
+        model.animations.animation_control = self.saved_control;
+
+        if let Some(i) = model.animations.active_animation() {
+            model.animations.animations[i].current_time = self.saved_time;
+        }
+    }
+
+    /// Camera orbiting around the model, one full turn over the whole export
+    fn turntable_camera(&self, model: &Model) -> Camera {
+        let bounds = model.bounds();
+        let (center, radius) = if bounds.is_empty() {
+            (Vec3::ZERO, 1.)
+        } else {
+            (bounds.center(), bounds.radius())
+        };
+
+        let angle = TAU * self.frame as f32 / self.frame_count as f32;
+        let elevation = 15f32.to_radians();
+        // The whole bounding sphere fits into the field of view
+        let half_fov = (self.camera.projection.fov * 0.4).to_radians();
+        let distance = radius / half_fov.sin();
+
+        let offset = Vec3::new(
+            angle.sin() * elevation.cos(),
+            elevation.sin(),
+            angle.cos() * elevation.cos(),
+        );
+
+        let mut camera = self.camera.clone();
+        camera.set_pos(center + offset * distance);
+        camera.look_at(center);
+        camera
+    }
+}
@@ -0,0 +1,50 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Dead zones and speeds of the game controller, persisted with the settings
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    /// Stick deflections (0 - 1) smaller than this are ignored
+    pub stick_dead_zone: f32,
+    /// Trigger values (0 - 1) smaller than this are ignored
+    pub trigger_dead_zone: f32,
+    /// Looking speed at the full deflection of the right stick in degrees per second
+    pub look_speed: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            stick_dead_zone: 0.15,
+            trigger_dead_zone: 0.1,
+            look_speed: 120.,
+        }
+    }
+}
+
+impl GamepadSettings {
+    /// Removes the dead zone of a stick, the rest of the range is rescaled to 0 - 1.
+    ///
+    /// The dead zone is radial, so small diagonal movements aren't snapped to the axes.
+    pub fn stick(&self, x: f32, y: f32) -> Vec2 {
+        let stick = Vec2::new(x, y);
+        let length = stick.length().min(1.);
+
+        if length <= self.stick_dead_zone {
+            return Vec2::ZERO;
+        }
+
+        let scaled = (length - self.stick_dead_zone) / (1. - self.stick_dead_zone);
+        stick.normalize() * scaled
+    }
+
+    /// Removes the dead zone of a trigger, the rest of the range is rescaled to 0 - 1
+    pub fn trigger(&self, value: f32) -> f32 {
+        if value <= self.trigger_dead_zone {
+            0.
+        } else {
+            (value - self.trigger_dead_zone) / (1. - self.trigger_dead_zone)
+        }
+    }
+}
@@ -1,230 +1,2755 @@
-use std::time::Instant;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
+
+use egui::{CollapsingHeader, CtxRef, Response, RichText, Slider, Ui};
+use glam::{EulerRot, Mat4, Quat, Vec3, Vec4};
+use log::{error, warn, Level};
+use sdl2::video::SwapInterval;
+
+use leoric::{
+    bookmarks::{Bookmark, Bookmarks},
+    camera::{Camera, CameraMode},
+    console::{self, LogEntry},
+    export::{ExportFormat, ExportSettings},
+    joint_export::JointExportFormat,
+    loader::PendingLoad,
+    model::{
+        Aabb, Animation, AnimationControl, Animations, Joint, Mesh, Model, Node, PoseHistory,
+        Retargeted, TimeSource, Transform, TwoBoneIk,
+    },
+    ogl::{PassTime, TextureFiltering},
+    poses::{PosePreset, PosePresets, SavedAnimation, SavedMask},
+    renderer::{
+        Background, FrameStats, Light, LightType, Outline, RenderOptions, ToneMapping, Winding,
+        Wireframe, MAX_LIGHTS,
+    },
+};
+
+use crate::{
+    gamepad::GamepadSettings,
+    key_bindings::{Action, KeyBindings},
+};
+
+use self::{
+    status_bar::StatusBar,
+    timeline::{Timeline, TimelineView},
+};
+
+mod status_bar;
+mod timeline;
+
+/// Result of reloading a model from disk
+pub enum ReloadStatus {
+    /// When the model was last reloaded
+    Reloaded(Instant),
+    /// The error of the last reload
+    Failed(String),
+}
+
+/// Contains the current state of the GUI.
+/// Implements methods for displaying the widgets.
+pub struct Gui {
+    /// Default 0, out of range when the scene is empty
+    pub selected_model: usize,
+    /// Index of the joint selected in the joints window
+    pub selected_joint: Option<usize>,
+    /// Index of the node selected in the hierarchy window (its statistics are shown)
+    selected_node: Option<usize>,
+    /// Index of the node whose ancestors are expanded in the hierarchy window this frame
+    reveal_node: Option<usize>,
+    /// Pixel that was clicked in the viewport, the node under it is selected
+    pub pick: Option<(u32, u32)>,
+    /// If the skeleton is also drawn alone in a smaller view in the bottom-left corner
+    pub show_skeleton_inset: bool,
+    /// Size of the skeleton view relative to the window
+    pub skeleton_inset_size: f32,
+    /// Index of the node hovered in the hierarchy window, its meshes are outlined
+    hovered_node: Option<usize>,
+    /// Brightness of the hovered node's outline, pulses over time
+    hover_flash: f32,
+    /// If the selected model is outlined
+    pub outline_selected: bool,
+    pub outline_color: Vec3,
+    /// Outline width in pixels
+    pub outline_width: f32,
+    pub wireframe: bool,
+    /// If the wireframe is drawn over the shaded mesh
+    pub wireframe_overlay: bool,
+    pub wireframe_color: Vec3,
+    /// Wireframe line width in pixels
+    pub wireframe_width: f32,
+    /// If the back faces of the meshes are culled
+    pub face_culling: bool,
+    pub front_face: Winding,
+    /// If the meshes are depth tested
+    pub depth_test: bool,
+    /// If all nodes draw the 'forced_lod' level of detail
+    pub force_lod: bool,
+    pub forced_lod: usize,
+    /// The model drawn next to the selected one with the same animation (comparison mode)
+    pub compare_model: Option<usize>,
+    /// If the joints that diverge from the compared model's joints are highlighted
+    pub highlight_divergence: bool,
+    /// Distance (in bone lengths) at which the joints are highlighted
+    pub divergence_threshold: f32,
+    /// Text filtering the joints window (the results are a flat list)
+    joint_filter: String,
+    /// Text filtering the nodes of the model hierarchy window by their (and their meshes') names
+    node_filter: String,
+    /// The selected joint whose ancestors were last expanded in the joints window
+    expanded_joint: Option<usize>,
+    /// Pose presets of the models (by model name), loaded when the joints window is first shown
+    pose_presets: HashMap<String, PosePresets>,
+    /// Name of the next saved pose preset
+    pose_name: String,
+    /// Index of the animation created in the GUI that keyframes are inserted into
+    keyframe_animation: Option<usize>,
+    /// If keyframes should be inserted for all joints (otherwise only for the edited ones)
+    key_all_joints: bool,
+    /// The IK chain posed in the joints window
+    ik: Option<TwoBoneIk>,
+    /// Transforms of the root and mid joint of the IK chain at the start of the current drag
+    ik_drag_start: Option<(Transform, Transform)>,
+    /// If the joint mask of the active animation was changed this frame (and should be saved)
+    mask_changed: bool,
+    /// Zoom / pan of the animation timelines (by model path and animation index)
+    timeline_views: HashMap<(String, usize), TimelineView>,
+    /// If the last joint edit of the selected model should be undone this frame
+    pub undo_pose: bool,
+    /// If the last undone joint edit of the selected model should be redone this frame
+    pub redo_pose: bool,
+    /// If the camera should focus the selected joint / model this frame
+    pub focus_selected: bool,
+    /// If the animations of the models that aren't selected keep playing
+    pub animate_unselected: bool,
+    /// If the animations advance by the real frame time or by a fixed step
+    pub time_source: TimeSource,
+    /// If joints should be visible inside of the mesh
+    pub draw_skeleton: bool,
+    /// Multiplies the size of the skeleton overlay
+    pub skeleton_scale: f32,
+    /// If the names of the joints are shown next to the skeleton overlay
+    pub show_joint_labels: bool,
+    /// If only the selected joint and its descendants are labeled
+    pub label_selected_subtree: bool,
+    /// If the mesh should be visible
+    pub mesh_visible: bool,
+    /// If the skinned meshes are shown in the bind pose
+    pub bind_pose: bool,
+    /// If nodes outside of the view frustum should be skipped
+    pub frustum_culling: bool,
+    /// If the opaque draw calls should be recorded and sorted by textures before drawing
+    /// (otherwise they are drawn immediately in the node order)
+    pub sort_draws: bool,
+    /// If rendering should be sRGB-correct (textures decoded from sRGB, output gamma-corrected)
+    pub srgb: bool,
+    /// Filtering of the model textures
+    pub texture_filtering: TextureFiltering,
+    /// Anisotropic filtering level
+    pub anisotropy: f32,
+    /// If skinned meshes blend the joint transforms as dual quaternions instead of matrices
+    /// (no candy-wrapper artifacts on twisted joints, non-uniform joint scale isn't supported)
+    pub dual_quaternion_skinning: bool,
+    /// If skinned meshes are skinned on the CPU and drawn without skinning in the shader
+    /// (for checking that both give the same result)
+    pub cpu_skinning: bool,
+    /// If skinned meshes are skinned by a compute shader once per frame
+    /// (falls back to the vertex shader if compute shaders aren't supported)
+    pub compute_skinning: bool,
+    /// Lights illuminating the scene (at most MAX_LIGHTS)
+    pub lights: Vec<Light>,
+    /// Color of the ambient light
+    pub ambient_light: Vec3,
+    /// What is drawn behind the models
+    pub background: Background,
+    /// Color of the solid background
+    pub background_color: Vec3,
+    /// Multiplies the environment map
+    pub environment_intensity: f32,
+    /// How the HDR colors are mapped to the screen
+    pub tone_mapping: ToneMapping,
+    /// Multiplies the HDR colors before the tone mapping
+    pub exposure: f32,
+    /// Path of the environment map image (.hdr or LDR)
+    pub environment_file: String,
+    /// If the environment map should be loaded this frame
+    pub load_environment: bool,
+    /// If a screenshot should be saved at the end of this frame
+    pub take_screenshot: bool,
+    /// If the screenshot should be taken before the GUI is drawn
+    pub screenshot_without_ui: bool,
+    /// If the "Retarget animation" window is open
+    show_retarget_window: bool,
+    retarget_settings: RetargetSettings,
+    /// Result of the last retargeting
+    retarget_report: Vec<String>,
+    /// If the "Export" window is open
+    show_export_window: bool,
+    /// Settings of the next export
+    pub export_settings: ExportSettings,
+    /// If an export should be started this frame
+    pub start_export: bool,
+    /// File name of the exported posed mesh (in the 'exports' directory)
+    pub posed_mesh_file: String,
+    /// If the selected model should be exported in its current pose this frame
+    pub export_posed_mesh: bool,
+    /// File name of the exported joint positions without the extension (in the 'exports' directory)
+    pub joint_export_file: String,
+    pub joint_export_format: JointExportFormat,
+    /// Samples per second of the exported joint positions
+    pub joint_export_rate: f32,
+    /// Index of the animation whose joint positions are exported
+    pub joint_export_animation: usize,
+    /// Names of the joints whose positions are exported
+    pub joint_export_joints: HashSet<String>,
+    /// If the joint positions of the selected model should be exported this frame
+    pub export_joint_positions: bool,
+    /// Path of the saved / opened scene file
+    pub scene_file: String,
+    /// If the scene should be saved this frame
+    pub save_scene: bool,
+    /// If the scene file should be opened this frame (replacing the current scene)
+    pub open_scene: bool,
+    /// Progress of the running export (None if no export is running)
+    pub export_progress: Option<f32>,
+    /// Saved camera views
+    pub bookmarks: Bookmarks,
+    /// If the window should switch between windowed and fullscreen this frame
+    pub toggle_fullscreen: bool,
+    /// Scale of the GUI relative to the scale of the display
+    pub ui_scale: f32,
+    /// If the persisted settings should be reset to the defaults this frame
+    pub reset_settings: bool,
+    /// Index of the model that should be removed from the scene this frame
+    pub remove_model: Option<usize>,
+    /// Index of the model that should be reloaded from disk this frame
+    pub reload_model: Option<usize>,
+    /// Result of the last reload of the models (by path)
+    pub reload_status: HashMap<String, ReloadStatus>,
+    /// Models that are still loading
+    pub loading: Vec<PendingLoad>,
+    /// Id of the load that should be cancelled this frame
+    pub cancel_load: Option<u64>,
+    /// If the shaders should be recompiled this frame
+    pub reload_shaders: bool,
+    /// Compiler log of the shaders that failed to reload
+    pub shader_error: Option<String>,
+    /// If the "Log" window is open
+    show_log_window: bool,
+    /// If the "Controls" window is open
+    show_controls_window: bool,
+    /// Keys of the actions (camera movement, shortcuts)
+    pub key_bindings: KeyBindings,
+    /// The action that will be bound to the next pressed key
+    pub capture_key: Option<Action>,
+    /// Dead zones and speeds of the game controller
+    pub gamepad: GamepadSettings,
+    /// The least severe level of the messages shown in the "Log" window
+    log_level: Level,
+    /// Errors shown to the user until they are dismissed
+    pub errors: Vec<String>,
+    /// Immediate / VSync / Adaptive VSync
+    pub swap_interval: SwapInterval,
+    /// If the frame rate should be limited to `max_fps`
+    pub limit_fps: bool,
+    pub max_fps: u32,
+    /// Average duration of a frame in seconds
+    pub frame_time: f32,
+    /// Average CPU time of a frame in seconds (without waiting for the buffer swap)
+    pub cpu_frame_time: f32,
+    pub status_bar: StatusBar,
+}
+
+impl Gui {
+    pub fn new() -> Self {
+        let options = RenderOptions::default();
+
+        Self {
+            selected_model: options.selected_model,
+            selected_joint: None,
+            selected_node: None,
+            reveal_node: None,
+            pick: None,
+            show_skeleton_inset: false,
+            skeleton_inset_size: 0.3,
+            hovered_node: None,
+            hover_flash: 0.,
+            outline_selected: options.outline.is_some(),
+            outline_color: options.outline.map_or(Vec3::ONE, |outline| outline.color),
+            outline_width: options.outline.map_or(3., |outline| outline.width),
+            wireframe: options.wireframe.is_some(),
+            wireframe_overlay: options.wireframe.is_none_or(|wireframe| wireframe.overlay),
+            wireframe_color: options
+                .wireframe
+                .map_or(Vec3::splat(0.05), |wireframe| wireframe.color),
+            wireframe_width: options.wireframe.map_or(1., |wireframe| wireframe.width),
+            face_culling: options.face_culling,
+            front_face: options.front_face,
+            depth_test: options.depth_test,
+            force_lod: options.forced_lod.is_some(),
+            forced_lod: options.forced_lod.unwrap_or(0),
+            compare_model: options.compare_model,
+            highlight_divergence: options.divergence_threshold.is_some(),
+            divergence_threshold: options.divergence_threshold.unwrap_or(0.1),
+            joint_filter: String::new(),
+            node_filter: String::new(),
+            expanded_joint: None,
+            pose_presets: HashMap::new(),
+            pose_name: String::new(),
+            keyframe_animation: None,
+            key_all_joints: false,
+            ik: None,
+            ik_drag_start: None,
+            mask_changed: false,
+            timeline_views: HashMap::new(),
+            undo_pose: false,
+            redo_pose: false,
+            focus_selected: false,
+            animate_unselected: false,
+            time_source: TimeSource::WallClock,
+            draw_skeleton: options.draw_skeleton,
+            skeleton_scale: options.skeleton_scale,
+            show_joint_labels: false,
+            label_selected_subtree: false,
+            mesh_visible: options.mesh_visible,
+            bind_pose: options.bind_pose,
+            frustum_culling: options.frustum_culling,
+            sort_draws: options.sort_draws,
+            srgb: options.srgb,
+            texture_filtering: options.texture_filtering,
+            anisotropy: options.anisotropy,
+            dual_quaternion_skinning: options.dual_quaternion_skinning,
+            cpu_skinning: options.cpu_skinning,
+            compute_skinning: options.compute_skinning,
+            lights: options.lights,
+            ambient_light: options.ambient_light,
+            background: options.background,
+            background_color: options.background_color,
+            environment_intensity: options.environment_intensity,
+            tone_mapping: options.tone_mapping,
+            exposure: options.exposure,
+            environment_file: "environment.hdr".to_string(),
+            load_environment: false,
+            take_screenshot: false,
+            screenshot_without_ui: false,
+            show_retarget_window: false,
+            retarget_settings: RetargetSettings::new(),
+            retarget_report: Vec::new(),
+            show_export_window: false,
+            export_settings: ExportSettings::new(),
+            start_export: false,
+            posed_mesh_file: "posed.obj".to_string(),
+            export_posed_mesh: false,
+            joint_export_file: "joints".to_string(),
+            joint_export_format: JointExportFormat::Csv,
+            joint_export_rate: 30.,
+            joint_export_animation: 0,
+            joint_export_joints: HashSet::new(),
+            export_joint_positions: false,
+            scene_file: "scene.json".to_string(),
+            save_scene: false,
+            open_scene: false,
+            export_progress: None,
+            bookmarks: Bookmarks::load(),
+            toggle_fullscreen: false,
+            ui_scale: 1.,
+            reset_settings: false,
+            remove_model: None,
+            reload_model: None,
+            reload_status: HashMap::new(),
+            loading: Vec::new(),
+            cancel_load: None,
+            reload_shaders: false,
+            shader_error: None,
+            show_log_window: false,
+            show_controls_window: false,
+            key_bindings: KeyBindings::default(),
+            capture_key: None,
+            gamepad: GamepadSettings::default(),
+            log_level: Level::Info,
+            errors: Vec::new(),
+            swap_interval: SwapInterval::Immediate,
+            limit_fps: false,
+            max_fps: 144,
+            frame_time: 0.,
+            cpu_frame_time: 0.,
+            status_bar: StatusBar::new(),
+        }
+    }
+
+    /// Sets the render settings shown in the GUI (eg. from an opened scene)
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        self.selected_model = options.selected_model;
+        self.draw_skeleton = options.draw_skeleton;
+        self.skeleton_scale = options.skeleton_scale;
+        self.mesh_visible = options.mesh_visible;
+        self.bind_pose = options.bind_pose;
+        self.frustum_culling = options.frustum_culling;
+        self.sort_draws = options.sort_draws;
+        self.srgb = options.srgb;
+        self.texture_filtering = options.texture_filtering;
+        self.anisotropy = options.anisotropy;
+        self.dual_quaternion_skinning = options.dual_quaternion_skinning;
+        self.cpu_skinning = options.cpu_skinning;
+        self.compute_skinning = options.compute_skinning;
+        self.lights = options.lights;
+        self.ambient_light = options.ambient_light;
+        self.background = options.background;
+        self.background_color = options.background_color;
+        self.environment_intensity = options.environment_intensity;
+        self.tone_mapping = options.tone_mapping;
+        self.exposure = options.exposure;
+        self.outline_selected = options.outline.is_some();
+        if let Some(outline) = options.outline {
+            self.outline_color = outline.color;
+            self.outline_width = outline.width;
+        }
+        self.wireframe = options.wireframe.is_some();
+        if let Some(wireframe) = options.wireframe {
+            self.wireframe_overlay = wireframe.overlay;
+            self.wireframe_color = wireframe.color;
+            self.wireframe_width = wireframe.width;
+        }
+        self.face_culling = options.face_culling;
+        self.front_face = options.front_face;
+        self.depth_test = options.depth_test;
+        self.force_lod = options.forced_lod.is_some();
+        if let Some(level) = options.forced_lod {
+            self.forced_lod = level;
+        }
+        self.compare_model = options.compare_model;
+        self.highlight_divergence = options.divergence_threshold.is_some();
+        if let Some(threshold) = options.divergence_threshold {
+            self.divergence_threshold = threshold;
+        }
+    }
+
+    /// Rectangle of the skeleton view in pixels (x, y, width, height from the top-left corner),
+    /// None if the view is hidden
+    pub fn skeleton_inset(&self, (width, height): (u32, u32)) -> Option<(u32, u32, u32, u32)> {
+        if !self.show_skeleton_inset {
+            return None;
+        }
+
+        // The view can't be larger than the window
+        let size = self.skeleton_inset_size.clamp(0., 1.);
+        let inset_width = (width as f32 * size) as u32;
+        let inset_height = (height as f32 * size) as u32;
+        Some((0, height - inset_height, inset_width, inset_height))
+    }
+
+    /// The render settings selected in the GUI
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            selected_model: self.selected_model,
+            draw_skeleton: self.draw_skeleton,
+            skeleton_scale: self.skeleton_scale,
+            mesh_visible: self.mesh_visible,
+            bind_pose: self.bind_pose,
+            frustum_culling: self.frustum_culling,
+            sort_draws: self.sort_draws,
+            srgb: self.srgb,
+            texture_filtering: self.texture_filtering,
+            anisotropy: self.anisotropy,
+            dual_quaternion_skinning: self.dual_quaternion_skinning,
+            cpu_skinning: self.cpu_skinning,
+            compute_skinning: self.compute_skinning,
+            lights: self.lights.clone(),
+            ambient_light: self.ambient_light,
+            background: self.background,
+            background_color: self.background_color,
+            environment_intensity: self.environment_intensity,
+            tone_mapping: self.tone_mapping,
+            exposure: self.exposure,
+            outline: self.outline(),
+            forced_lod: self.force_lod.then_some(self.forced_lod),
+            compare_model: self.compare_model,
+            divergence_threshold: self
+                .highlight_divergence
+                .then_some(self.divergence_threshold),
+            face_culling: self.face_culling,
+            front_face: self.front_face,
+            depth_test: self.depth_test,
+            wireframe: self.wireframe.then_some(Wireframe {
+                color: self.wireframe_color,
+                width: self.wireframe_width,
+                overlay: self.wireframe_overlay,
+            }),
+        }
+    }
+
+    /// The hovered node flashes, otherwise the selected model is outlined (if enabled)
+    fn outline(&self) -> Option<Outline> {
+        match self.hovered_node {
+            Some(node) => Some(Outline {
+                color: self.outline_color.lerp(Vec3::ONE, self.hover_flash),
+                width: self.outline_width,
+                node: Some(node),
+            }),
+            None if self.outline_selected => Some(Outline {
+                color: self.outline_color,
+                width: self.outline_width,
+                node: None,
+            }),
+            None => None,
+        }
+    }
+
+    /// Creates the GUI.
+    ///
+    /// Immediate mode GUI - is called every frame.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_gui(
+        &mut self,
+        scene: &mut [Model],
+        camera: &mut Camera,
+        stats: &FrameStats,
+        gpu_times: &[PassTime],
+        joint_positions: &[Vec3],
+        viewport: (u32, u32),
+        egui_ctx: &mut CtxRef,
+    ) {
+        // The selection can be out of range after the models failed to load
+        if !scene.is_empty() {
+            self.selected_model = self.selected_model.min(scene.len() - 1);
+        }
+
+        match scene.get_mut(self.selected_model) {
+            Some(model) => {
+                self.gui_model_hierarchy_window(model, egui_ctx);
+                self.gui_joints_window(model, egui_ctx);
+
+                if self.draw_skeleton && self.show_joint_labels {
+                    self.gui_joint_labels(model, camera, joint_positions, viewport, egui_ctx);
+                }
+            }
+            None if self.loading.is_empty() => self.gui_empty_scene_window(egui_ctx),
+            None => {}
+        }
+
+        // The bottom panel spans the whole width if it's created before the side panel
+        self.status_bar
+            .update(stats, scene.get(self.selected_model));
+        self.status_bar.show(egui_ctx);
+
+        self.gui_side_panel(scene, camera, stats, gpu_times, egui_ctx);
+        self.gui_export_window(egui_ctx);
+        self.gui_retarget_window(scene, egui_ctx);
+        self.gui_errors_window(egui_ctx);
+        self.gui_shader_error_window(egui_ctx);
+        self.gui_log_window(egui_ctx);
+        self.gui_controls_window(egui_ctx);
+    }
+
+    /// Create the window with the log messages (load warnings, errors...)
+    fn gui_log_window(&mut self, egui_ctx: &mut CtxRef) {
+        if !self.show_log_window {
+            return;
+        }
+
+        let mut open = self.show_log_window;
+        let level = &mut self.log_level;
+
+        egui::Window::new("Log")
+            .open(&mut open)
+            .default_width(600.)
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for l in [Level::Error, Level::Warn, Level::Info, Level::Debug] {
+                        ui.radio_value(level, l, l.as_str());
+                    }
+                });
+
+                let entries: Vec<LogEntry> = console::entries()
+                    .into_iter()
+                    .filter(|entry| entry.level <= *level)
+                    .collect();
+
+                let format = |entry: &LogEntry| {
+                    format!("{:9.3} {:5} {}", entry.time, entry.level, entry.message)
+                };
+
+                ui.horizontal(|ui| {
+                    if ui.button("Copy").clicked() {
+                        let text: Vec<String> = entries.iter().map(format).collect();
+                        ui.output().copied_text = text.join("\n");
+                    }
+
+                    if ui.button("Clear").clicked() {
+                        console::clear();
+                    }
+                });
+
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for entry in &entries {
+                        let color = match entry.level {
+                            Level::Error => egui::Color32::RED,
+                            Level::Warn => egui::Color32::YELLOW,
+                            Level::Info => egui::Color32::LIGHT_GRAY,
+                            Level::Debug | Level::Trace => egui::Color32::GRAY,
+                        };
+
+                        ui.colored_label(color, format(entry));
+                    }
+                });
+            });
+
+        self.show_log_window = open;
+    }
+
+    /// Create the window for changing the key bindings
+    fn gui_controls_window(&mut self, egui_ctx: &mut CtxRef) {
+        if !self.show_controls_window {
+            return;
+        }
+
+        let mut open = self.show_controls_window;
+
+        egui::Window::new("Controls")
+            .open(&mut open)
+            .resizable(false)
+            .show(egui_ctx, |ui| {
+                ui.label("Click on a key and press the new key (Escape cancels)");
+                ui.separator();
+
+                egui::Grid::new("key_bindings").show(ui, |ui| {
+                    for action in Action::ALL {
+                        ui.label(action.name());
+
+                        let text = if self.capture_key == Some(action) {
+                            "Press a key...".to_string()
+                        } else {
+                            self.key_bindings.key_name(action).to_string()
+                        };
+
+                        if ui.button(text).clicked() {
+                            self.capture_key = Some(action);
+                        }
+
+                        let conflicts = self.key_bindings.conflicts(action);
+                        if conflicts.is_empty() {
+                            ui.label("");
+                        } else {
+                            let names: Vec<&str> = conflicts.iter().map(|a| a.name()).collect();
+                            ui.colored_label(
+                                egui::Color32::RED,
+                                format!("Also bound to: {}", names.join(", ")),
+                            );
+                        }
+
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+
+                ui.label("Game controller");
+                ui.add(
+                    Slider::new(&mut self.gamepad.stick_dead_zone, 0.0..=0.5)
+                        .text("Stick dead zone"),
+                );
+                ui.add(
+                    Slider::new(&mut self.gamepad.trigger_dead_zone, 0.0..=0.5)
+                        .text("Trigger dead zone"),
+                );
+                ui.add(
+                    Slider::new(&mut self.gamepad.look_speed, 10.0..=360.0)
+                        .text("Look speed (°/s)"),
+                );
+                ui.small("A plays / pauses the animation, B and X select the next / previous one");
+
+                ui.separator();
+
+                if ui.button("Reset to defaults").clicked() {
+                    self.key_bindings = KeyBindings::default();
+                    self.gamepad = GamepadSettings::default();
+                    self.capture_key = None;
+                }
+            });
+
+        if !open {
+            self.capture_key = None;
+        }
+
+        self.show_controls_window = open;
+    }
+
+    /// Create the window with the compiler log of the shaders that failed to reload
+    fn gui_shader_error_window(&mut self, egui_ctx: &mut CtxRef) {
+        let error = match &self.shader_error {
+            Some(error) => error,
+            None => return,
+        };
+
+        let mut dismissed = false;
+
+        egui::Window::new("Shader error").show(egui_ctx, |ui| {
+            ui.label("The shaders failed to reload, the previous version is still used.");
+
+            egui::ScrollArea::vertical()
+                .max_height(300.)
+                .show(ui, |ui| ui.monospace(error));
+
+            dismissed = ui.button("Dismiss").clicked();
+        });
+
+        if dismissed {
+            self.shader_error = None;
+        }
+    }
+
+    /// Create the window listing the errors (eg. models that failed to load)
+    fn gui_errors_window(&mut self, egui_ctx: &mut CtxRef) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        egui::Window::new("Errors")
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0., 10.])
+            .show(egui_ctx, |ui| {
+                for error in &self.errors {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                if ui.button("Dismiss").clicked() {
+                    self.errors.clear();
+                }
+            });
+    }
+
+    /// Bounding box of the selected model, empty if there is no model
+    pub fn selected_bounds(&self, scene: &[Model]) -> Aabb {
+        scene
+            .get(self.selected_model)
+            .map(|model| model.bounds())
+            .unwrap_or(Aabb::EMPTY)
+    }
+
+    /// Create the window explaining how to load models into an empty scene
+    fn gui_empty_scene_window(&mut self, egui_ctx: &mut CtxRef) {
+        egui::Window::new("No models loaded")
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+            .show(egui_ctx, |ui| {
+                ui.label("No models loaded - drag a glTF / GLB / OBJ file onto the window,");
+                ui.label("pass paths as command-line arguments:");
+                ui.monospace("leoric model.gltf other.glb:scale=0.015");
+                ui.label("or put the files into the 'resources' directory.");
+
+                if ui.button("Show log").clicked() {
+                    self.show_log_window = true;
+                }
+            });
+    }
+
+    /// Create the window with the animation export settings
+    fn gui_export_window(&mut self, egui_ctx: &mut CtxRef) {
+        let mut open = self.show_export_window;
+        let settings = &mut self.export_settings;
+
+        egui::Window::new("Export")
+            .open(&mut open)
+            .resizable(false)
+            .show(egui_ctx, |ui| {
+                let running = self.export_progress.is_some();
+
+                ui.add_enabled_ui(!running, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Duration (s)");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.duration)
+                                .speed(0.1)
+                                .clamp_range(0.1..=60.),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("FPS");
+                        ui.add(egui::DragValue::new(&mut settings.fps).clamp_range(1..=60));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Resolution");
+                        ui.add(egui::DragValue::new(&mut settings.width).clamp_range(16..=4096));
+                        ui.label("x");
+                        ui.add(egui::DragValue::new(&mut settings.height).clamp_range(16..=4096));
+                    });
+
+                    ui.checkbox(&mut settings.turntable, "Turntable");
+
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut settings.format, ExportFormat::Gif, "GIF");
+                        ui.radio_value(&mut settings.format, ExportFormat::Png, "PNG sequence");
+                    });
+
+                    if ui.button("Export").clicked() {
+                        self.start_export = true;
+                    }
+                });
+
+                if let Some(progress) = self.export_progress {
+                    ui.add(egui::ProgressBar::new(progress).show_percentage());
+                    ui.ctx().request_repaint();
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("File");
+                    ui.text_edit_singleline(&mut self.posed_mesh_file);
+                });
+
+                if ui.button("Export posed mesh").clicked() {
+                    self.export_posed_mesh = true;
+                }
+            });
+
+        self.show_export_window = open;
+    }
+
+    /// Create the window for copying an animation of one model onto another model
+    fn gui_retarget_window(&mut self, scene: &mut [Model], egui_ctx: &mut CtxRef) {
+        if scene.is_empty() {
+            return;
+        }
+
+        let mut open = self.show_retarget_window;
+        let settings = &mut self.retarget_settings;
+        let report = &mut self.retarget_report;
+
+        egui::Window::new("Retarget animation")
+            .open(&mut open)
+            .show(egui_ctx, |ui| {
+                settings.source = settings.source.min(scene.len() - 1);
+                settings.target = settings.target.min(scene.len() - 1);
+
+                Self::model_combo_box(ui, "Source model", &mut settings.source, scene);
+
+                let animations = &scene[settings.source].animations.animations;
+                if animations.is_empty() {
+                    ui.label("The source model has no animations");
+                    return;
+                }
+
+                settings.animation = settings.animation.min(animations.len() - 1);
+                let animation_name = |i: usize| {
+                    animations[i]
+                        .name
+                        .clone()
+                        .unwrap_or(format!("Animation {i}"))
+                };
+
+                egui::ComboBox::from_label("Animation")
+                    .selected_text(animation_name(settings.animation))
+                    .show_ui(ui, |ui| {
+                        for i in 0..animations.len() {
+                            ui.selectable_value(&mut settings.animation, i, animation_name(i));
+                        }
+                    });
+
+                Self::model_combo_box(ui, "Target model", &mut settings.target, scene);
+
+                ui.separator();
+                ui.label("Joint names that differ (source -> target)");
+
+                let mut to_delete = None;
+                for (i, (from, to)) in settings.mapping.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(from);
+                        ui.label("->");
+                        ui.text_edit_singleline(to);
+
+                        if ui.button("Delete").clicked() {
+                            to_delete = Some(i);
+                        }
+                    });
+                }
+
+                if let Some(i) = to_delete {
+                    settings.mapping.remove(i);
+                }
+
+                if ui.button("Add name").clicked() {
+                    settings.mapping.push((String::new(), String::new()));
+                }
+
+                ui.separator();
+
+                if settings.source == settings.target {
+                    ui.label("Pick two different models");
+                } else if ui.button("Retarget").clicked() {
+                    *report = Self::retarget(settings, scene);
+                }
+
+                for line in report.iter() {
+                    ui.label(line);
+                }
+            });
+
+        self.show_retarget_window = open;
+    }
+
+    /// Retargets the animation according to the settings, returns the report for the user
+    fn retarget(settings: &RetargetSettings, scene: &mut [Model]) -> Vec<String> {
+        let source = &scene[settings.source];
+        let target = &scene[settings.target];
+
+        let (source_joints, target_joints) = match (source.root.joints(), target.root.joints()) {
+            (Some(source), Some(target)) => (source, target),
+            _ => return vec!["Both models need a skeleton".to_string()],
+        };
+
+        let mapping: HashMap<String, String> = settings
+            .mapping
+            .iter()
+            .filter(|(from, _)| !from.is_empty())
+            .cloned()
+            .collect();
+
+        let retargeted = Retargeted::new(
+            &source.animations.animations[settings.animation],
+            &source_joints.joints,
+            &target_joints.joints,
+            &mapping,
+        );
+
+        let mut report = vec![format!(
+            "Added '{}' to '{}' ({} channels, {} dropped)",
+            retargeted.animation.name.as_deref().unwrap_or_default(),
+            target.name,
+            retargeted.animation.channels.len(),
+            retargeted.dropped.len()
+        )];
+        report.extend(retargeted.dropped);
+
+        scene[settings.target]
+            .animations
+            .animations
+            .push(retargeted.animation);
+
+        report
+    }
+
+    /// A combo box for picking a model of the scene
+    fn model_combo_box(ui: &mut Ui, label: &str, selected: &mut usize, scene: &[Model]) {
+        egui::ComboBox::from_label(label)
+            .selected_text(scene[*selected].name.clone())
+            .show_ui(ui, |ui| {
+                for (i, model) in scene.iter().enumerate() {
+                    ui.selectable_value(selected, i, model.name.clone());
+                }
+            });
+    }
+
+    /// Selects the node (picked in the viewport) and expands the hierarchy down to it,
+    /// None clears the selection
+    pub fn select_node(&mut self, node: Option<usize>) {
+        self.selected_node = node;
+        self.reveal_node = node;
+    }
+
+    /// Create the subwindow containing the model hierarchy
+    fn gui_model_hierarchy_window(&mut self, model: &mut Model, egui_ctx: &mut CtxRef) {
+        self.hovered_node = None;
+        self.hover_flash = ((egui_ctx.input().time * 8.).sin() * 0.5 + 0.5) as f32;
+
+        egui::Window::new("Model Hierarchy")
+            .scroll2([false, true])
+            .resizable(true)
+            .show(egui_ctx, |ui| {
+                CollapsingHeader::new("Statistics").show(ui, |ui| {
+                    ui.label(RichText::new("Model").strong());
+                    ui.label(model.stats.summary());
+
+                    for (stage, time) in &model.load_times {
+                        ui.label(format!(
+                            "{}: {:.1} ms",
+                            stage.name(),
+                            time.as_secs_f32() * 1000.
+                        ));
+                    }
+
+                    if let Some(node) = self.selected_node.and_then(|i| model.root.find(i)) {
+                        ui.separator();
+                        ui.label(RichText::new(format!("Node '{}'", node.name)).strong());
+                        ui.label(node.stats().summary());
+                    }
+                });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Search");
+                    ui.text_edit_singleline(&mut self.node_filter);
+                });
+
+                let filter = self.node_filter.to_lowercase();
+                if filter.is_empty() {
+                    self.gui_node(&mut model.root, None, ui);
+                } else {
+                    let mut visible = HashSet::new();
+                    let matches = Self::filter_nodes(&model.root, &filter, &mut visible);
+                    ui.label(format!("{matches} matching nodes"));
+
+                    self.gui_node(&mut model.root, Some(&visible), ui);
+                }
+            });
+
+        self.reveal_node = None;
+    }
+
+    /// Recursive - collects the nodes whose subtree contains a node matching the (lowercase)
+    /// filter, returns the number of the matching nodes
+    fn filter_nodes(node: &Node, filter: &str, visible: &mut HashSet<usize>) -> usize {
+        let mesh_name = node.mesh.as_ref().and_then(|mesh| mesh.name.as_deref());
+        let is_match = node.name.to_lowercase().contains(filter)
+            || mesh_name.is_some_and(|name| name.to_lowercase().contains(filter));
+
+        let mut matches = is_match as usize;
+        for child in &node.children {
+            matches += Self::filter_nodes(child, filter, visible);
+        }
+
+        if matches > 0 {
+            visible.insert(node.index);
+        }
+
+        matches
+    }
+
+    /// Recusrive - creates the node hierarchy inside the model hierarchy window.
+    ///
+    /// With a filter, only the 'visible' nodes are shown and all of them are expanded.
+    /// The filtered headers have their own ids, so the expansion is restored without the filter.
+    fn gui_node(&mut self, node: &mut Node, visible: Option<&HashSet<usize>>, ui: &mut Ui) {
+        if visible.is_some_and(|visible| !visible.contains(&node.index)) {
+            return;
+        }
+
+        let has_children = match visible {
+            Some(visible) => node
+                .children
+                .iter()
+                .any(|child| visible.contains(&child.index)),
+            None => !node.children.is_empty(),
+        };
+        let default_open = node.children.len() == 1;
+
+        let selected = self.selected_node == Some(node.index);
+        // Expand the ancestors of the picked node
+        let reveal = self
+            .reveal_node
+            .filter(|&index| index != node.index && node.find(index).is_some())
+            .map(|_| true);
+
+        ui.horizontal(|ui| {
+            if has_children {
+                let header = match visible {
+                    Some(_) => CollapsingHeader::new(&node.name)
+                        .id_source(("filtered", node.index))
+                        .open(Some(true)),
+                    None => CollapsingHeader::new(&node.name)
+                        .id_source(node.index)
+                        .default_open(default_open)
+                        .open(reveal),
+                };
+
+                let response = header.selectable(true).selected(selected).show(ui, |ui| {
+                    for child_node in &mut node.children {
+                        self.gui_node(child_node, visible, ui);
+                    }
+                });
+
+                if response.header_response.clicked() {
+                    self.selected_node = Some(node.index);
+                }
+
+                if response.header_response.hovered() {
+                    self.hovered_node = Some(node.index);
+                }
+            } else {
+                let response = ui.selectable_label(selected, &node.name);
+
+                if response.clicked() {
+                    self.selected_node = Some(node.index);
+                }
+
+                if response.hovered() {
+                    self.hovered_node = Some(node.index);
+                }
+            }
+
+            if let Some(mesh) = &mut node.mesh {
+                ui.separator();
+
+                let mesh_name = mesh.name.as_deref().unwrap_or("N/A");
+                CollapsingHeader::new(mesh_name)
+                    .id_source(("mesh", node.index))
+                    .show(ui, |ui| {
+                        Self::gui_primitives(mesh, ui);
+                    });
+
+                ui.end_row()
+            }
+        });
+    }
+
+    /// Visibility toggles and the material inspector of the primitives of a mesh.
+    ///
+    /// The overrides are only kept until the model is reloaded.
+    fn gui_primitives(mesh: &mut Mesh, ui: &mut Ui) {
+        for (i, prim) in mesh.primitives.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut prim.visible, format!("Primitive {i}"));
+
+                let mut rgba = prim.material.base_color_factor.to_array();
+                ui.color_edit_button_rgba_unmultiplied(&mut rgba);
+                prim.material.base_color_factor = Vec4::from(rgba);
+
+                if ui.button("Reset material").clicked() {
+                    prim.reset_material();
+                }
+            });
+
+            let material = &prim.material;
+            let textures: Vec<&str> = [
+                (material.base_color_texture, "base color"),
+                (material.normal_texture, "normal"),
+                (material.emissive_texture, "emissive"),
+                (material.occlusion_texture, "occlusion"),
+            ]
+            .iter()
+            .filter(|(texture, _)| texture.is_some())
+            .map(|(_, name)| *name)
+            .collect();
+
+            ui.label(format!(
+                "Alpha mode: {:?}, double-sided: {}, unlit: {}, textures: {}",
+                material.alpha_mode,
+                material.double_sided,
+                material.unlit,
+                textures.join(", ")
+            ));
+
+            let uv = &material.base_color_uv_transform;
+            if !uv.is_identity() {
+                ui.label(format!(
+                    "Base color UV transform: offset {:.3}, {:.3}, rotation {:.3}, scale {:.3}, {:.3}",
+                    uv.offset.x, uv.offset.y, uv.rotation, uv.scale.x, uv.scale.y
+                ));
+            }
+        }
+    }
+
+    fn gui_joints_window(&mut self, model: &mut Model, egui_ctx: &mut CtxRef) {
+        let mut presets = match self.pose_presets.remove(&model.name) {
+            Some(presets) => presets,
+            None => {
+                let presets = PosePresets::load(&model.name);
+                Self::add_saved_animations(model, &presets);
+                presets
+            }
+        };
+
+        let skin_transform = model
+            .root
+            .skin_transform(model.transform)
+            .unwrap_or(model.transform);
+
+        self.gui_joints_window_helper(
+            &mut model.root,
+            skin_transform,
+            &mut model.animations,
+            &mut model.pose_history,
+            &mut presets,
+            egui_ctx,
+        );
+
+        self.pose_presets.insert(model.name.clone(), presets);
+    }
+
+    /// Draws the names of the joints next to their projected positions.
+    ///
+    /// Labels that would overlap are moved down below each other.
+    fn gui_joint_labels(
+        &self,
+        model: &Model,
+        camera: &mut Camera,
+        joint_positions: &[Vec3],
+        viewport: (u32, u32),
+        egui_ctx: &mut CtxRef,
+    ) {
+        /// Approximate size of the label text in points
+        const CHAR_WIDTH: f32 = 7.;
+        const LINE_HEIGHT: f32 = 18.;
+
+        let joints = match model.root.joints() {
+            Some(joints) => &joints.joints,
+            None => return,
+        };
+
+        let in_selected_subtree = |mut index: usize| -> bool {
+            let selected = match self.selected_joint {
+                Some(selected) if self.label_selected_subtree => selected,
+                _ => return true,
+            };
+
+            loop {
+                if index == selected {
+                    return true;
+                }
+
+                match joints.get(index).and_then(|joint| joint.parent) {
+                    Some(parent) => index = parent,
+                    None => return false,
+                }
+            }
+        };
+
+        let pixels_per_point = egui_ctx.pixels_per_point();
+        let mut labels: Vec<(egui::Pos2, &str)> = joints
+            .iter()
+            .zip(joint_positions)
+            .enumerate()
+            .filter(|&(i, _)| in_selected_subtree(i))
+            .filter_map(|(_, (joint, &pos))| {
+                let pixel = camera.world_to_pixel(pos, viewport)?;
+                let point = egui::pos2(pixel.x / pixels_per_point, pixel.y / pixels_per_point);
+                Some((point, joint.name.as_str()))
+            })
+            .collect();
+
+        labels.sort_by(|(a, _), (b, _)| a.y.total_cmp(&b.y));
+
+        // (left, right, top) of the labels that were already placed
+        let mut placed: Vec<(f32, f32, f32)> = Vec::new();
+        for (i, (point, name)) in labels.into_iter().enumerate() {
+            let left = point.x + LINE_HEIGHT * 0.5;
+            let right = left + name.len() as f32 * CHAR_WIDTH;
+            let mut top = point.y - LINE_HEIGHT * 0.5;
+
+            while let Some(&(_, _, other_top)) = placed
+                .iter()
+                .find(|&&(l, r, t)| left < r && l < right && (top - t).abs() < LINE_HEIGHT)
+            {
+                top = other_top + LINE_HEIGHT;
+            }
+            placed.push((left, right, top));
+
+            egui::Area::new(format!("Joint label {i}"))
+                .fixed_pos(egui::pos2(left, top))
+                .order(egui::Order::Background)
+                .interactable(false)
+                .show(egui_ctx, |ui| {
+                    egui::Frame::none()
+                        .fill(egui::Color32::from_black_alpha(160))
+                        .show(ui, |ui| {
+                            ui.label(RichText::new(name).color(egui::Color32::WHITE));
+                        });
+                });
+        }
+    }
+
+    /// Adds the animations created in the GUI and the animation masks (loaded with the pose presets)
+    /// to the model
+    fn add_saved_animations(model: &mut Model, presets: &PosePresets) {
+        let joints = match model.root.joints_mut() {
+            Some(joints) => joints,
+            None => return,
+        };
+
+        for saved in &presets.animations {
+            let (anim, missing) = saved.to_animation(&joints.joints);
+            for name in missing {
+                warn!("Animation '{}': joint '{name}' doesn't exist", saved.name);
+            }
+
+            model.animations.animations.push(anim);
+        }
+
+        for saved in &presets.masks {
+            let missing = saved.apply(&mut model.animations.animations, &joints.joints);
+            for name in missing {
+                warn!(
+                    "Mask of '{}': joint '{name}' doesn't exist",
+                    saved.animation
+                );
+            }
+        }
+    }
+
+    /// Recursive - creates the joints window and it's nodes
+    fn gui_joints_window_helper(
+        &mut self,
+        node: &mut Node,
+        skin_transform: Mat4,
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        presets: &mut PosePresets,
+        egui_ctx: &mut CtxRef,
+    ) {
+        if let Some(joints) = &mut node.joints {
+            egui::Window::new("Joints").show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(history.can_undo(), egui::Button::new("Undo (Ctrl+Z)"))
+                        .clicked()
+                    {
+                        self.undo_pose = true;
+                    }
+
+                    if ui
+                        .add_enabled(history.can_redo(), egui::Button::new("Redo (Ctrl+Shift+Z)"))
+                        .clicked()
+                    {
+                        self.redo_pose = true;
+                    }
+                });
+
+                // A running animation would overwrite the restored pose immediately
+                if ui.button("Reset all joints").clicked() {
+                    joints.reset();
+                    animations.animation_control = AnimationControl::Static;
+
+                    for i in 0..joints.joints.len() {
+                        history.mark_edited(i);
+                    }
+                }
+
+                CollapsingHeader::new("Pose presets").show(ui, |ui| {
+                    self.gui_pose_presets(&mut joints.joints, animations, history, presets, ui);
+                });
+
+                CollapsingHeader::new("Keyframes").show(ui, |ui| {
+                    self.gui_keyframes(&joints.joints, animations, history, presets, ui);
+                });
+
+                CollapsingHeader::new("Two-bone IK").show(ui, |ui| {
+                    self.gui_ik(&mut joints.joints, skin_transform, animations, history, ui);
+                });
+
+                CollapsingHeader::new("Animation mask").show(ui, |ui| {
+                    self.gui_animation_mask(&joints.joints, animations, ui);
+                });
+
+                CollapsingHeader::new("Export joint positions").show(ui, |ui| {
+                    self.gui_joint_export(&joints.joints, animations, ui);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Search");
+                    ui.text_edit_singleline(&mut self.joint_filter);
+                });
+
+                let tree = JointTree::new(&joints.joints);
+
+                // Ancestors of a newly selected joint are expanded (only once, so they can be closed)
+                let mut expand = HashSet::new();
+                if self.selected_joint != self.expanded_joint {
+                    self.expanded_joint = self.selected_joint;
+
+                    let mut ancestor = self
+                        .selected_joint
+                        .and_then(|i| joints.joints.get(i))
+                        .and_then(|joint| joint.parent);
+
+                    while let Some(i) = ancestor {
+                        expand.insert(i);
+                        ancestor = joints.joints[i].parent;
+                    }
+                }
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let filter = self.joint_filter.to_lowercase();
+
+                    if filter.is_empty() {
+                        for &root in &tree.roots {
+                            self.gui_joint_subtree(
+                                root,
+                                &mut joints.joints,
+                                &tree,
+                                &expand,
+                                animations,
+                                history,
+                                ui,
+                            );
+                        }
+                    } else {
+                        // Search results are flattened
+                        for i in 0..joints.joints.len() {
+                            let joint = &mut joints.joints[i];
+                            if joint.name.to_lowercase().contains(&filter) {
+                                CollapsingHeader::new(&joint.name)
+                                    .id_source(("joint_search", i))
+                                    .show(ui, |ui| {
+                                        self.gui_joint_controls(i, joint, animations, history, ui);
+                                    });
+                            }
+                        }
+                    }
+                });
+
+                if std::mem::take(&mut self.mask_changed) {
+                    presets.masks = animations
+                        .animations
+                        .iter()
+                        .filter_map(|a| SavedMask::from_animation(a, &joints.joints))
+                        .collect();
+
+                    if let Err(e) = presets.save() {
+                        error!("Couldn't save the animation masks: {e}");
+                    }
+                }
+            });
+        } else {
+            // I assume there is only 1 skeleton in the models we are going to work with
+            for child_node in &mut node.children {
+                self.gui_joints_window_helper(
+                    child_node,
+                    skin_transform,
+                    animations,
+                    history,
+                    presets,
+                    egui_ctx,
+                );
+            }
+        }
+    }
+
+    /// Creates the list of the saved poses of the skeleton
+    fn gui_pose_presets(
+        &mut self,
+        joints: &mut [Joint],
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        presets: &mut PosePresets,
+        ui: &mut Ui,
+    ) {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.pose_name);
+
+            if ui.button("Save pose").clicked() {
+                let name = if self.pose_name.is_empty() {
+                    format!("Pose {}", presets.list.len() + 1)
+                } else {
+                    std::mem::take(&mut self.pose_name)
+                };
+
+                presets.list.push(PosePreset::capture(name, joints));
+                changed = true;
+            }
+        });
+
+        let mut to_delete = None;
+
+        for (i, preset) in presets.list.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("Apply").clicked() {
+                    for name in preset.apply(joints) {
+                        warn!("Pose '{}': joint '{name}' doesn't exist", preset.name);
+                    }
+
+                    // A running animation would overwrite the pose immediately
+                    animations.animation_control = AnimationControl::Static;
+
+                    for i in 0..joints.len() {
+                        history.mark_edited(i);
+                    }
+                }
+
+                if ui.button("Delete").clicked() {
+                    to_delete = Some(i);
+                }
+
+                ui.label(&preset.name);
+            });
+        }
+
+        if let Some(i) = to_delete {
+            presets.list.remove(i);
+            changed = true;
+        }
+
+        if changed {
+            if let Err(e) = presets.save() {
+                error!("Couldn't save the pose presets: {e}");
+            }
+        }
+    }
+
+    /// Creates the controls for recording the pose into an animation created in the GUI.
+    ///
+    /// Scrubbing the time shows the recorded animation, editing a joint stops it, so the edited pose
+    /// can be inserted as a keyframe.
+    fn gui_keyframes(
+        &mut self,
+        joints: &[Joint],
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        presets: &mut PosePresets,
+        ui: &mut Ui,
+    ) {
+        for (i, anim) in animations.animations.iter().enumerate() {
+            if anim.authored {
+                let name = anim.name.as_deref().unwrap_or("Unnamed");
+                ui.radio_value(&mut self.keyframe_animation, Some(i), name);
+            }
+        }
+
+        if ui.button("New animation").clicked() {
+            let count = animations.animations.iter().filter(|a| a.authored).count();
+            let name = format!("Animation {}", count + 1);
+
+            animations.animations.push(Animation::new_authored(name));
+            self.keyframe_animation = Some(animations.animations.len() - 1);
+        }
+
+        let index = match self.keyframe_animation {
+            Some(i) if animations.animations.get(i).is_some_and(|a| a.authored) => i,
+            _ => return,
+        };
+
+        let anim = &mut animations.animations[index];
+
+        ui.horizontal(|ui| {
+            ui.label("Time");
+            let response = ui.add(
+                egui::DragValue::new(&mut anim.current_time)
+                    .speed(0.01)
+                    .clamp_range(0.0..=3600.),
+            );
+
+            if response.changed() {
+                animations.animation_control = AnimationControl::Controllable {
+                    active_animation: index,
+                };
+            }
+        });
+
+        ui.checkbox(&mut self.key_all_joints, "All joints");
+
+        let count = if self.key_all_joints {
+            joints.len()
+        } else {
+            history.edited_count()
+        };
+
+        let button = egui::Button::new(format!("Insert keyframe ({count} joints)"));
+        if ui.add_enabled(count > 0, button).clicked() {
+            let edited = history.take_edited();
+            let time = anim.current_time;
+
+            for (i, joint) in joints.iter().enumerate() {
+                if self.key_all_joints || edited.contains(&i) {
+                    anim.insert_keyframe(joint.node_index, time, &joint.transform);
+                }
+            }
+
+            // Joints without keyframes keep their pose
+            animations.animation_control = AnimationControl::Controllable {
+                active_animation: index,
+            };
+
+            presets.animations = animations
+                .animations
+                .iter()
+                .filter(|a| a.authored)
+                .map(|a| SavedAnimation::from_animation(a, joints))
+                .collect();
+
+            if let Err(e) = presets.save() {
+                error!("Couldn't save the animations: {e}");
+            }
+        }
+
+        let anim = &animations.animations[index];
+        ui.label(format!(
+            "{} channels, {:.2} s long",
+            anim.channels.len(),
+            anim.end_time
+        ));
+    }
+
+    /// Creates the controls of the IK chain ending at the selected joint.
+    ///
+    /// The whole drag of the target / pole is recorded as a single edit of the root and the mid joint.
+    fn gui_ik(
+        &mut self,
+        joints: &mut [Joint],
+        skin_transform: Mat4,
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        ui: &mut Ui,
+    ) {
+        let selected = self
+            .selected_joint
+            .filter(|&i| TwoBoneIk::chain(joints, i).is_some());
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(selected.is_some(), egui::Button::new("Use selected joint"))
+                .clicked()
+            {
+                self.ik = selected.and_then(|i| TwoBoneIk::new(joints, i, skin_transform));
+            }
+
+            if self.ik.is_some() && ui.button("Stop").clicked() {
+                self.ik = None;
+            }
+        });
+
+        // The chain might belong to a different model
+        let chain = self
+            .ik
+            .as_ref()
+            .and_then(|ik| TwoBoneIk::chain(joints, ik.end));
+
+        let (ik, root, mid) = match (&mut self.ik, chain) {
+            (Some(ik), Some((root, mid))) => (ik, root, mid),
+            _ => {
+                self.ik = None;
+                ui.label("Select a joint with at least two ancestors (eg. a hand)");
+                return;
+            }
+        };
+
+        ui.label(format!(
+            "{} - {} - {}",
+            joints[root].name, joints[mid].name, joints[ik.end].name
+        ));
+
+        let before = (joints[root].transform, joints[mid].transform);
+        let mut responses = Vec::new();
+
+        let mut changed = Self::vec3_row(ui, "Target", &mut ik.target, 0.03, &mut responses);
+        changed |= Self::vec3_row(ui, "Pole", &mut ik.pole, 0.03, &mut responses);
+
+        if changed {
+            ik.solve(joints, skin_transform);
+            animations.animation_control = AnimationControl::Static;
+        }
+
+        if responses.iter().any(|r| r.drag_started()) {
+            self.ik_drag_start = Some(before);
+        }
+
+        let start = if responses.iter().any(|r| r.drag_released()) {
+            self.ik_drag_start.take()
+        } else if responses.iter().any(|r| r.changed() && !r.dragged()) {
+            // Typed in values
+            Some(before)
+        } else {
+            None
+        };
+
+        if let Some((root_before, mid_before)) = start {
+            history.record(root, root_before, joints[root].transform);
+            history.record(mid, mid_before, joints[mid].transform);
+        }
+    }
+
+    /// Creates the switch restricting the active animation to a subset of the joints
+    fn gui_animation_mask(&mut self, joints: &[Joint], animations: &mut Animations, ui: &mut Ui) {
+        let anim = match animations.active_animation() {
+            Some(i) => &mut animations.animations[i],
+            None => {
+                ui.label("Play or scrub an animation to mask it");
+                return;
+            }
+        };
+
+        let mut masked = anim.mask.is_some();
+        if ui
+            .checkbox(&mut masked, "Only animate the checked joints")
+            .changed()
+        {
+            anim.mask = masked.then(|| joints.iter().map(|j| j.node_index).collect());
+            self.mask_changed = true;
+        }
+
+        if let Some(mask) = &anim.mask {
+            let count = joints
+                .iter()
+                .filter(|j| mask.contains(&j.node_index))
+                .count();
+
+            ui.label(format!("{count} / {} joints animated", joints.len()));
+        }
+    }
+
+    /// Creates the settings of the joint position export, the joints are checked in the joint tree
+    fn gui_joint_export(&mut self, joints: &[Joint], animations: &Animations, ui: &mut Ui) {
+        if animations.animations.is_empty() {
+            ui.label("The model doesn't have any animations");
+            return;
+        }
+
+        self.joint_export_animation = self
+            .joint_export_animation
+            .min(animations.animations.len() - 1);
+
+        let animation_name = |i: usize| match &animations.animations[i].name {
+            Some(name) => name.clone(),
+            None => format!("Animation {i}"),
+        };
+
+        egui::ComboBox::from_label("Animation")
+            .selected_text(animation_name(self.joint_export_animation))
+            .show_ui(ui, |ui| {
+                for i in 0..animations.animations.len() {
+                    ui.selectable_value(&mut self.joint_export_animation, i, animation_name(i));
+                }
+            });
+
+        ui.add(
+            egui::DragValue::new(&mut self.joint_export_rate)
+                .clamp_range(1.0..=1000.0)
+                .suffix(" samples / s"),
+        );
+
+        ui.horizontal(|ui| {
+            for format in JointExportFormat::ALL {
+                ui.radio_value(&mut self.joint_export_format, format, format.name());
+            }
+        });
+
+        let count = joints
+            .iter()
+            .filter(|joint| self.joint_export_joints.contains(&joint.name))
+            .count();
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{count} / {} joints checked", joints.len()));
+
+            if ui.button("Check all").clicked() {
+                self.joint_export_joints
+                    .extend(joints.iter().map(|joint| joint.name.clone()));
+            }
+
+            if ui.button("Uncheck all").clicked() {
+                self.joint_export_joints.clear();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut self.joint_export_file);
+            ui.label(format!(".{}", self.joint_export_format.extension()));
+        });
+
+        if ui
+            .add_enabled(count > 0, egui::Button::new("Export"))
+            .clicked()
+        {
+            self.export_joint_positions = true;
+        }
+    }
+
+    /// Creates the selection of the animation time source.
+    ///
+    /// The step is only recomputed when the rate is changed, so it stays the same between runs.
+    fn gui_time_source(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Animation time");
+
+            let mut fixed = matches!(self.time_source, TimeSource::FixedStep(_));
+            ui.radio_value(&mut fixed, false, "Real time");
+            ui.radio_value(&mut fixed, true, "Fixed step");
+
+            self.time_source = match (fixed, self.time_source) {
+                (false, _) => TimeSource::WallClock,
+                (true, TimeSource::WallClock) => TimeSource::FixedStep(1. / 60.),
+                (true, source) => source,
+            };
+        });
+
+        if let TimeSource::FixedStep(step) = &mut self.time_source {
+            let mut rate = (1. / *step).round();
+            if ui
+                .add(Slider::new(&mut rate, 1.0..=240.0).text("Steps per second"))
+                .changed()
+            {
+                *step = 1. / rate;
+            }
+        }
+    }
+
+    /// Creates the mask checkbox of the joint (if the active animation is masked)
+    fn gui_joint_mask(
+        &mut self,
+        index: usize,
+        joints: &[Joint],
+        tree: &JointTree,
+        animations: &mut Animations,
+        ui: &mut Ui,
+    ) {
+        let mask = match animations.active_animation() {
+            Some(i) => match &mut animations.animations[i].mask {
+                Some(mask) => mask,
+                None => return,
+            },
+            None => return,
+        };
+
+        let node = joints[index].node_index;
+
+        ui.horizontal(|ui| {
+            let mut animated = mask.contains(&node);
+            if ui.checkbox(&mut animated, "Animated").changed() {
+                if animated {
+                    mask.insert(node);
+                } else {
+                    mask.remove(&node);
+                }
+
+                self.mask_changed = true;
+            }
+
+            if ui.button("Check subtree").clicked() {
+                mask.extend(tree.subtree(index).map(|i| joints[i].node_index));
+                self.mask_changed = true;
+            }
+
+            if ui.button("Uncheck subtree").clicked() {
+                for i in tree.subtree(index) {
+                    mask.remove(&joints[i].node_index);
+                }
+
+                self.mask_changed = true;
+            }
+        });
+    }
+
+    /// Recursive - creates the header of the joint with the headers of its children nested inside
+    #[allow(clippy::too_many_arguments)]
+    fn gui_joint_subtree(
+        &mut self,
+        index: usize,
+        joints: &mut [Joint],
+        tree: &JointTree,
+        expand: &HashSet<usize>,
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        ui: &mut Ui,
+    ) {
+        let children = &tree.children[index];
+
+        let title = if children.is_empty() {
+            joints[index].name.clone()
+        } else {
+            format!("{} ({})", joints[index].name, tree.subtree_sizes[index])
+        };
+
+        let mut header = CollapsingHeader::new(title)
+            .id_source(("joint", index))
+            .selectable(true)
+            .selected(self.selected_joint == Some(index));
+
+        if expand.contains(&index) {
+            header = header.open(Some(true));
+        }
+
+        let response = header.show(ui, |ui| {
+            self.gui_joint_mask(index, joints, tree, animations, ui);
+            self.gui_joint_controls(index, &mut joints[index], animations, history, ui);
+
+            for &child in children {
+                self.gui_joint_subtree(child, joints, tree, expand, animations, history, ui);
+            }
+        });
+
+        if response.header_response.clicked() {
+            self.selected_joint = Some(index);
+        }
+    }
+
+    /// Creates the buttons and the transform editor of a joint
+    fn gui_joint_controls(
+        &mut self,
+        index: usize,
+        joint: &mut Joint,
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        ui: &mut Ui,
+    ) {
+        ui.horizontal(|ui| {
+            if ui.button("Focus").clicked() {
+                self.selected_joint = Some(index);
+                self.focus_selected = true;
+            }
+
+            if ui.button("Reset").clicked() {
+                joint.reset();
+                animations.animation_control = AnimationControl::Static;
+                history.mark_edited(index);
+            }
+
+            let mut exported = self.joint_export_joints.contains(&joint.name);
+            if ui.checkbox(&mut exported, "Export position").changed() {
+                if exported {
+                    self.joint_export_joints.insert(joint.name.clone());
+                } else {
+                    self.joint_export_joints.remove(&joint.name);
+                }
+            }
+        });
+
+        Self::show_joint_transforms(index, joint, animations, history, ui);
+    }
+
+    /// Creates the gui for transforms of a specific joint.
+    ///
+    /// Finished edits are recorded into the history, a whole drag is a single edit.
+    fn show_joint_transforms(
+        index: usize,
+        joint: &mut Joint,
+        animations: &mut Animations,
+        history: &mut PoseHistory,
+        ui: &mut Ui,
+    ) {
+        let before = joint.transform;
+        let transform = &mut joint.transform;
+
+        // Euler angles are only converted back to the quaternion when they are edited,
+        // so the rotation doesn't drift
+        let (x, y, z) = transform.rotation.to_euler(EulerRot::XYZ);
+        let mut euler = Vec3::new(x.to_degrees(), y.to_degrees(), z.to_degrees());
+
+        let mut responses = Vec::new();
+        let mut rotation_changed = false;
+
+        let response = ui.group(|ui| {
+            Self::vec3_row(
+                ui,
+                "Translation",
+                &mut transform.translation,
+                0.03,
+                &mut responses,
+            );
+            rotation_changed =
+                Self::vec3_row(ui, "Rotation (XYZ)", &mut euler, 1.0, &mut responses);
+            Self::vec3_row(ui, "Scale", &mut transform.scale, 0.01, &mut responses);
+        });
+
+        if response.response.hovered() {
+            animations.animation_control = AnimationControl::Static;
+        }
+
+        if rotation_changed {
+            joint.transform.rotation = Quat::from_euler(
+                EulerRot::XYZ,
+                euler.x.to_radians(),
+                euler.y.to_radians(),
+                euler.z.to_radians(),
+            );
+        }
+
+        if responses.iter().any(|r| r.drag_started()) {
+            history.begin_drag(index, before);
+        }
+
+        if responses.iter().any(|r| r.drag_released()) {
+            history.end_drag(index, joint.transform);
+        } else if responses.iter().any(|r| r.changed() && !r.dragged()) {
+            // Typed in values
+            history.record(index, before, joint.transform);
+        }
+    }
+
+    /// A labeled row of x, y, z DragValues, returns true if any of the values changed
+    fn vec3_row(
+        ui: &mut Ui,
+        label: &str,
+        v: &mut Vec3,
+        speed: f32,
+        responses: &mut Vec<Response>,
+    ) -> bool {
+        ui.label(label);
+        ui.horizontal(|ui| {
+            let mut values = v.to_array();
+            let mut changed = false;
+
+            for (name, value) in ["x", "y", "z"].into_iter().zip(&mut values) {
+                ui.label(name);
+                let response = ui.add(egui::DragValue::new(value).speed(speed));
+                changed |= response.changed();
+                responses.push(response);
+            }
+
+            *v = Vec3::from(values);
+            changed
+        })
+        .inner
+    }
+
+    /// Creates a gui for the side panel
+    fn gui_side_panel(
+        &mut self,
+        scene: &mut [Model],
+        camera: &mut Camera,
+        stats: &FrameStats,
+        gpu_times: &[PassTime],
+        egui_ctx: &mut CtxRef,
+    ) {
+        egui::SidePanel::right("Side Panel").show(egui_ctx, |ui| {
+            ui.group(|ui| {
+                ui.add(egui::Label::new(RichText::new("Scenes").heading().strong()));
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    // The export refers to the model by its index
+                    let can_remove = self.export_progress.is_none();
+
+                    for (i, model) in scene.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button(&model.name)
+                                .on_hover_text(model.stats.summary())
+                                .clicked()
+                            {
+                                self.selected_model = i;
+                                self.selected_joint = None;
+                                self.selected_node = None;
+                                camera.set_target(model.bounds().center());
+                            }
+
+                            if ui.button("Reload").clicked() {
+                                self.reload_model = Some(i);
+                            }
+
+                            if ui
+                                .add_enabled(can_remove, egui::Button::new("Remove"))
+                                .clicked()
+                            {
+                                self.remove_model = Some(i);
+                            }
+                        });
+
+                        match self.reload_status.get(&model.path) {
+                            Some(ReloadStatus::Reloaded(time)) => {
+                                let secs = time.elapsed().as_secs();
+                                ui.label(format!("Reloaded {secs} seconds ago"));
+                            }
+                            Some(ReloadStatus::Failed(e)) => {
+                                ui.colored_label(egui::Color32::RED, format!("Reload failed: {e}"));
+                            }
+                            None => {}
+                        }
+
+                        if !model.warnings.is_empty() {
+                            egui::CollapsingHeader::new(
+                                RichText::new(format!("{} load warnings", model.warnings.len()))
+                                    .color(egui::Color32::YELLOW),
+                            )
+                            .id_source(&model.path)
+                            .show(ui, |ui| {
+                                for warning in &model.warnings {
+                                    ui.label(warning);
+                                }
+                            });
+                        }
+                    }
+                });
+
+                for pending in &self.loading {
+                    ui.label(format!("Loading '{}'", pending.path));
+
+                    ui.horizontal(|ui| {
+                        let progress = egui::ProgressBar::new(pending.stage.progress())
+                            .text(pending.stage.name())
+                            .desired_width(200.);
+                        ui.add(progress);
+
+                        if ui.button("Cancel").clicked() {
+                            self.cancel_load = Some(pending.id);
+                        }
+                    });
+                }
+
+                if !self.loading.is_empty() {
+                    // Keep redrawing, so the models show up when they are loaded
+                    ui.ctx().request_repaint();
+                }
+
+                let key = self.key_bindings.key_name(Action::FocusSelected);
+                if ui.button(format!("Focus selected ({key})")).clicked() {
+                    self.focus_selected = true;
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Scene file");
+                    ui.text_edit_singleline(&mut self.scene_file);
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save scene").clicked() {
+                        self.save_scene = true;
+                    }
+
+                    // The export refers to the model by its index
+                    if ui
+                        .add_enabled(
+                            self.export_progress.is_none(),
+                            egui::Button::new("Open scene"),
+                        )
+                        .clicked()
+                    {
+                        self.open_scene = true;
+                    }
+                });
+            });
+
+            ui.group(|ui| {
+                ui.add(egui::Label::new(
+                    RichText::new("Settings").heading().strong(),
+                ));
+
+                ui.separator();
+
+                let key = self.key_bindings.key_name(Action::ToggleSkeleton);
+                if ui.button(format!("Debug joints ({key})")).clicked() {
+                    self.draw_skeleton = !self.draw_skeleton;
+                }
+
+                if self.draw_skeleton {
+                    ui.add(
+                        Slider::new(&mut self.skeleton_scale, 0.1..=10.0)
+                            .logarithmic(true)
+                            .text("Skeleton overlay scale"),
+                    );
+                    ui.checkbox(&mut self.show_joint_labels, "Joint names");
+                    if self.show_joint_labels {
+                        ui.checkbox(
+                            &mut self.label_selected_subtree,
+                            "Only the selected joint's subtree",
+                        );
+                    }
+                }
+
+                ui.checkbox(&mut self.show_skeleton_inset, "Skeleton view");
+                if self.show_skeleton_inset {
+                    ui.add(
+                        Slider::new(&mut self.skeleton_inset_size, 0.1..=0.5)
+                            .text("Skeleton view size"),
+                    );
+                }
+                if ui.button("Draw mesh").clicked() {
+                    self.mesh_visible = !self.mesh_visible;
+                }
+
+                ui.checkbox(&mut self.bind_pose, "Show the bind pose");
+
+                self.show_frame_rate_settings(ui);
+                ui.checkbox(&mut self.status_bar.visible, "Status bar");
+
+                ui.checkbox(&mut self.srgb, "sRGB rendering");
+
+                CollapsingHeader::new("Outline").show(ui, |ui| {
+                    ui.checkbox(&mut self.outline_selected, "Outline the selected model");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Color");
+                        Self::color_edit(&mut self.outline_color, ui);
+                    });
+
+                    ui.add(
+                        Slider::new(&mut self.outline_width, 1.0..=10.0)
+                            .text("Width (px)")
+                            .smart_aim(false),
+                    );
+
+                    ui.label("Hovering a node in the hierarchy outlines its meshes");
+                });
+
+                CollapsingHeader::new("Wireframe").show(ui, |ui| {
+                    ui.checkbox(&mut self.wireframe, "Draw the wireframe");
+                    ui.checkbox(&mut self.wireframe_overlay, "Over the shaded mesh");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Color");
+                        Self::color_edit(&mut self.wireframe_color, ui);
+                    });
+
+                    ui.add(
+                        Slider::new(&mut self.wireframe_width, 1.0..=5.0)
+                            .text("Width (px, limited by the driver)"),
+                    );
+                });
+
+                CollapsingHeader::new("Levels of detail").show(ui, |ui| {
+                    let lod_nodes = scene
+                        .get(self.selected_model)
+                        .map(|model| model.root.lod_nodes())
+                        .unwrap_or_default();
+
+                    if lod_nodes.is_empty() {
+                        ui.label("The model doesn't have levels of detail");
+                    }
+
+                    let mut max_level = 0;
+                    for (name, lods) in &lod_nodes {
+                        ui.label(format!("{name}: LOD {} of {}", lods.active, lods.levels()));
+                        max_level = max_level.max(lods.levels() - 1);
+                    }
+
+                    ui.checkbox(&mut self.force_lod, "Force the level of detail");
+                    if self.force_lod {
+                        ui.add(Slider::new(&mut self.forced_lod, 0..=max_level).text("Level"));
+                    }
+                });
+
+                CollapsingHeader::new("Compare models").show(ui, |ui| {
+                    self.show_compare_settings(scene, ui);
+                });
+
+                CollapsingHeader::new("Texture filtering").show(ui, |ui| {
+                    for filtering in TextureFiltering::ALL {
+                        ui.radio_value(&mut self.texture_filtering, filtering, filtering.name());
+                    }
+
+                    if self.texture_filtering.uses_anisotropy() {
+                        ui.add(
+                            Slider::new(&mut self.anisotropy, 1.0..=16.0)
+                                .text("Anisotropy (limited by the driver)"),
+                        );
+                    }
+                });
+
+                ui.checkbox(
+                    &mut self.dual_quaternion_skinning,
+                    "Dual quaternion skinning",
+                );
+                ui.checkbox(&mut self.cpu_skinning, "CPU skinning");
+                ui.checkbox(&mut self.compute_skinning, "Compute shader skinning");
+                ui.checkbox(&mut self.frustum_culling, "Frustum culling");
+                ui.checkbox(&mut self.face_culling, "Back-face culling");
+                ui.horizontal(|ui| {
+                    ui.label("Front faces");
+                    for winding in Winding::ALL {
+                        ui.radio_value(&mut self.front_face, winding, winding.name());
+                    }
+                });
+                ui.checkbox(&mut self.depth_test, "Depth test");
+                ui.checkbox(&mut self.sort_draws, "Sort draw calls");
+                ui.checkbox(&mut self.animate_unselected, "Animate unselected models");
+                self.gui_time_source(ui);
+                ui.label(format!(
+                    "Primitives drawn: {}, culled: {}, instances drawn: {}, triangles drawn: {}",
+                    stats.drawn_primitives,
+                    stats.culled_primitives,
+                    stats.drawn_instances,
+                    stats.drawn_triangles
+                ));
+
+                egui::CollapsingHeader::new("Profiler").show(ui, |ui| {
+                    self.show_profiler(gpu_times, ui);
+                });
+
+                ui.add(
+                    Slider::new(&mut camera.move_speed, 0.0..=40.0)
+                        .text("Camera move speed")
+                        .smart_aim(false),
+                );
+
+                ui.add(
+                    Slider::new(&mut camera.acceleration, 1.0..=50.0)
+                        .text("Camera acceleration")
+                        .smart_aim(false),
+                );
 
-use egui::{CollapsingHeader, CtxRef, RichText, Slider, Ui};
-use glam::{Quat, Vec3};
+                ui.add(
+                    Slider::new(&mut camera.damping, 1.0..=50.0)
+                        .text("Camera damping")
+                        .smart_aim(false),
+                );
 
-use crate::{
-    camera::Camera,
-    model::{AnimationControl, Animations, Joint, Model, Node},
-};
+                ui.add(
+                    Slider::new(&mut camera.look_smoothing, 0.0..=0.3)
+                        .text("Mouse look smoothing")
+                        .smart_aim(false),
+                );
 
-/// Contains the current state of the GUI.
-/// Implements methods for displaying the widgets.
-pub struct Gui {
-    /// Default 0 (assuming that there is at least 1 model in the scene)
-    pub selected_model: usize,
-    /// If joints should be visible inside of the mesh
-    pub draw_skeleton: bool,
-    /// If the mesh should be visible
-    pub mesh_visible: bool,
-}
+                if ui.button("Reset Camera").clicked() {
+                    camera.set_pos(Vec3::new(0.0, 0.0, 3.0));
+                }
 
-impl Gui {
-    pub fn new() -> Self {
-        Self {
-            selected_model: 0,
-            draw_skeleton: false,
-            mesh_visible: true,
-        }
-    }
+                Self::show_projection_settings(camera, ui);
 
-    /// Creates the GUI.
-    ///
-    /// Immediate mode GUI - is called every frame.
-    pub fn create_gui(&mut self, scene: &mut [Model], camera: &mut Camera, egui_ctx: &mut CtxRef) {
-        self.gui_model_hierarchy_window(scene, egui_ctx);
-        self.gui_joints_window(&mut scene[self.selected_model], egui_ctx);
-        self.gui_side_panel(scene, camera, egui_ctx);
-    }
+                let model_center = self.selected_bounds(scene).center();
+                let mut mode = camera.mode();
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut mode, CameraMode::Free, "Free camera");
+                    let key = self.key_bindings.key_name(Action::ToggleOrbit);
+                    ui.radio_value(
+                        &mut mode,
+                        CameraMode::Orbit,
+                        format!("Orbit camera ({key})"),
+                    );
+                });
+                camera.set_mode(mode, model_center);
 
-    /// Create the subwindow containing the model hierarchy
-    fn gui_model_hierarchy_window(&mut self, scene: &[Model], egui_ctx: &mut CtxRef) {
-        let model = &scene[self.selected_model];
+                if mode == CameraMode::Orbit && ui.button("Focus selected model").clicked() {
+                    camera.set_target(model_center);
+                }
 
-        egui::Window::new("Model Hierarchy")
-            .scroll2([false, true])
-            .resizable(true)
-            .show(egui_ctx, |ui| {
-                self.gui_node(&model.root, ui);
+                ui.horizontal(|ui| {
+                    if ui.button("Screenshot").clicked() {
+                        self.take_screenshot = true;
+                    }
+
+                    ui.checkbox(&mut self.screenshot_without_ui, "Without UI");
+                });
+
+                if ui.button("Export animation").clicked() {
+                    self.show_export_window = true;
+                }
+
+                if ui.button("Retarget animation").clicked() {
+                    self.show_retarget_window = true;
+                }
+
+                if ui.button("Log").clicked() {
+                    self.show_log_window = true;
+                }
+
+                if ui.button("Controls").clicked() {
+                    self.show_controls_window = true;
+                }
+
+                let key = self.key_bindings.key_name(Action::ReloadShaders);
+                if ui.button(format!("Reload shaders ({key})")).clicked() {
+                    self.reload_shaders = true;
+                }
+
+                let key = self.key_bindings.key_name(Action::Fullscreen);
+                if ui.button(format!("Fullscreen ({key})")).clicked() {
+                    self.toggle_fullscreen = true;
+                }
+
+                ui.add(
+                    Slider::new(&mut self.ui_scale, 0.5..=3.0)
+                        .step_by(0.05)
+                        .text("UI scale"),
+                );
+
+                if ui.button("Reset to defaults").clicked() {
+                    self.reset_settings = true;
+                }
+
+                egui::global_dark_light_mode_switch(ui);
             });
-    }
 
-    /// Recusrive - creates the node hierarchy inside the model hierarchy window
-    fn gui_node(&mut self, node: &Node, ui: &mut Ui) {
-        let default_open = node.children.len() == 1;
+            ui.group(|ui| {
+                ui.add(egui::Label::new(
+                    RichText::new("Camera bookmarks").heading().strong(),
+                ));
 
-        ui.horizontal(|ui| {
-            if !&node.children.is_empty() {
-                CollapsingHeader::new(&node.name)
-                    .id_source(node.index)
-                    .default_open(default_open)
-                    .selectable(true)
-                    .show(ui, |ui| {
-                        for child_node in &node.children {
-                            self.gui_node(child_node, ui);
-                        }
-                    });
-            } else {
-                ui.label(&node.name);
-            }
+                ui.separator();
+
+                self.show_bookmarks(camera, ui);
+            });
+
+            ui.group(|ui| {
+                ui.add(egui::Label::new(
+                    RichText::new("Lighting").heading().strong(),
+                ));
 
-            if let Some(mesh) = &node.mesh {
                 ui.separator();
 
-                let mesh_name = mesh.name.as_deref().unwrap_or("N/A");
-                ui.add(egui::Label::new(mesh_name));
+                self.show_lighting_settings(ui);
+            });
 
-                ui.end_row()
-            }
+            ui.group(|ui| {
+                ui.add(egui::Label::new(
+                    RichText::new("Animations").heading().strong(),
+                ));
+
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.show_animation_view(scene, ui);
+                });
+            });
         });
     }
 
-    fn gui_joints_window(&mut self, model: &mut Model, egui_ctx: &mut CtxRef) {
-        self.gui_joints_window_helper(&mut model.root, &mut model.animations, egui_ctx);
-    }
+    /// Creates the comparison mode controls, the compared model plays the animation
+    /// of the selected model (its timeline is shared by both)
+    fn show_compare_settings(&mut self, scene: &[Model], ui: &mut Ui) {
+        let selected = match scene.get(self.selected_model) {
+            Some(model) => model,
+            None => return,
+        };
 
-    /// Recursive - creates the joints window and it's nodes
-    fn gui_joints_window_helper(
-        &mut self,
-        node: &mut Node,
-        animations: &mut Animations,
-        egui_ctx: &mut CtxRef,
-    ) {
-        if let Some(joints) = &mut node.joints {
-            egui::Window::new("Joints").show(egui_ctx, |ui| {
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    for joint in joints.joints.iter_mut() {
-                        let joint_name = &joint.name;
+        let name = |i: Option<usize>| match i.and_then(|i| scene.get(i)) {
+            Some(model) => model.name.clone(),
+            None => "None".to_string(),
+        };
 
-                        // FIXME: for some reason there is an ID collision when rendering the droid model... maybe a bug in egui ?
-                        CollapsingHeader::new(joint_name).show(ui, |ui| {
-                            Self::show_joint_transforms(joint, animations, ui);
-                        });
-                    }
-                });
+        egui::ComboBox::from_label("Compare with")
+            .selected_text(name(self.compare_model))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.compare_model, None, "None");
+                for i in (0..scene.len()).filter(|&i| i != self.selected_model) {
+                    ui.selectable_value(&mut self.compare_model, Some(i), name(Some(i)));
+                }
             });
-        } else {
-            // I assume there is only 1 skeleton in the models we are going to work with
-            for child_node in &mut node.children {
-                self.gui_joints_window_helper(child_node, animations, egui_ctx);
+
+        let compared = match self.compare_model.and_then(|i| scene.get(i)) {
+            Some(model) => model,
+            None => return,
+        };
+
+        let animation_name =
+            |animations: &Animations, i: usize| match &animations.animations[i].name {
+                Some(name) => name.clone(),
+                None => format!("Animation {i}"),
+            };
+
+        match (
+            selected.animations.active_animation(),
+            compared.animations.matching_animation(&selected.animations),
+        ) {
+            (Some(i), Some(j)) => ui.label(format!(
+                "Playing '{}' with '{}'",
+                animation_name(&selected.animations, i),
+                animation_name(&compared.animations, j)
+            )),
+            (Some(_), None) => ui.label("The compared model doesn't have a matching animation"),
+            (None, _) => ui.label("Play an animation of the selected model"),
+        };
+
+        ui.checkbox(&mut self.highlight_divergence, "Highlight diverging joints");
+        if self.highlight_divergence {
+            ui.add(
+                Slider::new(&mut self.divergence_threshold, 0.01..=1.0)
+                    .logarithmic(true)
+                    .text("Threshold (bone lengths)"),
+            );
+            ui.small("Joints are compared by name, the skeleton has to be drawn");
+        }
+    }
+
+    /// Shows the GPU times of the render passes (nested passes are indented)
+    /// and the CPU frame time
+    fn show_profiler(&self, gpu_times: &[PassTime], ui: &mut Ui) {
+        egui::Grid::new("profiler").striped(true).show(ui, |ui| {
+            for pass in gpu_times {
+                ui.label(format!("{}{}", "    ".repeat(pass.depth), pass.name));
+                ui.label(format!("{:.3} ms", pass.ms));
+                ui.end_row();
             }
+
+            ui.label("CPU frame time");
+            ui.label(format!("{:.3} ms", self.cpu_frame_time * 1000.));
+            ui.end_row();
+        });
+
+        if gpu_times.is_empty() {
+            ui.label("Waiting for the GPU timings...");
         }
     }
 
-    /// Creates the gui for transforms of a specific joint
-    fn show_joint_transforms(joint: &mut Joint, animations: &mut Animations, ui: &mut Ui) {
-        let trans = &mut joint.transform.translation;
-        let (axis, angle) = joint.transform.rotation.to_axis_angle();
-        let mut angle = angle.to_degrees();
+    /// Creates the VSync / FPS limit controls and shows the frame time
+    fn show_frame_rate_settings(&mut self, ui: &mut Ui) {
+        let fps = if self.frame_time > 0. {
+            1. / self.frame_time
+        } else {
+            0.
+        };
+        ui.label(format!(
+            "Frame time: {:.2} ms ({fps:.0} FPS)",
+            self.frame_time * 1000.
+        ));
 
-        let response = ui.group(|ui| {
-            ui.label("Translation");
-            ui.horizontal(|ui| {
-                ui.label("x");
-                ui.add(egui::DragValue::new(&mut trans.x).speed(0.03));
-                ui.label("y");
-                ui.add(egui::DragValue::new(&mut trans.y).speed(0.03));
-                ui.label("z");
-                ui.add(egui::DragValue::new(&mut trans.z).speed(0.03));
-            });
+        ui.horizontal(|ui| {
+            ui.radio_value(
+                &mut self.swap_interval,
+                SwapInterval::Immediate,
+                "Immediate",
+            );
+            ui.radio_value(&mut self.swap_interval, SwapInterval::VSync, "VSync");
+            ui.radio_value(
+                &mut self.swap_interval,
+                SwapInterval::LateSwapTearing,
+                "Adaptive",
+            );
+        });
 
-            ui.label("Rotation");
-            ui.horizontal(|ui| {
-                ui.label("angle");
-                ui.add(
-                    egui::DragValue::new(&mut angle)
-                        .speed(1.0)
-                        .clamp_range((0.1)..=(359.9)),
-                );
-            });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.limit_fps, "Limit FPS");
+            ui.add_enabled(
+                self.limit_fps,
+                egui::DragValue::new(&mut self.max_fps).clamp_range(10..=1000),
+            );
         });
+    }
 
-        if response.response.hovered() {
-            animations.animation_control = AnimationControl::Static;
+    /// Creates the projection controls inside the settings group
+    fn show_projection_settings(camera: &mut Camera, ui: &mut Ui) {
+        let projection = &mut camera.projection;
+
+        ui.checkbox(&mut projection.orthographic, "Orthographic projection");
+
+        if projection.orthographic {
+            ui.add(
+                Slider::new(&mut projection.ortho_size, 0.1..=100.0)
+                    .logarithmic(true)
+                    .text("Orthographic size"),
+            );
+        } else {
+            ui.add(Slider::new(&mut projection.fov, 10.0..=120.0).text("Field of view"));
         }
 
-        joint.transform.rotation = Quat::from_axis_angle(axis.normalize(), angle.to_radians());
+        ui.horizontal(|ui| {
+            ui.label("Near");
+            ui.add(
+                egui::DragValue::new(&mut projection.near)
+                    .speed(0.01)
+                    .clamp_range(0.001..=10.),
+            );
+            ui.label("Far");
+            ui.add(
+                egui::DragValue::new(&mut projection.far)
+                    .speed(10.)
+                    .clamp_range(1.0..=100000.),
+            );
+        });
     }
 
-    /// Creates a gui for the side panel
-    fn gui_side_panel(&mut self, scene: &mut [Model], camera: &mut Camera, egui_ctx: &mut CtxRef) {
-        egui::SidePanel::right("Side Panel").show(egui_ctx, |ui| {
-            ui.group(|ui| {
-                ui.add(egui::Label::new(RichText::new("Scenes").heading().strong()));
-                ui.separator();
+    /// Creates the list of camera bookmarks inside the side panel
+    fn show_bookmarks(&mut self, camera: &mut Camera, ui: &mut Ui) {
+        let mut changed = false;
 
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    for (i, model) in scene.iter().enumerate() {
-                        if ui.button(&model.name).clicked() {
-                            self.selected_model = i;
-                        }
-                    }
-                });
+        if ui.button("Save view").clicked() {
+            self.bookmarks.list.push(Bookmark {
+                name: format!("View {}", self.bookmarks.list.len() + 1),
+                pose: camera.pose(),
             });
+            changed = true;
+        }
 
-            ui.group(|ui| {
-                ui.add(egui::Label::new(
-                    RichText::new("Settings").heading().strong(),
-                ));
+        let mut to_delete = None;
 
-                ui.separator();
+        for (i, bookmark) in self.bookmarks.list.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                // The first 9 bookmarks can be recalled with the number keys
+                let text = if i < 9 {
+                    format!("Go ({})", i + 1)
+                } else {
+                    "Go".to_string()
+                };
 
-                if ui.button("Debug joints").clicked() {
-                    self.draw_skeleton = !self.draw_skeleton;
+                if ui.button(text).clicked() {
+                    camera.set_pose(&bookmark.pose);
                 }
 
-                if ui.button("Draw mesh").clicked() {
-                    self.mesh_visible = !self.mesh_visible;
+                if ui.text_edit_singleline(&mut bookmark.name).lost_focus() {
+                    changed = true;
                 }
 
-                ui.add(
-                    Slider::new(&mut camera.move_speed, 0.0..=0.2)
-                        .text("Camera move speed")
-                        .smart_aim(false),
-                );
+                if ui.button("Delete").clicked() {
+                    to_delete = Some(i);
+                }
+            });
+        }
 
-                if ui.button("Reset Camera").clicked() {
-                    camera.set_pos(Vec3::new(0.0, 0.0, 3.0));
+        if let Some(i) = to_delete {
+            self.bookmarks.list.remove(i);
+            changed = true;
+        }
+
+        if changed {
+            if let Err(e) = self.bookmarks.save() {
+                error!("Couldn't save the camera bookmarks: {e}");
+            }
+        }
+    }
+
+    /// Creates the lighting controls inside the side panel
+    fn show_lighting_settings(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Ambient");
+            Self::color_edit(&mut self.ambient_light, ui);
+        });
+
+        CollapsingHeader::new("Background").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.background, Background::Color, "Color");
+                ui.radio_value(&mut self.background, Background::Environment, "Environment");
+            });
+
+            match self.background {
+                Background::Color => {
+                    Self::color_edit(&mut self.background_color, ui);
                 }
+                Background::Environment => {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.environment_file);
+                        if ui.button("Load").clicked() {
+                            self.load_environment = true;
+                        }
+                    });
 
-                egui::global_dark_light_mode_switch(ui);
+                    ui.add(
+                        Slider::new(&mut self.environment_intensity, 0.0..=8.0)
+                            .text("Intensity")
+                            .smart_aim(false),
+                    );
+                }
+            }
+        });
+
+        CollapsingHeader::new("Tone mapping").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for tone_mapping in ToneMapping::ALL {
+                    ui.radio_value(&mut self.tone_mapping, tone_mapping, tone_mapping.name());
+                }
             });
 
-            ui.group(|ui| {
-                ui.add(egui::Label::new(
-                    RichText::new("Animations").heading().strong(),
-                ));
+            ui.add(
+                Slider::new(&mut self.exposure, 0.1..=8.0)
+                    .logarithmic(true)
+                    .text("Exposure")
+                    .smart_aim(false),
+            );
+        });
 
-                ui.separator();
+        let can_add = self.lights.len() < MAX_LIGHTS;
+        if ui
+            .add_enabled(can_add, egui::Button::new("Add light"))
+            .clicked()
+        {
+            self.lights.push(Light::new_point(Vec3::new(0., 10., 10.)));
+        }
 
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    self.show_animation_view(scene, ui);
+        let mut to_delete = None;
+
+        for (i, light) in self.lights.iter_mut().enumerate() {
+            CollapsingHeader::new(format!("Light {i}"))
+                .id_source(("light", i))
+                .default_open(i == 0)
+                .show(ui, |ui| {
+                    Self::show_light(light, ui);
+
+                    if ui.button("Delete").clicked() {
+                        to_delete = Some(i);
+                    }
                 });
-            });
+        }
+
+        if let Some(i) = to_delete {
+            self.lights.remove(i);
+        }
+    }
+
+    /// Creates the controls of a single light
+    fn show_light(light: &mut Light, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut light.typ, LightType::Point, "Point");
+            ui.radio_value(&mut light.typ, LightType::Directional, "Directional");
+        });
+
+        match light.typ {
+            LightType::Point => {
+                ui.label("Position");
+                Self::vec3_drag_values(&mut light.position, 5.0, ui);
+            }
+            LightType::Directional => {
+                ui.label("Direction");
+                Self::vec3_drag_values(&mut light.direction, 0.01, ui);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            Self::color_edit(&mut light.color, ui);
         });
+
+        ui.add(
+            Slider::new(&mut light.intensity, 0.0..=5.0)
+                .text("Intensity")
+                .smart_aim(false),
+        );
+    }
+
+    /// Creates x, y, z DragValues for a vector
+    fn vec3_drag_values(v: &mut Vec3, speed: f64, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut v.x).speed(speed));
+            ui.label("y");
+            ui.add(egui::DragValue::new(&mut v.y).speed(speed));
+            ui.label("z");
+            ui.add(egui::DragValue::new(&mut v.z).speed(speed));
+        });
+    }
+
+    /// Creates a color picker for an RGB color stored in a vector
+    fn color_edit(color: &mut Vec3, ui: &mut Ui) {
+        let mut rgb = color.to_array();
+        ui.color_edit_button_rgb(&mut rgb);
+        *color = Vec3::from(rgb);
     }
 
     /// Creates a gui for the animations inside the side panel
     fn show_animation_view(&mut self, scene: &mut [Model], ui: &mut Ui) {
-        let selected_model = &mut scene[self.selected_model];
+        let selected_model = match scene.get_mut(self.selected_model) {
+            Some(model) => model,
+            None => return,
+        };
         let animations = &mut selected_model.animations;
         for (i, animation) in animations.animations.iter_mut().enumerate() {
             ui.group(|ui| {
-                let response = ui.add(
-                    Slider::new(&mut animation.current_time, 0.0..=animation.end_time)
-                        .text("Animation time")
-                        .smart_aim(false),
-                );
+                if let Some(name) = &animation.name {
+                    ui.label(name);
+                }
 
-                if response.clicked() || response.dragged() || response.changed() {
+                let view = self
+                    .timeline_views
+                    .entry((selected_model.path.clone(), i))
+                    .or_insert_with(TimelineView::new);
+
+                if Timeline::new(animation, view, &selected_model.root, i).show(ui) {
                     animations.animation_control = AnimationControl::Controllable {
                         active_animation: i,
                     };
                 }
 
+                let root_motion = &mut animation.root_motion;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut root_motion.in_place, "In place");
+                    ui.add_enabled(
+                        root_motion.in_place,
+                        egui::Checkbox::new(&mut root_motion.keep_vertical, "Keep vertical motion"),
+                    );
+                });
+
+                let active = match animations.animation_control {
+                    AnimationControl::Loop { active_animation }
+                    | AnimationControl::Controllable { active_animation } => active_animation == i,
+                    AnimationControl::Static => false,
+                };
+
+                if active {
+                    let delta = root_motion.delta;
+                    ui.small(format!(
+                        "Root motion: {:.3} {:.3} {:.3} per frame",
+                        delta.x, delta.y, delta.z
+                    ));
+                }
+
                 if let AnimationControl::Loop {
                     active_animation: _,
-                    start_time: _,
                 } = animations.animation_control
                 {
                     ui.ctx().request_repaint();
@@ -238,12 +2763,10 @@ impl Gui {
                         } => {
                             animations.animation_control = AnimationControl::Loop {
                                 active_animation: i,
-                                start_time: Instant::now(),
                             }
                         }
                         AnimationControl::Loop {
                             active_animation: _,
-                            start_time: _,
                         } => {
                             animations.animation_control = AnimationControl::Controllable {
                                 active_animation: i,
@@ -255,3 +2778,75 @@ impl Gui {
         }
     }
 }
+
+/// Settings of the "Retarget animation" window
+struct RetargetSettings {
+    /// Index of the model with the animation
+    source: usize,
+    /// Index of the animation in the source model
+    animation: usize,
+    /// Index of the model the animation is copied to
+    target: usize,
+    /// Source joint name -> target joint name, for joints whose names differ
+    mapping: Vec<(String, String)>,
+}
+
+impl RetargetSettings {
+    fn new() -> Self {
+        Self {
+            source: 0,
+            animation: 0,
+            target: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+/// Parent / child relations of the joints of a skin, for displaying them as a tree
+struct JointTree {
+    /// Joints without a parent
+    roots: Vec<usize>,
+    /// Indices of the children of each joint
+    children: Vec<Vec<usize>>,
+    /// Number of joints in the subtree of each joint (including itself)
+    subtree_sizes: Vec<usize>,
+}
+
+impl JointTree {
+    fn new(joints: &[Joint]) -> Self {
+        let mut roots = Vec::new();
+        let mut children = vec![Vec::new(); joints.len()];
+
+        for (i, joint) in joints.iter().enumerate() {
+            match joint.parent {
+                Some(parent) => children[parent].push(i),
+                None => roots.push(i),
+            }
+        }
+
+        // Parents are always placed before their children, so the children are summed first
+        let mut subtree_sizes = vec![1; joints.len()];
+        for i in (0..joints.len()).rev() {
+            if let Some(parent) = joints[i].parent {
+                subtree_sizes[parent] += subtree_sizes[i];
+            }
+        }
+
+        Self {
+            roots,
+            children,
+            subtree_sizes,
+        }
+    }
+
+    /// Indices of the joint and all of its descendants
+    fn subtree(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut stack = vec![index];
+
+        std::iter::from_fn(move || {
+            let i = stack.pop()?;
+            stack.extend(&self.children[i]);
+            Some(i)
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+
+use egui::{
+    plot::{Line, Plot, Values},
+    CtxRef,
+};
+
+use leoric::{model::Model, renderer::FrameStats};
+
+/// Number of frame times shown in the graph
+const FRAME_TIME_SAMPLES: usize = 120;
+
+/// The strip at the bottom of the window with the FPS, frame time graph, draw counts
+/// and the active animation
+pub struct StatusBar {
+    pub visible: bool,
+    /// If the numbers stay the same until unfrozen (for screenshots)
+    pub frozen: bool,
+    /// Durations of the last frames in seconds, the oldest first
+    frame_times: VecDeque<f32>,
+    stats: FrameStats,
+    /// Name and time of the active animation of the selected model
+    animation: Option<(String, f32, f32)>,
+}
+
+impl StatusBar {
+    pub fn new() -> Self {
+        Self {
+            visible: true,
+            frozen: false,
+            frame_times: VecDeque::with_capacity(FRAME_TIME_SAMPLES),
+            stats: FrameStats::default(),
+            animation: None,
+        }
+    }
+
+    /// Adds the duration of a frame to the graph
+    pub fn record_frame_time(&mut self, dt: f32) {
+        if !self.visible || self.frozen {
+            return;
+        }
+
+        if self.frame_times.len() == FRAME_TIME_SAMPLES {
+            self.frame_times.pop_front();
+        }
+
+        self.frame_times.push_back(dt);
+    }
+
+    /// Takes the numbers of the frame, unless the bar is hidden or frozen
+    pub fn update(&mut self, stats: &FrameStats, model: Option<&Model>) {
+        if !self.visible || self.frozen {
+            return;
+        }
+
+        self.stats = stats.clone();
+        self.animation = model.and_then(|model| {
+            let animations = &model.animations;
+            let index = animations.active_animation()?;
+            let animation = &animations.animations[index];
+            let name = match &animation.name {
+                Some(name) => name.clone(),
+                None => format!("Animation {index}"),
+            };
+
+            Some((name, animation.current_time, animation.end_time))
+        });
+    }
+
+    pub fn show(&mut self, egui_ctx: &mut CtxRef) {
+        if !self.visible {
+            return;
+        }
+
+        egui::TopBottomPanel::bottom("status_bar").show(egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                let average = match self.frame_times.len() {
+                    0 => 0.,
+                    len => self.frame_times.iter().sum::<f32>() / len as f32,
+                };
+                let fps = if average > 0. { 1. / average } else { 0. };
+                ui.label(format!("{fps:.0} FPS, {:.2} ms", average * 1000.));
+
+                let frame_times: Vec<f32> = self.frame_times.iter().map(|t| t * 1000.).collect();
+                Plot::new("frame_times")
+                    .width(FRAME_TIME_SAMPLES as f32)
+                    .height(24.)
+                    .show_x(false)
+                    .show_y(false)
+                    .allow_zoom(false)
+                    .allow_drag(false)
+                    .include_y(0.)
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(Line::new(Values::from_ys_f32(&frame_times)));
+                    });
+
+                ui.separator();
+                ui.label(format!(
+                    "Draw calls: {}, triangles: {}",
+                    self.stats.draw_calls, self.stats.submitted_triangles
+                ));
+
+                ui.separator();
+                match &self.animation {
+                    Some((name, time, end_time)) => {
+                        ui.label(format!("{name}: {time:.2} / {end_time:.2} s"))
+                    }
+                    None => ui.label("No animation"),
+                };
+
+                ui.separator();
+                ui.checkbox(&mut self.frozen, "Freeze stats");
+            });
+        });
+    }
+}
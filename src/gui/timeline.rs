@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use egui::{CollapsingHeader, Color32, Pos2, Sense, Stroke, Ui, Vec2};
+
+use leoric::model::{Animation, AnimationTransforms, Channel, Node};
+
+/// Clicks closer than this (in points) to a keyframe snap the playhead to it
+const SNAP_DISTANCE: f32 = 5.;
+/// Shortest visible time range (in seconds)
+const MIN_VIEW_LENGTH: f32 = 0.01;
+
+const KEYFRAME_COLOR: Color32 = Color32::from_rgb(200, 200, 200);
+const PLAYHEAD_COLOR: Color32 = Color32::from_rgb(230, 80, 60);
+
+/// The visible time range of a timeline, zoomed with the scroll wheel and panned with
+/// shift + scroll wheel
+pub struct TimelineView {
+    /// Time at the left edge
+    start: f32,
+    /// Visible duration, 0 shows the whole animation
+    length: f32,
+}
+
+impl TimelineView {
+    pub fn new() -> Self {
+        Self {
+            start: 0.,
+            length: 0.,
+        }
+    }
+
+    /// Keeps the range inside of the animation
+    fn clamp(&mut self, end_time: f32) {
+        if self.length <= 0. || self.length > end_time {
+            self.length = end_time;
+        }
+
+        self.start = self.start.clamp(0., end_time - self.length);
+    }
+}
+
+/// A timeline of an animation - a strip with all of the keyframes and a draggable playhead,
+/// with rows of the keyframes of each channel grouped by the target node
+pub struct Timeline<'a> {
+    animation: &'a mut Animation,
+    view: &'a mut TimelineView,
+    /// Root of the model, for looking up the names of the nodes
+    root: &'a Node,
+    /// Distinguishes the timelines of different animations
+    id: usize,
+}
+
+impl<'a> Timeline<'a> {
+    pub fn new(
+        animation: &'a mut Animation,
+        view: &'a mut TimelineView,
+        root: &'a Node,
+        id: usize,
+    ) -> Self {
+        Self {
+            animation,
+            view,
+            root,
+            id,
+        }
+    }
+
+    /// Shows the timeline, returns true if the user moved the playhead
+    pub fn show(self, ui: &mut Ui) -> bool {
+        let Animation {
+            channels,
+            current_time,
+            end_time,
+            ..
+        } = self.animation;
+
+        let end_time = end_time.max(MIN_VIEW_LENGTH);
+        self.view.clamp(end_time);
+
+        ui.label(format!(
+            "{current_time:.2} / {end_time:.2} s (scroll to zoom, shift + scroll to pan)"
+        ));
+
+        let mut all_times: Vec<f32> = channels
+            .iter()
+            .flat_map(|c| c.keyframe_times.iter().copied())
+            .collect();
+        all_times.sort_by(|a, b| a.total_cmp(b));
+        all_times.dedup();
+
+        let mut moved = Self::row(ui, &all_times, current_time, self.view, end_time, 20.);
+
+        let mut nodes: BTreeMap<usize, Vec<&Channel>> = BTreeMap::new();
+        for channel in channels.iter() {
+            nodes.entry(channel.node).or_default().push(channel);
+        }
+
+        CollapsingHeader::new("Channels")
+            .id_source(("timeline", self.id))
+            .show(ui, |ui| {
+                for (node, channels) in nodes {
+                    let name = match self.root.find(node) {
+                        Some(node) => node.name.clone(),
+                        None => format!("Node {node}"),
+                    };
+
+                    CollapsingHeader::new(name)
+                        .id_source(("timeline_node", self.id, node))
+                        .show(ui, |ui| {
+                            for channel in channels {
+                                let property = match channel.transforms {
+                                    AnimationTransforms::Translations(_) => "Translation",
+                                    AnimationTransforms::Rotations(_) => "Rotation",
+                                    AnimationTransforms::Scales(_) => "Scale",
+                                };
+
+                                ui.small(property);
+                                moved |= Self::row(
+                                    ui,
+                                    &channel.keyframe_times,
+                                    current_time,
+                                    self.view,
+                                    end_time,
+                                    12.,
+                                );
+                            }
+                        });
+                }
+            });
+
+        moved
+    }
+
+    /// A strip with the keyframe ticks and the playhead, returns true if the playhead was moved
+    fn row(
+        ui: &mut Ui,
+        times: &[f32],
+        current_time: &mut f32,
+        view: &mut TimelineView,
+        end_time: f32,
+        height: f32,
+    ) -> bool {
+        let size = Vec2::new(ui.available_width(), height);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+
+        let to_x = |time: f32| rect.left() + (time - view.start) / view.length * rect.width();
+        let to_time = |x: f32| view.start + (x - rect.left()) / rect.width() * view.length;
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2., ui.visuals().extreme_bg_color);
+
+        for &time in times {
+            if time >= view.start && time <= view.start + view.length {
+                let x = to_x(time);
+                painter.line_segment(
+                    [
+                        Pos2::new(x, rect.top() + 2.),
+                        Pos2::new(x, rect.bottom() - 2.),
+                    ],
+                    Stroke::new(1.5, KEYFRAME_COLOR),
+                );
+            }
+        }
+
+        let playhead = to_x(*current_time);
+        painter.line_segment(
+            [
+                Pos2::new(playhead, rect.top()),
+                Pos2::new(playhead, rect.bottom()),
+            ],
+            Stroke::new(2., PLAYHEAD_COLOR),
+        );
+
+        let mut moved = false;
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            // Clicks snap to the nearest keyframe, drags move the playhead freely
+            let snapped = times
+                .iter()
+                .map(|&time| (time, (to_x(time) - pos.x).abs()))
+                .filter(|(_, distance)| *distance < SNAP_DISTANCE)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            *current_time = match snapped {
+                Some((time, _)) if response.clicked() => time,
+                _ => to_time(pos.x).clamp(0., end_time),
+            };
+
+            moved = true;
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let (scroll, shift) = {
+                let input = ui.input();
+                (input.scroll_delta, input.modifiers.shift)
+            };
+
+            let delta = scroll.x + scroll.y;
+            if shift && delta != 0. {
+                view.start -= delta / rect.width() * view.length;
+            } else if scroll.y != 0. {
+                // Zoom around the time under the cursor
+                let time = to_time(pos.x);
+                let length =
+                    (view.length * (-scroll.y * 0.005).exp()).clamp(MIN_VIEW_LENGTH, end_time);
+
+                view.start = time - (time - view.start) * length / view.length;
+                view.length = length;
+            }
+
+            view.clamp(end_time);
+        }
+
+        moved
+    }
+}
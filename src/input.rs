@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+
+use glam::Vec2;
+use leoric::{
+    camera::{Camera, CameraMode},
+    model::Model,
+};
+use sdl2::{
+    controller::{Axis, Button},
+    keyboard::{KeyboardState, Scancode},
+};
+
+use crate::{gui::Gui, key_bindings::Action, window::MyWindow};
+
+/// Keys held down in the previous frame, used for detecting single key presses
+pub struct InputState {
+    held_keys: HashSet<Scancode>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self {
+            held_keys: HashSet::new(),
+        }
+    }
+
+    /// Returns true only in the frame when the key was pressed down
+    fn pressed(&mut self, k: &KeyboardState, key: Scancode) -> bool {
+        if k.is_scancode_pressed(key) {
+            self.held_keys.insert(key)
+        } else {
+            self.held_keys.remove(&key);
+            false
+        }
+    }
+}
+
+/// Modifies camera state based on the mouse / keyboard inputs.
+///
+/// Has to be called after `MyWindow::begin_frame`, inputs captured by egui
+/// (typing into a text field, dragging a slider, scrolling a panel) don't affect the camera.
+/// 'dt' is the duration of the frame in seconds.
+pub fn handle_inputs(
+    window: &mut MyWindow,
+    scene: &mut [Model],
+    camera: &mut Camera,
+    gui: &mut Gui,
+    input: &mut InputState,
+    dt: f32,
+) {
+    let wants_keyboard = window.egui_ctx.wants_keyboard_input();
+    let wants_pointer = window.egui_ctx.wants_pointer_input();
+
+    // Clicks on the GUI are handled by egui
+    if let Some(click) = window.take_click() {
+        if !wants_pointer {
+            gui.pick = Some(click);
+        }
+    }
+
+    let scroll = window.take_scroll();
+    if scroll != 0. && !wants_pointer {
+        let bounds = gui.selected_bounds(scene);
+        let scene_distance = if bounds.is_empty() {
+            1.
+        } else {
+            (bounds.center() - camera.pos()).length()
+        };
+
+        camera.zoom(scroll, scene_distance);
+    }
+
+    // Controller inputs are gated like the keyboard ones
+    let buttons = window.take_controller_buttons();
+    if !wants_keyboard && gui.capture_key.is_none() {
+        handle_gamepad(window, &buttons, scene, camera, gui, dt);
+    }
+
+    let event_pump = &window.event_pump;
+    let k = event_pump.keyboard_state();
+
+    if let Some(action) = gui.capture_key {
+        // The next pressed key is bound to the action in the "Controls" window, Escape cancels
+        if let Some(key) = k.pressed_scancodes().find(|&key| input.pressed(&k, key)) {
+            if key != Scancode::Escape {
+                gui.key_bindings.set(action, key);
+            }
+            gui.capture_key = None;
+        }
+        return;
+    }
+
+    let bindings = &gui.key_bindings;
+
+    if input.pressed(&k, bindings.key(Action::Screenshot)) {
+        gui.take_screenshot = true;
+    }
+
+    if input.pressed(&k, bindings.key(Action::Fullscreen)) {
+        gui.toggle_fullscreen = true;
+    }
+
+    if !wants_keyboard {
+        let ctrl = k.is_scancode_pressed(Scancode::LCtrl) || k.is_scancode_pressed(Scancode::RCtrl);
+        let shift =
+            k.is_scancode_pressed(Scancode::LShift) || k.is_scancode_pressed(Scancode::RShift);
+
+        if ctrl && input.pressed(&k, Scancode::Z) {
+            if shift {
+                gui.redo_pose = true;
+            } else {
+                gui.undo_pose = true;
+            }
+        }
+
+        handle_camera_keys(&k, scene, camera, gui, input);
+    }
+
+    let mouse_state = event_pump.mouse_state();
+    let mouse_x = mouse_state.x() as f32;
+    let mouse_y = mouse_state.y() as f32;
+
+    // Relative mouse mode is used for looking, so the camera can spin indefinitely.
+    // Looking can't start over the GUI, but it continues when the cursor gets over it.
+    let looking = mouse_state.right() && (window.mouse_look() || !wants_pointer);
+    window.set_mouse_look(looking);
+
+    if looking {
+        let relative = window.event_pump.relative_mouse_state();
+        camera.look_delta(relative.x() as f32, relative.y() as f32);
+    } else if mouse_state.middle() && !wants_pointer {
+        camera.pan(mouse_x, mouse_y);
+    } else {
+        camera.set_x_y(mouse_x, mouse_y)
+    }
+}
+
+/// Moves the camera with the sticks and the triggers and controls the animation
+/// of the selected model with the face buttons
+fn handle_gamepad(
+    window: &MyWindow,
+    buttons: &[Button],
+    scene: &mut [Model],
+    camera: &mut Camera,
+    gui: &Gui,
+    dt: f32,
+) {
+    let settings = &gui.gamepad;
+
+    if let Some(model) = scene.get_mut(gui.selected_model) {
+        for button in buttons {
+            match button {
+                Button::A => model.animations.toggle_playing(),
+                Button::B => model.animations.select_relative(1),
+                Button::X => model.animations.select_relative(-1),
+                _ => {}
+            }
+        }
+    }
+
+    // The movement is requested like with the keyboard, `Camera::update` scales it by the frame time
+    if camera.mode() == CameraMode::Free {
+        let movement = settings.stick(
+            window.controller_axis(Axis::LeftX),
+            window.controller_axis(Axis::LeftY),
+        );
+        camera.strafe_right(movement.x);
+        // The Y axis of the sticks points down
+        camera.move_forward(-movement.y);
+
+        camera.move_up(settings.trigger(window.controller_axis(Axis::TriggerRight)));
+        camera.move_down(settings.trigger(window.controller_axis(Axis::TriggerLeft)));
+    }
+
+    let look = settings.stick(
+        window.controller_axis(Axis::RightX),
+        window.controller_axis(Axis::RightY),
+    );
+    if look != Vec2::ZERO && camera.look_sensitivity > 0. {
+        // `look_delta` takes pixels, convert the angle so the speed doesn't depend on the sensitivity
+        let degrees = look * settings.look_speed * dt / camera.look_sensitivity;
+        camera.look_delta(degrees.x, degrees.y);
+    }
+}
+
+/// Handles the keyboard camera controls and shortcuts
+fn handle_camera_keys(
+    k: &KeyboardState,
+    scene: &[Model],
+    camera: &mut Camera,
+    gui: &mut Gui,
+    input: &mut InputState,
+) {
+    let bindings = gui.key_bindings.clone();
+
+    if input.pressed(k, bindings.key(Action::FocusSelected)) {
+        gui.focus_selected = true;
+    }
+
+    if input.pressed(k, bindings.key(Action::ReloadShaders)) {
+        gui.reload_shaders = true;
+    }
+
+    if input.pressed(k, bindings.key(Action::ToggleSkeleton)) {
+        gui.draw_skeleton = !gui.draw_skeleton;
+    }
+
+    let bookmark_keys = [
+        Scancode::Num1,
+        Scancode::Num2,
+        Scancode::Num3,
+        Scancode::Num4,
+        Scancode::Num5,
+        Scancode::Num6,
+        Scancode::Num7,
+        Scancode::Num8,
+        Scancode::Num9,
+    ];
+
+    for (i, key) in bookmark_keys.into_iter().enumerate() {
+        if input.pressed(k, key) {
+            if let Some(bookmark) = gui.bookmarks.list.get(i) {
+                camera.set_pose(&bookmark.pose);
+            }
+        }
+    }
+
+    if input.pressed(k, bindings.key(Action::ToggleOrbit)) {
+        let mode = match camera.mode() {
+            CameraMode::Free => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Free,
+        };
+
+        camera.set_mode(mode, gui.selected_bounds(scene).center());
+    }
+
+    if camera.mode() == CameraMode::Free {
+        if bindings.is_held(k, Action::MoveForward) {
+            camera.move_forward(1.0);
+        }
+
+        if bindings.is_held(k, Action::MoveBackward) {
+            camera.move_backward(1.0);
+        }
+
+        if bindings.is_held(k, Action::MoveLeft) {
+            camera.strafe_left(1.0);
+        }
+
+        if bindings.is_held(k, Action::MoveRight) {
+            camera.strafe_right(1.0);
+        }
+
+        if bindings.is_held(k, Action::MoveUp) {
+            camera.move_up(1.0);
+        }
+
+        if bindings.is_held(k, Action::MoveDown) {
+            camera.move_down(1.0);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::{fmt::Write, fs, path::Path};
+
+use eyre::{eyre, Result};
+use serde::Serialize;
+
+use crate::model::{pose, Model};
+
+/// File format of the exported joint positions
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JointExportFormat {
+    /// One 'time,joint,x,y,z' row per joint and sample
+    Csv,
+    /// The joint names and an array of the positions of each sample
+    Json,
+}
+
+impl JointExportFormat {
+    pub const ALL: [JointExportFormat; 2] = [JointExportFormat::Csv, JointExportFormat::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            JointExportFormat::Csv => "CSV",
+            JointExportFormat::Json => "JSON",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            JointExportFormat::Csv => "csv",
+            JointExportFormat::Json => "json",
+        }
+    }
+}
+
+/// World-space positions of the joints at one time
+#[derive(Serialize)]
+pub struct JointSample {
+    /// Time in the animation in seconds
+    pub time: f32,
+    /// Positions in the order of the sampled joints
+    pub positions: Vec<[f32; 3]>,
+}
+
+/// The JSON file of the exported positions
+#[derive(Serialize)]
+struct JointPositionsFile<'a> {
+    model: &'a str,
+    animation: Option<&'a str>,
+    sample_rate: f32,
+    joints: Vec<&'a str>,
+    samples: Vec<JointSample>,
+}
+
+/// Samples the world-space positions of the joints ('joints' are indices into the skin)
+/// over the whole animation, 'sample_rate' times per second.
+///
+/// The sample times are computed from the sample index, so the result doesn't depend
+/// on the frame rate. The joints of the model aren't modified.
+pub fn sample_joint_positions(
+    model: &Model,
+    animation: usize,
+    sample_rate: f32,
+    joints: &[usize],
+) -> Result<Vec<JointSample>> {
+    let skin = model
+        .root
+        .joints()
+        .ok_or_else(|| eyre!("The model doesn't have a skin"))?;
+
+    let anim = model
+        .animations
+        .animations
+        .get(animation)
+        .ok_or_else(|| eyre!("The model doesn't have animation {animation}"))?;
+
+    if sample_rate <= 0. {
+        return Err(eyre!("The sample rate has to be positive"));
+    }
+
+    if let Some(&joint) = joints.iter().find(|&&j| j >= skin.joints.len()) {
+        return Err(eyre!("The skin doesn't have joint {joint}"));
+    }
+
+    let outer_transform = model
+        .root
+        .skin_transform(model.transform)
+        .unwrap_or(model.transform);
+
+    let sample_count = (anim.end_time * sample_rate).floor() as usize + 1;
+
+    let samples = (0..sample_count)
+        .map(|i| {
+            let time = i as f32 / sample_rate;
+            let world_transforms =
+                pose::compute_sampled_world_transforms(&skin.joints, anim, time, outer_transform);
+
+            JointSample {
+                time,
+                positions: joints
+                    .iter()
+                    .map(|&j| world_transforms[j].w_axis.truncate().to_array())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(samples)
+}
+
+/// Samples the joint positions (see `sample_joint_positions`) and writes them into the file
+pub fn export_joint_positions(
+    model: &Model,
+    animation: usize,
+    sample_rate: f32,
+    joints: &[usize],
+    format: JointExportFormat,
+    path: &Path,
+) -> Result<()> {
+    let samples = sample_joint_positions(model, animation, sample_rate, joints)?;
+
+    // Checked by the sampling
+    let skin = &model.root.joints().expect("The model has a skin").joints;
+    let names: Vec<&str> = joints.iter().map(|&j| skin[j].name.as_str()).collect();
+
+    let out = match format {
+        JointExportFormat::Csv => {
+            let mut out = String::from("time,joint,x,y,z\n");
+            for sample in &samples {
+                for (name, [x, y, z]) in names.iter().zip(&sample.positions) {
+                    writeln!(out, "{},{},{x},{y},{z}", sample.time, csv_field(name))?;
+                }
+            }
+
+            out
+        }
+        JointExportFormat::Json => {
+            let file = JointPositionsFile {
+                model: &model.name,
+                animation: model.animations.animations[animation].name.as_deref(),
+                sample_rate,
+                joints: names,
+                samples,
+            };
+
+            serde_json::to_string_pretty(&file)?
+        }
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, out)?;
+
+    Ok(())
+}
+
+/// Quotes the field if it contains a separator or a quote
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+    use gltf::animation::Interpolation;
+
+    use super::*;
+    use crate::model::{Animation, AnimationTransforms, Channel, Joint, Joints, Node, Transform};
+
+    fn translation(t: Vec3) -> Transform {
+        Transform {
+            translation: t,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+
+    /// A root joint and a joint 1 unit above it, the root moves 2 units along X in 1 second
+    fn two_joint_model() -> Model {
+        let joints = vec![
+            Joint::new(
+                0,
+                None,
+                Mat4::IDENTITY,
+                translation(Vec3::ZERO),
+                "root".into(),
+            ),
+            Joint::new(
+                1,
+                Some(0),
+                Mat4::IDENTITY,
+                translation(Vec3::Y),
+                "tip, left".into(),
+            ),
+        ];
+
+        let skin = Node {
+            index: 0,
+            name: "root".to_string(),
+            children: Vec::new(),
+            mesh: None,
+            lods: None,
+            transform: Mat4::IDENTITY,
+            joints: Some(Joints { joints }),
+        };
+
+        let root = Node {
+            index: usize::MAX,
+            name: "Root".to_string(),
+            children: vec![skin],
+            mesh: None,
+            lods: None,
+            transform: Mat4::IDENTITY,
+            joints: None,
+        };
+
+        let channel = Channel::new(
+            0,
+            vec![0., 1.],
+            AnimationTransforms::Translations(vec![Vec3::ZERO, Vec3::new(2., 0., 0.)]),
+            Interpolation::Linear,
+        );
+        let animation = Animation::new(vec![channel], 0., 1., Some("Walk".to_string()));
+
+        Model::from_nodes(root, vec![animation])
+    }
+
+    fn export(format: JointExportFormat) -> String {
+        let dir = std::env::temp_dir().join(format!("leoric_joint_export_{}", std::process::id()));
+        let path = dir.join("joints").with_extension(format.extension());
+
+        export_joint_positions(&two_joint_model(), 0, 2., &[0, 1], format, &path).unwrap();
+
+        let out = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        out
+    }
+
+    #[test]
+    fn samples_cover_the_whole_animation() {
+        let samples = sample_joint_positions(&two_joint_model(), 0, 2., &[1]).unwrap();
+
+        let times: Vec<f32> = samples.iter().map(|s| s.time).collect();
+        assert_eq!(times, vec![0., 0.5, 1.]);
+        assert_eq!(samples[1].positions, vec![[1., 1., 0.]]);
+    }
+
+    #[test]
+    fn invalid_arguments_are_errors() {
+        let model = two_joint_model();
+
+        assert!(sample_joint_positions(&model, 1, 2., &[0]).is_err());
+        assert!(sample_joint_positions(&model, 0, 0., &[0]).is_err());
+        assert!(sample_joint_positions(&model, 0, 2., &[2]).is_err());
+    }
+
+    #[test]
+    fn csv_export() {
+        let csv = export(JointExportFormat::Csv);
+
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "time,joint,x,y,z",
+                "0,root,0,0,0",
+                "0,\"tip, left\",0,1,0",
+                "0.5,root,1,0,0",
+                "0.5,\"tip, left\",1,1,0",
+                "1,root,2,0,0",
+                "1,\"tip, left\",2,1,0",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_export() {
+        let json: serde_json::Value =
+            serde_json::from_str(&export(JointExportFormat::Json)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "model": "test",
+                "animation": "Walk",
+                "sample_rate": 2.0,
+                "joints": ["root", "tip, left"],
+                "samples": [
+                    { "time": 0.0, "positions": [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0]] },
+                    { "time": 0.5, "positions": [[1.0, 0.0, 0.0], [1.0, 1.0, 0.0]] },
+                    { "time": 1.0, "positions": [[2.0, 0.0, 0.0], [2.0, 1.0, 0.0]] },
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use sdl2::keyboard::{KeyboardState, Scancode};
+use serde::{Deserialize, Serialize};
+
+/// A logical action of the viewer that can be bound to a key
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ToggleSkeleton,
+    Screenshot,
+    FocusSelected,
+    Fullscreen,
+    ReloadShaders,
+    ToggleOrbit,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::ToggleSkeleton,
+        Action::Screenshot,
+        Action::FocusSelected,
+        Action::Fullscreen,
+        Action::ReloadShaders,
+        Action::ToggleOrbit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::ToggleSkeleton => "Toggle skeleton",
+            Action::Screenshot => "Screenshot",
+            Action::FocusSelected => "Focus selected",
+            Action::Fullscreen => "Fullscreen",
+            Action::ReloadShaders => "Reload shaders",
+            Action::ToggleOrbit => "Toggle orbit camera",
+        }
+    }
+
+    fn default_key(self) -> Scancode {
+        match self {
+            Action::MoveForward => Scancode::W,
+            Action::MoveBackward => Scancode::S,
+            Action::MoveLeft => Scancode::A,
+            Action::MoveRight => Scancode::D,
+            Action::MoveUp => Scancode::Space,
+            Action::MoveDown => Scancode::LCtrl,
+            Action::ToggleSkeleton => Scancode::J,
+            Action::Screenshot => Scancode::F12,
+            Action::FocusSelected => Scancode::F,
+            Action::Fullscreen => Scancode::F11,
+            Action::ReloadShaders => Scancode::R,
+            Action::ToggleOrbit => Scancode::O,
+        }
+    }
+}
+
+/// Keys of the actions, persisted with the settings.
+///
+/// The keys are stored by their SDL scancode names ("W", "Space", "F11"...), actions
+/// missing from the file or with an unknown key name use the default key.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyBindings {
+    keys: HashMap<Action, String>,
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> Scancode {
+        self.keys
+            .get(&action)
+            .and_then(|name| Scancode::from_name(name))
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    /// Name of the key shown in the GUI
+    pub fn key_name(&self, action: Action) -> &'static str {
+        self.key(action).name()
+    }
+
+    pub fn set(&mut self, action: Action, key: Scancode) {
+        self.keys.insert(action, key.name().to_string());
+    }
+
+    /// If the key of the action is held down
+    pub fn is_held(&self, k: &KeyboardState, action: Action) -> bool {
+        k.is_scancode_pressed(self.key(action))
+    }
+
+    /// Other actions bound to the same key as the action
+    pub fn conflicts(&self, action: Action) -> Vec<Action> {
+        let key = self.key(action);
+
+        Action::ALL
+            .into_iter()
+            .filter(|&other| other != action && self.key(other) == key)
+            .collect()
+    }
+}
@@ -0,0 +1,48 @@
+//! PGRF2 project - skeletal animation
+//!
+//! Loading glTF models, animating and skinning them and rendering them with OpenGL.
+//! The viewer binary (`main.rs`) adds the window and the GUI around the library.
+//!
+//! The renderer needs a current OpenGL context with the functions loaded (`gl::load_with`).
+
+// Most of the types wrap OpenGL objects, creating them shouldn't be hidden behind Default
+#![allow(clippy::new_without_default)]
+
+/// A module for working with a basic free camera.
+pub mod camera;
+
+/// Represents a single gltf 2.0 model (used models only have 1 scene).
+pub mod model;
+
+/// Handles rendering the whole scene.
+pub mod renderer;
+
+/// Abstractions for working with OpenGL.
+pub mod ogl;
+
+/// Saved camera views.
+pub mod bookmarks;
+
+/// Saved joint poses.
+pub mod poses;
+
+/// Exporting the posed meshes into OBJ files.
+pub mod obj;
+
+/// Exporting the animated joint positions into CSV or JSON files.
+pub mod joint_export;
+
+/// Exporting animations into GIFs or PNG sequences.
+pub mod export;
+
+/// Comparing two models playing the same animation side by side.
+pub mod comparison;
+
+/// Loading models on background threads.
+pub mod loader;
+
+/// Saving and restoring the whole scene.
+pub mod scene;
+
+/// Keeping the log messages for the in-app console.
+pub mod console;
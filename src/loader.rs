@@ -0,0 +1,192 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use eyre::{eyre, Result};
+use glam::{Mat4, Vec3};
+use log::warn;
+
+use crate::model::{LoadStage, Model};
+
+/// What should happen with the model once it's loaded
+#[derive(Clone, Copy)]
+pub enum LoadTarget {
+    /// Add the model to the scene, optionally selecting it
+    Add { select: bool },
+    /// Replace the model loaded from the same path (hot-reload)
+    Replace,
+    /// A model of a scene file, 'entry' is its index in the file
+    Scene { entry: usize },
+}
+
+/// A model loaded on a background thread (not yet uploaded to the GPU)
+pub struct LoadedModel {
+    /// Path of the gltf file
+    pub path: String,
+    pub model: Result<Model>,
+    pub target: LoadTarget,
+    /// Id of the load (see `PendingLoad`)
+    id: u64,
+}
+
+/// A model that is still loading
+#[derive(Clone)]
+pub struct PendingLoad {
+    /// Identifies the load (the same file can be loading multiple times)
+    pub id: u64,
+    /// Path of the gltf file
+    pub path: String,
+    /// The stage the loader thread is in
+    pub stage: LoadStage,
+    /// Set when the load is cancelled, the loader thread stops at the next stage
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Messages sent by the loader threads
+enum LoaderMessage {
+    Stage {
+        id: u64,
+        stage: LoadStage,
+    },
+    /// Boxed, the model is much larger than the stage messages
+    Finished(Box<LoadedModel>),
+}
+
+/// Loads models on background threads, so the window doesn't freeze.
+///
+/// Reading and decoding the files happens on the loader threads,
+/// the GPU upload has to be done by the render loop (which owns the OpenGL context).
+pub struct Loader {
+    sender: Sender<LoaderMessage>,
+    receiver: Receiver<LoaderMessage>,
+    /// Models that are still loading
+    pending: Vec<PendingLoad>,
+    /// Id of the next load
+    next_id: u64,
+    /// If invalid data should fail the loading (instead of being fixed with a warning)
+    strict: bool,
+}
+
+impl Loader {
+    pub fn new(strict: bool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            sender,
+            receiver,
+            pending: Vec::new(),
+            next_id: 0,
+            strict,
+        }
+    }
+
+    /// Starts loading the model on a new thread.
+    ///
+    /// 'scale' is applied to the model transform.
+    pub fn load(&mut self, path: String, scale: f32, target: LoadTarget) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.pending.push(PendingLoad {
+            id,
+            path: path.clone(),
+            stage: LoadStage::Import,
+            cancelled: Arc::clone(&cancelled),
+        });
+
+        let sender = self.sender.clone();
+        let strict = self.strict;
+
+        thread::spawn(move || {
+            let mut on_stage = |stage| {
+                if cancelled.load(Ordering::Relaxed) {
+                    return Err(eyre!("The loading was cancelled"));
+                }
+
+                // The receiver only disappears when the application is quitting
+                let _ = sender.send(LoaderMessage::Stage { id, stage });
+                Ok(())
+            };
+
+            let model = Model::load_with_progress(&path, strict, &mut on_stage).map(|mut model| {
+                for warning in &model.warnings {
+                    warn!("'{path}': {warning}");
+                }
+
+                model.transform = Mat4::from_scale(Vec3::splat(scale));
+                model
+            });
+
+            let _ = sender.send(LoaderMessage::Finished(Box::new(LoadedModel {
+                path,
+                model,
+                target,
+                id,
+            })));
+        });
+    }
+
+    /// Models that are still loading
+    pub fn pending(&self) -> &[PendingLoad] {
+        &self.pending
+    }
+
+    /// Abandons the load, its model is dropped when the loader thread finishes.
+    ///
+    /// Models are only uploaded to the GPU after they are returned by `finished` / `wait`,
+    /// so cancelled loads never create any OpenGL objects.
+    pub fn cancel(&mut self, id: u64) {
+        if let Some(i) = self.pending.iter().position(|p| p.id == id) {
+            let pending = self.pending.remove(i);
+            pending.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the models that finished loading since the last call (doesn't block)
+    pub fn finished(&mut self) -> Vec<LoadedModel> {
+        let mut finished = Vec::new();
+
+        while let Ok(message) = self.receiver.try_recv() {
+            self.handle_message(message, &mut finished);
+        }
+
+        finished
+    }
+
+    /// Blocks until all of the pending models are loaded and returns them
+    pub fn wait(&mut self) -> Vec<LoadedModel> {
+        let mut finished = Vec::new();
+
+        while !self.pending.is_empty() {
+            match self.receiver.recv() {
+                Ok(message) => self.handle_message(message, &mut finished),
+                Err(_) => break,
+            }
+        }
+
+        finished
+    }
+
+    /// Updates the pending loads, models of the cancelled loads are dropped
+    fn handle_message(&mut self, message: LoaderMessage, finished: &mut Vec<LoadedModel>) {
+        match message {
+            LoaderMessage::Stage { id, stage } => {
+                if let Some(pending) = self.pending.iter_mut().find(|p| p.id == id) {
+                    pending.stage = stage;
+                }
+            }
+            LoaderMessage::Finished(loaded) => {
+                if let Some(i) = self.pending.iter().position(|p| p.id == loaded.id) {
+                    self.pending.remove(i);
+                    finished.push(*loaded);
+                }
+            }
+        }
+    }
+}
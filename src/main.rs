@@ -1,126 +1,165 @@
 //! PGRF2 project - skeletal animation
 //!
+//! The viewer binary - the window, the GUI and the render loop around the library.
 //! `main` function is the entry-point
-use std::{thread, time::Duration};
+use eyre::{eyre, Result};
 
-use camera::Camera;
-use eyre::Result;
-use glam::{Mat4, Vec3};
-use gui::Gui;
-use model::Model;
-use renderer::Renderer;
-use sdl2::{keyboard::Scancode, EventPump};
-
-use window::MyWindow;
-
-/// A module for working with a basic free camera.
-mod camera;
+use viewer::Viewer;
 
 /// All of the code for drawing the GUI using egui.
 mod gui;
 
-/// Represents a single gltf 2.0 model (used models only have 1 scene).
-mod model;
-
-/// Handles rendering the whole scene.
-mod renderer;
-
-/// Abstractions for working with OpenGL.
-mod ogl;
-
 /// Handles window creation and egui boilerplate.
 mod window;
 
-/// Creates the window, configures OpenGL, sets up the scene and begins the render loop.
-fn main() -> Result<()> {
-    let mut window = MyWindow::new("PGRF2 Projekt - Skeletální Animace - Tomáš Král")?;
-
-    ogl::init_debug();
-
-    let mut scene = setup_scene()?;
-    let mut gui = Gui::new();
-    let mut renderer = Renderer::new()?;
-    let mut camera = Camera::new(
-        Vec3::new(0.2, 3., 7.5),
-        0.05,
-        0.05,
-        window.width,
-        window.height,
-    );
-
-    'render_loop: loop {
-        handle_inputs(&mut window.event_pump, &mut camera);
-
-        window.begin_frame();
+/// The render loop and the state of the viewer.
+mod viewer;
+
+/// Keyboard, mouse and game controller inputs.
+mod input;
+
+/// Detecting changes of files on disk.
+mod watcher;
+
+/// Viewer settings kept between runs.
+mod settings;
+
+/// Keys bound to the actions of the viewer.
+mod key_bindings;
+
+/// Dead zones and speeds of the game controller.
+mod gamepad;
+
+/// Number of MSAA samples used when not specified with '--msaa <samples>'
+const DEFAULT_MSAA_SAMPLES: u8 = 4;
+
+/// OpenGL version requested when not specified with '--gl <major>.<minor>'
+const DEFAULT_GL_VERSION: (u8, u8) = (4, 2);
+
+/// Command-line options
+struct Args {
+    /// '--msaa <samples>' - number of MSAA samples of the window
+    msaa_samples: u8,
+    /// '--gl <major>.<minor>' - requested OpenGL version (eg. 3.3 on older drivers)
+    gl_version: (u8, u8),
+    /// '--offscreen <directory>' - render each model into an image in the directory and exit
+    offscreen_dir: Option<String>,
+    /// '--time <seconds>' - animation time used in the offscreen mode (wraps around the end)
+    offscreen_time: f32,
+    /// '--strict' - invalid model data is an error instead of a warning
+    strict: bool,
+    /// '--scene <file>' - scene file opened at startup
+    scene: Option<String>,
+    /// Models given as positional arguments
+    models: Vec<ModelArg>,
+}
 
-        renderer.render(&mut scene, &mut camera, &window, &gui);
-        gui.create_gui(&mut scene, &mut camera, &mut window.egui_ctx);
+/// A model file with optional per-model options: '<path>[:scale=<factor>]'
+struct ModelArg {
+    path: String,
+    /// Uniform scale applied to the whole model
+    scale: f32,
+}
 
-        let should_quit = window.end_frame();
-        if should_quit {
-            break 'render_loop;
+impl ModelArg {
+    /// Parses the path and the options, options are separated by ':' and contain '='
+    /// (so Windows drive letters aren't mistaken for options)
+    fn parse(arg: &str) -> Result<Self> {
+        let mut path = arg;
+        let mut scale = 1.;
+
+        while let Some((rest, option)) = path.rsplit_once(':') {
+            let (key, value) = match option.split_once('=') {
+                Some(kv) => kv,
+                None => break,
+            };
+
+            match key {
+                "scale" => {
+                    scale = value
+                        .parse()
+                        .map_err(|e| eyre!("Invalid scale '{value}' of '{arg}': {e}"))?;
+                }
+                _ => return Err(eyre!("Unknown model option '{key}' of '{arg}'")),
+            }
+
+            path = rest;
         }
 
-        thread::sleep(Duration::from_millis(3));
+        Ok(Self {
+            path: path.to_string(),
+            scale,
+        })
     }
-
-    Ok(())
 }
 
-/// Adds models to the scene
-fn setup_scene() -> Result<Vec<Model>> {
-    let mut scene = Vec::new();
-
-    let mut add = |path: &str| -> Result<()> {
-        let start = std::time::Instant::now();
-
-        let model = Model::from_gltf(path)?;
-
-        let time = std::time::Instant::now().duration_since(start);
-        println!("Loading '{path}' took '{time:?}'");
-
-        scene.push(model);
-        Ok(())
-    };
-
-    add("resources/dancing_stormtrooper/Stormtrooper.gltf")?;
-    add("resources/animated_humanoid_robot/Droid.gltf")?;
-    add("resources/pakistan_girl_-_animated/Girl.gltf")?;
-    add("resources/toon_cat_free/Cat.gltf")?;
-
-    let cat = scene.last_mut().unwrap();
-    cat.transform = Mat4::from_scale(Vec3::splat(0.015));
-
-    Ok(scene)
-}
-
-/// Modifies camera state based on the mouse / keyboard inputs
-fn handle_inputs(event_pump: &mut EventPump, camera: &mut Camera) {
-    let k = event_pump.keyboard_state();
-
-    if k.is_scancode_pressed(Scancode::W) {
-        camera.move_forward(1.0);
-    }
+impl Args {
+    /// Parses the command-line arguments
+    fn parse() -> Result<Self> {
+        let mut res = Self {
+            msaa_samples: DEFAULT_MSAA_SAMPLES,
+            gl_version: DEFAULT_GL_VERSION,
+            offscreen_dir: None,
+            offscreen_time: 0.,
+            strict: false,
+            scene: None,
+            models: Vec::new(),
+        };
+
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| eyre!("'{arg}' requires a value"));
+
+            match arg.as_str() {
+                "--msaa" => {
+                    let samples = value()?;
+                    res.msaa_samples = samples
+                        .parse()
+                        .map_err(|e| eyre!("Invalid MSAA sample count '{samples}': {e}"))?;
+                }
+                "--gl" => {
+                    let version = value()?;
+                    res.gl_version = Self::parse_gl_version(&version)
+                        .ok_or_else(|| eyre!("Invalid OpenGL version '{version}'"))?;
+                }
+                "--offscreen" => res.offscreen_dir = Some(value()?),
+                "--time" => {
+                    let time = value()?;
+                    res.offscreen_time = time
+                        .parse()
+                        .map_err(|e| eyre!("Invalid animation time '{time}': {e}"))?;
+                }
+                "--strict" => res.strict = true,
+                "--scene" => res.scene = Some(value()?),
+                _ if arg.starts_with("--") => return Err(eyre!("Unknown argument '{arg}'")),
+                _ => res.models.push(ModelArg::parse(&arg)?),
+            }
+        }
 
-    if k.is_scancode_pressed(Scancode::S) {
-        camera.move_backward(1.0);
+        Ok(res)
     }
 
-    if k.is_scancode_pressed(Scancode::A) {
-        camera.strafe_left(1.0);
+    /// Parses '<major>.<minor>'
+    fn parse_gl_version(version: &str) -> Option<(u8, u8)> {
+        let (major, minor) = version.split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
     }
+}
 
-    if k.is_scancode_pressed(Scancode::D) {
-        camera.strafe_right(1.0);
-    }
+/// Parses the arguments, creates the viewer and either renders the models offscreen
+/// or begins the render loop.
+fn main() -> Result<()> {
+    leoric::console::init();
 
-    let mouse_state = event_pump.mouse_state();
-    let mouse_x = mouse_state.x() as f32;
-    let mouse_y = mouse_state.y() as f32;
+    let args = Args::parse()?;
+    let mut viewer = Viewer::new(&args)?;
 
-    if mouse_state.right() {
-        camera.adjust_look(mouse_x, mouse_y);
-    } else {
-        camera.set_x_y(mouse_x, mouse_y)
+    match &args.offscreen_dir {
+        Some(dir) => viewer.render_offscreen(dir, args.offscreen_time),
+        None => {
+            viewer.run();
+            Ok(())
+        }
     }
 }
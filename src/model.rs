@@ -1,69 +1,229 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use eyre::{eyre, Result};
 use glam::{Mat4, Quat, Vec3};
 use gltf::scene::Transform as GTransform;
 
+use crate::ogl::{self, Texture2D, TextureFiltering};
+
+use self::mesh::TextureSource;
+
+mod aabb;
 mod animation;
+mod dequantize;
+mod gltf_json;
+mod ik;
+mod instancing;
 mod joints;
+mod lod;
 mod mesh;
+pub mod pose;
+mod pose_history;
+mod retarget;
+mod statistics;
+mod tangents;
+mod texture_image;
 mod transform;
+mod wavefront;
 
 pub use self::{
-    animation::{Animation, AnimationControl, AnimationTransform, AnimationTransforms, Animations},
+    aabb::Aabb,
+    animation::{
+        Animation, AnimationControl, AnimationTransform, AnimationTransforms, Animations, Channel,
+        TimeSource,
+    },
+    ik::TwoBoneIk,
     joints::{Joint, Joints},
-    mesh::{Mesh, Primitive, PrimitiveTexture},
+    lod::Lods,
+    mesh::{Mesh, Primitive, PrimitiveMaterial, UvTransform},
+    pose_history::PoseHistory,
+    retarget::Retargeted,
+    statistics::Statistics,
     transform::Transform,
 };
 
-/// Image and vertex data of the asset.
+/// Vertex data of the asset, only needed while the model is being loaded.
 pub struct DataBundle {
     /// Vertex data
     buffers: Vec<gltf::buffer::Data>,
-    /// Texture data
-    images: Vec<gltf::image::Data>,
-    /// To keep track if which textures were already sent to the GPU
-    pub gl_textures: Vec<Option<PrimitiveTexture>>,
+    /// If problems in the data should fail the loading instead of being fixed
+    strict: bool,
+    /// Problems found (and fixed) while loading
+    warnings: Vec<String>,
+    /// Instance transforms of the nodes (EXT_mesh_gpu_instancing), keyed by the node index
+    instances: HashMap<usize, Vec<Mat4>>,
+    /// Levels of detail of the nodes (MSFT_lod), keyed by the node index
+    lods: HashMap<usize, lod::LodNodes>,
 }
 
 impl DataBundle {
-    fn new(buffers: Vec<gltf::buffer::Data>, images: Vec<gltf::image::Data>) -> Self {
+    fn new(buffers: Vec<gltf::buffer::Data>, strict: bool) -> Self {
         Self {
             buffers,
-            gl_textures: vec![Option::None; images.len()],
+            strict,
+            warnings: Vec::new(),
+            instances: HashMap::new(),
+            lods: HashMap::new(),
+        }
+    }
+
+    /// Records a problem with the data, in the strict mode it's an error
+    fn warn(&mut self, warning: String) -> Result<()> {
+        if self.strict {
+            return Err(eyre!(warning));
+        }
+
+        self.warnings.push(warning);
+        Ok(())
+    }
+}
+
+/// Image data of the asset, needed until the model is uploaded to the GPU.
+pub struct TextureBundle {
+    /// Texture data
+    images: Vec<gltf::image::Data>,
+    /// To keep track if which textures were already sent to the GPU.
+    /// Maps (image index, is sRGB, sampler) to the textures shared by the primitives.
+    /// Arc instead of Rc, because models are sent between threads.
+    pub gl_textures: HashMap<TextureSource, Arc<Texture2D>>,
+    /// Name of the model, for naming the textures in OpenGL debuggers
+    pub model_name: String,
+}
+
+impl TextureBundle {
+    fn new(images: Vec<gltf::image::Data>, model_name: String) -> Self {
+        Self {
             images,
+            gl_textures: HashMap::new(),
+            model_name,
         }
     }
 }
 
+/// A stage of loading a model
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoadStage {
+    /// Reading the file and decoding the buffers and the images
+    Import,
+    /// Reading the nodes, meshes and skins
+    Meshes,
+    Animations,
+    /// Creating the OpenGL buffers and textures (on the render thread)
+    Upload,
+}
+
+impl LoadStage {
+    /// Number of the stages
+    pub const COUNT: usize = 4;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoadStage::Import => "Reading the file",
+            LoadStage::Meshes => "Reading the meshes",
+            LoadStage::Animations => "Reading the animations",
+            LoadStage::Upload => "Uploading to the GPU",
+        }
+    }
+
+    /// Fraction of the whole loading done before this stage starts
+    pub fn progress(&self) -> f32 {
+        *self as usize as f32 / Self::COUNT as f32
+    }
+}
+
 /// This represents a gltf model and contains necessary data for rendering.
 pub struct Model {
     /// An artifical root node
     pub root: Node,
     /// Name of the model
     pub name: String,
+    /// Path of the gltf file the model was loaded from
+    pub path: String,
     /// Animation data
     pub animations: Animations,
     /// Model transforms of the whole object
     pub transform: Mat4,
+    /// Texture data, until the model is uploaded to the GPU
+    textures: Option<TextureBundle>,
+    /// Problems with the data that were fixed while loading (shown in the GUI)
+    pub warnings: Vec<String>,
+    /// Statistics of the whole model
+    pub stats: Statistics,
+    /// Joint edits made in the GUI
+    pub pose_history: PoseHistory,
+    /// How long the stages of loading the model took
+    pub load_times: Vec<(LoadStage, Duration)>,
+    /// The textures created on the GPU, kept so their filtering can be changed
+    gl_textures: HashMap<TextureSource, Arc<Texture2D>>,
+    /// The filtering and the anisotropy level last set by `set_texture_filtering`
+    filtering: Option<(TextureFiltering, Option<f32>)>,
 }
 
 impl Model {
-    /// Load the model from a path to a gltf file
-    pub fn from_gltf(path: &str) -> Result<Model> {
+    /// Load the model from a path to a gltf file without uploading it to the GPU.
+    ///
+    /// Doesn't call OpenGL, so it can be used from a background thread.
+    /// The model can't be rendered until `upload_to_gpu` is called.
+    ///
+    /// Invalid skin data is fixed and reported in `warnings`, unless 'strict' is set,
+    /// in which case it's an error.
+    ///
+    /// OBJ files are loaded as static models (without animations).
+    pub fn load(path: &str, strict: bool) -> Result<Model> {
+        Self::load_with_progress(path, strict, &mut |_| Ok(()))
+    }
+
+    /// Like `load`, but 'on_stage' is called at the start of each stage (not including
+    /// the GPU upload) and the durations of the stages are stored in `load_times`.
+    ///
+    /// The loading is aborted when 'on_stage' returns an error (eg. it was cancelled).
+    pub fn load_with_progress(
+        path: &str,
+        strict: bool,
+        on_stage: &mut dyn FnMut(LoadStage) -> Result<()>,
+    ) -> Result<Model> {
+        let mut timer = StageTimer::new(on_stage);
+
+        let is_obj = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("obj"));
+
+        let mut model = if is_obj {
+            Self::load_obj(path, strict, &mut timer)?
+        } else {
+            Self::load_gltf(path, strict, &mut timer)?
+        };
+
+        model.load_times = timer.finish();
+        Ok(model)
+    }
+
+    fn load_gltf(path: &str, strict: bool, timer: &mut StageTimer) -> Result<Model> {
+        timer.start(LoadStage::Import)?;
         let (gltf, buffers, images) = gltf::import(path)?;
+        // Converted here, in the background, so any image can be uploaded
+        let images: Vec<_> = images.into_iter().map(texture_image::prepare).collect();
         let name = Path::new(path)
             .file_name()
             .map(|osstr| osstr.to_string_lossy().to_string())
             .unwrap_or_else(|| "N/A".to_string());
 
-        let mut bundle = DataBundle::new(buffers, images);
+        let mut bundle = DataBundle::new(buffers, strict);
+        bundle.instances = instancing::read_instances(path, &gltf, &mut bundle)?;
+        bundle.lods = lod::read_lods(path, &gltf)?;
 
         if gltf.scenes().len() != 1 {
             return Err(eyre!("GLTF file contains more than 1 scene"));
         }
         let scene = gltf.scenes().next().unwrap();
 
+        timer.start(LoadStage::Meshes)?;
+
         let mut id = 1;
         let mut nodes = Vec::new();
         for node in scene.nodes() {
@@ -72,24 +232,154 @@ impl Model {
             nodes.push(node);
         }
 
-        let animations = Animation::from_gltf(&gltf, &bundle)?;
-
-        let root = Node {
+        let mut root = Node {
             index: usize::MAX,
             name: "Root".to_string(),
             children: nodes,
             mesh: None,
+            lods: None,
             transform: Mat4::IDENTITY,
             joints: None,
         };
+        lod::attach_lods(&mut root, &gltf, &mut bundle)?;
+
+        timer.start(LoadStage::Animations)?;
+        let animations = Animation::from_gltf(&gltf, &bundle)?;
+
+        let mut stats = root.stats();
+        stats.animations = animations.animations.len();
+        stats.texture_bytes = images.iter().map(Statistics::texture_bytes).sum();
+
+        let textures = TextureBundle::new(images, name.clone());
 
         Ok(Model {
             root,
             name,
+            path: path.to_string(),
             animations,
             transform: Mat4::IDENTITY,
+            textures: Some(textures),
+            warnings: bundle.warnings,
+            stats,
+            pose_history: PoseHistory::new(),
+            load_times: Vec::new(),
+            gl_textures: HashMap::new(),
+            filtering: None,
         })
     }
+
+    /// Creates the OpenGL buffers and textures of the whole model.
+    ///
+    /// Has to be called on the thread with the OpenGL context. Does nothing if the model
+    /// was already uploaded.
+    ///
+    /// If the upload fails, the objects that were already created are deleted with the model.
+    pub fn upload_to_gpu(&mut self) -> Result<()> {
+        let mut textures = match self.textures.take() {
+            Some(textures) => textures,
+            None => return Ok(()),
+        };
+
+        let start = Instant::now();
+        self.root.upload_to_gpu(&mut textures)?;
+        self.load_times.push((LoadStage::Upload, start.elapsed()));
+
+        // The limit depends on the context, so it can't be checked while loading
+        let max_joints = ogl::Capabilities::query().max_joints();
+        if let Some(warning) = self
+            .root
+            .joints()
+            .and_then(|joints| joints.joint_limit_warning(max_joints))
+        {
+            self.warnings.push(warning);
+        }
+
+        self.gl_textures = std::mem::take(&mut textures.gl_textures);
+
+        Ok(())
+    }
+
+    /// Changes the filtering of all textures of the model (without recreating them).
+    ///
+    /// Does nothing if the filtering is already set or the model isn't uploaded yet.
+    /// 'anisotropy' is None if anisotropic filtering isn't supported.
+    pub fn set_texture_filtering(&mut self, filtering: TextureFiltering, anisotropy: Option<f32>) {
+        if self.textures.is_some() || self.filtering == Some((filtering, anisotropy)) {
+            return;
+        }
+
+        for (source, texture) in &self.gl_textures {
+            source.apply_filtering(texture, filtering, anisotropy);
+        }
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        self.filtering = Some((filtering, anisotropy));
+    }
+
+    /// Reverts the last joint edit made in the GUI.
+    ///
+    /// The animation is stopped, so it doesn't overwrite the restored pose.
+    pub fn undo_pose_edit(&mut self) {
+        if let Some(joints) = self.root.joints_mut() {
+            if self.pose_history.undo(&mut joints.joints) {
+                self.animations.animation_control = AnimationControl::Static;
+            }
+        }
+    }
+
+    /// Applies the last undone joint edit again
+    pub fn redo_pose_edit(&mut self) {
+        if let Some(joints) = self.root.joints_mut() {
+            if self.pose_history.redo(&mut joints.joints) {
+                self.animations.animation_control = AnimationControl::Static;
+            }
+        }
+    }
+
+    /// Bounding box of the model (in the bind pose) in world space
+    pub fn bounds(&self) -> Aabb {
+        self.root.bounds(self.transform)
+    }
+}
+
+/// Measures the durations of the loading stages
+struct StageTimer<'a> {
+    on_stage: &'a mut dyn FnMut(LoadStage) -> Result<()>,
+    /// The running stage and its start
+    current: Option<(LoadStage, Instant)>,
+    times: Vec<(LoadStage, Duration)>,
+}
+
+impl<'a> StageTimer<'a> {
+    fn new(on_stage: &'a mut dyn FnMut(LoadStage) -> Result<()>) -> Self {
+        Self {
+            on_stage,
+            current: None,
+            times: Vec::new(),
+        }
+    }
+
+    /// Ends the running stage and starts the next one
+    fn start(&mut self, stage: LoadStage) -> Result<()> {
+        self.end_current();
+        (self.on_stage)(stage)?;
+        self.current = Some((stage, Instant::now()));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<(LoadStage, Duration)> {
+        self.end_current();
+        self.times
+    }
+
+    fn end_current(&mut self) {
+        if let Some((stage, start)) = self.current.take() {
+            self.times.push((stage, start.elapsed()));
+        }
+    }
 }
 
 /// A Node represents a subset of a gltf scene
@@ -103,6 +393,8 @@ pub struct Node {
     pub children: Vec<Node>,
     /// Optional mesh data of the node (can contain multiple primitives)
     pub mesh: Option<Mesh>,
+    /// Optional coarser versions of the mesh, selected by the size on the screen
+    pub lods: Option<Lods>,
     /// Transform of the node on the hierarchy
     pub transform: Mat4,
     /// Optional skeleton data this node is root of
@@ -127,11 +419,24 @@ impl Node {
             children.push(node);
         }
 
-        let mesh = match node.mesh() {
-            Some(m) => Some(Mesh::from_gltf(&m, bundle)?),
+        // Skinned vertices are validated against the joints of the skin
+        let joint_count = node.skin().map(|skin| skin.joints().count());
+
+        let mut mesh = match node.mesh() {
+            Some(m) => Some(Mesh::from_gltf(&m, bundle, joint_count)?),
             None => None,
         };
 
+        if let (Some(mesh), Some(instances)) = (&mut mesh, bundle.instances.remove(&node.index())) {
+            if node.skin().is_some() {
+                bundle.warn(format!(
+                    "Node '{name}': instancing of skinned meshes isn't supported, only one instance is drawn"
+                ))?;
+            } else {
+                mesh.set_instances(instances);
+            }
+        }
+
         let transform = match node.transform() {
             GTransform::Matrix { matrix } => Mat4::from_cols_array_2d(&matrix),
             GTransform::Decomposed {
@@ -160,9 +465,181 @@ impl Node {
             index: node.index(),
             children,
             mesh,
+            lods: None,
             transform,
             name,
             joints,
         })
     }
+
+    /// Recursive - uploads the meshes of this node and its children to the GPU
+    fn upload_to_gpu(&mut self, textures: &mut TextureBundle) -> Result<()> {
+        if let Some(mesh) = &mut self.mesh {
+            mesh.upload_to_gpu(textures)?;
+        }
+
+        if let Some(lods) = &mut self.lods {
+            for mesh in &mut lods.meshes {
+                mesh.upload_to_gpu(textures)?;
+            }
+        }
+
+        for child in &mut self.children {
+            child.upload_to_gpu(textures)?;
+        }
+
+        Ok(())
+    }
+
+    /// Recursive - the first skin in this subtree (only a single skin per model is supported)
+    pub fn joints(&self) -> Option<&Joints> {
+        if self.joints.is_some() {
+            return self.joints.as_ref();
+        }
+
+        self.children.iter().find_map(|child| child.joints())
+    }
+
+    /// Recursive - the first skin in this subtree (only a single skin per model is supported)
+    pub fn joints_mut(&mut self) -> Option<&mut Joints> {
+        if self.joints.is_some() {
+            return self.joints.as_mut();
+        }
+
+        self.children
+            .iter_mut()
+            .find_map(|child| child.joints_mut())
+    }
+
+    /// Recursive - the transform the joints of the first skin in this subtree are relative to
+    pub fn skin_transform(&self, outer_transform: Mat4) -> Option<Mat4> {
+        let transform = outer_transform * self.transform;
+        if self.joints.is_some() {
+            return Some(transform);
+        }
+
+        self.children
+            .iter()
+            .find_map(|child| child.skin_transform(transform))
+    }
+
+    /// Recursive - finds the node with the gltf index in this subtree
+    pub fn find(&self, index: usize) -> Option<&Node> {
+        if self.index == index {
+            return Some(self);
+        }
+
+        self.children.iter().find_map(|child| child.find(index))
+    }
+
+    /// Recursive - names and levels of detail of the nodes in this subtree that have them
+    pub fn lod_nodes(&self) -> Vec<(&str, &Lods)> {
+        let own = self.lods.as_ref().map(|lods| (self.name.as_str(), lods));
+
+        own.into_iter()
+            .chain(self.children.iter().flat_map(|child| child.lod_nodes()))
+            .collect()
+    }
+
+    /// Recursive - statistics of the meshes and skins of this node and its children
+    pub fn stats(&self) -> Statistics {
+        let mut stats = Statistics::default();
+
+        if let Some(mesh) = &self.mesh {
+            stats += mesh.stats;
+        }
+
+        if let Some(joints) = &self.joints {
+            stats.joints += joints.joints.len();
+        }
+
+        for child in &self.children {
+            stats += child.stats();
+        }
+
+        stats
+    }
+
+    /// Recursive - bounding box of the meshes of this node and its children.
+    ///
+    /// 'outer_transform' is the transform of the parent node.
+    pub fn bounds(&self, outer_transform: Mat4) -> Aabb {
+        let transform = outer_transform * self.transform;
+
+        let own = self
+            .mesh
+            .as_ref()
+            .map(|mesh| mesh.bounds.transform(&transform))
+            .unwrap_or(Aabb::EMPTY);
+
+        self.children
+            .iter()
+            .fold(own, |aabb, child| aabb.union(child.bounds(transform)))
+    }
+}
+
+#[cfg(test)]
+impl Model {
+    /// A model of the nodes and the animations without any textures (doesn't call OpenGL)
+    pub(crate) fn from_nodes(root: Node, animations: Vec<Animation>) -> Self {
+        Self {
+            stats: root.stats(),
+            root,
+            name: "test".to_string(),
+            path: String::new(),
+            animations: Animations {
+                animations,
+                animation_control: AnimationControl::Controllable {
+                    active_animation: 0,
+                },
+                sync_group: None,
+            },
+            transform: Mat4::IDENTITY,
+            textures: None,
+            warnings: Vec::new(),
+            pose_history: PoseHistory::new(),
+            load_times: Vec::new(),
+            gl_textures: HashMap::new(),
+            filtering: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::mesh::PrimSkin;
+
+    /// Reports the problems of the skin like the mesh loading does
+    fn load_skin(bundle: &mut DataBundle) -> Result<()> {
+        let mut skin = PrimSkin::new(vec![[0, 1, 0, 0]], vec![[1., -1., 0., 0.]]);
+        for problem in skin.sanitize(2) {
+            bundle.warn(format!("Mesh 'test', primitive 0: {problem}"))?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn skin_problems_are_warnings() {
+        let mut bundle = DataBundle::new(Vec::new(), false);
+
+        assert!(load_skin(&mut bundle).is_ok());
+        assert_eq!(
+            bundle.warnings,
+            vec!["Mesh 'test', primitive 0: 1 vertices had negative or NaN weights (clamped to 0)"]
+        );
+    }
+
+    #[test]
+    fn skin_problems_are_errors_in_strict_mode() {
+        let mut bundle = DataBundle::new(Vec::new(), true);
+
+        let error = load_skin(&mut bundle).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Mesh 'test', primitive 0: 1 vertices had negative or NaN weights (clamped to 0)"
+        );
+        assert!(bundle.warnings.is_empty());
+    }
 }
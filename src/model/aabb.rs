@@ -0,0 +1,81 @@
+use glam::{const_vec3, Mat4, Vec3};
+
+/// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// An "inverted" box that contains nothing, useful as a starting value for unions
+    pub const EMPTY: Aabb = Aabb {
+        min: const_vec3!([f32::MAX; 3]),
+        max: const_vec3!([f32::MIN; 3]),
+    };
+
+    /// Creates the smallest box containing all of the points
+    pub fn from_points(points: &[Vec3]) -> Self {
+        points.iter().fold(Self::EMPTY, |aabb, p| aabb.extend(*p))
+    }
+
+    /// Returns true if the box doesn't contain any point
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// Grows the box so it contains the point
+    pub fn extend(self, point: Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    /// The smallest box containing both boxes
+    pub fn union(self, other: Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Grows the box by 'amount' in every direction
+    pub fn inflate(self, amount: f32) -> Self {
+        Self {
+            min: self.min - Vec3::splat(amount),
+            max: self.max + Vec3::splat(amount),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Radius of the bounding sphere centered at `center()`
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).length() * 0.5
+    }
+
+    /// Transforms all 8 corners of the box and returns a new box containing them
+    pub fn transform(&self, mat: &Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let corners = [
+            Vec3::new(self.min.x, self.min.y, self.min.z),
+            Vec3::new(self.min.x, self.min.y, self.max.z),
+            Vec3::new(self.min.x, self.max.y, self.min.z),
+            Vec3::new(self.min.x, self.max.y, self.max.z),
+            Vec3::new(self.max.x, self.min.y, self.min.z),
+            Vec3::new(self.max.x, self.min.y, self.max.z),
+            Vec3::new(self.max.x, self.max.y, self.min.z),
+            Vec3::new(self.max.x, self.max.y, self.max.z),
+        ];
+
+        corners
+            .iter()
+            .fold(Self::EMPTY, |aabb, c| aabb.extend(mat.transform_point3(*c)))
+    }
+}
@@ -1,29 +1,232 @@
-use std::time::Instant;
+use std::collections::HashSet;
 
 use eyre::{eyre, Result};
-use glam::{Quat, Vec3};
+use glam::{Mat4, Quat, Vec3};
 use gltf::animation::{
     util::{ReadOutputs, Rotations},
     Interpolation,
 };
 
-use super::DataBundle;
+use super::{DataBundle, Transform};
+
+/// Keyframes closer than this (in seconds) are considered to be at the same time
+const KEYFRAME_EPSILON: f32 = 0.001;
 
 /// Contains animation data and also the current state of the animation
 pub struct Animations {
     pub animations: Vec<Animation>,
     pub animation_control: AnimationControl,
+    /// Models in the same sync group play the active animation of the selected model
+    /// on a single clock (see `follow`)
+    pub sync_group: Option<usize>,
+}
+
+impl Animations {
+    /// Returns the index of the animation that is currently applied (if any)
+    pub fn active_animation(&self) -> Option<usize> {
+        match self.animation_control {
+            AnimationControl::Loop { active_animation }
+            | AnimationControl::Controllable { active_animation } => Some(active_animation),
+            AnimationControl::Static => None,
+        }
+    }
+
+    /// Pauses the playing animation or plays the paused one (the first one if none is active)
+    pub fn toggle_playing(&mut self) {
+        self.animation_control = match self.animation_control {
+            AnimationControl::Loop { active_animation } => {
+                AnimationControl::Controllable { active_animation }
+            }
+            AnimationControl::Controllable { active_animation } => {
+                AnimationControl::Loop { active_animation }
+            }
+            AnimationControl::Static if !self.animations.is_empty() => AnimationControl::Loop {
+                active_animation: 0,
+            },
+            AnimationControl::Static => AnimationControl::Static,
+        };
+    }
+
+    /// Activates the animation 'offset' places after the active one (wrapping around),
+    /// the animation keeps playing or stays paused
+    pub fn select_relative(&mut self, offset: isize) {
+        let count = self.animations.len() as isize;
+        if count == 0 {
+            return;
+        }
+
+        let active_animation = match self.active_animation() {
+            Some(active) => (active as isize + offset).rem_euclid(count) as usize,
+            None => 0,
+        };
+
+        self.animation_control = match self.animation_control {
+            AnimationControl::Controllable { .. } => {
+                AnimationControl::Controllable { active_animation }
+            }
+            AnimationControl::Loop { .. } | AnimationControl::Static => {
+                AnimationControl::Loop { active_animation }
+            }
+        };
+    }
+
+    /// Index of the animation matching the active animation of 'leader'.
+    ///
+    /// Animations are matched by name, unnamed animations and animations without a namesake
+    /// are matched by their index.
+    pub fn matching_animation(&self, leader: &Animations) -> Option<usize> {
+        let index = leader.active_animation()?;
+
+        let by_name = leader.animations[index].name.as_ref().and_then(|name| {
+            self.animations
+                .iter()
+                .position(|a| a.name.as_ref() == Some(name))
+        });
+
+        by_name.or_else(|| (index < self.animations.len()).then_some(index))
+    }
+
+    /// Plays the animation matching the active one of 'leader' at the same time,
+    /// the animation is paused if the leader's one is paused
+    pub fn follow(&mut self, leader: &Animations) {
+        let (leader_index, index) =
+            match (leader.active_animation(), self.matching_animation(leader)) {
+                (Some(leader_index), Some(index)) => (leader_index, index),
+                _ => {
+                    self.animation_control = AnimationControl::Static;
+                    return;
+                }
+            };
+
+        let time = leader.animations[leader_index].current_time;
+        let anim = &mut self.animations[index];
+        anim.current_time = if anim.end_time > 0. {
+            time % anim.end_time
+        } else {
+            0.
+        };
+
+        self.animation_control = match leader.animation_control {
+            AnimationControl::Loop { .. } => AnimationControl::Loop {
+                active_animation: index,
+            },
+            _ => AnimationControl::Controllable {
+                active_animation: index,
+            },
+        };
+    }
+
+    /// Continues the active animation of 'other' (eg. the previous version of a reloaded model).
+    ///
+    /// Animations are matched by name, unnamed or missing animations are not restored.
+    pub fn continue_from(&mut self, other: &Animations) {
+        let old = match other.active_animation() {
+            Some(i) => &other.animations[i],
+            None => return,
+        };
+
+        let index = match &old.name {
+            Some(name) => self
+                .animations
+                .iter()
+                .position(|a| a.name.as_ref() == Some(name)),
+            None => None,
+        };
+
+        if let Some(i) = index {
+            let anim = &mut self.animations[i];
+            anim.current_time = old.current_time.min(anim.end_time);
+
+            self.animation_control = match other.animation_control {
+                AnimationControl::Loop { .. } => AnimationControl::Loop {
+                    active_animation: i,
+                },
+                _ => AnimationControl::Controllable {
+                    active_animation: i,
+                },
+            };
+        }
+    }
+
+    /// Moves the animations created in the GUI from 'other' (eg. the previous version of a reloaded model).
+    ///
+    /// If one of them is active, it stays active (this overrides `continue_from`).
+    pub fn keep_authored(&mut self, other: &mut Animations) {
+        let active = other.active_animation();
+
+        for (i, anim) in other.animations.drain(..).enumerate() {
+            if !anim.authored {
+                continue;
+            }
+
+            if active == Some(i) {
+                let active_animation = self.animations.len();
+                self.animation_control = match other.animation_control {
+                    AnimationControl::Loop { .. } => AnimationControl::Loop { active_animation },
+                    _ => AnimationControl::Controllable { active_animation },
+                };
+            }
+
+            self.animations.push(anim);
+        }
+    }
+
+    /// Copies the joint masks of the animations with the same names from 'other'
+    pub fn keep_masks(&mut self, other: &Animations) {
+        for old in &other.animations {
+            if old.mask.is_none() || old.name.is_none() {
+                continue;
+            }
+
+            if let Some(anim) = self.animations.iter_mut().find(|a| a.name == old.name) {
+                anim.mask.clone_from(&old.mask);
+            }
+        }
+    }
+
+    /// Advances the looping animation by 'dt' seconds.
+    ///
+    /// Time is stepped explicitly (not read from the clock), so the animation can also be
+    /// played back with a fixed timestep.
+    pub fn advance(&mut self, dt: f32) {
+        if let AnimationControl::Loop { active_animation } = self.animation_control {
+            let anim = &mut self.animations[active_animation];
+
+            anim.current_time += dt;
+            if anim.end_time > 0. {
+                anim.current_time %= anim.end_time;
+            } else {
+                anim.current_time = 0.;
+            }
+        }
+    }
+}
+
+/// How far the looping animations advance in a rendered frame
+#[derive(Clone, Copy, PartialEq)]
+pub enum TimeSource {
+    /// The real duration of the frame
+    WallClock,
+    /// A fixed step in seconds regardless of the real time, so the played frames can be
+    /// reproduced exactly
+    FixedStep(f32),
+}
+
+impl TimeSource {
+    /// Time the animations advance by in a frame that took 'frame_time' seconds
+    pub fn step(self, frame_time: f32) -> f32 {
+        match self {
+            TimeSource::WallClock => frame_time,
+            TimeSource::FixedStep(step) => step,
+        }
+    }
 }
 
 /// The type of current animation
+#[derive(Clone, Copy)]
 pub enum AnimationControl {
-    Loop {
-        active_animation: usize,
-        start_time: Instant,
-    },
-    Controllable {
-        active_animation: usize,
-    },
+    Loop { active_animation: usize },
+    Controllable { active_animation: usize },
     Static,
 }
 
@@ -36,6 +239,11 @@ pub struct Animation {
     pub end_time: f32,
     /// Optional name of the animation
     pub name: Option<String>,
+    /// If the animation was created in the GUI (it's saved with the pose presets)
+    pub authored: bool,
+    /// Indices of the nodes the animation is allowed to affect (None affects all of them)
+    pub mask: Option<HashSet<usize>>,
+    pub root_motion: RootMotion,
 }
 
 impl Animation {
@@ -50,9 +258,119 @@ impl Animation {
             current_time,
             end_time,
             name,
+            authored: false,
+            mask: None,
+            root_motion: RootMotion::default(),
         }
     }
 
+    /// Creates an empty animation for recording keyframes in the GUI
+    pub fn new_authored(name: String) -> Self {
+        Self {
+            authored: true,
+            ..Self::new(Vec::new(), 0., 0., Some(name))
+        }
+    }
+
+    /// Extracts the motion of the root joint from its sampled 'translation'.
+    ///
+    /// The translation relative to the first keyframe is removed when playing in place
+    /// (except for the vertical part if it should be kept).
+    /// 'to_world' transforms from the space of the root joint's parent to world space.
+    pub fn extract_root_motion(&mut self, root: usize, translation: &mut Vec3, to_world: Mat4) {
+        let (first, last) = match self.channels.iter().find_map(|c| match &c.transforms {
+            AnimationTransforms::Translations(t) if c.node == root && !t.is_empty() => {
+                Some((t[0], t[t.len() - 1]))
+            }
+            _ => None,
+        }) {
+            Some(range) => range,
+            None => return,
+        };
+
+        let motion = &mut self.root_motion;
+
+        let mut offset = *translation - first;
+        // Offset at the end of the animation, for the delta of a frame where the animation looped
+        let mut end_offset = last - first;
+
+        if motion.keep_vertical {
+            let up = to_world
+                .inverse()
+                .transform_vector3(Vec3::Y)
+                .normalize_or_zero();
+            offset -= up * offset.dot(up);
+            end_offset -= up * end_offset.dot(up);
+        }
+
+        let delta = if self.current_time < motion.last_time {
+            (end_offset - motion.offset) + offset
+        } else {
+            offset - motion.offset
+        };
+
+        motion.delta = to_world.transform_vector3(delta);
+        motion.offset = offset;
+        motion.last_time = self.current_time;
+
+        if motion.in_place {
+            *translation -= offset;
+        }
+    }
+
+    /// The transforms of the animated nodes at 'time' as (node index, transform),
+    /// nodes outside of the mask aren't animated
+    pub fn sample(&self, time: f32) -> impl Iterator<Item = (usize, AnimationTransform)> + '_ {
+        self.channels
+            .iter()
+            .filter(|channel| self.affects(channel.node))
+            .filter_map(move |channel| channel.sample(time).map(|t| (channel.node, t)))
+    }
+
+    /// If the animation is allowed to affect the node
+    pub fn affects(&self, node: usize) -> bool {
+        match &self.mask {
+            Some(mask) => mask.contains(&node),
+            None => true,
+        }
+    }
+
+    /// Records the transform of the node as a keyframe at 'time' (a keyframe at the same time is replaced).
+    ///
+    /// The channels of the node are created when it gets its first keyframe.
+    pub fn insert_keyframe(&mut self, node: usize, time: f32, transform: &Transform) {
+        let keys = [
+            AnimationTransform::Translation(transform.translation),
+            AnimationTransform::Rotation(transform.rotation),
+            AnimationTransform::Scale(transform.scale),
+        ];
+
+        for key in keys {
+            let index = self
+                .channels
+                .iter()
+                .position(|c| c.node == node && c.transforms.accepts(&key));
+
+            let channel = match index {
+                Some(i) => &mut self.channels[i],
+                None => {
+                    let transforms = AnimationTransforms::empty_for(&key);
+                    self.channels.push(Channel::new(
+                        node,
+                        Vec::new(),
+                        transforms,
+                        Interpolation::Linear,
+                    ));
+                    self.channels.last_mut().unwrap()
+                }
+            };
+
+            channel.insert_key(time, key);
+        }
+
+        self.end_time = self.end_time.max(time);
+    }
+
     /// Creates the animation from a gltf::Document struct and the DataBundle
     pub fn from_gltf(gltf: &gltf::Document, bundle: &DataBundle) -> Result<Animations> {
         let mut animations = Vec::new();
@@ -107,6 +425,7 @@ impl Animation {
         Ok(Animations {
             animations,
             animation_control: AnimationControl::Static,
+            sync_group: None,
         })
     }
 
@@ -135,6 +454,21 @@ impl Animation {
     }
 }
 
+/// Motion of the root joint (the joint of the skin without a parent) in an animation
+#[derive(Clone, Copy, Default)]
+pub struct RootMotion {
+    /// If the root joint should stay in place (eg. for walk cycles that move forward)
+    pub in_place: bool,
+    /// If the vertical motion should be kept when playing in place
+    pub keep_vertical: bool,
+    /// Root motion since the previous frame in world space (could move the model instead)
+    pub delta: Vec3,
+    /// Root translation relative to the first keyframe in the previous frame
+    offset: Vec3,
+    /// Animation time of the previous frame, for detecting when the animation looped
+    last_time: f32,
+}
+
 /// One channel (sometimes called track) of the animation
 /// == keyframe times and their respective transforms applied to a single node
 pub struct Channel {
@@ -163,6 +497,66 @@ impl Channel {
         }
     }
 
+    /// Inserts the keyframe, keeping the keyframe times sorted.
+    /// A keyframe at the same time is replaced.
+    pub fn insert_key(&mut self, time: f32, key: AnimationTransform) {
+        let i = self
+            .keyframe_times
+            .partition_point(|t| *t < time - KEYFRAME_EPSILON);
+        let replace = self
+            .keyframe_times
+            .get(i)
+            .is_some_and(|t| (*t - time).abs() < KEYFRAME_EPSILON);
+
+        match (&mut self.transforms, key) {
+            (AnimationTransforms::Translations(values), AnimationTransform::Translation(value))
+            | (AnimationTransforms::Scales(values), AnimationTransform::Scale(value)) => {
+                if replace {
+                    values[i] = value;
+                } else {
+                    values.insert(i, value);
+                }
+            }
+            (AnimationTransforms::Rotations(values), AnimationTransform::Rotation(value)) => {
+                if replace {
+                    values[i] = value;
+                } else {
+                    values.insert(i, value);
+                }
+            }
+            // The key is for a different property
+            _ => return,
+        }
+
+        if !replace {
+            self.keyframe_times.insert(i, time);
+        }
+    }
+
+    /// The transform of the channel at 'time', the first / last keyframe is held
+    /// before / after the keyframes
+    pub fn sample(&self, time: f32) -> Option<AnimationTransform> {
+        let keyframe_times = &self.keyframe_times;
+
+        for i in 0..keyframe_times.len() {
+            let start_time = keyframe_times[i];
+
+            // If the time is before the start time of this specific channel, take the first transform.
+            if (i == keyframe_times.len() - 1) || (i == 0 && time < start_time) {
+                return Some(self.get_fixed_transform(i));
+            }
+
+            let end_time = keyframe_times[i + 1];
+
+            if start_time <= time && end_time > time {
+                let coeff = (time - start_time) / (end_time - start_time);
+                return Some(self.interpolate_transforms(i, coeff));
+            }
+        }
+
+        None
+    }
+
     /// Get a transform at a specific index of (keyframe_times - transforms)
     pub fn get_fixed_transform(&self, index: usize) -> AnimationTransform {
         match self.interpolation_type {
@@ -234,9 +628,148 @@ pub enum AnimationTransforms {
     Scales(Vec<Vec3>),
 }
 
+impl AnimationTransforms {
+    /// An empty list of the same property as 'key'
+    fn empty_for(key: &AnimationTransform) -> Self {
+        match key {
+            AnimationTransform::Translation(_) => AnimationTransforms::Translations(Vec::new()),
+            AnimationTransform::Rotation(_) => AnimationTransforms::Rotations(Vec::new()),
+            AnimationTransform::Scale(_) => AnimationTransforms::Scales(Vec::new()),
+        }
+    }
+
+    /// If 'key' is of the same property as these transforms
+    fn accepts(&self, key: &AnimationTransform) -> bool {
+        matches!(
+            (self, key),
+            (
+                AnimationTransforms::Translations(_),
+                AnimationTransform::Translation(_)
+            ) | (
+                AnimationTransforms::Rotations(_),
+                AnimationTransform::Rotation(_)
+            ) | (AnimationTransforms::Scales(_), AnimationTransform::Scale(_))
+        )
+    }
+}
+
 /// The type of a single animation transform
 pub enum AnimationTransform {
     Translation(Vec3),
     Rotation(Quat),
     Scale(Vec3),
 }
+
+impl AnimationTransform {
+    /// Overwrites the property of the transform
+    pub fn apply_to(&self, transform: &mut Transform) {
+        match *self {
+            AnimationTransform::Translation(translation) => transform.translation = translation,
+            AnimationTransform::Rotation(rotation) => transform.rotation = rotation,
+            AnimationTransform::Scale(scale) => transform.scale = scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Mat4;
+
+    use super::*;
+    use crate::model::{pose, Joint};
+
+    /// A two-joint chain, the child rotates around Z over 1 second
+    fn scene() -> (Vec<Joint>, Animations) {
+        let transform = Transform {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        };
+        let child_transform = Transform {
+            translation: Vec3::X,
+            ..transform
+        };
+
+        let joints = vec![
+            Joint::new(0, None, Mat4::IDENTITY, transform, "root".into()),
+            Joint::new(
+                1,
+                Some(0),
+                Mat4::from_translation(-Vec3::X),
+                child_transform,
+                "child".into(),
+            ),
+        ];
+
+        let channel = Channel::new(
+            1,
+            vec![0., 0.5, 1.],
+            AnimationTransforms::Rotations(vec![
+                Quat::IDENTITY,
+                Quat::from_rotation_z(1.),
+                Quat::from_rotation_z(2.),
+            ]),
+            Interpolation::Linear,
+        );
+
+        let animations = Animations {
+            animations: vec![Animation::new(vec![channel], 0., 1., None)],
+            animation_control: AnimationControl::Loop {
+                active_animation: 0,
+            },
+            sync_group: None,
+        };
+
+        (joints, animations)
+    }
+
+    /// Plays the animation for the frame times and returns the bits of the skin matrices
+    /// of every frame
+    fn play(time_source: TimeSource, frame_times: &[f32]) -> Vec<Vec<u32>> {
+        let (mut joints, mut animations) = scene();
+
+        frame_times
+            .iter()
+            .map(|&frame_time| {
+                animations.advance(time_source.step(frame_time));
+
+                let anim = &animations.animations[0];
+                let sampled = pose::sample_animation(anim, anim.current_time);
+                pose::apply_animation(&mut joints, &sampled);
+
+                let world = pose::compute_world_transforms(&joints, Mat4::IDENTITY);
+                pose::compute_skin_matrices(&joints, &world)
+                    .iter()
+                    .flat_map(|m| m.to_cols_array())
+                    .map(f32::to_bits)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_step_ignores_frame_time() {
+        let source = TimeSource::FixedStep(1. / 60.);
+        assert_eq!(source.step(0.1), 1. / 60.);
+        assert_eq!(TimeSource::WallClock.step(0.1), 0.1);
+    }
+
+    #[test]
+    fn fixed_step_runs_are_bit_identical() {
+        // Different real frame times in each run (past the loop wrap)
+        let steady = vec![1. / 60.; 150];
+        let jittery: Vec<f32> = (0..150).map(|i| 0.005 + (i % 7) as f32 * 0.01).collect();
+
+        let source = TimeSource::FixedStep(1. / 60.);
+        let first = play(source, &steady);
+        let second = play(source, &jittery);
+
+        assert_eq!(first, second);
+
+        // The wall clock follows the real frame times
+        assert_ne!(
+            play(TimeSource::WallClock, &steady),
+            play(TimeSource::WallClock, &jittery)
+        );
+    }
+}
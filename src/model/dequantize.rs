@@ -0,0 +1,60 @@
+use eyre::{eyre, Result};
+use gltf::{
+    accessor::{DataType, Item, Iter},
+    Accessor,
+};
+
+/// Reads a vertex attribute with N components as floats.
+///
+/// Besides floats, the attributes can be quantized (KHR_mesh_quantization) - stored as
+/// (normalized) bytes or shorts. Normalized values are mapped to [0, 1] / [-1, 1],
+/// the other ones are converted as they are (the node transform scales them).
+pub(super) fn read_floats<const N: usize>(
+    accessor: &Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Vec<[f32; N]>>
+where
+    [f32; N]: Item,
+    [i8; N]: Item,
+    [u8; N]: Item,
+    [i16; N]: Item,
+    [u16; N]: Item,
+    [u32; N]: Item,
+{
+    let components = accessor.dimensions().multiplicity();
+    if components != N {
+        return Err(eyre!(
+            "accessor {} has {components} components instead of {N}",
+            accessor.index()
+        ));
+    }
+
+    match (accessor.data_type(), accessor.normalized()) {
+        (DataType::F32, _) => read(accessor, buffers, |v: f32| v),
+        (DataType::I8, true) => read(accessor, buffers, |v: i8| (v as f32 / 127.).max(-1.)),
+        (DataType::U8, true) => read(accessor, buffers, |v: u8| v as f32 / 255.),
+        (DataType::I16, true) => read(accessor, buffers, |v: i16| (v as f32 / 32767.).max(-1.)),
+        (DataType::U16, true) => read(accessor, buffers, |v: u16| v as f32 / 65535.),
+        (DataType::I8, false) => read(accessor, buffers, |v: i8| v as f32),
+        (DataType::U8, false) => read(accessor, buffers, |v: u8| v as f32),
+        (DataType::I16, false) => read(accessor, buffers, |v: i16| v as f32),
+        (DataType::U16, false) => read(accessor, buffers, |v: u16| v as f32),
+        (DataType::U32, _) => read(accessor, buffers, |v: u32| v as f32),
+    }
+}
+
+fn read<T: Copy, const N: usize>(
+    accessor: &Accessor,
+    buffers: &[gltf::buffer::Data],
+    convert: impl Fn(T) -> f32,
+) -> Result<Vec<[f32; N]>>
+where
+    [T; N]: Item,
+{
+    let iter = Iter::<[T; N]>::new(accessor.clone(), |buffer| {
+        buffers.get(buffer.index()).map(|data| &data[..])
+    })
+    .ok_or_else(|| eyre!("accessor {} doesn't have any data", accessor.index()))?;
+
+    Ok(iter.map(|value| value.map(&convert)).collect())
+}
@@ -0,0 +1,18 @@
+use std::{borrow::Cow, fs};
+
+use eyre::Result;
+use serde_json::Value;
+
+/// Parses the JSON of a gltf or glb file.
+///
+/// Used for the extensions the gltf crate doesn't support.
+pub(super) fn read_json(path: &str) -> Result<Value> {
+    let data = fs::read(path)?;
+    let json: Cow<[u8]> = if data.starts_with(b"glTF") {
+        gltf::Glb::from_slice(&data)?.json
+    } else {
+        Cow::Borrowed(&data)
+    };
+
+    Ok(serde_json::from_slice(&json)?)
+}
@@ -0,0 +1,129 @@
+use glam::{Mat4, Quat, Vec3};
+
+use super::{pose, Joint};
+
+/// Chains shorter than this can't be solved
+const MIN_LENGTH: f32 = 1e-5;
+
+/// An analytic two-bone IK chain (eg. shoulder - elbow - hand).
+///
+/// The end joint is moved to the target by rotating its parent (mid) and grandparent (root),
+/// the mid joint bends towards the pole. Only the local rotations of the joints are changed.
+pub struct TwoBoneIk {
+    /// Index of the end joint
+    pub end: usize,
+    /// World-space position the end joint should reach
+    pub target: Vec3,
+    /// World-space position the mid joint bends towards
+    pub pole: Vec3,
+}
+
+impl TwoBoneIk {
+    /// Creates the chain with the target at the end joint and the pole at the mid joint
+    /// (so the pose doesn't change until the target is moved).
+    ///
+    /// Returns None if the joint doesn't have two ancestors.
+    /// 'outer_transform' is the transform the joints are relative to.
+    pub fn new(joints: &[Joint], end: usize, outer_transform: Mat4) -> Option<Self> {
+        let (_, mid) = Self::chain(joints, end)?;
+        let world = pose::compute_world_transforms(joints, outer_transform);
+
+        Some(Self {
+            end,
+            target: world[end].w_axis.truncate(),
+            pole: world[mid].w_axis.truncate(),
+        })
+    }
+
+    /// Indices of the root and the mid joint of the chain
+    pub fn chain(joints: &[Joint], end: usize) -> Option<(usize, usize)> {
+        let mid = joints.get(end)?.parent?;
+        let root = joints[mid].parent?;
+        Some((root, mid))
+    }
+
+    /// Rotates the root and mid joint so the end joint reaches the target.
+    ///
+    /// An unreachable target is clamped to the maximum extension of the chain.
+    pub fn solve(&self, joints: &mut [Joint], outer_transform: Mat4) {
+        let (root, mid) = match Self::chain(joints, self.end) {
+            Some(chain) => chain,
+            None => return,
+        };
+
+        let world = pose::compute_world_transforms(joints, outer_transform);
+        let root_pos = world[root].w_axis.truncate();
+        let mid_pos = world[mid].w_axis.truncate();
+        let end_pos = world[self.end].w_axis.truncate();
+
+        let upper = mid_pos.distance(root_pos);
+        let lower = end_pos.distance(mid_pos);
+        let to_target = self.target - root_pos;
+
+        if upper < MIN_LENGTH || lower < MIN_LENGTH || to_target.length() < MIN_LENGTH {
+            return;
+        }
+
+        let dir = to_target.normalize();
+        let distance = to_target
+            .length()
+            .clamp((upper - lower).abs() + MIN_LENGTH, upper + lower);
+
+        // The elbow bends in the plane of the target and the pole
+        let to_pole = self.pole - root_pos;
+        let mut bend = (to_pole - dir * to_pole.dot(dir)).normalize_or_zero();
+        if bend == Vec3::ZERO {
+            // The pole is on the line to the target, keep the current bend direction
+            let to_mid = mid_pos - root_pos;
+            bend = (to_mid - dir * to_mid.dot(dir)).normalize_or_zero();
+        }
+        if bend == Vec3::ZERO {
+            bend = dir.any_orthonormal_vector();
+        }
+
+        // Law of cosines for the angle at the root
+        let cos_root = ((upper * upper + distance * distance - lower * lower)
+            / (2. * upper * distance))
+            .clamp(-1., 1.);
+        let sin_root = (1. - cos_root * cos_root).max(0.).sqrt();
+
+        let new_mid = root_pos + (dir * cos_root + bend * sin_root) * upper;
+        let new_end = root_pos + dir * distance;
+
+        let root_rotation = Quat::from_rotation_arc(
+            (mid_pos - root_pos).normalize(),
+            (new_mid - root_pos).normalize(),
+        );
+        Self::rotate_joint(joints, root, &world, root_rotation, outer_transform);
+
+        // The mid joint moved with the root, the end has to be rotated from its new position
+        let world = pose::compute_world_transforms(joints, outer_transform);
+        let mid_pos = world[mid].w_axis.truncate();
+        let end_pos = world[self.end].w_axis.truncate();
+
+        let mid_rotation = Quat::from_rotation_arc(
+            (end_pos - mid_pos).normalize(),
+            (new_end - mid_pos).normalize(),
+        );
+        Self::rotate_joint(joints, mid, &world, mid_rotation, outer_transform);
+    }
+
+    /// Applies a world-space rotation to the local rotation of the joint
+    fn rotate_joint(
+        joints: &mut [Joint],
+        index: usize,
+        world: &[Mat4],
+        rotation: Quat,
+        outer_transform: Mat4,
+    ) {
+        let parent = match joints[index].parent {
+            Some(parent) => world[parent],
+            None => outer_transform,
+        };
+
+        let (_, parent_rotation, _) = parent.to_scale_rotation_translation();
+
+        let local = &mut joints[index].transform.rotation;
+        *local = (parent_rotation.inverse() * rotation * parent_rotation * *local).normalize();
+    }
+}
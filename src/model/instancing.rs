@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use eyre::{eyre, Result};
+use glam::{Mat4, Quat, Vec3};
+use gltf::{Accessor, Document};
+use serde_json::Value;
+
+use super::{dequantize, gltf_json, DataBundle};
+
+/// Name of the extension in the gltf file
+const EXTENSION: &str = "EXT_mesh_gpu_instancing";
+
+/// Reads the instance transforms of the EXT_mesh_gpu_instancing extension, keyed by the node index.
+///
+/// The gltf crate doesn't support the extension, so the JSON of the file is parsed again
+/// (only when the file declares the extension).
+pub(super) fn read_instances(
+    path: &str,
+    document: &Document,
+    bundle: &mut DataBundle,
+) -> Result<HashMap<usize, Vec<Mat4>>> {
+    let mut instances = HashMap::new();
+
+    if !document.extensions_used().any(|ext| ext == EXTENSION) {
+        return Ok(instances);
+    }
+
+    let json = gltf_json::read_json(path)?;
+
+    let nodes = match json["nodes"].as_array() {
+        Some(nodes) => nodes,
+        None => return Ok(instances),
+    };
+
+    for (index, node) in nodes.iter().enumerate() {
+        let attributes = &node["extensions"][EXTENSION]["attributes"];
+        if attributes.is_null() {
+            continue;
+        }
+
+        match read_node_instances(attributes, document, bundle) {
+            Ok(transforms) => {
+                instances.insert(index, transforms);
+            }
+            Err(e) => bundle.warn(format!("Node {index}: invalid {EXTENSION} data: {e}"))?,
+        }
+    }
+
+    Ok(instances)
+}
+
+/// Composes the TRANSLATION, ROTATION and SCALE attributes (all of them are optional)
+fn read_node_instances(
+    attributes: &Value,
+    document: &Document,
+    bundle: &DataBundle,
+) -> Result<Vec<Mat4>> {
+    let accessor = |name: &str| -> Result<Option<Accessor>> {
+        match attributes[name].as_u64() {
+            Some(index) => document
+                .accessors()
+                .nth(index as usize)
+                .map(Some)
+                .ok_or_else(|| eyre!("{name} accessor {index} doesn't exist")),
+            None => Ok(None),
+        }
+    };
+
+    // Rotations can be normalized bytes / shorts
+    let translations: Option<Vec<[f32; 3]>> = accessor("TRANSLATION")?
+        .map(|a| dequantize::read_floats(&a, &bundle.buffers))
+        .transpose()?;
+    let rotations: Option<Vec<[f32; 4]>> = accessor("ROTATION")?
+        .map(|a| dequantize::read_floats(&a, &bundle.buffers))
+        .transpose()?;
+    let scales: Option<Vec<[f32; 3]>> = accessor("SCALE")?
+        .map(|a| dequantize::read_floats(&a, &bundle.buffers))
+        .transpose()?;
+
+    let lengths = [
+        translations.as_ref().map(Vec::len),
+        rotations.as_ref().map(Vec::len),
+        scales.as_ref().map(Vec::len),
+    ];
+    let count = match lengths.iter().flatten().min() {
+        Some(&count) => count,
+        None => return Err(eyre!("no instance attributes")),
+    };
+
+    if lengths.iter().flatten().any(|&len| len != count) {
+        return Err(eyre!("the attributes have different counts"));
+    }
+
+    let transforms = (0..count)
+        .map(|i| {
+            let translation = translations.as_ref().map_or(Vec3::ZERO, |t| t[i].into());
+            let rotation = rotations
+                .as_ref()
+                .map_or(Quat::IDENTITY, |r| Quat::from_array(r[i]).normalize());
+            let scale = scales.as_ref().map_or(Vec3::ONE, |s| s[i].into());
+
+            Mat4::from_scale_rotation_translation(scale, rotation, translation)
+        })
+        .collect();
+
+    Ok(transforms)
+}
@@ -1,5 +1,6 @@
 use eyre::Result;
 use glam::Mat4;
+use log::info;
 
 use super::{DataBundle, Transform};
 
@@ -41,9 +42,43 @@ impl Joints {
             &inverse_bind_matrices,
         );
 
+        info!(
+            "Skin '{}' has {} joints",
+            skin.name().unwrap_or("unnamed"),
+            joints.len()
+        );
+
         Ok(Self { joints })
     }
 
+    /// World transforms of the joints in the bind pose, the inverses of the inverse bind matrices.
+    ///
+    /// Unlike `reset`, the current transforms of the joints are kept.
+    pub fn bind_world_transforms(joints: &[Joint], outer_transform: Mat4) -> Vec<Mat4> {
+        joints
+            .iter()
+            .map(|joint| outer_transform * joint.inverse_bind_matrix.inverse())
+            .collect()
+    }
+
+    /// Describes the problem if the skin has more joints than the GPU can animate ('max_joints'),
+    /// the vertices of the extra joints would be deformed by stale matrices
+    pub fn joint_limit_warning(&self, max_joints: usize) -> Option<String> {
+        let count = self.joints.len();
+        (count > max_joints).then(|| {
+            format!(
+                "The skin has {count} joints, but the GPU only supports {max_joints} (the vertices of the other joints aren't animated correctly)"
+            )
+        })
+    }
+
+    /// Restores the transforms of all joints to the ones they were loaded with (the bind pose)
+    pub fn reset(&mut self) {
+        for joint in &mut self.joints {
+            joint.reset();
+        }
+    }
+
     /// Traverse the scene and arrange the joint nodes into a correct hierarchy
     /// <https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#joint-hierarchy>
     /// "A node object does not specify whether it is a joint.
@@ -116,6 +151,8 @@ pub struct Joint {
     pub inverse_bind_matrix: Mat4,
     /// The transform of the joint relative to it's parent
     pub transform: Transform,
+    /// The transform the joint was loaded with (restored by `reset`)
+    pub bind_transform: Transform,
     /// Name for debug purposes
     pub name: String,
 }
@@ -133,7 +170,71 @@ impl Joint {
             parent,
             inverse_bind_matrix,
             transform,
+            bind_transform: transform,
             name,
         }
     }
+
+    /// Restores the transform the joint was loaded with
+    pub fn reset(&mut self) {
+        self.transform = self.bind_transform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+
+    use super::*;
+    use crate::model::pose;
+
+    /// A synthetic skin of 'count' joints in a chain along X, 1 unit apart
+    fn chain(count: usize) -> Joints {
+        let joints = (0..count)
+            .map(|i| {
+                let transform = Transform {
+                    translation: if i == 0 { Vec3::ZERO } else { Vec3::X },
+                    rotation: Quat::IDENTITY,
+                    scale: Vec3::ONE,
+                };
+
+                Joint::new(
+                    i,
+                    i.checked_sub(1),
+                    Mat4::from_translation(-Vec3::X * i as f32),
+                    transform,
+                    format!("Joint-{i}"),
+                )
+            })
+            .collect();
+
+        Joints { joints }
+    }
+
+    #[test]
+    fn skin_with_300_joints() {
+        let skin = chain(300);
+
+        let world = pose::compute_world_transforms(&skin.joints, Mat4::IDENTITY);
+        let skin_matrices = pose::compute_skin_matrices(&skin.joints, &world);
+
+        assert_eq!(skin_matrices.len(), 300);
+        assert_eq!(world[299].w_axis.truncate(), Vec3::new(299., 0., 0.));
+        // The chain is in the bind pose
+        assert!(skin_matrices
+            .iter()
+            .all(|m| m.abs_diff_eq(Mat4::IDENTITY, 1e-4)));
+    }
+
+    #[test]
+    fn skin_over_the_joint_limit_is_reported() {
+        let skin = chain(300);
+
+        assert_eq!(
+            skin.joint_limit_warning(256).as_deref(),
+            Some("The skin has 300 joints, but the GPU only supports 256 (the vertices of the other joints aren't animated correctly)")
+        );
+        assert_eq!(skin.joint_limit_warning(300), None);
+        assert_eq!(skin.joint_limit_warning(1024), None);
+    }
 }
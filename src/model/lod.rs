@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+
+use eyre::Result;
+use gltf::Document;
+
+use super::{gltf_json, DataBundle, Mesh, Node};
+
+/// Name of the extension in the gltf file
+const EXTENSION: &str = "MSFT_lod";
+
+/// The screen size has to cross a threshold by this fraction before the level changes,
+/// so the levels don't flicker when the camera stays near a threshold
+const HYSTERESIS: f32 = 0.1;
+
+/// Screen size thresholds of the levels when the file doesn't specify them,
+/// each level is used at half the size of the previous one
+const DEFAULT_FIRST_THRESHOLD: f32 = 0.5;
+
+/// Coarser versions of a node's mesh and the state of the level selection
+pub struct Lods {
+    /// Meshes of the levels 1, 2, ... (level 0 is the mesh of the node)
+    pub meshes: Vec<Mesh>,
+    /// Level i is drawn while the screen size is at least thresholds[i], the last level
+    /// is drawn at any size
+    thresholds: Vec<f32>,
+    /// The level that was drawn in the last frame
+    pub active: usize,
+}
+
+impl Lods {
+    fn new(meshes: Vec<Mesh>, thresholds: Option<Vec<f32>>) -> Self {
+        let thresholds = match thresholds {
+            Some(thresholds) if thresholds.len() >= meshes.len() => {
+                thresholds[..meshes.len()].to_vec()
+            }
+            _ => (0..meshes.len())
+                .map(|level| DEFAULT_FIRST_THRESHOLD / 2f32.powi(level as i32))
+                .collect(),
+        };
+
+        Self {
+            meshes,
+            thresholds,
+            active: 0,
+        }
+    }
+
+    /// Number of levels including the node's own mesh
+    pub fn levels(&self) -> usize {
+        self.meshes.len() + 1
+    }
+
+    /// Selects the level for the screen size (the projected diameter of the bounding sphere
+    /// divided by the viewport height), 'forced' overrides the selection.
+    pub fn select(&mut self, screen_size: f32, forced: Option<usize>) -> usize {
+        let last = self.meshes.len();
+        let level_at = |scale: f32| {
+            self.thresholds
+                .iter()
+                .position(|&threshold| screen_size >= threshold * scale)
+                .unwrap_or(last)
+        };
+
+        self.active = match forced {
+            Some(level) => level.min(last),
+            None => {
+                let finer = level_at(1. + HYSTERESIS);
+                let coarser = level_at(1. - HYSTERESIS);
+
+                if finer < self.active {
+                    finer
+                } else if coarser > self.active {
+                    coarser
+                } else {
+                    self.active.min(last)
+                }
+            }
+        };
+
+        self.active
+    }
+
+    /// The mesh of the level, None for level 0 (the node's own mesh)
+    pub fn mesh_mut(&mut self, level: usize) -> Option<&mut Mesh> {
+        level.checked_sub(1).and_then(|i| self.meshes.get_mut(i))
+    }
+}
+
+/// The MSFT_lod data of a node
+pub(super) struct LodNodes {
+    /// Indices of the nodes whose meshes are the levels 1, 2, ...
+    ids: Vec<usize>,
+    /// MSFT_screencoverage from the extras of the node (fractions of the screen area)
+    coverages: Option<Vec<f32>>,
+}
+
+/// Reads the MSFT_lod extension of the nodes, keyed by the node index.
+///
+/// The gltf crate doesn't support the extension, so the JSON of the file is parsed again
+/// (only when the file declares the extension).
+pub(super) fn read_lods(path: &str, document: &Document) -> Result<HashMap<usize, LodNodes>> {
+    let mut lods = HashMap::new();
+
+    if !document.extensions_used().any(|ext| ext == EXTENSION) {
+        return Ok(lods);
+    }
+
+    let json = gltf_json::read_json(path)?;
+    let nodes = match json["nodes"].as_array() {
+        Some(nodes) => nodes,
+        None => return Ok(lods),
+    };
+
+    for (index, node) in nodes.iter().enumerate() {
+        let ids: Vec<usize> = match node["extensions"][EXTENSION]["ids"].as_array() {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| id.as_u64().map(|id| id as usize))
+                .collect(),
+            None => continue,
+        };
+
+        let coverages = node["extras"]["MSFT_screencoverage"]
+            .as_array()
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|v| v.as_f64().map(|v| v as f32))
+                    .collect()
+            });
+
+        lods.insert(index, LodNodes { ids, coverages });
+    }
+
+    Ok(lods)
+}
+
+/// Recursive - creates the levels of detail of the nodes in this subtree.
+///
+/// MSFT_lod levels are loaded from the meshes of the referenced nodes. Without the extension,
+/// sibling nodes named 'Name_LOD1', 'Name_LOD2', ... become the levels of the sibling
+/// named 'Name' (or 'Name_LOD0'), the transforms of the siblings are expected to be the same.
+pub(super) fn attach_lods(
+    node: &mut Node,
+    document: &Document,
+    bundle: &mut DataBundle,
+) -> Result<()> {
+    if let Some(lod_nodes) = bundle.lods.remove(&node.index) {
+        attach_msft_lods(node, lod_nodes, document, bundle)?;
+    }
+
+    group_named_lods(&mut node.children);
+
+    for child in &mut node.children {
+        attach_lods(child, document, bundle)?;
+    }
+
+    Ok(())
+}
+
+fn attach_msft_lods(
+    node: &mut Node,
+    lod_nodes: LodNodes,
+    document: &Document,
+    bundle: &mut DataBundle,
+) -> Result<()> {
+    if node.mesh.is_none() {
+        return bundle.warn(format!(
+            "Node '{}': {EXTENSION} on a node without a mesh isn't supported",
+            node.name
+        ));
+    }
+
+    // The LOD meshes are skinned by the skin of the node
+    let joint_count = document
+        .nodes()
+        .nth(node.index)
+        .and_then(|n| n.skin())
+        .map(|skin| skin.joints().count());
+
+    let mut meshes = Vec::new();
+    for &id in &lod_nodes.ids {
+        match document.nodes().nth(id).and_then(|n| n.mesh()) {
+            Some(mesh) => meshes.push(Mesh::from_gltf(&mesh, bundle, joint_count)?),
+            None => {
+                bundle.warn(format!(
+                    "Node '{}': {EXTENSION} node {id} doesn't exist or doesn't have a mesh",
+                    node.name
+                ))?;
+                break;
+            }
+        }
+    }
+
+    // The LOD nodes shouldn't be drawn by themselves
+    let ids: HashSet<usize> = lod_nodes.ids.iter().copied().collect();
+    node.children.retain(|child| !ids.contains(&child.index));
+
+    if !meshes.is_empty() {
+        // Screen coverage is an area, the selection uses the projected diameter
+        let thresholds = lod_nodes
+            .coverages
+            .map(|coverages| coverages.iter().map(|c| c.max(0.).sqrt()).collect());
+        node.lods = Some(Lods::new(meshes, thresholds));
+    }
+
+    Ok(())
+}
+
+/// Splits 'Name_LOD2' into ("Name", 2)
+fn parse_lod_name(name: &str) -> Option<(&str, usize)> {
+    let (base, level) = name.rsplit_once("_LOD")?;
+    Some((base, level.parse().ok()?))
+}
+
+fn group_named_lods(children: &mut Vec<Node>) {
+    // (child index, level) of the coarser levels for each base child
+    let mut levels: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+
+    for (i, child) in children.iter().enumerate() {
+        let (base, level) = match parse_lod_name(&child.name) {
+            Some((base, level)) if level > 0 => (base, level),
+            _ => continue,
+        };
+
+        if child.mesh.is_none() || !child.children.is_empty() {
+            continue;
+        }
+
+        let base_index = children.iter().position(|c| {
+            c.mesh.is_some()
+                && c.lods.is_none()
+                && (c.name == base || parse_lod_name(&c.name) == Some((base, 0)))
+        });
+
+        if let Some(base_index) = base_index {
+            levels.entry(base_index).or_default().push((i, level));
+        }
+    }
+
+    if levels.is_empty() {
+        return;
+    }
+
+    let mut grouped = HashSet::new();
+    for (base_index, mut base_levels) in levels {
+        base_levels.sort_by_key(|&(_, level)| level);
+
+        let meshes = base_levels
+            .iter()
+            .filter_map(|&(i, _)| children[i].mesh.take())
+            .collect();
+        grouped.extend(base_levels.iter().map(|&(i, _)| i));
+
+        children[base_index].lods = Some(Lods::new(meshes, None));
+    }
+
+    let mut i = 0;
+    children.retain(|_| {
+        let keep = !grouped.contains(&i);
+        i += 1;
+        keep
+    });
+}
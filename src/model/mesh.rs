@@ -1,17 +1,20 @@
-use std::mem::size_of;
+use std::{mem::size_of, sync::Arc};
 
 use eyre::{eyre, Result};
 use gl::types::GLenum;
-use glam::{Vec2, Vec3, Vec4};
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4};
+use gltf::Semantic;
 use gltf::{
     image::Format,
-    mesh::util::ReadIndices,
+    material::AlphaMode,
+    mesh::util::{ReadIndices, ReadWeights},
     texture::{MagFilter, MinFilter, WrappingMode},
 };
+use log::debug;
 
-use crate::ogl;
+use crate::ogl::{self, gl_check, shader::Shader, Buffer, Texture2D, TextureFiltering, Vao};
 
-use super::DataBundle;
+use super::{dequantize, tangents, Aabb, DataBundle, Statistics, TextureBundle};
 
 /// Gltf terminology is needlessly confusing.
 /// A gltf 'Mesh' contains multiple real sub-meshes (called Primitives in the gltf parlance)
@@ -21,33 +24,143 @@ pub struct Mesh {
     pub primitives: Vec<Primitive>,
     /// Name of the 'Mesh'
     pub name: Option<String>,
+    /// Bounding box of all the primitives in the mesh space
+    pub bounds: Aabb,
+    /// Sum of the statistics of the primitives
+    pub stats: Statistics,
+    /// Transforms of the instances in the node space (EXT_mesh_gpu_instancing), empty if the
+    /// mesh isn't instanced
+    pub instances: Vec<Mat4>,
+    /// OpenGL buffer of the instance transforms
+    instance_buffer: Option<Buffer>,
 }
 
 impl Mesh {
-    /// Create a mesh from the gltf::Mesh struct and the DataBundle
-    pub fn from_gltf(mesh: &gltf::Mesh, bundle: &mut DataBundle) -> Result<Self> {
+    /// Create a mesh from the gltf::Mesh struct and the DataBundle.
+    ///
+    /// 'joint_count' is the number of joints of the skin of the node (if it has one).
+    pub fn from_gltf(
+        mesh: &gltf::Mesh,
+        bundle: &mut DataBundle,
+        joint_count: Option<usize>,
+    ) -> Result<Self> {
         let name = mesh.name().map(|n| n.to_owned());
 
         let mut primitives = Vec::new();
-        for primitive in mesh.primitives() {
-            let primitive = Primitive::from_gltf(&primitive, bundle)?;
+        for gltf_primitive in mesh.primitives() {
+            let label = format!(
+                "Mesh '{}', primitive {}",
+                name.as_deref().unwrap_or("unnamed"),
+                gltf_primitive.index()
+            );
+
+            let mut primitive = Primitive::from_gltf(&gltf_primitive, bundle, &label)?;
+
+            if let (Some(skin), Some(joint_count)) = (&mut primitive.skin, joint_count) {
+                for problem in skin.sanitize(joint_count) {
+                    bundle.warn(format!("{label}: {problem}"))?;
+                }
+            }
+
             primitives.push(primitive);
         }
 
-        Ok(Mesh { primitives, name })
+        Ok(Mesh::from_primitives(primitives, name))
+    }
+
+    /// Creates a non-instanced mesh from already loaded primitives
+    pub(super) fn from_primitives(primitives: Vec<Primitive>, name: Option<String>) -> Self {
+        let bounds = primitives
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, p| aabb.union(p.bounds));
+
+        let mut stats = Statistics::default();
+        for prim in &primitives {
+            stats += prim.stats;
+        }
+
+        Self {
+            primitives,
+            name,
+            bounds,
+            stats,
+            instances: Vec::new(),
+            instance_buffer: None,
+        }
+    }
+
+    /// Makes the mesh instanced, the bounds are extended to cover all of the instances
+    pub(super) fn set_instances(&mut self, instances: Vec<Mat4>) {
+        self.bounds = instances
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, t| aabb.union(self.bounds.transform(t)));
+        self.stats.instances = instances.len();
+        self.instances = instances;
+    }
+
+    /// Uploads the vertex data and the textures of all primitives to the GPU
+    pub fn upload_to_gpu(&mut self, textures: &mut TextureBundle) -> Result<()> {
+        let name = self.name.as_deref().unwrap_or("unnamed mesh");
+        let label = format!("{}: {name}", textures.model_name);
+
+        for (i, primitive) in self.primitives.iter_mut().enumerate() {
+            primitive.upload_to_gpu(textures, &format!("{label} #{i}"))?;
+        }
+
+        if !self.instances.is_empty() {
+            let buffer = ogl::create_instance_buf(&self.instances);
+
+            for primitive in &self.primitives {
+                if let Some(vao) = &primitive.vao {
+                    vao.bind();
+                    ogl::bind_instance_transforms(&buffer);
+                    vao.unbind();
+                }
+            }
+
+            ogl::label_object(gl::BUFFER, buffer.id, &format!("{label} instances"));
+            self.instance_buffer = Some(buffer);
+        }
+
+        Ok(())
     }
 }
 
+// Indices into Primitive::buffers, in the order they are created by upload_to_gpu
+// (the joints and weights follow the tangents, or the normals if there are no tangents)
+const POSITIONS_BUFFER: usize = 0;
+const NORMALS_BUFFER: usize = 2;
+const TANGENTS_BUFFER: usize = 3;
+
+/// Local work group size of the skinning compute shader
+const SKINNING_GROUP_SIZE: u32 = 64;
+
 /// A Primitive represents a single 'mesh' in the normal meaning of that word
 /// (a collection of vertices with a specific topology like Triangles or Lines).
 ///
 // TODO: It's not needed to store all this data in RAM.
 // TODO: load vertex data without allocation and copying
 pub struct Primitive {
-    /// A texture (if any) of this mesh
-    pub texture_info: PrimitiveTexture,
-    /// OpenGL VAO identifier
-    pub vao: u32,
+    /// Material (textures and factors) of this mesh
+    pub material: PrimitiveMaterial,
+    /// Base color factor from the gltf file (the one in the material can be overriden in the GUI)
+    gltf_base_color_factor: Vec4,
+    /// If the primitive should be drawn (toggled in the GUI)
+    pub visible: bool,
+    /// Textures of the material, until they are uploaded to the GPU
+    pending_textures: Option<MaterialTextures>,
+    /// OpenGL VAO (None until the primitive is uploaded to the GPU)
+    pub vao: Option<Vao>,
+    /// OpenGL buffers of the vertex attributes and indices
+    buffers: Vec<Buffer>,
+    /// If the vertex buffers hold skinned vertices (skinned on the CPU or by the compute shader)
+    /// instead of the bind pose
+    pre_skinned: bool,
+    /// Bind pose positions, normals and tangents read by the skinning compute shader
+    /// (created on the first use)
+    bind_pose_buffer: Option<Buffer>,
+    /// Textures of the material (shared with other primitives of the model)
+    textures: Vec<Arc<Texture2D>>,
     /// Vertex indices
     pub indices: Indices,
     /// Vertex positions
@@ -56,13 +169,28 @@ pub struct Primitive {
     pub texcoords: Vec<Vec2>,
     /// Vertex normals
     pub normals: Vec<Vec3>,
+    /// Vertex tangents (w is the sign of the bitangent), only present if the material has a normal map
+    pub tangents: Vec<Vec4>,
     /// Vertex skin data (joints indices, weights)
     pub skin: Option<PrimSkin>,
+    /// Bounding box of the vertex positions
+    pub bounds: Aabb,
+    /// Vertex / triangle counts and sizes
+    pub stats: Statistics,
 }
 
 impl Primitive {
-    /// Creates the primitive from the gltf::Primitive struct and the DataBundle
-    pub fn from_gltf(primitive: &gltf::Primitive, bundle: &mut DataBundle) -> Result<Self> {
+    /// Creates the primitive from the gltf::Primitive struct and the DataBundle.
+    ///
+    /// Only reads the data, doesn't call OpenGL (can be called from any thread).
+    /// The primitive can't be rendered until `upload_to_gpu` is called.
+    ///
+    /// 'label' identifies the primitive in the load warnings.
+    pub fn from_gltf(
+        primitive: &gltf::Primitive,
+        bundle: &mut DataBundle,
+        label: &str,
+    ) -> Result<Self> {
         let mode = primitive.mode();
 
         if mode != gltf::mesh::Mode::Triangles {
@@ -71,12 +199,22 @@ impl Primitive {
 
         let reader = primitive.reader(|buffer| Some(&bundle.buffers[buffer.index()]));
 
-        let positions = reader
-            .read_positions()
+        // The attributes can be quantized (KHR_mesh_quantization), so they aren't read by the reader
+        let attribute = |semantic: Semantic| -> Result<Option<Vec<[f32; 3]>>> {
+            primitive
+                .get(&semantic)
+                .map(|accessor| dequantize::read_floats(&accessor, &bundle.buffers))
+                .transpose()
+        };
+
+        let positions: Vec<Vec3> = attribute(Semantic::Positions)?
             .ok_or(eyre!("primitive doesn't containt positions"))?
+            .into_iter()
             .map(Vec3::from)
             .collect();
 
+        let bounds = Aabb::from_points(&positions);
+
         let indices = match reader
             .read_indices()
             .ok_or(eyre!("primitive doesn't containt indices"))?
@@ -86,155 +224,587 @@ impl Primitive {
             ReadIndices::U8(b) => Indices::U8(b.collect()),
         };
 
-        let mut texcoords = Vec::new();
-        let mut texture_set = 0;
-        while let Some(texcoords_reader) = reader.read_tex_coords(texture_set) {
-            if texture_set >= 1 {
-                // Used for loading textures other than the diffuse map
-                //eprintln!("WARN: primitive has more than 1 texture coordinate set");
-                break;
+        let mut texcoords: Vec<Vec2> = match primitive.get(&Semantic::TexCoords(0)) {
+            Some(accessor) => dequantize::read_floats(&accessor, &bundle.buffers)?
+                .into_iter()
+                .map(Vec2::from)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        if primitive.get(&Semantic::TexCoords(1)).is_some() {
+            // Used for loading textures other than the diffuse map
+            debug!("Primitive has more than 1 texture coordinate set, only the first one is used");
+        }
+
+        let material = primitive.material();
+        let pbr = material.pbr_metallic_roughness();
+
+        let has_textures = pbr.base_color_texture().is_some()
+            || material.normal_texture().is_some()
+            || material.emissive_texture().is_some()
+            || material.occlusion_texture().is_some();
+
+        // Reported once the reader (which borrows the bundle) isn't needed anymore
+        let mut problems = Vec::new();
+
+        // The shader always reads the texture coordinates, so there has to be one per vertex
+        let mut use_textures = true;
+        if texcoords.is_empty() {
+            if has_textures {
+                problems.push(
+                    "the material has textures, but there are no texture coordinates, the textures are ignored"
+                        .to_string(),
+                );
+                use_textures = false;
             }
 
-            texcoords = texcoords_reader.into_f32().map(Vec2::from).collect();
+            texcoords = vec![Vec2::ZERO; positions.len()];
+        } else if texcoords.len() != positions.len() {
+            problems.push(format!(
+                "{} texture coordinates for {} vertices, truncated / padded with zeros",
+                texcoords.len(),
+                positions.len()
+            ));
 
-            texture_set += 1;
+            texcoords.resize(positions.len(), Vec2::ZERO);
         }
 
-        let normals = reader
-            .read_normals()
+        // Quantized normals aren't exactly unit length
+        let normals: Vec<Vec3> = attribute(Semantic::Normals)?
             .ok_or(eyre!("primitive doesn't containt normals"))?
-            .map(Vec3::from)
+            .into_iter()
+            .map(|normal| Vec3::from(normal).normalize_or_zero())
             .collect();
 
+        // Tangents are only needed for normal mapping, which also needs texture coordinates
+        let normal_texture = material.normal_texture().filter(|_| use_textures);
+        let tangents = match (&normal_texture, primitive.get(&Semantic::Tangents)) {
+            (Some(_), Some(accessor)) => dequantize::read_floats(&accessor, &bundle.buffers)?
+                .into_iter()
+                .map(|tangent| {
+                    let tangent = Vec4::from(tangent);
+                    tangent
+                        .truncate()
+                        .normalize_or_zero()
+                        .extend(tangent.w.signum())
+                })
+                .collect(),
+            (Some(_), None) => {
+                tangents::compute_tangents(&positions, &normals, &texcoords, &indices)
+            }
+            _ => Vec::new(),
+        };
+
         let skin = match (reader.read_joints(0), reader.read_weights(0)) {
             (Some(joints), Some(weights)) => {
                 let joints = joints.into_u16().map(|j| j.map(|ji| ji as u32)).collect();
-                // TODO: u8 / u16 joint weights normalization
-                match weights {
-                    gltf::mesh::util::ReadWeights::U8(_) => todo!("U8 weights"),
-                    gltf::mesh::util::ReadWeights::U16(_) => todo!("U16 weights"),
-                    _ => {}
+                let weights = Self::read_weights(weights);
+
+                let mut skin = PrimSkin::new(joints, weights);
+
+                // Rigs with up to 8 influences per vertex have a second set
+                if let (Some(joints), Some(weights)) =
+                    (reader.read_joints(1), reader.read_weights(1))
+                {
+                    skin.joints_1 = joints.into_u16().map(|j| j.map(|ji| ji as u32)).collect();
+                    skin.weights_1 = Self::read_weights(weights);
                 }
-                let weights = weights.into_f32().collect();
 
-                Some(PrimSkin::new(joints, weights))
+                Some(skin)
             }
             _ => None,
         };
 
-        let material = primitive.material();
+        for problem in problems {
+            bundle.warn(format!("{label}: {problem}"))?;
+        }
 
-        let mut primitive = Self {
-            vao: 0,
-            texture_info: PrimitiveTexture::None {
-                base_color_factor: Vec4::splat(1.),
-            },
+        let base_color_texture = pbr.base_color_texture().filter(|_| use_textures);
+        let base_color = base_color_texture
+            .as_ref()
+            .map(|tex_info| TextureSource::new(&tex_info.texture(), true));
+
+        let base_color_uv_transform = base_color_texture
+            .and_then(|tex_info| tex_info.texture_transform())
+            .map(|transform| UvTransform {
+                offset: Vec2::from(transform.offset()),
+                rotation: transform.rotation(),
+                scale: Vec2::from(transform.scale()),
+            })
+            .unwrap_or_default();
+
+        // Normal mapping doesn't work without tangents
+        let (normal, normal_scale) = match normal_texture {
+            Some(normal_tex) if !tangents.is_empty() => (
+                Some(TextureSource::new(&normal_tex.texture(), false)),
+                normal_tex.scale(),
+            ),
+            _ => (None, 1.),
+        };
+
+        let emissive = material
+            .emissive_texture()
+            .filter(|_| use_textures)
+            .map(|tex_info| TextureSource::new(&tex_info.texture(), true));
+
+        let (occlusion, occlusion_strength) = match material.occlusion_texture() {
+            Some(occlusion_tex) if use_textures => (
+                Some(TextureSource::new(&occlusion_tex.texture(), false)),
+                occlusion_tex.strength(),
+            ),
+            _ => (None, 1.),
+        };
+
+        let primitive_material = PrimitiveMaterial {
+            base_color_factor: Vec4::from(pbr.base_color_factor()),
+            normal_scale,
+            emissive_factor: Vec3::from(material.emissive_factor()),
+            occlusion_strength,
+            alpha_mode: material.alpha_mode(),
+            // The default value defined by the spec
+            alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            double_sided: material.double_sided(),
+            unlit: material.unlit(),
+            base_color_uv_transform,
+            ..PrimitiveMaterial::new()
+        };
+
+        let mut prim = Self {
+            vao: None,
+            buffers: Vec::new(),
+            pre_skinned: false,
+            bind_pose_buffer: None,
+            textures: Vec::new(),
+            gltf_base_color_factor: primitive_material.base_color_factor,
+            material: primitive_material,
+            visible: true,
+            pending_textures: Some(MaterialTextures {
+                base_color,
+                normal,
+                emissive,
+                occlusion,
+            }),
             indices,
             positions,
             texcoords,
             normals,
+            tangents,
             skin,
+            bounds,
+            stats: Statistics::default(),
         };
 
-        primitive.create_buffers(&material, bundle);
+        prim.stats = Statistics::from_primitive(&prim);
+        Ok(prim)
+    }
+
+    /// Creates a static primitive (without a skin and a normal map) from raw vertex data,
+    /// eg. from an OBJ file.
+    ///
+    /// Doesn't call OpenGL, the primitive can't be rendered until `upload_to_gpu` is called.
+    pub(super) fn new_static(
+        indices: Vec<u32>,
+        positions: Vec<Vec3>,
+        texcoords: Vec<Vec2>,
+        normals: Vec<Vec3>,
+        material: PrimitiveMaterial,
+        base_color: Option<TextureSource>,
+    ) -> Self {
+        let bounds = Aabb::from_points(&positions);
 
-        if primitive.vao == 0 {
-            return Err(eyre!("primitive VAO wasn't correctly initialized"));
+        let mut prim = Self {
+            vao: None,
+            buffers: Vec::new(),
+            pre_skinned: false,
+            bind_pose_buffer: None,
+            textures: Vec::new(),
+            gltf_base_color_factor: material.base_color_factor,
+            material,
+            visible: true,
+            pending_textures: Some(MaterialTextures {
+                base_color,
+                normal: None,
+                emissive: None,
+                occlusion: None,
+            }),
+            indices: Indices::U32(indices),
+            positions,
+            texcoords,
+            normals,
+            tangents: Vec::new(),
+            skin: None,
+            bounds,
+            stats: Statistics::default(),
+        };
+
+        prim.stats = Statistics::from_primitive(&prim);
+        prim
+    }
+
+    /// Normalized u8 / u16 weights are mapped to [0, 1], their sums are fixed by `PrimSkin::sanitize`
+    fn read_weights(weights: ReadWeights) -> Vec<[f32; 4]> {
+        weights.into_f32().collect()
+    }
+
+    /// Restores the material values overriden in the GUI to the ones from the gltf file
+    pub fn reset_material(&mut self) {
+        self.material.base_color_factor = self.gltf_base_color_factor;
+    }
+
+    /// If the vertices are influenced by 8 joints (JOINTS_1 / WEIGHTS_1 are present)
+    pub fn has_eight_influences(&self) -> bool {
+        self.skin
+            .as_ref()
+            .is_some_and(|skin| !skin.joints_1.is_empty())
+    }
+
+    /// The weighted sum of the joint matrices influencing the vertex, the same as in the vertex shader.
+    ///
+    /// Returns the identity for primitives without a skin.
+    pub fn skin_transform(&self, vertex: usize, joint_matrices: &[Mat4]) -> Mat4 {
+        let skin = match &self.skin {
+            Some(skin) => skin,
+            None => return Mat4::IDENTITY,
+        };
+
+        let mut transform =
+            Self::weighted_sum(skin.joints[vertex], skin.weights[vertex], joint_matrices);
+        if self.has_eight_influences() {
+            transform += Self::weighted_sum(
+                skin.joints_1[vertex],
+                skin.weights_1[vertex],
+                joint_matrices,
+            );
         }
 
-        Ok(primitive)
+        transform
     }
 
-    /// Creates the OpenGL buffer from the loaded vertex data
-    fn create_buffers(&mut self, material: &gltf::Material, bundle: &mut DataBundle) {
-        let mut indices = 0;
-        let mut vao = 0;
+    fn weighted_sum(joints: [u32; 4], weights: [f32; 4], joint_matrices: &[Mat4]) -> Mat4 {
+        let mut sum = Mat4::ZERO;
+        for (joint, weight) in joints.iter().zip(weights) {
+            // Weights of out of range joints are zeroed while loading
+            if let Some(matrix) = joint_matrices.get(*joint as usize) {
+                sum += *matrix * weight;
+            }
+        }
 
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::BindVertexArray(vao);
-
-            let _positions = ogl::create_float_buf(&self.positions, 3, ogl::POS_INDEX, gl::FLOAT);
-            let _texcoords =
-                ogl::create_float_buf(&self.texcoords, 2, ogl::TEXCOORDS_INDEX, gl::FLOAT);
-            let _normals = ogl::create_float_buf(&self.normals, 3, ogl::NORMALS_INDEX, gl::FLOAT);
-
-            if let Some(skin) = &self.skin {
-                let _joints =
-                    ogl::create_int_buf(&skin.joints, 4, ogl::JOINTS_INDEX, gl::UNSIGNED_INT);
-                let _weights =
-                    ogl::create_float_buf(&skin.weights, 4, ogl::WEIGHTS_INDEX, gl::FLOAT);
+        sum
+    }
+
+    /// Skins the vertex positions on the CPU (the same math as the vertex shader).
+    ///
+    /// The positions end up in the space of the joint matrices (world space in the renderer).
+    pub fn skin_on_cpu(&self, joint_matrices: &[Mat4]) -> Vec<Vec3> {
+        self.positions
+            .iter()
+            .enumerate()
+            .map(|(i, pos)| {
+                self.skin_transform(i, joint_matrices)
+                    .transform_point3(*pos)
+            })
+            .collect()
+    }
+
+    /// Skins the vertex normals and tangents on the CPU, like `skin_on_cpu`
+    pub fn skin_normals_on_cpu(&self, joint_matrices: &[Mat4]) -> (Vec<Vec3>, Vec<Vec4>) {
+        let mut normals = Vec::with_capacity(self.normals.len());
+        let mut tangents = Vec::with_capacity(self.tangents.len());
+
+        for (i, normal) in self.normals.iter().enumerate() {
+            let transform = Mat3::from_mat4(self.skin_transform(i, joint_matrices));
+            normals.push(transform.inverse().transpose() * *normal);
+
+            if let Some(tangent) = self.tangents.get(i) {
+                let skinned = transform * tangent.truncate();
+                tangents.push(skinned.extend(tangent.w));
+            }
+        }
+
+        (normals, tangents)
+    }
+
+    /// Replaces the vertex buffers with vertices skinned on the CPU, so the primitive
+    /// can be drawn without skinning in the shader (and an identity model matrix).
+    pub fn upload_cpu_skinned(&mut self, joint_matrices: &[Mat4]) {
+        if self.vao.is_none() || self.skin.is_none() {
+            return;
+        }
+
+        let positions = self.skin_on_cpu(joint_matrices);
+        let (normals, tangents) = self.skin_normals_on_cpu(joint_matrices);
+
+        self.upload_vertices(&positions, &normals, &tangents);
+        self.pre_skinned = true;
+    }
+
+    /// Skins the vertices with the compute shader (cs_skinning.comp) into the vertex buffers,
+    /// like `upload_cpu_skinned`.
+    ///
+    /// The joint matrices are read from the JointTransforms uniform buffer.
+    pub fn skin_on_gpu(&mut self, shader: &Shader) {
+        if self.vao.is_none() || self.skin.is_none() {
+            return;
+        }
+
+        let eight_influences = self.has_eight_influences();
+        let has_tangents = !self.tangents.is_empty();
+
+        let bind_pose = self.bind_pose_buffer.get_or_insert_with(|| {
+            let vertices: Vec<[Vec4; 3]> = (0..self.positions.len())
+                .map(|i| {
+                    [
+                        self.positions[i].extend(1.),
+                        self.normals[i].extend(0.),
+                        self.tangents.get(i).copied().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+
+            ogl::create_storage_buf(&vertices)
+        });
+
+        let first_skin_buffer = if has_tangents {
+            TANGENTS_BUFFER + 1
+        } else {
+            TANGENTS_BUFFER
+        };
+        let skin_buffers = &self.buffers[first_skin_buffer..];
+
+        bind_pose.bind_storage(0);
+        skin_buffers[0].bind_storage(1);
+        skin_buffers[1].bind_storage(2);
+        // Unused blocks are bound to something valid as well
+        let (joints_1, weights_1) = if eight_influences {
+            (&skin_buffers[2], &skin_buffers[3])
+        } else {
+            (&skin_buffers[0], &skin_buffers[1])
+        };
+        joints_1.bind_storage(3);
+        weights_1.bind_storage(4);
+        self.buffers[POSITIONS_BUFFER].bind_storage(5);
+        self.buffers[NORMALS_BUFFER].bind_storage(6);
+        if has_tangents {
+            self.buffers[TANGENTS_BUFFER].bind_storage(7);
+        } else {
+            bind_pose.bind_storage(7);
+        }
+
+        let groups = (self.positions.len() as u32).div_ceil(SKINNING_GROUP_SIZE);
+        shader.dispatch(groups, || {
+            shader.set_bool(eight_influences, "eightInfluences");
+            shader.set_bool(has_tangents, "hasTangents");
+        });
+
+        self.pre_skinned = true;
+    }
+
+    /// Restores the bind pose vertices replaced by `upload_cpu_skinned` or `skin_on_gpu`
+    pub fn restore_bind_pose(&mut self) {
+        if !self.pre_skinned {
+            return;
+        }
+
+        self.upload_vertices(&self.positions, &self.normals, &self.tangents);
+        self.pre_skinned = false;
+    }
+
+    fn upload_vertices(&self, positions: &[Vec3], normals: &[Vec3], tangents: &[Vec4]) {
+        ogl::update_float_buf(&self.buffers[POSITIONS_BUFFER], positions);
+        ogl::update_float_buf(&self.buffers[NORMALS_BUFFER], normals);
+
+        if !tangents.is_empty() {
+            ogl::update_float_buf(&self.buffers[TANGENTS_BUFFER], tangents);
+        }
+    }
+
+    /// Creates the OpenGL buffers and textures from the loaded data.
+    ///
+    /// Has to be called on the thread with the OpenGL context.
+    /// 'label' names the VAO and the buffers in OpenGL debuggers.
+    pub fn upload_to_gpu(&mut self, textures: &mut TextureBundle, label: &str) -> Result<()> {
+        let vao = Vao::new();
+        if vao.id == 0 {
+            return Err(eyre!("primitive VAO wasn't correctly initialized"));
+        }
+
+        vao.bind();
+
+        self.buffers.push(ogl::create_float_buf(
+            &self.positions,
+            3,
+            ogl::POS_INDEX,
+            gl::FLOAT,
+        ));
+        self.buffers.push(ogl::create_float_buf(
+            &self.texcoords,
+            2,
+            ogl::TEXCOORDS_INDEX,
+            gl::FLOAT,
+        ));
+        self.buffers.push(ogl::create_float_buf(
+            &self.normals,
+            3,
+            ogl::NORMALS_INDEX,
+            gl::FLOAT,
+        ));
+
+        if !self.tangents.is_empty() {
+            self.buffers.push(ogl::create_float_buf(
+                &self.tangents,
+                4,
+                ogl::TANGENTS_INDEX,
+                gl::FLOAT,
+            ));
+        }
+
+        if let Some(skin) = &self.skin {
+            self.buffers.push(ogl::create_int_buf(
+                &skin.joints,
+                4,
+                ogl::JOINTS_INDEX,
+                gl::UNSIGNED_INT,
+            ));
+            self.buffers.push(ogl::create_float_buf(
+                &skin.weights,
+                4,
+                ogl::WEIGHTS_INDEX,
+                gl::FLOAT,
+            ));
+
+            if !skin.joints_1.is_empty() {
+                self.buffers.push(ogl::create_int_buf(
+                    &skin.joints_1,
+                    4,
+                    ogl::JOINTS_1_INDEX,
+                    gl::UNSIGNED_INT,
+                ));
+                self.buffers.push(ogl::create_float_buf(
+                    &skin.weights_1,
+                    4,
+                    ogl::WEIGHTS_1_INDEX,
+                    gl::FLOAT,
+                ));
             }
+        }
 
-            // Indices
-            gl::GenBuffers(1, &mut indices);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indices);
+        // Indices
+        let indices = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
+        indices.bind();
 
+        unsafe {
             gl::BufferData(
                 gl::ELEMENT_ARRAY_BUFFER,
                 self.indices.size() as isize,
                 self.indices.ptr(),
                 gl::STATIC_DRAW,
             );
+        }
 
-            let pbr = material.pbr_metallic_roughness();
-            let texture_index = match pbr.base_color_texture() {
-                Some(tex_info) => {
-                    self.create_texture(&tex_info.texture(), pbr.base_color_factor(), bundle)
-                }
-                None => {
-                    let base_color_factor = Vec4::from(pbr.base_color_factor());
-                    PrimitiveTexture::None { base_color_factor }
-                }
+        if let Some(pending) = self.pending_textures.take() {
+            let primitive_textures = &mut self.textures;
+            let mut create = |source: Option<TextureSource>| -> Result<Option<u32>> {
+                source
+                    .map(|source| {
+                        let texture = Self::create_texture(&source, textures)?;
+                        let id = texture.id;
+                        primitive_textures.push(texture);
+                        Ok(id)
+                    })
+                    .transpose()
             };
 
-            // Unbind buffers
-            gl::BindVertexArray(0);
+            self.material.base_color_texture = create(pending.base_color)?;
+            self.material.normal_texture = create(pending.normal)?;
+            self.material.emissive_texture = create(pending.emissive)?;
+            self.material.occlusion_texture = create(pending.occlusion)?;
+        }
+
+        // Unbind the VAO first, so the index buffer stays bound to it
+        vao.unbind();
+        indices.unbind();
+        unsafe {
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
             gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        self.buffers.push(indices);
 
-            self.vao = vao;
-            self.texture_info = texture_index;
+        // The buffers in the order they were created
+        let mut buffer_names = vec!["positions", "texcoords", "normals"];
+        if !self.tangents.is_empty() {
+            buffer_names.push("tangents");
         }
+        if self.skin.is_some() {
+            buffer_names.extend(["joints", "weights"]);
+        }
+        if self.has_eight_influences() {
+            buffer_names.extend(["joints_1", "weights_1"]);
+        }
+        buffer_names.push("indices");
+
+        ogl::label_object(gl::VERTEX_ARRAY, vao.id, label);
+        for (buffer, name) in self.buffers.iter().zip(buffer_names) {
+            ogl::label_object(gl::BUFFER, buffer.id, &format!("{label} {name}"));
+        }
+
+        self.vao = Some(vao);
+
+        Ok(())
     }
 
     /// Creates a new OpenGL texture.
     ///
-    /// If the texture already exists (bundle.gl_textures\[texture_index\] == Some(...)),
-    /// no new texture is created, only the Texture struct is cloned.
+    /// Color textures (base color, emissive) are stored in sRGB, data textures (normal, occlusion...) are linear.
+    ///
+    /// If a texture with the same image, color space and sampler already exists,
+    /// no new texture is created, the existing one is shared.
+    /// Material factors aren't a part of the texture, they are stored per primitive.
+    ///
+    /// Fails if the image wasn't converted to a supported format (see `texture_image::prepare`).
     fn create_texture(
-        &mut self,
-        tex: &gltf::Texture,
-        base_color_factor: [f32; 4],
-        bundle: &mut DataBundle,
-    ) -> PrimitiveTexture {
-        let tex_index = tex.source().index();
-        if let Some(texture) = bundle.gl_textures[tex_index].clone() {
-            return texture;
+        source: &TextureSource,
+        textures: &mut TextureBundle,
+    ) -> Result<Arc<Texture2D>> {
+        if let Some(texture) = textures.gl_textures.get(source) {
+            return Ok(Arc::clone(texture));
         }
 
-        let gl_tex_id = unsafe {
-            let mut texture = 0;
+        let image = textures
+            .images
+            .get(source.image)
+            .ok_or_else(|| eyre!("Image {} doesn't exist", source.image))?;
 
-            gl::GenTextures(1, &mut texture);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
-
-            self.set_texture_sampler(&tex.sampler());
+        if !image.width.is_power_of_two() || !image.height.is_power_of_two() {
+            return Err(eyre!(
+                "Image {} has dimensions that aren't powers of two: {}x{}",
+                source.image,
+                image.width,
+                image.height
+            ));
+        }
 
-            let image = &bundle.images[tex_index];
+        let (internal_format, format) = match (image.format, source.srgb) {
+            (Format::R8G8B8, false) => (gl::RGB8, gl::RGB),
+            (Format::R8G8B8, true) => (gl::SRGB8, gl::RGB),
+            (Format::R8G8B8A8, false) => (gl::RGBA8, gl::RGBA),
+            (Format::R8G8B8A8, true) => (gl::SRGB8_ALPHA8, gl::RGBA),
+            (f, _) => {
+                return Err(eyre!(
+                    "Image {} has an unsupported format: '{f:?}'",
+                    source.image
+                ))
+            }
+        };
 
-            assert!(image.width.is_power_of_two());
-            assert!(image.height.is_power_of_two());
+        let texture = Texture2D::new();
+        texture.bind();
 
-            let (internal_format, format) = match image.format {
-                Format::R8G8 => (gl::RG8, gl::RG),
-                Format::R8G8B8 => (gl::RGB8, gl::RGB),
-                Format::R8G8B8A8 => (gl::RGBA8, gl::RGBA),
-                f => unimplemented!("Unimplemented image format: '{f:?}'"),
-            };
+        unsafe {
+            source.sampler.apply();
 
-            gl::TexImage2D(
+            gl_check!(gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
                 internal_format as i32,
@@ -244,22 +814,107 @@ impl Primitive {
                 format,
                 gl::UNSIGNED_BYTE,
                 image.pixels.as_ptr() as _,
-            );
+            ));
             gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
 
-            texture
-        };
+        let label = format!("{}: image {}", textures.model_name, source.image);
+        ogl::label_object(gl::TEXTURE, texture.id, &label);
 
-        let texture = PrimitiveTexture::Some {
-            gl_id: gl_tex_id,
-            base_color_factor: Vec4::from(base_color_factor),
-        };
-        bundle.gl_textures[tex_index] = Some(texture.clone());
-        texture
+        let texture = Arc::new(texture);
+        textures
+            .gl_textures
+            .insert(source.clone(), Arc::clone(&texture));
+        Ok(texture)
+    }
+}
+
+/// Textures of a material that weren't uploaded to the GPU yet
+struct MaterialTextures {
+    base_color: Option<TextureSource>,
+    normal: Option<TextureSource>,
+    emissive: Option<TextureSource>,
+    occlusion: Option<TextureSource>,
+}
+
+/// A texture that wasn't uploaded to the GPU yet.
+///
+/// Also the key of the uploaded textures, which are shared between primitives.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct TextureSource {
+    /// Index of the image in the gltf file
+    image: usize,
+    /// If the image contains color (and should be stored in sRGB)
+    srgb: bool,
+    sampler: SamplerParams,
+}
+
+impl TextureSource {
+    /// Changes the filtering of the texture created from this source, the wrapping stays
+    pub(super) fn apply_filtering(
+        &self,
+        texture: &Texture2D,
+        filtering: TextureFiltering,
+        anisotropy: Option<f32>,
+    ) {
+        let (min_filter, mag_filter) = filtering
+            .filters()
+            .unwrap_or((self.sampler.min_filter, self.sampler.mag_filter));
+
+        let anisotropy = anisotropy.map(|level| {
+            if filtering.uses_anisotropy() {
+                level
+            } else {
+                1.
+            }
+        });
+
+        texture.bind();
+        Texture2D::set_filters(min_filter, mag_filter, anisotropy);
+    }
+
+    fn new(tex: &gltf::Texture, srgb: bool) -> Self {
+        Self {
+            image: tex.source().index(),
+            srgb,
+            sampler: SamplerParams::from_gltf(&tex.sampler()),
+        }
     }
 
-    /// Sets the appropriate sampler functions for the currently created texture.
-    fn set_texture_sampler(&self, sampler: &gltf::texture::Sampler) {
+    /// A texture of an image that isn't from a gltf file (uses the default sampler)
+    pub(super) fn from_image(image: usize, srgb: bool) -> Self {
+        Self {
+            image,
+            srgb,
+            sampler: SamplerParams::default(),
+        }
+    }
+}
+
+/// OpenGL sampling parameters of a texture
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SamplerParams {
+    min_filter: GLenum,
+    mag_filter: GLenum,
+    wrap_s: GLenum,
+    wrap_t: GLenum,
+}
+
+impl Default for SamplerParams {
+    /// The same values as for a gltf sampler without any parameters
+    fn default() -> Self {
+        Self {
+            min_filter: gl::LINEAR_MIPMAP_LINEAR,
+            mag_filter: gl::LINEAR,
+            wrap_s: gl::REPEAT,
+            wrap_t: gl::REPEAT,
+        }
+    }
+}
+
+impl SamplerParams {
+    /// Converts the gltf sampler into the OpenGL values
+    fn from_gltf(sampler: &gltf::texture::Sampler) -> Self {
         let min_filter = match sampler.min_filter() {
             Some(min_filter) => match min_filter {
                 MinFilter::Nearest => gl::NEAREST,
@@ -280,50 +935,273 @@ impl Primitive {
             None => gl::LINEAR,
         };
 
-        unsafe {
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as i32);
-        }
-
-        let wrap_s = match sampler.wrap_s() {
+        let wrap = |mode| match mode {
             WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
             WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
             WrappingMode::Repeat => gl::REPEAT,
         };
 
-        let wrap_t = match sampler.wrap_t() {
-            WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
-            WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
-            WrappingMode::Repeat => gl::REPEAT,
-        };
+        Self {
+            min_filter,
+            mag_filter,
+            wrap_s: wrap(sampler.wrap_s()),
+            wrap_t: wrap(sampler.wrap_t()),
+        }
+    }
 
+    /// Sets the sampling parameters of the currently bound texture
+    fn apply(&self) {
         unsafe {
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap_s as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap_t as i32);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                self.min_filter as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAG_FILTER,
+                self.mag_filter as i32,
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, self.wrap_s as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, self.wrap_t as i32);
         }
     }
 }
 
-/// Texture info for a primitive.
+/// Material info for a primitive.
 ///
-/// If the primitive has a texture, copy the texture information from the Model's gl_textures.
+/// Textures are owned by the primitives (and shared between them), so only their ids are stored.
 ///
-/// If not, the base_color_factor serves as the object color.
+/// If the primitive doesn't have a base color texture, the base_color_factor serves as the object color.
 #[derive(Clone)]
-pub enum PrimitiveTexture {
-    None { base_color_factor: Vec4 },
-    Some { gl_id: u32, base_color_factor: Vec4 },
+pub struct PrimitiveMaterial {
+    pub base_color_factor: Vec4,
+    /// OpenGL id of the base color texture
+    pub base_color_texture: Option<u32>,
+    /// OpenGL id of the tangent-space normal map
+    pub normal_texture: Option<u32>,
+    /// Scale applied to the x and y components of the normal map
+    pub normal_scale: f32,
+    /// OpenGL id of the emissive texture
+    pub emissive_texture: Option<u32>,
+    /// Multiplies the emissive texture, or serves as the emitted color if there's no texture
+    pub emissive_factor: Vec3,
+    /// OpenGL id of the ambient occlusion texture (only the red channel is used)
+    pub occlusion_texture: Option<u32>,
+    /// How much the occlusion texture affects the ambient light
+    pub occlusion_strength: f32,
+    /// How the alpha value of the base color is interpreted
+    pub alpha_mode: AlphaMode,
+    /// Fragments with a lower alpha value are discarded in the MASK alpha mode
+    pub alpha_cutoff: f32,
+    /// Back faces shouldn't be culled
+    pub double_sided: bool,
+    /// The material isn't affected by lighting (KHR_materials_unlit), only the base color is used
+    pub unlit: bool,
+    /// Transform of the base color texture coordinates (KHR_texture_transform)
+    pub base_color_uv_transform: UvTransform,
+}
+
+impl PrimitiveMaterial {
+    pub fn new() -> Self {
+        Self {
+            base_color_factor: Vec4::splat(1.),
+            base_color_texture: None,
+            normal_texture: None,
+            normal_scale: 1.,
+            emissive_texture: None,
+            emissive_factor: Vec3::ZERO,
+            occlusion_texture: None,
+            occlusion_strength: 1.,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            unlit: false,
+            base_color_uv_transform: UvTransform::default(),
+        }
+    }
+}
+
+/// Texture coordinate transform of the KHR_texture_transform extension
+#[derive(Clone, Copy, PartialEq)]
+pub struct UvTransform {
+    /// Offset of the UV origin as a factor of the texture dimensions
+    pub offset: Vec2,
+    /// Counter-clockwise rotation of the UVs in radians
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl UvTransform {
+    /// The matrix defined by the extension: translation * rotation * scale
+    pub fn matrix(&self) -> Mat3 {
+        let (sin, cos) = self.rotation.sin_cos();
+
+        let translation = Mat3::from_cols(
+            Vec3::X,
+            Vec3::Y,
+            Vec3::new(self.offset.x, self.offset.y, 1.),
+        );
+        let rotation = Mat3::from_cols(Vec3::new(cos, -sin, 0.), Vec3::new(sin, cos, 0.), Vec3::Z);
+        let scale = Mat3::from_diagonal(Vec3::new(self.scale.x, self.scale.y, 1.));
+
+        translation * rotation * scale
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            rotation: 0.,
+            scale: Vec2::ONE,
+        }
+    }
 }
 
 /// Optional skin data for a primitive.
 pub struct PrimSkin {
     pub joints: Vec<[u32; 4]>,
     pub weights: Vec<[f32; 4]>,
+    /// The second set of joint indices (JOINTS_1), empty if not present
+    pub joints_1: Vec<[u32; 4]>,
+    /// The second set of joint weights (WEIGHTS_1), empty if not present
+    pub weights_1: Vec<[f32; 4]>,
 }
 
 impl PrimSkin {
     pub fn new(joints: Vec<[u32; 4]>, weights: Vec<[f32; 4]>) -> Self {
-        Self { joints, weights }
+        Self {
+            joints,
+            weights,
+            joints_1: Vec::new(),
+            weights_1: Vec::new(),
+        }
+    }
+
+    /// Fixes weights that would deform the vertices into garbage and returns the descriptions
+    /// of the problems (empty if the data is valid).
+    ///
+    /// Negative (and NaN) weights are clamped to 0, weights of joints past 'joint_count' are
+    /// zeroed and the weights of each vertex are normalized to sum to 1. Vertices without any
+    /// influence are bound to the first joint.
+    ///
+    /// Weights missing for some vertices are treated as zero (extra weights are dropped)
+    /// and a second set of influences that doesn't match the vertices is ignored.
+    pub fn sanitize(&mut self, joint_count: usize) -> Vec<String> {
+        // Exporters quantize the weights, so small errors are expected
+        const SUM_TOLERANCE: f32 = 1e-3;
+
+        let mut problems = Vec::new();
+        let vertices = self.joints.len();
+
+        if self.weights.len() != vertices {
+            problems.push(format!(
+                "the skin had weights for {} vertices instead of {vertices} (resized)",
+                self.weights.len()
+            ));
+            self.weights.resize(vertices, [0.; 4]);
+        }
+
+        let second_set_present = !self.joints_1.is_empty() || !self.weights_1.is_empty();
+        if second_set_present
+            && (self.joints_1.len() != vertices || self.weights_1.len() != vertices)
+        {
+            problems.push(format!(
+                "the second set of influences had {} joints and {} weights for {vertices} vertices (ignored)",
+                self.joints_1.len(),
+                self.weights_1.len()
+            ));
+            self.joints_1.clear();
+            self.weights_1.clear();
+        }
+
+        let mut negative = 0;
+        let mut out_of_range = 0;
+        let mut unnormalized = 0;
+        let mut no_influence = 0;
+
+        let second_set = !self.joints_1.is_empty();
+
+        // Both sets of influences are processed together
+        let count = if second_set { 8 } else { 4 };
+
+        for v in 0..self.joints.len() {
+            let mut joints = [0u32; 8];
+            let mut weights = [0f32; 8];
+            joints[..4].copy_from_slice(&self.joints[v]);
+            weights[..4].copy_from_slice(&self.weights[v]);
+            if second_set {
+                joints[4..].copy_from_slice(&self.joints_1[v]);
+                weights[4..].copy_from_slice(&self.weights_1[v]);
+            }
+
+            let mut has_negative = false;
+            let mut has_out_of_range = false;
+
+            for (joint, weight) in joints[..count].iter_mut().zip(&mut weights[..count]) {
+                if weight.is_nan() || *weight < 0. {
+                    has_negative = true;
+                    *weight = 0.;
+                }
+
+                if *joint as usize >= joint_count {
+                    has_out_of_range |= *weight != 0.;
+                    *weight = 0.;
+                    // Keep the index in the bounds of the joint matrices array
+                    *joint = 0;
+                }
+            }
+
+            negative += has_negative as usize;
+            out_of_range += has_out_of_range as usize;
+
+            let sum: f32 = weights[..count].iter().sum();
+            if sum == 0. {
+                no_influence += 1;
+                joints[0] = 0;
+                weights[0] = 1.;
+            } else if (sum - 1.).abs() > SUM_TOLERANCE {
+                unnormalized += 1;
+                for weight in &mut weights[..count] {
+                    *weight /= sum;
+                }
+            }
+
+            self.joints[v].copy_from_slice(&joints[..4]);
+            self.weights[v].copy_from_slice(&weights[..4]);
+            if second_set {
+                self.joints_1[v].copy_from_slice(&joints[4..]);
+                self.weights_1[v].copy_from_slice(&weights[4..]);
+            }
+        }
+
+        if negative > 0 {
+            problems.push(format!(
+                "{negative} vertices had negative or NaN weights (clamped to 0)"
+            ));
+        }
+        if out_of_range > 0 {
+            problems.push(format!(
+                "{out_of_range} vertices referenced joints past the {joint_count} joints of the skin (weights zeroed)"
+            ));
+        }
+        if unnormalized > 0 {
+            problems.push(format!(
+                "{unnormalized} vertices had weights that didn't sum to 1 (normalized)"
+            ));
+        }
+        if no_influence > 0 {
+            problems.push(format!(
+                "{no_influence} vertices weren't influenced by any joint (bound to the first joint)"
+            ));
+        }
+
+        problems
     }
 }
 
@@ -364,6 +1242,15 @@ impl Indices {
         }
     }
 
+    /// Iterates over the indices converted to u32
+    pub fn iter(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Indices::U32(buf) => Box::new(buf.iter().copied()),
+            Indices::U16(buf) => Box::new(buf.iter().map(|i| *i as u32)),
+            Indices::U8(buf) => Box::new(buf.iter().map(|i| *i as u32)),
+        }
+    }
+
     /// A GL_TYPE corresponding to the variant of the buffer
     pub fn gl_type(&self) -> GLenum {
         match self {
@@ -373,3 +1260,197 @@ impl Indices {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_keeps_valid_skin() {
+        let mut skin = PrimSkin::new(vec![[0, 1, 0, 0]], vec![[0.25, 0.75, 0., 0.]]);
+
+        assert!(skin.sanitize(2).is_empty());
+        assert_eq!(skin.joints, vec![[0, 1, 0, 0]]);
+        assert_eq!(skin.weights, vec![[0.25, 0.75, 0., 0.]]);
+    }
+
+    #[test]
+    fn sanitize_clamps_negative_and_nan_weights() {
+        let mut skin = PrimSkin::new(
+            vec![[0, 1, 0, 0], [1, 0, 0, 0]],
+            vec![[1., -0.5, 0., 0.], [1., f32::NAN, 0., 0.]],
+        );
+
+        let problems = skin.sanitize(2);
+
+        assert_eq!(skin.weights, vec![[1., 0., 0., 0.], [1., 0., 0., 0.]]);
+        assert_eq!(
+            problems,
+            vec!["2 vertices had negative or NaN weights (clamped to 0)"]
+        );
+    }
+
+    #[test]
+    fn sanitize_zeroes_out_of_range_joints() {
+        let mut skin = PrimSkin::new(vec![[0, 5, 0, 0]], vec![[0.5, 0.5, 0., 0.]]);
+
+        let problems = skin.sanitize(2);
+
+        // The remaining weight is renormalized
+        assert_eq!(skin.joints, vec![[0, 0, 0, 0]]);
+        assert_eq!(skin.weights, vec![[1., 0., 0., 0.]]);
+        assert_eq!(
+            problems,
+            vec![
+                "1 vertices referenced joints past the 2 joints of the skin (weights zeroed)",
+                "1 vertices had weights that didn't sum to 1 (normalized)",
+            ]
+        );
+    }
+
+    #[test]
+    fn sanitize_normalizes_weights() {
+        let mut skin = PrimSkin::new(
+            vec![[0, 1, 0, 0], [0, 1, 0, 0]],
+            vec![[1., 3., 0., 0.], [0.5, 0.4996, 0., 0.]],
+        );
+
+        let problems = skin.sanitize(2);
+
+        // The second vertex is within the tolerance of the quantized weights
+        assert_eq!(
+            skin.weights,
+            vec![[0.25, 0.75, 0., 0.], [0.5, 0.4996, 0., 0.]]
+        );
+        assert_eq!(
+            problems,
+            vec!["1 vertices had weights that didn't sum to 1 (normalized)"]
+        );
+    }
+
+    #[test]
+    fn sanitize_binds_vertices_without_influence() {
+        let mut skin = PrimSkin::new(vec![[1, 1, 1, 1]], vec![[0.; 4]]);
+
+        let problems = skin.sanitize(2);
+
+        assert_eq!(skin.joints, vec![[0, 1, 1, 1]]);
+        assert_eq!(skin.weights, vec![[1., 0., 0., 0.]]);
+        assert_eq!(
+            problems,
+            vec!["1 vertices weren't influenced by any joint (bound to the first joint)"]
+        );
+    }
+
+    #[test]
+    fn sanitize_normalizes_both_sets_together() {
+        let mut skin = PrimSkin::new(vec![[0, 0, 0, 0]], vec![[1., 0., 0., 0.]]);
+        skin.joints_1 = vec![[1, 0, 0, 0]];
+        skin.weights_1 = vec![[1., 0., 0., 0.]];
+
+        let problems = skin.sanitize(2);
+
+        assert_eq!(skin.weights, vec![[0.5, 0., 0., 0.]]);
+        assert_eq!(skin.weights_1, vec![[0.5, 0., 0., 0.]]);
+        assert_eq!(
+            problems,
+            vec!["1 vertices had weights that didn't sum to 1 (normalized)"]
+        );
+    }
+
+    #[test]
+    fn sanitize_fixes_mismatched_lengths() {
+        let mut skin = PrimSkin::new(vec![[0, 0, 0, 0], [1, 0, 0, 0]], vec![[1., 0., 0., 0.]]);
+        skin.joints_1 = vec![[1, 0, 0, 0], [0, 0, 0, 0]];
+        skin.weights_1 = vec![[0.5, 0., 0., 0.]];
+
+        let problems = skin.sanitize(2);
+
+        assert_eq!(skin.weights, vec![[1., 0., 0., 0.], [1., 0., 0., 0.]]);
+        assert!(skin.joints_1.is_empty());
+        assert!(skin.weights_1.is_empty());
+        assert_eq!(
+            problems,
+            vec![
+                "the skin had weights for 1 vertices instead of 2 (resized)",
+                "the second set of influences had 2 joints and 1 weights for 2 vertices (ignored)",
+                "1 vertices weren't influenced by any joint (bound to the first joint)",
+            ]
+        );
+    }
+
+    /// Vertices along the X axis, the first bone goes from 0 to 1 and the second one from 1 to 2
+    fn two_bone_strip() -> Primitive {
+        let positions = vec![
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(1.5, 0., 0.),
+            Vec3::new(2., 0., 0.),
+        ];
+        let normals = vec![Vec3::Y; 4];
+
+        let mut prim = Primitive::new_static(
+            vec![0, 1, 2, 1, 3, 2],
+            positions,
+            Vec::new(),
+            normals,
+            PrimitiveMaterial::new(),
+            None,
+        );
+
+        prim.skin = Some(PrimSkin::new(
+            vec![[0, 0, 0, 0], [1, 0, 0, 0], [0, 1, 0, 0], [1, 0, 0, 0]],
+            vec![
+                [1., 0., 0., 0.],
+                [1., 0., 0., 0.],
+                [0.5, 0.5, 0., 0.],
+                [1., 0., 0., 0.],
+            ],
+        ));
+
+        prim
+    }
+
+    #[test]
+    fn skin_on_cpu_bends_two_bone_strip() {
+        let prim = two_bone_strip();
+
+        // The second bone is rotated by 90 degrees around Z at its start
+        let bend = Mat4::from_translation(Vec3::X)
+            * Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2)
+            * Mat4::from_translation(-Vec3::X);
+        let joint_matrices = [Mat4::IDENTITY, bend];
+
+        let skinned = prim.skin_on_cpu(&joint_matrices);
+
+        let expected = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            // Halfway between (1.5, 0, 0) and (1, 0.5, 0)
+            Vec3::new(1.25, 0.25, 0.),
+            Vec3::new(1., 1., 0.),
+        ];
+
+        for (skinned, expected) in skinned.iter().zip(expected) {
+            assert!(
+                skinned.abs_diff_eq(expected, 1e-6),
+                "{skinned} != {expected}"
+            );
+        }
+
+        // The normals of the second bone are rotated with it
+        let (normals, tangents) = prim.skin_normals_on_cpu(&joint_matrices);
+        assert!(normals[0].abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(normals[3].abs_diff_eq(-Vec3::X, 1e-6));
+        assert!(tangents.is_empty());
+    }
+
+    #[test]
+    fn skin_on_cpu_with_identity_matrices_keeps_bind_pose() {
+        let prim = two_bone_strip();
+
+        let skinned = prim.skin_on_cpu(&[Mat4::IDENTITY; 2]);
+
+        assert_eq!(skinned, prim.positions);
+    }
+}
@@ -0,0 +1,283 @@
+use glam::Mat4;
+
+use super::{Animation, AnimationTransform, Joint, Transform};
+
+/// A struct that holds which transforms should be aplied to which nodes for the current frame
+pub struct NodeAnimationTransform {
+    /// Index of the node
+    pub node: usize,
+    /// Transform that should overwrite the node's current transform
+    pub transform: AnimationTransform,
+}
+
+/// Interpolates the channels of the animation at 'time'.
+///
+/// Channels of the nodes masked out of the animation are skipped.
+pub fn sample_animation(animation: &Animation, time: f32) -> Vec<NodeAnimationTransform> {
+    animation
+        .sample(time)
+        .map(|(node, transform)| NodeAnimationTransform { node, transform })
+        .collect()
+}
+
+/// Overwrites the transforms of the joints animated by the sampled transforms
+pub fn apply_animation(joints: &mut [Joint], transforms: &[NodeAnimationTransform]) {
+    for joint in joints {
+        for nat in transforms {
+            if joint.node_index == nat.node {
+                nat.transform.apply_to(&mut joint.transform);
+            }
+        }
+    }
+}
+
+/// Calculates the world transforms of the joints.
+///
+/// Parent joints are always placed before child joints in the buffer, so they are processed first
+/// and the transformation can be cascaded from parents to children.
+pub fn compute_world_transforms(joints: &[Joint], outer_transform: Mat4) -> Vec<Mat4> {
+    let transforms: Vec<Transform> = joints.iter().map(|joint| joint.transform).collect();
+    cascade(joints, &transforms, outer_transform)
+}
+
+/// World transforms of the joints posed by the animation at 'time'.
+///
+/// The same computation as the renderer does for a frame, but the joints aren't modified
+/// (and the root motion isn't extracted).
+pub fn compute_sampled_world_transforms(
+    joints: &[Joint],
+    animation: &Animation,
+    time: f32,
+    outer_transform: Mat4,
+) -> Vec<Mat4> {
+    let mut transforms: Vec<Transform> = joints.iter().map(|joint| joint.transform).collect();
+
+    for nat in sample_animation(animation, time) {
+        for (joint, joint_transform) in joints.iter().zip(&mut transforms) {
+            if joint.node_index == nat.node {
+                nat.transform.apply_to(joint_transform);
+            }
+        }
+    }
+
+    cascade(joints, &transforms, outer_transform)
+}
+
+/// The matrices uploaded for skinning, the world transforms combined with the inverse bind matrices
+pub fn compute_skin_matrices(joints: &[Joint], world_transforms: &[Mat4]) -> Vec<Mat4> {
+    joints
+        .iter()
+        .zip(world_transforms)
+        .map(|(joint, world)| *world * joint.inverse_bind_matrix)
+        .collect()
+}
+
+/// Multiplies the local transforms down the hierarchy (parents come before their children)
+fn cascade(joints: &[Joint], transforms: &[Transform], outer_transform: Mat4) -> Vec<Mat4> {
+    let mut world_transforms = vec![Mat4::IDENTITY; joints.len()];
+
+    for (i, joint) in joints.iter().enumerate() {
+        world_transforms[i] = match joint.parent {
+            Some(parent_index) => world_transforms[parent_index] * transforms[i].matrix(),
+            None => outer_transform * transforms[i].matrix(),
+        };
+    }
+
+    world_transforms
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+    use gltf::animation::Interpolation;
+
+    use super::*;
+    use crate::model::{AnimationControl, AnimationTransforms, Animations, Channel};
+
+    fn translation(t: Vec3) -> Transform {
+        Transform {
+            translation: t,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+
+    /// Node 0 moves from (0, 0, 0) at 1 s to (2, 0, 0) at 2 s and to (2, 4, 0) at 3 s
+    fn animation() -> Animation {
+        let channel = Channel::new(
+            0,
+            vec![1., 2., 3.],
+            AnimationTransforms::Translations(vec![
+                Vec3::ZERO,
+                Vec3::new(2., 0., 0.),
+                Vec3::new(2., 4., 0.),
+            ]),
+            Interpolation::Linear,
+        );
+
+        Animation::new(vec![channel], 0., 3., None)
+    }
+
+    fn sampled_translation(animation: &Animation, time: f32) -> Vec3 {
+        let sampled = sample_animation(animation, time);
+        assert_eq!(sampled.len(), 1);
+        assert_eq!(sampled[0].node, 0);
+
+        match sampled[0].transform {
+            AnimationTransform::Translation(t) => t,
+            _ => panic!("Expected a translation"),
+        }
+    }
+
+    /// A root at (0, 1, 0) rotated by 90 degrees around Z and its child 2 units along X
+    fn two_joint_chain() -> Vec<Joint> {
+        let root_transform = Transform {
+            translation: Vec3::new(0., 1., 0.),
+            rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            scale: Vec3::ONE,
+        };
+
+        let root_bind = Mat4::from_translation(Vec3::new(0., 1., 0.));
+        let child_bind = Mat4::from_translation(Vec3::new(2., 1., 0.));
+
+        vec![
+            Joint::new(0, None, root_bind.inverse(), root_transform, "root".into()),
+            Joint::new(
+                1,
+                Some(0),
+                child_bind.inverse(),
+                translation(Vec3::new(2., 0., 0.)),
+                "child".into(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn sample_before_first_key_holds_first_key() {
+        assert_eq!(sampled_translation(&animation(), 0.5), Vec3::ZERO);
+    }
+
+    #[test]
+    fn sample_on_key_returns_key() {
+        let animation = animation();
+        assert_eq!(sampled_translation(&animation, 1.), Vec3::ZERO);
+        assert_eq!(sampled_translation(&animation, 2.), Vec3::new(2., 0., 0.));
+        assert_eq!(sampled_translation(&animation, 3.), Vec3::new(2., 4., 0.));
+    }
+
+    #[test]
+    fn sample_between_keys_interpolates() {
+        let animation = animation();
+        assert_eq!(sampled_translation(&animation, 1.5), Vec3::new(1., 0., 0.));
+        assert_eq!(sampled_translation(&animation, 2.25), Vec3::new(2., 1., 0.));
+    }
+
+    #[test]
+    fn sample_past_last_key_holds_last_key() {
+        assert_eq!(
+            sampled_translation(&animation(), 10.),
+            Vec3::new(2., 4., 0.)
+        );
+    }
+
+    #[test]
+    fn sample_after_loop_wrap() {
+        let mut animations = Animations {
+            animations: vec![animation()],
+            animation_control: AnimationControl::Loop {
+                active_animation: 0,
+            },
+            sync_group: None,
+        };
+
+        animations.animations[0].current_time = 2.5;
+        animations.advance(1.);
+
+        let anim = &animations.animations[0];
+        assert!((anim.current_time - 0.5).abs() < 1e-6);
+        assert_eq!(sampled_translation(anim, anim.current_time), Vec3::ZERO);
+    }
+
+    #[test]
+    fn sample_skips_masked_nodes() {
+        let mut animation = animation();
+        animation.mask = Some([1].into_iter().collect());
+
+        assert!(sample_animation(&animation, 1.5).is_empty());
+    }
+
+    #[test]
+    fn world_transforms_of_two_joint_chain() {
+        let joints = two_joint_chain();
+        let outer = Mat4::from_translation(Vec3::new(0., 0., 5.));
+
+        let world = compute_world_transforms(&joints, outer);
+
+        let rotation = Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let root = Mat4::from_translation(Vec3::new(0., 1., 5.)) * rotation;
+        // The child is rotated with the root, so it ends up above it
+        let child = Mat4::from_translation(Vec3::new(0., 3., 5.)) * rotation;
+
+        assert!(world[0].abs_diff_eq(root, 1e-6));
+        assert!(world[1].abs_diff_eq(child, 1e-6));
+        assert!(world[1]
+            .w_axis
+            .truncate()
+            .abs_diff_eq(Vec3::new(0., 3., 5.), 1e-6));
+    }
+
+    #[test]
+    fn skin_matrices_of_two_joint_chain() {
+        let joints = two_joint_chain();
+
+        // In the bind pose (no rotation) the skin matrices are identities
+        let mut bind_pose = two_joint_chain();
+        bind_pose[0].transform = translation(Vec3::new(0., 1., 0.));
+        let world = compute_world_transforms(&bind_pose, Mat4::IDENTITY);
+        for skin in compute_skin_matrices(&bind_pose, &world) {
+            assert!(skin.abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        }
+
+        let world = compute_world_transforms(&joints, Mat4::IDENTITY);
+        let skin = compute_skin_matrices(&joints, &world);
+
+        // A vertex at the child joint in the bind pose moves with the child
+        let vertex = Vec3::new(2., 1., 0.);
+        assert!(skin[1]
+            .transform_point3(vertex)
+            .abs_diff_eq(Vec3::new(0., 3., 0.), 1e-6));
+
+        // A vertex at the root is only rotated around it
+        let vertex = Vec3::new(1., 1., 0.);
+        assert!(skin[0]
+            .transform_point3(vertex)
+            .abs_diff_eq(Vec3::new(0., 2., 0.), 1e-6));
+    }
+
+    #[test]
+    fn sampled_world_transforms_leave_joints_unchanged() {
+        let joints = vec![Joint::new(
+            0,
+            None,
+            Mat4::IDENTITY,
+            translation(Vec3::ZERO),
+            "root".into(),
+        )];
+
+        let world = compute_sampled_world_transforms(&joints, &animation(), 2., Mat4::IDENTITY);
+
+        assert_eq!(world[0].w_axis.truncate(), Vec3::new(2., 0., 0.));
+        assert_eq!(joints[0].transform.translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn apply_animation_overwrites_animated_joints() {
+        let mut joints = two_joint_chain();
+        let sampled = sample_animation(&animation(), 2.);
+
+        apply_animation(&mut joints, &sampled);
+
+        assert_eq!(joints[0].transform.translation, Vec3::new(2., 0., 0.));
+        assert_eq!(joints[1].transform.translation, Vec3::new(2., 0., 0.));
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use super::{Joint, Transform};
+
+/// Maximum number of edits that can be undone
+const MAX_EDITS: usize = 256;
+
+/// A change of a joint transform made in the GUI
+struct PoseEdit {
+    /// Index of the joint in the skin
+    joint: usize,
+    old: Transform,
+    new: Transform,
+}
+
+/// Undo / redo stacks of the joint edits made in the GUI.
+///
+/// Only edits made by the user are recorded, animations change the joints directly.
+pub struct PoseHistory {
+    undo: VecDeque<PoseEdit>,
+    redo: Vec<PoseEdit>,
+    /// Joint index and its transform at the start of the current drag
+    drag_start: Option<(usize, Transform)>,
+    /// Joints changed since the last call to `take_edited` (for inserting keyframes)
+    edited: BTreeSet<usize>,
+}
+
+impl PoseHistory {
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            drag_start: None,
+            edited: BTreeSet::new(),
+        }
+    }
+
+    /// Records a finished edit, the redo stack is discarded
+    pub fn record(&mut self, joint: usize, old: Transform, new: Transform) {
+        if old == new {
+            return;
+        }
+
+        if self.undo.len() == MAX_EDITS {
+            self.undo.pop_front();
+        }
+
+        self.edited.insert(joint);
+        self.undo.push_back(PoseEdit { joint, old, new });
+        self.redo.clear();
+    }
+
+    /// Remembers the transform at the start of a drag, the whole drag is recorded as one edit
+    pub fn begin_drag(&mut self, joint: usize, transform: Transform) {
+        self.drag_start = Some((joint, transform));
+    }
+
+    /// Records the drag started by `begin_drag`
+    pub fn end_drag(&mut self, joint: usize, transform: Transform) {
+        match self.drag_start.take() {
+            Some((start_joint, old)) if start_joint == joint => self.record(joint, old, transform),
+            _ => {}
+        }
+    }
+
+    /// Reverts the last edit, returns false if there was nothing to undo
+    pub fn undo(&mut self, joints: &mut [Joint]) -> bool {
+        match self.undo.pop_back() {
+            Some(edit) => {
+                if let Some(joint) = joints.get_mut(edit.joint) {
+                    joint.transform = edit.old;
+                }
+
+                self.edited.insert(edit.joint);
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone edit again, returns false if there was nothing to redo
+    pub fn redo(&mut self, joints: &mut [Joint]) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                if let Some(joint) = joints.get_mut(edit.joint) {
+                    joint.transform = edit.new;
+                }
+
+                self.edited.insert(edit.joint);
+                self.undo.push_back(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks a joint changed outside of the recorded edits (eg. by a reset)
+    pub fn mark_edited(&mut self, joint: usize) {
+        self.edited.insert(joint);
+    }
+
+    /// Returns the joints changed since the last call and forgets them
+    pub fn take_edited(&mut self) -> BTreeSet<usize> {
+        std::mem::take(&mut self.edited)
+    }
+
+    /// Number of joints changed since the last call to `take_edited`
+    pub fn edited_count(&self) -> usize {
+        self.edited.len()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
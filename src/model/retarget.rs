@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use super::{Animation, AnimationTransforms, Channel, Joint};
+
+/// Bones shorter than this don't scale the translations
+const MIN_BONE_LENGTH: f32 = 1e-5;
+
+/// An animation retargeted onto a different skeleton
+pub struct Retargeted {
+    pub animation: Animation,
+    /// Descriptions of the channels that couldn't be matched to the target skeleton
+    pub dropped: Vec<String>,
+}
+
+impl Retargeted {
+    /// Creates a copy of the animation for the target skeleton.
+    ///
+    /// Channels are matched by joint name, 'mapping' renames the source joints whose names differ.
+    /// Translations are scaled by the ratio of the lengths of the corresponding bones
+    /// (the bind pose translations of the joints).
+    pub fn new(
+        animation: &Animation,
+        source: &[Joint],
+        target: &[Joint],
+        mapping: &HashMap<String, String>,
+    ) -> Self {
+        let mut channels = Vec::new();
+        let mut dropped = Vec::new();
+
+        for channel in &animation.channels {
+            let source_joint = match source.iter().find(|j| j.node_index == channel.node) {
+                Some(joint) => joint,
+                None => {
+                    dropped.push(format!("Node {} isn't a joint", channel.node));
+                    continue;
+                }
+            };
+
+            let name = mapping
+                .get(&source_joint.name)
+                .unwrap_or(&source_joint.name);
+
+            let target_joint = match target.iter().find(|j| j.name == *name) {
+                Some(joint) => joint,
+                None => {
+                    dropped.push(format!("Joint '{name}' doesn't exist in the target"));
+                    continue;
+                }
+            };
+
+            let transforms = match &channel.transforms {
+                AnimationTransforms::Translations(translations) => {
+                    let source_length = source_joint.bind_transform.translation.length();
+                    let target_length = target_joint.bind_transform.translation.length();
+
+                    let ratio = if source_length > MIN_BONE_LENGTH {
+                        target_length / source_length
+                    } else {
+                        1.
+                    };
+
+                    AnimationTransforms::Translations(
+                        translations.iter().map(|t| *t * ratio).collect(),
+                    )
+                }
+                AnimationTransforms::Rotations(rotations) => {
+                    AnimationTransforms::Rotations(rotations.clone())
+                }
+                AnimationTransforms::Scales(scales) => AnimationTransforms::Scales(scales.clone()),
+            };
+
+            channels.push(Channel::new(
+                target_joint.node_index,
+                channel.keyframe_times.clone(),
+                transforms,
+                channel.interpolation_type,
+            ));
+        }
+
+        let name = format!(
+            "{} (retargeted)",
+            animation.name.as_deref().unwrap_or("Unnamed")
+        );
+
+        Self {
+            animation: Animation::new(channels, 0., animation.end_time, Some(name)),
+            dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+    use gltf::animation::Interpolation;
+
+    use super::*;
+    use crate::model::Transform;
+
+    fn joint(node: usize, parent: Option<usize>, bone: Vec3, name: &str) -> Joint {
+        let transform = Transform {
+            translation: bone,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        };
+
+        Joint::new(node, parent, Mat4::IDENTITY, transform, name.into())
+    }
+
+    /// A hip and a knee 2 units below it
+    fn source() -> Vec<Joint> {
+        vec![
+            joint(0, None, Vec3::ZERO, "Hips"),
+            joint(1, Some(0), Vec3::new(0., -2., 0.), "Knee"),
+        ]
+    }
+
+    fn translations(node: usize, keys: Vec<Vec3>) -> Channel {
+        let times = (0..keys.len()).map(|i| i as f32).collect();
+        Channel::new(
+            node,
+            times,
+            AnimationTransforms::Translations(keys),
+            Interpolation::Linear,
+        )
+    }
+
+    fn translations_of(animation: &Animation, node: usize) -> &[Vec3] {
+        let channel = animation
+            .channels
+            .iter()
+            .find(|c| c.node == node)
+            .expect("The channel wasn't retargeted");
+
+        match &channel.transforms {
+            AnimationTransforms::Translations(t) => t,
+            _ => panic!("Expected translations"),
+        }
+    }
+
+    #[test]
+    fn joints_are_matched_by_mapped_names() {
+        let target = vec![
+            joint(10, None, Vec3::ZERO, "pelvis"),
+            joint(11, Some(0), Vec3::new(0., -2., 0.), "Knee"),
+        ];
+        let mapping = HashMap::from([("Hips".to_string(), "pelvis".to_string())]);
+
+        let rotation = Channel::new(
+            0,
+            vec![0., 1.],
+            AnimationTransforms::Rotations(vec![Quat::IDENTITY, Quat::from_rotation_y(1.)]),
+            Interpolation::Linear,
+        );
+        let animation = Animation::new(
+            vec![rotation, translations(1, vec![Vec3::ZERO, Vec3::X])],
+            0.,
+            1.,
+            Some("Walk".to_string()),
+        );
+
+        let retargeted = Retargeted::new(&animation, &source(), &target, &mapping);
+
+        assert!(retargeted.dropped.is_empty());
+        assert_eq!(
+            retargeted.animation.name.as_deref(),
+            Some("Walk (retargeted)")
+        );
+        assert_eq!(retargeted.animation.end_time, 1.);
+
+        let nodes: Vec<usize> = retargeted
+            .animation
+            .channels
+            .iter()
+            .map(|c| c.node)
+            .collect();
+        assert_eq!(nodes, vec![10, 11]);
+        assert_eq!(
+            retargeted.animation.channels[0].keyframe_times,
+            vec![0., 1.]
+        );
+        // The bones have the same length
+        assert_eq!(
+            translations_of(&retargeted.animation, 11),
+            &[Vec3::ZERO, Vec3::X]
+        );
+    }
+
+    #[test]
+    fn unmatched_channels_are_dropped() {
+        let target = vec![joint(10, None, Vec3::ZERO, "Hips")];
+        let animation = Animation::new(
+            vec![
+                translations(0, vec![Vec3::X]),
+                translations(1, vec![Vec3::X]),
+                // Not a joint of the source skeleton
+                translations(7, vec![Vec3::X]),
+            ],
+            0.,
+            0.,
+            None,
+        );
+
+        let retargeted = Retargeted::new(&animation, &source(), &target, &HashMap::new());
+
+        assert_eq!(retargeted.animation.channels.len(), 1);
+        assert_eq!(retargeted.animation.channels[0].node, 10);
+        assert_eq!(
+            retargeted.animation.name.as_deref(),
+            Some("Unnamed (retargeted)")
+        );
+        assert_eq!(
+            retargeted.dropped,
+            vec![
+                "Joint 'Knee' doesn't exist in the target",
+                "Node 7 isn't a joint",
+            ]
+        );
+    }
+
+    #[test]
+    fn translations_are_scaled_by_bone_length_ratio() {
+        // The knee of the target is 3 units below the hips instead of 2
+        let target = vec![
+            joint(0, None, Vec3::ZERO, "Hips"),
+            joint(1, Some(0), Vec3::new(0., -3., 0.), "Knee"),
+        ];
+        let animation = Animation::new(
+            vec![translations(
+                1,
+                vec![Vec3::new(0., -2., 0.), Vec3::new(2., -2., 0.)],
+            )],
+            0.,
+            1.,
+            None,
+        );
+
+        let retargeted = Retargeted::new(&animation, &source(), &target, &HashMap::new());
+
+        assert_eq!(
+            translations_of(&retargeted.animation, 1),
+            &[Vec3::new(0., -3., 0.), Vec3::new(3., -3., 0.)]
+        );
+    }
+
+    #[test]
+    fn zero_length_bones_keep_translations() {
+        // The hips of the source are at the origin (a bone shorter than MIN_BONE_LENGTH)
+        let target = vec![joint(0, None, Vec3::new(0., 5., 0.), "Hips")];
+        let animation = Animation::new(
+            vec![translations(0, vec![Vec3::new(1., 2., 3.)])],
+            0.,
+            0.,
+            None,
+        );
+
+        let retargeted = Retargeted::new(&animation, &source(), &target, &HashMap::new());
+
+        assert_eq!(
+            translations_of(&retargeted.animation, 0),
+            &[Vec3::new(1., 2., 3.)]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::{mem::size_of_val, ops::AddAssign};
+
+use super::mesh::Primitive;
+
+/// Size statistics of a model, a node subtree, a mesh or a primitive.
+///
+/// Computed when the model is loaded, the sums of the subtrees are computed on demand.
+#[derive(Clone, Copy, Default)]
+pub struct Statistics {
+    pub vertices: usize,
+    pub triangles: usize,
+    pub primitives: usize,
+    /// Size of the vertex attributes (including the skin) and the indices in bytes
+    pub vertex_bytes: usize,
+    /// Estimated size of the textures on the GPU in bytes (including the mipmaps)
+    pub texture_bytes: usize,
+    pub joints: usize,
+    pub animations: usize,
+    /// Instances of the instanced meshes (EXT_mesh_gpu_instancing)
+    pub instances: usize,
+}
+
+impl Statistics {
+    /// Statistics of a single primitive
+    pub fn from_primitive(prim: &Primitive) -> Self {
+        let mut vertex_bytes = size_of_val(prim.positions.as_slice())
+            + size_of_val(prim.texcoords.as_slice())
+            + size_of_val(prim.normals.as_slice())
+            + size_of_val(prim.tangents.as_slice())
+            + prim.indices.size();
+
+        if let Some(skin) = &prim.skin {
+            vertex_bytes += size_of_val(skin.joints.as_slice())
+                + size_of_val(skin.weights.as_slice())
+                + size_of_val(skin.joints_1.as_slice())
+                + size_of_val(skin.weights_1.as_slice());
+        }
+
+        Self {
+            vertices: prim.positions.len(),
+            triangles: prim.indices.len() / 3,
+            primitives: 1,
+            vertex_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Estimated GPU memory of a texture with a full mipmap chain (a third more than the image)
+    pub fn texture_bytes(image: &gltf::image::Data) -> usize {
+        image.pixels.len() * 4 / 3
+    }
+
+    /// A multi-line description for the GUI
+    pub fn summary(&self) -> String {
+        format!(
+            "Vertices: {}\nTriangles: {}\nPrimitives: {}\nVertex data: {:.2} MiB\nTextures: {:.2} MiB\nJoints: {}\nAnimations: {}\nInstances: {}",
+            self.vertices,
+            self.triangles,
+            self.primitives,
+            self.vertex_bytes as f32 / (1024. * 1024.),
+            self.texture_bytes as f32 / (1024. * 1024.),
+            self.joints,
+            self.animations,
+            self.instances
+        )
+    }
+}
+
+impl AddAssign for Statistics {
+    fn add_assign(&mut self, other: Self) {
+        self.vertices += other.vertices;
+        self.triangles += other.triangles;
+        self.primitives += other.primitives;
+        self.vertex_bytes += other.vertex_bytes;
+        self.texture_bytes += other.texture_bytes;
+        self.joints += other.joints;
+        self.animations += other.animations;
+        self.instances += other.instances;
+    }
+}
@@ -0,0 +1,72 @@
+use glam::{Vec2, Vec3, Vec4};
+
+use super::mesh::Indices;
+
+/// Generates per-vertex tangents for primitives that don't contain them.
+///
+/// Follows the same idea as MikkTSpace - tangents of each triangle are computed from the UV
+/// derivatives, accumulated per-vertex, orthogonalized against the normal (Gram-Schmidt)
+/// and the bitangent sign is stored in the w component.
+/// It doesn't split vertices on UV seams, so the result isn't bit-exact with MikkTSpace.
+pub fn compute_tangents(
+    positions: &[Vec3],
+    normals: &[Vec3],
+    texcoords: &[Vec2],
+    indices: &Indices,
+) -> Vec<Vec4> {
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
+
+    let indices: Vec<usize> = indices.iter().map(|i| i as usize).collect();
+
+    for tri in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (tri[0], tri[1], tri[2]);
+
+        let edge1 = positions[i1] - positions[i0];
+        let edge2 = positions[i2] - positions[i0];
+        let duv1 = texcoords[i1] - texcoords[i0];
+        let duv2 = texcoords[i2] - texcoords[i0];
+
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        if det.abs() < f32::EPSILON {
+            // Degenerate UV mapping, this triangle can't contribute
+            continue;
+        }
+
+        let r = 1. / det;
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    tangents
+        .iter()
+        .zip(bitangents.iter())
+        .zip(normals.iter())
+        .map(|((t, b), n)| {
+            let n = n.normalize_or_zero();
+            let tangent = (*t - n * n.dot(*t)).normalize_or_zero();
+
+            let tangent = if tangent != Vec3::ZERO {
+                tangent
+            } else if n != Vec3::ZERO {
+                // Pick any vector perpendicular to the normal
+                n.any_orthonormal_vector()
+            } else {
+                Vec3::X
+            };
+
+            let handedness = if n.cross(tangent).dot(*b) < 0. {
+                -1.
+            } else {
+                1.
+            };
+
+            tangent.extend(handedness)
+        })
+        .collect()
+}
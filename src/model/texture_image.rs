@@ -0,0 +1,132 @@
+use gltf::image::{Data, Format};
+use image::{imageops::FilterType, RgbaImage};
+
+/// Converts the image into a format the textures are created from.
+///
+/// 8-bit RGB and RGBA images are kept, the other formats (grayscale, BGR, 16-bit) are converted
+/// to 8-bit RGBA. Images with dimensions that aren't powers of two are scaled up
+/// (textures are mipmapped).
+pub(super) fn prepare(image: Data) -> Data {
+    let power_of_two = image.width.is_power_of_two() && image.height.is_power_of_two();
+    let supported = matches!(image.format, Format::R8G8B8 | Format::R8G8B8A8);
+
+    if power_of_two && supported {
+        return image;
+    }
+
+    let mut rgba = RgbaImage::from_raw(image.width, image.height, to_rgba8(&image))
+        .expect("The pixels match the dimensions");
+
+    if !power_of_two {
+        rgba = image::imageops::resize(
+            &rgba,
+            image.width.next_power_of_two(),
+            image.height.next_power_of_two(),
+            FilterType::Triangle,
+        );
+    }
+
+    Data {
+        width: rgba.width(),
+        height: rgba.height(),
+        format: Format::R8G8B8A8,
+        pixels: rgba.into_raw(),
+    }
+}
+
+/// Grayscale is spread into the color channels, 16-bit channels keep the high byte
+fn to_rgba8(image: &Data) -> Vec<u8> {
+    let (channels, wide) = match image.format {
+        Format::R8 => (1, false),
+        Format::R8G8 => (2, false),
+        Format::R8G8B8 | Format::B8G8R8 => (3, false),
+        Format::R8G8B8A8 | Format::B8G8R8A8 => (4, false),
+        Format::R16 => (1, true),
+        Format::R16G16 => (2, true),
+        Format::R16G16B16 => (3, true),
+        Format::R16G16B16A16 => (4, true),
+    };
+
+    let bytes = if wide { 2 } else { 1 };
+
+    image
+        .pixels
+        .chunks_exact(channels * bytes)
+        .flat_map(|pixel| {
+            // The 16-bit channels are in the native byte order
+            let channel = |i: usize| {
+                if wide {
+                    (u16::from_ne_bytes([pixel[2 * i], pixel[2 * i + 1]]) >> 8) as u8
+                } else {
+                    pixel[i]
+                }
+            };
+
+            match (image.format, channels) {
+                (Format::B8G8R8 | Format::B8G8R8A8, _) => {
+                    let alpha = if channels == 4 { channel(3) } else { 255 };
+                    [channel(2), channel(1), channel(0), alpha]
+                }
+                (_, 1) => [channel(0), channel(0), channel(0), 255],
+                (_, 2) => [channel(0), channel(0), channel(0), channel(1)],
+                (_, 3) => [channel(0), channel(1), channel(2), 255],
+                _ => [channel(0), channel(1), channel(2), channel(3)],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(format: Format, width: u32, height: u32, pixels: Vec<u8>) -> Data {
+        Data {
+            pixels,
+            format,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn power_of_two_rgba_is_kept() {
+        let pixels: Vec<u8> = (0..16).collect();
+        let image = prepare(data(Format::R8G8B8A8, 2, 2, pixels.clone()));
+
+        assert_eq!(image.format, Format::R8G8B8A8);
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn grayscale_and_bgr_are_converted() {
+        let gray = prepare(data(Format::R8G8, 1, 1, vec![100, 200]));
+        assert_eq!(gray.format, Format::R8G8B8A8);
+        assert_eq!(gray.pixels, vec![100, 100, 100, 200]);
+
+        let bgr = prepare(data(Format::B8G8R8, 1, 1, vec![1, 2, 3]));
+        assert_eq!(bgr.pixels, vec![3, 2, 1, 255]);
+    }
+
+    #[test]
+    fn wide_channels_keep_high_byte() {
+        let pixels = [0x1234u16, 0xabcd, 0xffff]
+            .iter()
+            .flat_map(|c| c.to_ne_bytes())
+            .collect();
+        let image = prepare(data(Format::R16G16B16, 1, 1, pixels));
+
+        assert_eq!(image.format, Format::R8G8B8A8);
+        assert_eq!(image.pixels, vec![0x12, 0xab, 0xff, 255]);
+    }
+
+    #[test]
+    fn non_power_of_two_is_scaled_up() {
+        let image = prepare(data(Format::R8G8B8, 3, 5, vec![50; 3 * 5 * 3]));
+
+        assert_eq!((image.width, image.height), (4, 8));
+        assert_eq!(image.format, Format::R8G8B8A8);
+        assert_eq!(image.pixels.len(), 4 * 8 * 4);
+        assert!(image.pixels.chunks_exact(4).all(|p| p == [50, 50, 50, 255]));
+    }
+}
@@ -2,6 +2,7 @@ use glam::{Mat4, Quat, Vec3};
 use gltf::scene::Transform as GTransform;
 
 /// Describes the transformation of a Node or a Joint
+#[derive(Clone, Copy, PartialEq)]
 pub struct Transform {
     /// Local translation relative to the parent joint
     pub translation: Vec3,
@@ -0,0 +1,217 @@
+use std::{collections::HashMap, path::Path};
+
+use eyre::Result;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use gltf::{image::Format, material::AlphaMode};
+use image::RgbaImage;
+
+use super::{
+    mesh::TextureSource, texture_image, AnimationControl, Animations, DataBundle, LoadStage, Mesh,
+    Model, Node, PoseHistory, Primitive, PrimitiveMaterial, StageTimer, Statistics, TextureBundle,
+};
+
+impl Model {
+    /// Loads a Wavefront OBJ file (with its MTL materials) as a static model (without animations).
+    ///
+    /// Every object of the file becomes a node with a single primitive. The diffuse color,
+    /// dissolve and diffuse texture of the materials are used, missing materials
+    /// and textures are reported in `warnings` (errors in the strict mode).
+    pub(super) fn load_obj(path: &str, strict: bool, timer: &mut StageTimer) -> Result<Model> {
+        timer.start(LoadStage::Import)?;
+        let (objects, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+        let name = Path::new(path)
+            .file_name()
+            .map(|osstr| osstr.to_string_lossy().to_string())
+            .unwrap_or_else(|| "N/A".to_string());
+
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+        let mut bundle = DataBundle::new(Vec::new(), strict);
+
+        let materials = match materials {
+            Ok(materials) => materials,
+            Err(e) => {
+                bundle.warn(format!("Couldn't load the materials: {e}"))?;
+                Vec::new()
+            }
+        };
+
+        let mut images = Vec::new();
+        // Maps the texture paths to the indices of the images
+        let mut image_indices: HashMap<String, usize> = HashMap::new();
+
+        timer.start(LoadStage::Meshes)?;
+
+        let mut nodes = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            let mesh = &object.mesh;
+            if mesh.indices.is_empty() {
+                continue;
+            }
+
+            let material = mesh.material_id.and_then(|id| materials.get(id));
+
+            let base_color = match material {
+                Some(material) if !material.diffuse_texture.is_empty() => {
+                    let texture = &material.diffuse_texture;
+
+                    let image = match image_indices.get(texture) {
+                        Some(&image) => Some(image),
+                        None => match load_image(&dir.join(texture)) {
+                            Ok(data) => {
+                                images.push(data);
+                                image_indices.insert(texture.clone(), images.len() - 1);
+                                Some(images.len() - 1)
+                            }
+                            Err(e) => {
+                                bundle.warn(format!("Couldn't load texture '{texture}': {e}"))?;
+                                None
+                            }
+                        },
+                    };
+
+                    image.map(|image| TextureSource::from_image(image, true))
+                }
+                _ => None,
+            };
+
+            let primitive = Primitive::new_static(
+                mesh.indices.clone(),
+                read_positions(mesh),
+                read_texcoords(mesh),
+                read_normals(mesh),
+                convert_material(material),
+                base_color,
+            );
+
+            nodes.push(Node {
+                index,
+                name: object.name.clone(),
+                children: Vec::new(),
+                mesh: Some(Mesh::from_primitives(
+                    vec![primitive],
+                    Some(object.name.clone()),
+                )),
+                lods: None,
+                transform: Mat4::IDENTITY,
+                joints: None,
+            });
+        }
+
+        let root = Node {
+            index: usize::MAX,
+            name: "Root".to_string(),
+            children: nodes,
+            mesh: None,
+            lods: None,
+            transform: Mat4::IDENTITY,
+            joints: None,
+        };
+
+        let mut stats = root.stats();
+        stats.texture_bytes = images.iter().map(Statistics::texture_bytes).sum();
+
+        let textures = TextureBundle::new(images, name.clone());
+
+        Ok(Model {
+            root,
+            name,
+            path: path.to_string(),
+            animations: Animations {
+                animations: Vec::new(),
+                animation_control: AnimationControl::Static,
+                sync_group: None,
+            },
+            transform: Mat4::IDENTITY,
+            textures: Some(textures),
+            warnings: bundle.warnings,
+            stats,
+            pose_history: PoseHistory::new(),
+            load_times: Vec::new(),
+            gl_textures: HashMap::new(),
+            filtering: None,
+        })
+    }
+}
+
+fn read_positions(mesh: &tobj::Mesh) -> Vec<Vec3> {
+    mesh.positions
+        .chunks_exact(3)
+        .map(Vec3::from_slice)
+        .collect()
+}
+
+/// OBJ texture coordinates start at the bottom of the image, gltf ones at the top.
+///
+/// Vertices without texture coordinates get zeroes (the shader always reads them).
+fn read_texcoords(mesh: &tobj::Mesh) -> Vec<Vec2> {
+    let vertex_count = mesh.positions.len() / 3;
+
+    if mesh.texcoords.len() != vertex_count * 2 {
+        return vec![Vec2::ZERO; vertex_count];
+    }
+
+    mesh.texcoords
+        .chunks_exact(2)
+        .map(|uv| Vec2::new(uv[0], 1. - uv[1]))
+        .collect()
+}
+
+/// Smooth normals are calculated if the file doesn't contain any
+fn read_normals(mesh: &tobj::Mesh) -> Vec<Vec3> {
+    let positions = read_positions(mesh);
+
+    if mesh.normals.len() == mesh.positions.len() {
+        return mesh.normals.chunks_exact(3).map(Vec3::from_slice).collect();
+    }
+
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        // Weighted by the area of the triangle
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+
+        normals[a] += normal;
+        normals[b] += normal;
+        normals[c] += normal;
+    }
+
+    normals
+        .into_iter()
+        .map(|normal| normal.try_normalize().unwrap_or(Vec3::Y))
+        .collect()
+}
+
+/// The MTL diffuse color and dissolve are the base color of the material
+fn convert_material(material: Option<&tobj::Material>) -> PrimitiveMaterial {
+    let material = match material {
+        Some(material) => material,
+        None => return PrimitiveMaterial::new(),
+    };
+
+    let alpha = material.dissolve.clamp(0., 1.);
+
+    PrimitiveMaterial {
+        base_color_factor: Vec4::from((Vec3::from(material.diffuse), alpha)),
+        alpha_mode: if alpha < 1. {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        },
+        ..PrimitiveMaterial::new()
+    }
+}
+
+/// Loads the image as RGBA, scaled up to power-of-two dimensions (textures are mipmapped)
+fn load_image(path: &Path) -> Result<gltf::image::Data> {
+    let image: RgbaImage = image::open(path)?.to_rgba8();
+
+    Ok(texture_image::prepare(gltf::image::Data {
+        width: image.width(),
+        height: image.height(),
+        format: Format::R8G8B8A8,
+        pixels: image.into_raw(),
+    }))
+}
@@ -0,0 +1,121 @@
+use std::{fmt::Write, fs, path::Path};
+
+use eyre::Result;
+use glam::{Mat3, Mat4};
+
+use crate::model::{pose, Mesh, Model, Node, Primitive};
+
+/// Writes the meshes of the model in their current pose into an OBJ file (one object per mesh).
+///
+/// The skinning is done on the CPU with the same math as the vertex shader,
+/// non-skinned meshes have their node and model transforms baked in.
+pub fn export_posed_model(model: &Model, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut writer = ObjWriter {
+        out: String::new(),
+        vertex_count: 0,
+        object_count: 0,
+    };
+
+    writeln!(writer.out, "# Posed '{}'", model.name)?;
+    writer.write_node(&model.root, model.transform)?;
+
+    fs::write(path, writer.out)?;
+
+    Ok(())
+}
+
+struct ObjWriter {
+    out: String,
+    /// Number of vertices written so far (OBJ indices are global and start at 1)
+    vertex_count: usize,
+    /// Number of objects written so far (for naming the unnamed meshes)
+    object_count: usize,
+}
+
+impl ObjWriter {
+    /// Recursive - writes the mesh of the node and the meshes of its children
+    fn write_node(&mut self, node: &Node, outer_transform: Mat4) -> Result<()> {
+        let next_level_transform = outer_transform * node.transform;
+
+        if let Some(mesh) = &node.mesh {
+            let skin_matrices = node.joints.as_ref().map(|joints| {
+                let world_transforms =
+                    pose::compute_world_transforms(&joints.joints, next_level_transform);
+                pose::compute_skin_matrices(&joints.joints, &world_transforms)
+            });
+
+            self.write_mesh(mesh, next_level_transform, skin_matrices.as_deref())?;
+        }
+
+        for child in &node.children {
+            self.write_node(child, next_level_transform)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_mesh(
+        &mut self,
+        mesh: &Mesh,
+        node_transform: Mat4,
+        skin_matrices: Option<&[Mat4]>,
+    ) -> Result<()> {
+        self.object_count += 1;
+        let name = match &mesh.name {
+            Some(name) => name.replace(char::is_whitespace, "_"),
+            None => format!("mesh_{}", self.object_count),
+        };
+
+        writeln!(self.out, "o {name}")?;
+
+        for prim in mesh.primitives.iter().filter(|prim| prim.visible) {
+            self.write_primitive(prim, node_transform, skin_matrices)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_primitive(
+        &mut self,
+        prim: &Primitive,
+        node_transform: Mat4,
+        skin_matrices: Option<&[Mat4]>,
+    ) -> Result<()> {
+        for i in 0..prim.positions.len() {
+            let transform = match (&prim.skin, skin_matrices) {
+                (Some(_), Some(matrices)) => prim.skin_transform(i, matrices),
+                _ => node_transform,
+            };
+
+            let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+
+            let pos = transform.transform_point3(prim.positions[i]);
+            let normal = (normal_matrix * prim.normals[i]).normalize_or_zero();
+
+            writeln!(self.out, "v {} {} {}", pos.x, pos.y, pos.z)?;
+            writeln!(self.out, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+
+            // OBJ texture coordinates start at the bottom-left corner, glTF ones at the top-left
+            let uv = prim.texcoords.get(i).copied().unwrap_or_default();
+            writeln!(self.out, "vt {} {}", uv.x, 1. - uv.y)?;
+        }
+
+        let indices: Vec<u32> = prim.indices.iter().collect();
+        for triangle in indices.chunks_exact(3) {
+            write!(self.out, "f")?;
+            for &index in triangle {
+                let index = self.vertex_count + index as usize + 1;
+                write!(self.out, " {index}/{index}/{index}")?;
+            }
+            writeln!(self.out)?;
+        }
+
+        self.vertex_count += prim.positions.len();
+
+        Ok(())
+    }
+}
@@ -1,21 +1,96 @@
 use std::{
     ffi::{c_void, CStr},
-    mem::size_of,
+    mem::{size_of, size_of_val},
     ptr,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+use eyre::{eyre, Result};
+use gl::types::GLenum;
+use glam::{Mat4, Vec4};
+use log::{info, warn};
+
+/// Runs an OpenGL call and reports all of the pending OpenGL errors with the call site.
+///
+/// The errors are only checked in debug builds. Has to be used inside of an unsafe block.
+macro_rules! gl_check {
+    ($call:expr) => {{
+        let res = $call;
+        #[cfg(debug_assertions)]
+        $crate::ogl::report_errors(file!(), line!(), stringify!($call));
+        res
+    }};
+}
+
+pub(crate) use gl_check;
+
+/// OpenGL buffer objects.
+mod buffer;
+
+/// GPU timing with timestamp queries.
+mod gpu_timer;
+
+/// Offscreen framebuffers for rendering into textures.
+pub mod framebuffer;
+
 /// Abstraction for working with OpenGL Shaders.
 pub mod shader;
 
+/// OpenGL 2D textures.
+mod texture2d;
+
+/// OpenGL cube map textures.
+mod texture_cube;
+
 /// Abstraction for working with OpenGL Uniform Buffers.
 pub mod uniform_buffer;
 
+/// OpenGL vertex array objects.
+mod vao;
+
+pub use self::{
+    buffer::Buffer,
+    gpu_timer::{GpuTimer, PassTime},
+    texture2d::{Texture2D, TextureFiltering},
+    texture_cube::TextureCube,
+    vao::{FullscreenTriangle, Vao},
+};
+
 // Indices of the vertex attributes
 pub const POS_INDEX: u32 = 0;
 pub const TEXCOORDS_INDEX: u32 = 1;
 pub const NORMALS_INDEX: u32 = 2;
 pub const JOINTS_INDEX: u32 = 3;
 pub const WEIGHTS_INDEX: u32 = 4;
+pub const TANGENTS_INDEX: u32 = 5;
+pub const JOINTS_1_INDEX: u32 = 6;
+pub const WEIGHTS_1_INDEX: u32 = 7;
+/// The instance transform is a mat4, so it takes 4 consecutive attributes
+pub const INSTANCE_TRANSFORM_INDEX: u32 = 8;
+
+/// The oldest OpenGL version the renderer works with
+/// (uniform blocks, integer vertex attributes, timer queries)
+pub const MIN_GL_VERSION: (i32, i32) = (3, 3);
+
+/// If KHR_debug is available (set by `init_debug`), debug groups and object labels
+/// are skipped without it
+static DEBUG_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+// Anisotropic filtering (ARB_texture_filter_anisotropic, core since OpenGL 4.6),
+// the gl crate only has the OpenGL 4.5 enums
+pub const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+pub const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+// Texture units of the material textures
+pub const BASE_COLOR_TEXTURE_UNIT: u32 = 0;
+pub const NORMAL_TEXTURE_UNIT: u32 = 1;
+pub const EMISSIVE_TEXTURE_UNIT: u32 = 2;
+pub const OCCLUSION_TEXTURE_UNIT: u32 = 3;
+// Texture units of the environment map
+pub const ENVIRONMENT_TEXTURE_UNIT: u32 = 4;
+pub const IRRADIANCE_TEXTURE_UNIT: u32 = 5;
+// Texture unit of the HDR color read by the tone mapping pass
+pub const HDR_COLOR_TEXTURE_UNIT: u32 = 6;
 
 /// Create an opengl buffer with floating-point content.
 ///
@@ -28,28 +103,26 @@ pub fn create_float_buf<T: Copy>(
     components: i32,
     attrib_index: u32,
     typ: u32,
-) -> u32 {
-    let mut id: u32 = 0;
+) -> Buffer {
+    let buf = Buffer::new(gl::ARRAY_BUFFER);
+    buf.bind();
 
     unsafe {
-        gl::GenBuffers(1, &mut id as *mut _);
-        gl::BindBuffer(gl::ARRAY_BUFFER, id);
-
         let buffer_size = buffer.len() * size_of::<T>();
 
-        gl::BufferData(
+        gl_check!(gl::BufferData(
             gl::ARRAY_BUFFER,
             buffer_size as isize,
             // The layout of Vec3 is #[repr(C)] (struct of 3 floats), so this should be correct
             buffer.as_ptr() as _,
             gl::STATIC_DRAW,
-        );
+        ));
 
         gl::VertexAttribPointer(attrib_index, components, typ, gl::FALSE, 0, 0 as _);
         gl::EnableVertexAttribArray(attrib_index);
     }
 
-    id
+    buf
 }
 
 /// Create an opengl buffer with integer content.
@@ -58,31 +131,261 @@ pub fn create_float_buf<T: Copy>(
 ///
 /// 'components', 'attrib index' and 'typ' have the same meaning as the respective
 /// arguments in glVertexAttribPointer.
-pub fn create_int_buf<T: Copy>(buffer: &[T], components: i32, attrib_index: u32, typ: u32) -> u32 {
-    let mut id: u32 = 0;
+pub fn create_int_buf<T: Copy>(
+    buffer: &[T],
+    components: i32,
+    attrib_index: u32,
+    typ: u32,
+) -> Buffer {
+    let buf = Buffer::new(gl::ARRAY_BUFFER);
+    buf.bind();
 
     unsafe {
-        gl::GenBuffers(1, &mut id as *mut _);
-        gl::BindBuffer(gl::ARRAY_BUFFER, id);
-
         let buffer_size = buffer.len() * size_of::<T>();
 
-        gl::BufferData(
+        gl_check!(gl::BufferData(
             gl::ARRAY_BUFFER,
             buffer_size as isize,
             // The layout of Vec3 is #[repr(C)] (struct of 3 floats), so it should be correct
             buffer.as_ptr() as _,
             gl::STATIC_DRAW,
-        );
+        ));
 
         gl::VertexAttribIPointer(attrib_index, components, typ, 0, 0 as _);
         gl::EnableVertexAttribArray(attrib_index);
     }
 
-    id
+    buf
+}
+
+/// Overwrites the content of a buffer created by `create_float_buf`.
+///
+/// 'buffer' has to have the same length as the original data.
+pub fn update_float_buf<T: Copy>(buf: &Buffer, buffer: &[T]) {
+    buf.bind();
+
+    unsafe {
+        let buffer_size = size_of_val(buffer);
+
+        gl::BufferSubData(
+            gl::ARRAY_BUFFER,
+            0,
+            buffer_size as isize,
+            buffer.as_ptr() as _,
+        );
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+}
+
+/// Creates a buffer with the per-instance transforms of an instanced mesh
+pub fn create_instance_buf(transforms: &[Mat4]) -> Buffer {
+    let buf = Buffer::new(gl::ARRAY_BUFFER);
+    buf.bind();
+
+    unsafe {
+        gl_check!(gl::BufferData(
+            gl::ARRAY_BUFFER,
+            size_of_val(transforms) as isize,
+            transforms.as_ptr() as _,
+            gl::STATIC_DRAW,
+        ));
+    }
+
+    buf.unbind();
+    buf
+}
+
+/// Sets up the instance transform attributes of the bound VAO (advanced once per instance).
+///
+/// 'buf' is a buffer created by `create_instance_buf`.
+pub fn bind_instance_transforms(buf: &Buffer) {
+    buf.bind();
+
+    unsafe {
+        let stride = size_of::<Mat4>() as i32;
+
+        for column in 0..4 {
+            let index = INSTANCE_TRANSFORM_INDEX + column;
+            let offset = column as usize * size_of::<Vec4>();
+
+            gl::VertexAttribPointer(index, 4, gl::FLOAT, gl::FALSE, stride, offset as _);
+            gl::EnableVertexAttribArray(index);
+            gl::VertexAttribDivisor(index, 1);
+        }
+    }
+
+    buf.unbind();
+}
+
+/// Creates a shader storage buffer (SSBO) with the content of 'buffer'.
+///
+/// The elements have to match the std430 layout of the block in the shader.
+pub fn create_storage_buf<T: Copy>(buffer: &[T]) -> Buffer {
+    let buf = Buffer::new(gl::SHADER_STORAGE_BUFFER);
+    buf.bind();
+
+    unsafe {
+        gl::BufferData(
+            gl::SHADER_STORAGE_BUFFER,
+            size_of_val(buffer) as isize,
+            buffer.as_ptr() as _,
+            gl::STATIC_DRAW,
+        );
+    }
+
+    buf.unbind();
+    buf
 }
 
+/// The version of the current OpenGL context (major, minor)
+pub fn gl_version() -> (i32, i32) {
+    let mut major = 0;
+    let mut minor = 0;
+
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+
+    (major, minor)
+}
+
+/// If the current context supports the extension (eg. "GL_KHR_debug")
+pub fn has_extension(name: &str) -> bool {
+    let mut count = 0;
+
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+
+        (0..count as u32).any(|i| {
+            let ext = gl::GetStringi(gl::EXTENSIONS, i);
+            !ext.is_null() && CStr::from_ptr(ext as _).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+/// Features and limits of the current OpenGL context
+pub struct Capabilities {
+    pub version: (i32, i32),
+    /// GL_MAX_UNIFORM_BLOCK_SIZE in bytes
+    pub max_uniform_block_size: usize,
+    /// Compute shaders and shader storage buffers (OpenGL 4.3)
+    pub compute_shaders: bool,
+    /// Debug output, debug groups and object labels (KHR_debug, core since OpenGL 4.3)
+    pub debug_output: bool,
+    /// GL_MAX_TEXTURE_MAX_ANISOTROPY, None if anisotropic filtering isn't supported
+    pub max_anisotropy: Option<f32>,
+    /// MSAA samples of the default framebuffer (0 without MSAA)
+    pub samples: u32,
+    /// Maximum width of aliased lines (GL_ALIASED_LINE_WIDTH_RANGE)
+    pub max_line_width: f32,
+}
+
+impl Capabilities {
+    /// Queries the current context
+    pub fn query() -> Self {
+        let version = gl_version();
+
+        let mut max_uniform_block_size = 0;
+        let mut samples = 0;
+        let mut line_width_range = [1f32; 2];
+        unsafe {
+            gl::GetIntegerv(gl::MAX_UNIFORM_BLOCK_SIZE, &mut max_uniform_block_size);
+            gl::GetIntegerv(gl::SAMPLES, &mut samples);
+            gl::GetFloatv(gl::ALIASED_LINE_WIDTH_RANGE, line_width_range.as_mut_ptr());
+        }
+
+        let anisotropic = version >= (4, 6)
+            || has_extension("GL_ARB_texture_filter_anisotropic")
+            || has_extension("GL_EXT_texture_filter_anisotropic");
+
+        let max_anisotropy = anisotropic.then(|| {
+            let mut max_anisotropy = 1.;
+            unsafe {
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+            }
+            max_anisotropy
+        });
+
+        Self {
+            version,
+            max_uniform_block_size: max_uniform_block_size.max(0) as usize,
+            compute_shaders: version >= (4, 3),
+            debug_output: version >= (4, 3) || has_extension("GL_KHR_debug"),
+            max_anisotropy,
+            samples: samples.max(0) as u32,
+            max_line_width: line_width_range[1].max(1.),
+        }
+    }
+
+    /// Maximum number of joint matrices that fit into a uniform block
+    pub fn max_joints(&self) -> usize {
+        self.max_uniform_block_size / uniform_buffer::STD140_MAT4_SIZE
+    }
+
+    /// Returns a descriptive error if the context can't run the renderer.
+    ///
+    /// 'min_uniform_block_size' is the size of the biggest uniform block of the shaders.
+    pub fn check(&self, min_uniform_block_size: usize) -> Result<()> {
+        let (major, minor) = self.version;
+        let (min_major, min_minor) = MIN_GL_VERSION;
+
+        if self.version < MIN_GL_VERSION {
+            return Err(eyre!(
+                "OpenGL {min_major}.{min_minor} is required, but the driver only provides {major}.{minor}"
+            ));
+        }
+
+        if self.max_uniform_block_size < min_uniform_block_size {
+            return Err(eyre!(
+                "Uniform blocks of {min_uniform_block_size} bytes are required, but the driver only supports {} bytes (GL_MAX_UNIFORM_BLOCK_SIZE)",
+                self.max_uniform_block_size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates a 1x1 RGBA texture of a single color.
+///
+/// Used in place of missing material textures, so the shaders don't have to branch.
+pub fn create_1x1_texture(color: [u8; 4]) -> Texture2D {
+    let texture = Texture2D::new();
+    texture.bind();
+
+    unsafe {
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as i32,
+            1,
+            1,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            color.as_ptr() as _,
+        );
+    }
+
+    texture.unbind();
+    texture
+}
+
+/// Enables the debug output (printed by `gl_debug_callback`).
+///
+/// Does nothing if the context doesn't support KHR_debug.
 pub fn init_debug() {
+    if !Capabilities::query().debug_output {
+        warn!("The OpenGL context doesn't support KHR_debug, the debug output is disabled");
+        return;
+    }
+
+    DEBUG_OUTPUT.store(true, Ordering::Relaxed);
+
     unsafe {
         gl::Enable(gl::DEBUG_OUTPUT);
         gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
@@ -95,9 +398,74 @@ pub fn init_debug() {
             ptr::null(),
             gl::TRUE,
         );
+
+        // The debug groups would report themselves every frame
+        for typ in [gl::DEBUG_TYPE_PUSH_GROUP, gl::DEBUG_TYPE_POP_GROUP] {
+            gl::DebugMessageControl(gl::DONT_CARE, typ, gl::DONT_CARE, 0, ptr::null(), gl::FALSE);
+        }
     };
 }
 
+/// Runs 'f' inside of a named debug group, so the OpenGL calls are grouped
+/// in frame captures (RenderDoc, apitrace...).
+///
+/// Does nothing special if the context doesn't support KHR_debug (core since OpenGL 4.3).
+pub fn debug_group<R>(name: &str, f: impl FnOnce() -> R) -> R {
+    let supported = DEBUG_OUTPUT.load(Ordering::Relaxed);
+
+    if supported {
+        unsafe {
+            gl::PushDebugGroup(
+                gl::DEBUG_SOURCE_APPLICATION,
+                0,
+                name.len() as i32,
+                name.as_ptr() as _,
+            );
+        }
+    }
+
+    let res = f();
+
+    if supported {
+        unsafe {
+            gl::PopDebugGroup();
+        }
+    }
+
+    res
+}
+
+/// Names an OpenGL object (gl::BUFFER, gl::TEXTURE, gl::PROGRAM...) for frame captures
+pub fn label_object(identifier: GLenum, id: u32, label: &str) {
+    if !DEBUG_OUTPUT.load(Ordering::Relaxed) {
+        return;
+    }
+
+    unsafe {
+        gl::ObjectLabel(identifier, id, label.len() as i32, label.as_ptr() as _);
+    }
+}
+
+/// Drains glGetError, used by `gl_check!`
+#[cfg(debug_assertions)]
+pub fn report_errors(file: &str, line: u32, call: &str) {
+    loop {
+        let error = unsafe { gl::GetError() };
+
+        let name = match error {
+            gl::NO_ERROR => break,
+            gl::INVALID_ENUM => "GL_INVALID_ENUM",
+            gl::INVALID_VALUE => "GL_INVALID_VALUE",
+            gl::INVALID_OPERATION => "GL_INVALID_OPERATION",
+            gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+            gl::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+            _ => "unknown error",
+        };
+
+        log::error!("OpenGL error {name} ({error:#x}) at {file}:{line}: {call}");
+    }
+}
+
 /// The OpenGL debug callback.
 ///
 /// 'extern "system"' specifies the correct ABI for all platforms
@@ -125,5 +493,5 @@ extern "system" fn gl_debug_callback(
 
     // TODO: check if the message is guaranteed to be ASCII
     let msg = unsafe { CStr::from_ptr(msg) };
-    println!("OpenGL debug message: '{}'", msg.to_string_lossy())
+    info!("OpenGL debug message: '{}'", msg.to_string_lossy())
 }
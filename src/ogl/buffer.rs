@@ -0,0 +1,47 @@
+/// An OpenGL buffer object, deleted when dropped
+pub struct Buffer {
+    pub id: u32,
+    /// The binding target (GL_ARRAY_BUFFER, GL_ELEMENT_ARRAY_BUFFER...)
+    target: u32,
+}
+
+impl Buffer {
+    /// Creates a new buffer object (without any storage) for the binding target
+    pub fn new(target: u32) -> Self {
+        let mut id = 0;
+
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+
+        Self { id, target }
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindBuffer(self.target, self.id);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindBuffer(self.target, 0);
+        }
+    }
+
+    /// Binds the buffer to an indexed shader storage buffer binding point
+    /// (any buffer can be used as an SSBO, eg. a vertex buffer written by a compute shader)
+    pub fn bind_storage(&self, index: u32) {
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, index, self.id);
+        }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use eyre::{eyre, Result};
+use gl::types::GLenum;
+
+use super::Texture2D;
+
+/// Format of the color attachment
+#[derive(Clone, Copy)]
+enum ColorFormat {
+    Rgba8,
+    Rgba16F,
+    /// A single unsigned integer channel
+    R32Ui,
+}
+
+impl ColorFormat {
+    /// The internal format, pixel format and pixel type
+    fn gl_formats(self) -> (GLenum, GLenum, GLenum) {
+        match self {
+            Self::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            Self::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::FLOAT),
+            Self::R32Ui => (gl::R32UI, gl::RED_INTEGER, gl::UNSIGNED_INT),
+        }
+    }
+}
+
+/// Storage of the color attachment
+enum ColorAttachment {
+    /// A texture that can be sampled (by the tone mapping pass)
+    Texture(Texture2D),
+    /// A multisampled renderbuffer, has to be resolved by `blit_to` before sampling
+    Renderbuffer(u32),
+}
+
+/// An offscreen framebuffer with a color attachment and a depth-stencil renderbuffer.
+///
+/// The OpenGL objects are deleted when the framebuffer is dropped.
+pub struct Framebuffer {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
+    color: ColorAttachment,
+    /// Depth and stencil attachment
+    depth_renderbuffer: u32,
+}
+
+impl Framebuffer {
+    /// Creates an RGBA8 framebuffer and checks that it's complete
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        Self::with_format(width, height, ColorFormat::Rgba8, 0)
+    }
+
+    /// Creates an RGBA16F framebuffer for rendering colors outside of [0, 1].
+    ///
+    /// The color is stored in a multisampled renderbuffer if 'samples' is larger than 0.
+    pub fn new_hdr(width: u32, height: u32, samples: u32) -> Result<Self> {
+        Self::with_format(width, height, ColorFormat::Rgba16F, samples)
+    }
+
+    /// Creates a framebuffer with a single unsigned integer channel (object ids for picking)
+    pub fn new_object_id(width: u32, height: u32) -> Result<Self> {
+        Self::with_format(width, height, ColorFormat::R32Ui, 0)
+    }
+
+    fn with_format(width: u32, height: u32, format: ColorFormat, samples: u32) -> Result<Self> {
+        let (internal_format, pixel_format, pixel_type) = format.gl_formats();
+
+        let mut id = 0;
+        let mut depth_renderbuffer = 0;
+
+        let (color, status) = unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+
+            let color = if samples > 0 {
+                let mut renderbuffer = 0;
+                gl::GenRenderbuffers(1, &mut renderbuffer);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+                gl::RenderbufferStorageMultisample(
+                    gl::RENDERBUFFER,
+                    samples as i32,
+                    internal_format,
+                    width as i32,
+                    height as i32,
+                );
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+                gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::RENDERBUFFER,
+                    renderbuffer,
+                );
+
+                ColorAttachment::Renderbuffer(renderbuffer)
+            } else {
+                let texture = Texture2D::new();
+
+                texture.bind();
+                // The textures are only sampled 1:1 (integer textures can't be filtered anyway)
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    internal_format as i32,
+                    width as i32,
+                    height as i32,
+                    0,
+                    pixel_format,
+                    pixel_type,
+                    std::ptr::null(),
+                );
+                texture.unbind();
+
+                gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_2D,
+                    texture.id,
+                    0,
+                );
+
+                ColorAttachment::Texture(texture)
+            };
+
+            gl::GenRenderbuffers(1, &mut depth_renderbuffer);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth_renderbuffer);
+            gl::RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                samples as i32,
+                gl::DEPTH24_STENCIL8,
+                width as i32,
+                height as i32,
+            );
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_STENCIL_ATTACHMENT,
+                gl::RENDERBUFFER,
+                depth_renderbuffer,
+            );
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            (color, status)
+        };
+
+        let framebuffer = Self {
+            id,
+            width,
+            height,
+            color,
+            depth_renderbuffer,
+        };
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(eyre!("Framebuffer is incomplete, status: '{status:#x}'"));
+        }
+
+        Ok(framebuffer)
+    }
+
+    /// The color texture, None if the color is multisampled
+    pub fn color_texture(&self) -> Option<&Texture2D> {
+        match &self.color {
+            ColorAttachment::Texture(texture) => Some(texture),
+            ColorAttachment::Renderbuffer(_) => None,
+        }
+    }
+
+    /// Reads the id at the pixel of an object id framebuffer (x and y are from the bottom-left corner)
+    pub fn read_object_id(&self, x: u32, y: u32) -> u32 {
+        let mut id = 0u32;
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                x as i32,
+                y as i32,
+                1,
+                1,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_INT,
+                &mut id as *mut u32 as _,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+
+        id
+    }
+
+    /// Copies (and resolves) the color into the other framebuffer of the same size
+    pub fn blit_to(&self, target: &Framebuffer) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.id);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                0,
+                0,
+                target.width as i32,
+                target.height as i32,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Binds the framebuffer for both drawing and reading
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+    }
+
+    /// Binds the default framebuffer
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            if let ColorAttachment::Renderbuffer(renderbuffer) = self.color {
+                gl::DeleteRenderbuffers(1, &renderbuffer);
+            }
+
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+        }
+    }
+}
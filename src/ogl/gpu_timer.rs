@@ -0,0 +1,224 @@
+/// Number of frames the queries are read back after (so reading the results never stalls)
+const FRAMES_IN_FLIGHT: usize = 3;
+
+/// How quickly the displayed times follow the new measurements (so they are readable)
+const SMOOTHING: f32 = 0.1;
+
+/// GPU time of a single pass
+pub struct PassTime {
+    pub name: String,
+    /// Number of the enclosing passes
+    pub depth: usize,
+    /// Smoothed duration in milliseconds
+    pub ms: f32,
+}
+
+/// A timed scope of a frame
+struct Scope {
+    name: String,
+    depth: usize,
+    /// Indices of the timestamp queries
+    start: usize,
+    end: Option<usize>,
+}
+
+/// Timestamp queries of a single frame
+struct FrameQueries {
+    /// Query objects, reused by the next frames in this slot
+    queries: Vec<u32>,
+    /// Number of queries used by the frame
+    used: usize,
+    scopes: Vec<Scope>,
+}
+
+/// Measures the GPU time of the passes of a frame with timestamp queries.
+///
+/// The queries of the last few frames are kept in a ring buffer and only read once
+/// they are available, so the displayed times lag a few frames behind.
+/// Scopes with the same name and depth in a single frame are summed.
+pub struct GpuTimer {
+    frames: Vec<FrameQueries>,
+    /// Slot of the current frame
+    current: usize,
+    /// If a frame is being recorded (start / stop are ignored outside of frames)
+    active: bool,
+    /// Indices of the scopes that were started but not stopped yet
+    open: Vec<usize>,
+    results: Vec<PassTime>,
+}
+
+impl GpuTimer {
+    pub fn new() -> Self {
+        let frames = (0..FRAMES_IN_FLIGHT)
+            .map(|_| FrameQueries {
+                queries: Vec::new(),
+                used: 0,
+                scopes: Vec::new(),
+            })
+            .collect();
+
+        Self {
+            frames,
+            current: 0,
+            active: false,
+            open: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Starts recording a new frame, reads the results of the oldest recorded frame if available
+    pub fn begin_frame(&mut self) {
+        self.current = (self.current + 1) % FRAMES_IN_FLIGHT;
+        self.collect();
+
+        let frame = &mut self.frames[self.current];
+        frame.used = 0;
+        frame.scopes.clear();
+
+        self.open.clear();
+        self.active = true;
+    }
+
+    /// Stops recording the frame, scopes that are still open are stopped
+    pub fn end_frame(&mut self) {
+        while !self.open.is_empty() {
+            self.stop();
+        }
+
+        self.active = false;
+    }
+
+    /// Starts a timed scope, scopes can be nested
+    pub fn start(&mut self, name: &str) {
+        if !self.active {
+            return;
+        }
+
+        let start = self.timestamp();
+        let frame = &mut self.frames[self.current];
+
+        frame.scopes.push(Scope {
+            name: name.to_string(),
+            depth: self.open.len(),
+            start,
+            end: None,
+        });
+        self.open.push(frame.scopes.len() - 1);
+    }
+
+    /// Stops the last started scope
+    pub fn stop(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        if let Some(scope) = self.open.pop() {
+            let end = self.timestamp();
+            self.frames[self.current].scopes[scope].end = Some(end);
+        }
+    }
+
+    /// Smoothed times of the passes, in the order they were started
+    pub fn results(&self) -> &[PassTime] {
+        &self.results
+    }
+
+    /// Issues a timestamp query, returns its index
+    fn timestamp(&mut self) -> usize {
+        let frame = &mut self.frames[self.current];
+
+        if frame.used == frame.queries.len() {
+            let mut query = 0;
+            unsafe {
+                gl::GenQueries(1, &mut query);
+            }
+            frame.queries.push(query);
+        }
+
+        unsafe {
+            gl::QueryCounter(frame.queries[frame.used], gl::TIMESTAMP);
+        }
+
+        frame.used += 1;
+        frame.used - 1
+    }
+
+    /// Reads the results of the frame in the current slot, if all of its queries are available
+    fn collect(&mut self) {
+        let frame = &self.frames[self.current];
+        if frame.used == 0 {
+            return;
+        }
+
+        // The queries complete in order, so only the last one has to be checked
+        let mut available = 0;
+        unsafe {
+            gl::GetQueryObjectiv(
+                frame.queries[frame.used - 1],
+                gl::QUERY_RESULT_AVAILABLE,
+                &mut available,
+            );
+        }
+
+        if available == 0 {
+            return;
+        }
+
+        let timestamps: Vec<u64> = frame.queries[..frame.used]
+            .iter()
+            .map(|query| {
+                let mut time = 0;
+                unsafe {
+                    gl::GetQueryObjectui64v(*query, gl::QUERY_RESULT, &mut time);
+                }
+                time
+            })
+            .collect();
+
+        let mut results: Vec<PassTime> = Vec::new();
+
+        for scope in &frame.scopes {
+            let end = match scope.end {
+                Some(end) => end,
+                None => continue,
+            };
+
+            let ms = timestamps[end].saturating_sub(timestamps[scope.start]) as f32 / 1_000_000.;
+
+            match results
+                .iter_mut()
+                .find(|r| r.name == scope.name && r.depth == scope.depth)
+            {
+                Some(result) => result.ms += ms,
+                None => results.push(PassTime {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    ms,
+                }),
+            }
+        }
+
+        // Passes that weren't in the frame are dropped
+        for result in &mut results {
+            if let Some(old) = self
+                .results
+                .iter()
+                .find(|old| old.name == result.name && old.depth == result.depth)
+            {
+                result.ms = old.ms + (result.ms - old.ms) * SMOOTHING;
+            }
+        }
+
+        self.results = results;
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        for frame in &self.frames {
+            unsafe {
+                gl::DeleteQueries(frame.queries.len() as i32, frame.queries.as_ptr());
+            }
+        }
+    }
+}
@@ -1,7 +1,15 @@
 use eyre::{eyre, Context, Result};
 use gl::types::GLenum;
-use glam::{Mat4, Vec3, Vec4};
-use std::{fs, ptr};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use log::warn;
+
+use super::uniform_buffer::UniformBufferElement;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs, ptr,
+};
 
 /// Represents an OpenGL shader.
 ///
@@ -10,22 +18,295 @@ use std::{fs, ptr};
 /// Use the `render` method for draw calls.
 pub struct Shader {
     pub id: u32,
+    /// Where the source code of the shader comes from
+    sources: ShaderSources,
+    /// Preprocessor definitions injected into the sources (name, value)
+    defines: Vec<(String, String)>,
+    /// Locations of the active uniforms (queried after linking)
+    uniform_locations: HashMap<String, i32>,
+    /// Unknown uniforms that were already reported, so they are only logged once
+    unknown_uniforms: RefCell<HashSet<String>>,
+    /// Uniform blocks and their binding points (set again after relinking)
+    block_bindings: Vec<(&'static str, u32)>,
+    /// Samplers and their texture units (set again after relinking)
+    sampler_units: Vec<(&'static str, u32)>,
+    /// Name of the program in OpenGL debuggers (set again after relinking)
+    label: Option<String>,
+}
+
+/// Source code of the vertex and fragment shaders (or of a compute shader)
+enum ShaderSources {
+    /// Paths of the source files (read again on every reload)
+    Files(String, String),
+    /// Source code given directly (eg. embedded into the binary)
+    Source(String, String),
+    /// Path of a compute shader source file (read again on every reload)
+    ComputeFile(String),
+    /// Source code of a compute shader given directly
+    ComputeSource(String),
 }
 
 impl Shader {
     /// Loads a vertex shader and a fragment shader from specified paths and tries to create a shader program
     pub fn from_file(vs_path: &str, fs_path: &str) -> Result<Shader> {
-        let mut vs_src = fs::read(vs_path).wrap_err("Couldn't load the vertex shader file")?;
-        let mut fs_src = fs::read(fs_path).wrap_err("Couldn't load the fragment shader file")?;
+        Self::new(ShaderSources::Files(
+            vs_path.to_string(),
+            fs_path.to_string(),
+        ))
+    }
+
+    /// Creates a shader program from the source code of the vertex and fragment shaders
+    pub fn from_source(vs_src: &str, fs_src: &str) -> Result<Shader> {
+        Self::new(ShaderSources::Source(
+            vs_src.to_string(),
+            fs_src.to_string(),
+        ))
+    }
+
+    /// Loads a compute shader from the specified path and tries to create a shader program.
+    ///
+    /// Compute shaders need OpenGL 4.3.
+    pub fn compute_from_file(path: &str) -> Result<Shader> {
+        Self::new(ShaderSources::ComputeFile(path.to_string()))
+    }
+
+    /// Creates a shader program from the source code of a compute shader
+    pub fn compute_from_source(src: &str) -> Result<Shader> {
+        Self::new(ShaderSources::ComputeSource(src.to_string()))
+    }
+
+    fn new(sources: ShaderSources) -> Result<Self> {
+        let defines = Vec::new();
+        let id = Self::create_program(&sources, &defines)?;
+
+        Ok(Self {
+            id,
+            sources,
+            defines,
+            uniform_locations: Self::query_uniform_locations(id),
+            unknown_uniforms: RefCell::new(HashSet::new()),
+            block_bindings: Vec::new(),
+            sampler_units: Vec::new(),
+            label: None,
+        })
+    }
+
+    /// Recompiles the shader program (shaders created from files read the files again).
+    ///
+    /// The old program is only replaced if the new one compiles and links successfully,
+    /// otherwise it stays in use. The uniform blocks are bound to the same binding points again,
+    /// a missing block is an error as well.
+    pub fn reload(&mut self) -> Result<()> {
+        let id = Self::create_program(&self.sources, &self.defines)?;
+
+        for (name, binding) in &self.block_bindings {
+            if let Err(e) = Self::bind_block(id, name, *binding) {
+                unsafe { gl::DeleteProgram(id) };
+                return Err(e);
+            }
+        }
+
+        for (name, unit) in &self.sampler_units {
+            Self::set_sampler_unit(id, name, *unit);
+        }
+
+        unsafe {
+            gl::DeleteProgram(self.id);
+        }
+
+        self.id = id;
+        if let Some(label) = &self.label {
+            super::label_object(gl::PROGRAM, id, label);
+        }
+        self.uniform_locations = Self::query_uniform_locations(id);
+        self.unknown_uniforms.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// Names the program in OpenGL debuggers
+    pub fn set_label(&mut self, label: &str) {
+        super::label_object(gl::PROGRAM, self.id, label);
+        self.label = Some(label.to_string());
+    }
+
+    /// Sets a preprocessor definition ('#define name value') and recompiles the shader
+    pub fn set_define(&mut self, name: &str, value: &str) -> Result<()> {
+        match self.defines.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.defines.push((name.to_string(), value.to_string())),
+        }
+
+        self.reload()
+    }
+
+    /// Binds the uniform block of the element to its binding point.
+    ///
+    /// Returns an error if the program doesn't have an active block with that name.
+    pub fn bind_uniform_block<T: UniformBufferElement>(&mut self) -> Result<()> {
+        Self::bind_block(self.id, T::NAME, T::BINDING)
+            .wrap_err_with(|| format!("Shader {}", self.id))?;
+
+        if !self.block_bindings.iter().any(|(name, _)| *name == T::NAME) {
+            self.block_bindings.push((T::NAME, T::BINDING));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the texture unit of a sampler uniform
+    /// (GLSL 3.30 doesn't support setting it with layout(binding)).
+    ///
+    /// Samplers that aren't active are ignored.
+    pub fn bind_sampler(&mut self, name: &'static str, unit: u32) {
+        Self::set_sampler_unit(self.id, name, unit);
+
+        if !self.sampler_units.iter().any(|(n, _)| *n == name) {
+            self.sampler_units.push((name, unit));
+        }
+    }
+
+    fn set_sampler_unit(program: u32, name: &str, unit: u32) {
+        let c_name = match CString::new(name) {
+            Ok(c_name) => c_name,
+            Err(_) => return,
+        };
+
+        unsafe {
+            let loc = gl::GetUniformLocation(program, c_name.as_ptr());
+
+            gl::UseProgram(program);
+            gl::Uniform1i(loc, unit as i32);
+            gl::UseProgram(0);
+        }
+    }
+
+    fn bind_block(program: u32, name: &str, binding: u32) -> Result<()> {
+        let c_name = CString::new(name)?;
+
+        unsafe {
+            let index = gl::GetUniformBlockIndex(program, c_name.as_ptr());
+            if index == gl::INVALID_INDEX {
+                return Err(eyre!(
+                    "The shader program doesn't have an active uniform block '{name}' (binding {binding})"
+                ));
+            }
+
+            gl::UniformBlockBinding(program, index, binding);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the shader sources (if needed) and creates the program
+    fn create_program(sources: &ShaderSources, defines: &[(String, String)]) -> Result<u32> {
+        match sources {
+            ShaderSources::Files(vs_path, fs_path) => {
+                let vs_src = fs::read(vs_path).wrap_err_with(|| {
+                    format!("Couldn't load the vertex shader file '{vs_path}'")
+                })?;
+                let fs_src = fs::read(fs_path).wrap_err_with(|| {
+                    format!("Couldn't load the fragment shader file '{fs_path}'")
+                })?;
+
+                Self::compile_program(vs_src, fs_src, defines)
+                    .wrap_err_with(|| format!("Shader files '{vs_path}' + '{fs_path}'"))
+            }
+            ShaderSources::Source(vs_src, fs_src) => Self::compile_program(
+                vs_src.as_bytes().to_vec(),
+                fs_src.as_bytes().to_vec(),
+                defines,
+            ),
+            ShaderSources::ComputeFile(path) => {
+                let src = fs::read(path)
+                    .wrap_err_with(|| format!("Couldn't load the compute shader file '{path}'"))?;
+
+                Self::compile_compute_program(src, defines)
+                    .wrap_err_with(|| format!("Shader file '{path}'"))
+            }
+            ShaderSources::ComputeSource(src) => {
+                Self::compile_compute_program(src.as_bytes().to_vec(), defines)
+            }
+        }
+    }
+
+    /// Inserts the definitions after the '#version' line (which has to be the first line)
+    fn inject_defines(src: Vec<u8>, defines: &[(String, String)]) -> Vec<u8> {
+        if defines.is_empty() {
+            return src;
+        }
+
+        let version_end = src
+            .iter()
+            .position(|c| *c == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(src.len());
+
+        let mut res = src[..version_end].to_vec();
+        for (name, value) in defines {
+            res.extend_from_slice(format!("#define {name} {value}\n").as_bytes());
+        }
+
+        // Keep the line numbers in the compiler errors the same as in the file
+        res.extend_from_slice(b"#line 2\n");
+        res.extend_from_slice(&src[version_end..]);
+        res
+    }
+
+    /// Compiles and links the shaders
+    fn compile_program(
+        vs_src: Vec<u8>,
+        fs_src: Vec<u8>,
+        defines: &[(String, String)],
+    ) -> Result<u32> {
+        let mut vs_src = Self::inject_defines(vs_src, defines);
+        let mut fs_src = Self::inject_defines(fs_src, defines);
 
         // Add null-terminators
         vs_src.push(b'\0');
         fs_src.push(b'\0');
 
-        let vs = Self::compile_shader(&vs_src, gl::VERTEX_SHADER)?;
-        let fs = Self::compile_shader(&fs_src, gl::FRAGMENT_SHADER)?;
-        let shader_program = Self::link_shaders(vs, fs)?;
-        Ok(Shader { id: shader_program })
+        let vs = Self::compile_shader(&vs_src, gl::VERTEX_SHADER)
+            .wrap_err("Couldn't compile the vertex shader")?;
+        let fs = match Self::compile_shader(&fs_src, gl::FRAGMENT_SHADER) {
+            Ok(fs) => fs,
+            Err(e) => {
+                unsafe { gl::DeleteShader(vs) };
+                return Err(e.wrap_err("Couldn't compile the fragment shader"));
+            }
+        };
+
+        Self::link_shaders(&[vs, fs])
+    }
+
+    /// Compiles and links the compute shader
+    fn compile_compute_program(src: Vec<u8>, defines: &[(String, String)]) -> Result<u32> {
+        let mut src = Self::inject_defines(src, defines);
+        src.push(b'\0');
+
+        let cs = Self::compile_shader(&src, gl::COMPUTE_SHADER)
+            .wrap_err("Couldn't compile the compute shader")?;
+
+        Self::link_shaders(&[cs])
+    }
+
+    /// Runs the compute shader with the number of work groups.
+    ///
+    /// Uniforms can be set in the `setup` function (the program is in use).
+    /// The results are visible to the vertex attribute fetches of the following draw calls.
+    pub fn dispatch<F>(&self, groups: u32, setup: F)
+    where
+        F: FnOnce(),
+    {
+        unsafe {
+            gl::UseProgram(self.id);
+
+            setup();
+
+            gl_check!(gl::DispatchCompute(groups, 1, 1));
+            gl::UseProgram(0);
+
+            gl::MemoryBarrier(gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+        }
     }
 
     /// Use this shader to render.
@@ -59,7 +340,9 @@ impl Shader {
 
             if res == 0 {
                 gl::GetShaderInfoLog(shader, 512, &mut info_len as _, info_log.as_mut_ptr() as _);
-                let info_msg = String::from_utf8_lossy(&info_log);
+                gl::DeleteShader(shader);
+
+                let info_msg = String::from_utf8_lossy(&info_log[..info_len as usize]);
                 return Err(eyre!("Failed to compile a shader: '{}'", info_msg));
             }
 
@@ -67,12 +350,15 @@ impl Shader {
         }
     }
 
-    /// Tries to link the vertex and fragment shaders (passed by their ids) and checks for linking errors.
-    fn link_shaders(vs: u32, fs: u32) -> Result<u32> {
+    /// Tries to link the shaders (passed by their ids) and checks for linking errors.
+    ///
+    /// The shaders are deleted afterwards.
+    fn link_shaders(shaders: &[u32]) -> Result<u32> {
         unsafe {
             let shader_program = gl::CreateProgram();
-            gl::AttachShader(shader_program, vs);
-            gl::AttachShader(shader_program, fs);
+            for shader in shaders {
+                gl::AttachShader(shader_program, *shader);
+            }
             gl::LinkProgram(shader_program);
 
             let mut res = 0;
@@ -88,81 +374,156 @@ impl Shader {
                     &mut info_len as _,
                     info_log.as_mut_ptr() as *mut i8,
                 );
-                let info_msg = String::from_utf8_lossy(&info_log);
+                gl::DeleteProgram(shader_program);
+                for shader in shaders {
+                    gl::DeleteShader(*shader);
+                }
+
+                let info_msg = String::from_utf8_lossy(&info_log[..info_len as usize]);
                 return Err(eyre!("Failed to create a shader program: '{}'", info_msg));
             }
 
-            gl::DeleteShader(vs);
-            gl::DeleteShader(fs);
+            for shader in shaders {
+                gl::DeleteShader(*shader);
+            }
 
             Ok(shader_program)
         }
     }
 
+    /// Finds the locations of all active uniforms of the program.
+    ///
+    /// Uniforms inside of uniform blocks don't have a location and are skipped.
+    /// Arrays are accessible both as 'name' and 'name[0]'.
+    fn query_uniform_locations(program: u32) -> HashMap<String, i32> {
+        let mut locations = HashMap::new();
+
+        unsafe {
+            let mut count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+
+            for i in 0..count as u32 {
+                let mut name_buf = [0u8; 256];
+                let mut len = 0;
+                let mut size = 0;
+                let mut typ = 0;
+
+                gl::GetActiveUniform(
+                    program,
+                    i,
+                    name_buf.len() as i32,
+                    &mut len,
+                    &mut size,
+                    &mut typ,
+                    name_buf.as_mut_ptr() as _,
+                );
+
+                // The buffer is zeroed, so the name stays null-terminated
+                let loc = gl::GetUniformLocation(program, name_buf.as_ptr() as _);
+                if loc < 0 {
+                    continue;
+                }
+
+                let name = String::from_utf8_lossy(&name_buf[..len as usize]).to_string();
+                if let Some(array_name) = name.strip_suffix("[0]") {
+                    locations.insert(array_name.to_string(), loc);
+                }
+
+                locations.insert(name, loc);
+            }
+        }
+
+        locations
+    }
+
+    /// Returns the location of the uniform, unknown uniforms are logged (once) and return -1,
+    /// which OpenGL ignores
+    fn uniform_location(&self, name: &str) -> i32 {
+        match self.uniform_locations.get(name) {
+            Some(loc) => *loc,
+            None => {
+                if self.unknown_uniforms.borrow_mut().insert(name.to_string()) {
+                    warn!("Shader {} doesn't have an active uniform '{name}'", self.id);
+                }
+
+                -1
+            }
+        }
+    }
+
     //
     // Uniform setters...
     //
 
     #[allow(unused)]
     pub fn set_mat4(&self, mat: Mat4, name: &str) {
-        Self::check_inform_name(name);
         unsafe {
-            let loc = gl::GetUniformLocation(self.id, name.as_ptr() as _);
+            let loc = self.uniform_location(name);
             gl::UniformMatrix4fv(loc, 1, gl::FALSE, mat.to_cols_array().as_ptr() as _);
         }
     }
 
     #[allow(unused)]
     pub fn set_mat4_arr(&self, mats: &[Mat4], name: &str) {
-        Self::check_inform_name(name);
-
         let mats_flat: Vec<f32> = mats.iter().flat_map(|m| m.to_cols_array()).collect();
 
         unsafe {
-            let loc = gl::GetUniformLocation(self.id, name.as_ptr() as _);
+            let loc = self.uniform_location(name);
             gl::UniformMatrix4fv(loc, mats.len() as i32, gl::FALSE, mats_flat.as_ptr() as _);
         }
     }
 
+    #[allow(unused)]
+    pub fn set_vec2(&self, vec: Vec2, name: &str) {
+        unsafe {
+            let loc = self.uniform_location(name);
+            gl::Uniform2f(loc, vec.x, vec.y);
+        }
+    }
+
     #[allow(unused)]
     pub fn set_vec3(&self, vec: Vec3, name: &str) {
-        Self::check_inform_name(name);
         unsafe {
-            let loc = gl::GetUniformLocation(self.id, name.as_ptr() as _);
+            let loc = self.uniform_location(name);
             gl::Uniform3f(loc, vec.x, vec.y, vec.z);
         }
     }
 
     #[allow(unused)]
     pub fn set_vec4(&self, vec: Vec4, name: &str) {
-        Self::check_inform_name(name);
         unsafe {
-            let loc = gl::GetUniformLocation(self.id, name.as_ptr() as _);
+            let loc = self.uniform_location(name);
             gl::Uniform4f(loc, vec.x, vec.y, vec.z, vec.w);
         }
     }
 
     #[allow(unused)]
     pub fn set_f32(&self, v: f32, name: &str) {
-        Self::check_inform_name(name);
         unsafe {
-            let loc = gl::GetUniformLocation(self.id, name.as_ptr() as _);
+            let loc = self.uniform_location(name);
             gl::Uniform1f(loc, v);
         }
     }
 
     #[allow(unused)]
     pub fn set_u32(&self, v: u32, name: &str) {
-        Self::check_inform_name(name);
         unsafe {
-            let loc = gl::GetUniformLocation(self.id, name.as_ptr() as _);
+            let loc = self.uniform_location(name);
             gl::Uniform1ui(loc, v);
         }
     }
 
-    /// Uniform names have to be null-terminated and have to be ASCII (I think...)
-    fn check_inform_name(name: &str) {
-        assert!(name.is_ascii());
-        assert!(name.ends_with('\0'));
+    #[allow(unused)]
+    pub fn set_i32(&self, v: i32, name: &str) {
+        unsafe {
+            let loc = self.uniform_location(name);
+            gl::Uniform1i(loc, v);
+        }
+    }
+
+    /// GLSL bools are set as integers
+    #[allow(unused)]
+    pub fn set_bool(&self, v: bool, name: &str) {
+        self.set_i32(v as i32, name);
     }
 }
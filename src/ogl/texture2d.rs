@@ -0,0 +1,104 @@
+use gl::types::GLenum;
+
+use super::TEXTURE_MAX_ANISOTROPY;
+
+/// How the textures of the models are filtered, overrides the samplers of the models
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureFiltering {
+    /// The filters of the samplers in the model files
+    Sampler,
+    Nearest,
+    Bilinear,
+    Trilinear,
+    /// Trilinear with anisotropic filtering
+    Anisotropic,
+}
+
+impl TextureFiltering {
+    pub const ALL: [Self; 5] = [
+        Self::Sampler,
+        Self::Nearest,
+        Self::Bilinear,
+        Self::Trilinear,
+        Self::Anisotropic,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sampler => "From the file",
+            Self::Nearest => "Nearest",
+            Self::Bilinear => "Bilinear",
+            Self::Trilinear => "Trilinear",
+            Self::Anisotropic => "Anisotropic",
+        }
+    }
+
+    /// The (min, mag) filters, None if the ones of the sampler should be used
+    pub fn filters(self) -> Option<(GLenum, GLenum)> {
+        match self {
+            Self::Sampler => None,
+            Self::Nearest => Some((gl::NEAREST_MIPMAP_NEAREST, gl::NEAREST)),
+            Self::Bilinear => Some((gl::LINEAR_MIPMAP_NEAREST, gl::LINEAR)),
+            Self::Trilinear | Self::Anisotropic => Some((gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR)),
+        }
+    }
+
+    /// If the anisotropy level applies (the explicit filters turn it off)
+    pub fn uses_anisotropy(self) -> bool {
+        matches!(self, Self::Sampler | Self::Anisotropic)
+    }
+}
+
+/// An OpenGL 2D texture, deleted when dropped.
+///
+/// Not `Clone`, textures that are used in multiple places have to be shared through `Arc`.
+pub struct Texture2D {
+    pub id: u32,
+}
+
+impl Texture2D {
+    /// Creates a new texture object (without any storage)
+    pub fn new() -> Self {
+        let mut id = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut id);
+        }
+
+        Self { id }
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    /// Sets the filters of the bound texture.
+    ///
+    /// 'anisotropy' is None if anisotropic filtering isn't supported.
+    pub fn set_filters(min_filter: GLenum, mag_filter: GLenum, anisotropy: Option<f32>) {
+        unsafe {
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+            if let Some(anisotropy) = anisotropy {
+                gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+        }
+    }
+}
+
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use glam::Vec3;
+
+/// An OpenGL cube map texture with floating-point faces, deleted when dropped
+pub struct TextureCube {
+    pub id: u32,
+}
+
+impl TextureCube {
+    /// Creates a cube map from the faces (+X, -X, +Y, -Y, +Z, -Z), each 'size' x 'size' texels.
+    ///
+    /// Mipmaps are generated if 'mipmaps' is set.
+    pub fn new(faces: &[Vec<Vec3>; 6], size: u32, mipmaps: bool) -> Self {
+        let mut id = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, id);
+
+            for (i, face) in faces.iter().enumerate() {
+                debug_assert_eq!(face.len(), (size * size) as usize);
+
+                gl_check!(gl::TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
+                    0,
+                    gl::RGB16F as i32,
+                    size as i32,
+                    size as i32,
+                    0,
+                    gl::RGB,
+                    gl::FLOAT,
+                    // Vec3 is #[repr(C)] (3 floats)
+                    face.as_ptr() as _,
+                ));
+            }
+
+            let min_filter = if mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+                gl::LINEAR_MIPMAP_LINEAR
+            } else {
+                gl::LINEAR
+            };
+
+            let parameters = [
+                (gl::TEXTURE_MIN_FILTER, min_filter),
+                (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+                (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE),
+            ];
+            for (name, value) in parameters {
+                gl::TexParameteri(gl::TEXTURE_CUBE_MAP, name, value as i32);
+            }
+
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+        }
+
+        Self { id }
+    }
+
+    /// Binds the cube map to the texture unit
+    pub fn bind_to_unit(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+}
+
+impl Drop for TextureCube {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use std::{mem, slice};
+
+use glam::Mat4;
+
 /// Abstraction for working with UniformBuffers.
 /// UniformBuffer is generic over T, and T must implement the UniformBufferElement trait.
 pub struct UniformBuffer<T: UniformBufferElement> {
@@ -39,13 +43,76 @@ where
             gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
         }
     }
+
+    /// Allocates the storage again (after the size of the element changed)
+    pub fn reallocate(&self) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.id);
+
+            self.inner.init_buffer();
+
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+
+        self.update();
+    }
+
+    /// Update only 'len' bytes of the UniformBuffer starting at 'offset'
+    pub fn update_range(&self, offset: usize, len: usize) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.id);
+
+            self.inner.update_range(offset, len);
+
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
 }
 
 pub trait UniformBufferElement {
     /// The binding port
     const BINDING: u32;
+    /// Name of the uniform block in the shaders
+    const NAME: &'static str;
     /// Update buffer data using gl::BufferSubData
     fn update(&self);
+    /// Update a part of the buffer data (in bytes).
+    ///
+    /// Elements that can't serialize only a part of their data update all of it.
+    fn update_range(&self, _offset: usize, _len: usize) {
+        self.update();
+    }
     /// Allocate data for the element with gl::BufferData
     fn init_buffer(&self);
 }
+
+/// Size of a mat4 in the std140 layout, also the stride of mat4 arrays
+pub const STD140_MAT4_SIZE: usize = 64;
+
+/// Reinterprets a slice of matrices as bytes.
+///
+/// Mat4 is 16 column-major floats without padding, which is also its std140 layout.
+pub fn mat4_bytes(mats: &[Mat4]) -> &[u8] {
+    unsafe { slice::from_raw_parts(mats.as_ptr() as *const u8, mem::size_of_val(mats)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mat4_bytes_match_std140() {
+        assert_eq!(mem::size_of::<Mat4>(), STD140_MAT4_SIZE);
+
+        let mats = [Mat4::IDENTITY, Mat4::from_cols_array(&[2.; 16])];
+        let bytes = mat4_bytes(&mats);
+
+        assert_eq!(bytes.len(), 2 * STD140_MAT4_SIZE);
+        // Column-major, the first float of the second matrix starts at its stride
+        assert_eq!(bytes[..4], 1f32.to_ne_bytes());
+        assert_eq!(
+            bytes[STD140_MAT4_SIZE..STD140_MAT4_SIZE + 4],
+            2f32.to_ne_bytes()
+        );
+    }
+}
@@ -0,0 +1,61 @@
+/// An OpenGL vertex array object, deleted when dropped.
+///
+/// Doesn't own the buffers, they have to be kept alive separately.
+pub struct Vao {
+    pub id: u32,
+}
+
+impl Vao {
+    pub fn new() -> Self {
+        let mut id = 0;
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut id);
+        }
+
+        Self { id }
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindVertexArray(self.id);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindVertexArray(0);
+        }
+    }
+}
+
+impl Drop for Vao {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.id);
+        }
+    }
+}
+
+/// A triangle covering the whole viewport.
+///
+/// Doesn't have any vertex buffers, the vertex shader generates the positions from gl_VertexID
+/// (see 'shaders/fullscreen.vert'), but a VAO has to be bound for the draw call.
+pub struct FullscreenTriangle {
+    vao: Vao,
+}
+
+impl FullscreenTriangle {
+    pub fn new() -> Self {
+        Self { vao: Vao::new() }
+    }
+
+    /// Draws the triangle with the currently used shader
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe {
+            gl_check!(gl::DrawArrays(gl::TRIANGLES, 0, 3));
+        }
+        self.vao.unbind();
+    }
+}
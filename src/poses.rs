@@ -0,0 +1,308 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use glam::{Quat, Vec3};
+use gltf::animation::Interpolation;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Animation, AnimationTransforms, Channel, Joint, Transform};
+
+/// The transform of a single joint in a pose preset
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JointPose {
+    /// Joints are matched by name, so the preset survives re-exports that reorder them
+    pub name: String,
+    pub translation: [f32; 3],
+    /// Quaternion (x, y, z, w)
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+/// A named snapshot of all joint transforms of a skeleton
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PosePreset {
+    pub name: String,
+    pub joints: Vec<JointPose>,
+}
+
+impl PosePreset {
+    /// Captures the current transforms of the joints
+    pub fn capture(name: String, joints: &[Joint]) -> Self {
+        let joints = joints
+            .iter()
+            .map(|joint| JointPose {
+                name: joint.name.clone(),
+                translation: joint.transform.translation.to_array(),
+                rotation: joint.transform.rotation.to_array(),
+                scale: joint.transform.scale.to_array(),
+            })
+            .collect();
+
+        Self { name, joints }
+    }
+
+    /// Sets the transforms of the joints with the same names.
+    ///
+    /// Returns the names of the joints in the preset that don't exist in the skeleton.
+    pub fn apply(&self, joints: &mut [Joint]) -> Vec<String> {
+        let indices: HashMap<&str, usize> = joints
+            .iter()
+            .enumerate()
+            .map(|(i, joint)| (joint.name.as_str(), i))
+            .collect();
+
+        let mut missing = Vec::new();
+        let mut transforms = Vec::new();
+
+        for pose in &self.joints {
+            match indices.get(pose.name.as_str()) {
+                Some(&i) => transforms.push((
+                    i,
+                    Transform {
+                        translation: Vec3::from(pose.translation),
+                        rotation: Quat::from_array(pose.rotation),
+                        scale: Vec3::from(pose.scale),
+                    },
+                )),
+                None => missing.push(pose.name.clone()),
+            }
+        }
+
+        for (i, transform) in transforms {
+            joints[i].transform = transform;
+        }
+
+        missing
+    }
+}
+
+/// Keyframes of a single property of a joint
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedChannel {
+    /// Joints are matched by name (like in the pose presets)
+    pub joint: String,
+    pub keyframe_times: Vec<f32>,
+    pub transforms: SavedTransforms,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SavedTransforms {
+    Translations(Vec<[f32; 3]>),
+    /// Quaternions (x, y, z, w)
+    Rotations(Vec<[f32; 4]>),
+    Scales(Vec<[f32; 3]>),
+}
+
+/// An animation created in the GUI
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedAnimation {
+    pub name: String,
+    pub channels: Vec<SavedChannel>,
+}
+
+impl SavedAnimation {
+    /// Converts the channels of the animation, channels of nodes that aren't joints are skipped
+    pub fn from_animation(anim: &Animation, joints: &[Joint]) -> Self {
+        let channels = anim
+            .channels
+            .iter()
+            .filter_map(|channel| {
+                let joint = joints.iter().find(|j| j.node_index == channel.node)?;
+
+                let transforms = match &channel.transforms {
+                    AnimationTransforms::Translations(t) => {
+                        SavedTransforms::Translations(t.iter().map(|v| v.to_array()).collect())
+                    }
+                    AnimationTransforms::Rotations(r) => {
+                        SavedTransforms::Rotations(r.iter().map(|q| q.to_array()).collect())
+                    }
+                    AnimationTransforms::Scales(s) => {
+                        SavedTransforms::Scales(s.iter().map(|v| v.to_array()).collect())
+                    }
+                };
+
+                Some(SavedChannel {
+                    joint: joint.name.clone(),
+                    keyframe_times: channel.keyframe_times.clone(),
+                    transforms,
+                })
+            })
+            .collect();
+
+        Self {
+            name: anim.name.clone().unwrap_or_default(),
+            channels,
+        }
+    }
+
+    /// Creates the animation for the skeleton.
+    ///
+    /// Returns the names of the joints in the saved animation that don't exist in the skeleton.
+    pub fn to_animation(&self, joints: &[Joint]) -> (Animation, Vec<String>) {
+        let mut anim = Animation::new_authored(self.name.clone());
+        let mut missing = Vec::new();
+
+        for saved in &self.channels {
+            let joint = match joints.iter().find(|j| j.name == saved.joint) {
+                Some(joint) => joint,
+                None => {
+                    missing.push(saved.joint.clone());
+                    continue;
+                }
+            };
+
+            let transforms = match &saved.transforms {
+                SavedTransforms::Translations(t) => {
+                    AnimationTransforms::Translations(t.iter().map(|v| Vec3::from(*v)).collect())
+                }
+                SavedTransforms::Rotations(r) => {
+                    AnimationTransforms::Rotations(r.iter().map(|q| Quat::from_array(*q)).collect())
+                }
+                SavedTransforms::Scales(s) => {
+                    AnimationTransforms::Scales(s.iter().map(|v| Vec3::from(*v)).collect())
+                }
+            };
+
+            let end_time = saved.keyframe_times.last().copied().unwrap_or(0.);
+            anim.end_time = anim.end_time.max(end_time);
+
+            anim.channels.push(Channel::new(
+                joint.node_index,
+                saved.keyframe_times.clone(),
+                transforms,
+                Interpolation::Linear,
+            ));
+        }
+
+        (anim, missing)
+    }
+}
+
+/// The joints an animation is allowed to affect
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedMask {
+    /// Name of the animation
+    pub animation: String,
+    /// Names of the joints (like in the pose presets)
+    pub joints: Vec<String>,
+}
+
+impl SavedMask {
+    /// Returns None for unnamed or unmasked animations
+    pub fn from_animation(anim: &Animation, joints: &[Joint]) -> Option<Self> {
+        let mask = anim.mask.as_ref()?;
+
+        Some(Self {
+            animation: anim.name.clone()?,
+            joints: joints
+                .iter()
+                .filter(|j| mask.contains(&j.node_index))
+                .map(|j| j.name.clone())
+                .collect(),
+        })
+    }
+
+    /// Sets the mask of the animation with the same name (if there is one).
+    ///
+    /// Returns the names of the masked joints that don't exist in the skeleton.
+    pub fn apply(&self, animations: &mut [Animation], joints: &[Joint]) -> Vec<String> {
+        let anim = match animations
+            .iter_mut()
+            .find(|a| a.name.as_deref() == Some(&self.animation))
+        {
+            Some(anim) => anim,
+            None => return Vec::new(),
+        };
+
+        let mut mask = HashSet::new();
+        let mut missing = Vec::new();
+
+        for name in &self.joints {
+            match joints.iter().find(|j| j.name == *name) {
+                Some(joint) => {
+                    mask.insert(joint.node_index);
+                }
+                None => missing.push(name.clone()),
+            }
+        }
+
+        anim.mask = Some(mask);
+        missing
+    }
+}
+
+/// Contents of the JSON file
+#[derive(Default, Serialize, Deserialize)]
+struct PoseFile {
+    poses: Vec<PosePreset>,
+    #[serde(default)]
+    animations: Vec<SavedAnimation>,
+    #[serde(default)]
+    masks: Vec<SavedMask>,
+}
+
+/// Pose presets, animations created in the GUI and animation masks of a single model,
+/// persisted to 'poses/<model_name>.json'
+pub struct PosePresets {
+    pub list: Vec<PosePreset>,
+    pub animations: Vec<SavedAnimation>,
+    pub masks: Vec<SavedMask>,
+    /// Path of the JSON file
+    path: PathBuf,
+}
+
+impl PosePresets {
+    /// Loads the presets, animations and masks of the model from the JSON file.
+    ///
+    /// A missing or invalid file isn't fatal, the list just starts empty.
+    pub fn load(model_name: &str) -> Self {
+        let path = Self::path(model_name);
+
+        let file: PoseFile = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Couldn't parse '{}': {e}", path.display());
+                PoseFile::default()
+            }),
+            Err(_) => PoseFile::default(),
+        };
+
+        Self {
+            list: file.poses,
+            animations: file.animations,
+            masks: file.masks,
+            path,
+        }
+    }
+
+    /// Writes the presets, animations and masks to the JSON file
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = PoseFile {
+            poses: self.list.clone(),
+            animations: self.animations.clone(),
+            masks: self.masks.clone(),
+        };
+
+        let json = serde_json::to_string_pretty(&file)?;
+        fs::write(&self.path, json)?;
+        Ok(())
+    }
+
+    /// 'poses/<model_name>.json', the extension of the model file is left out
+    fn path(model_name: &str) -> PathBuf {
+        let stem = Path::new(model_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| model_name.to_string());
+
+        Path::new("poses").join(format!("{stem}.json"))
+    }
+}
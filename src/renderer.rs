@@ -1,340 +1,1750 @@
-use std::{ptr, time::Instant};
+use std::{cmp::Ordering, collections::HashMap, mem::size_of, path::Path, ptr};
 
-use eyre::Result;
-use glam::{Mat4, Vec3, Vec4};
+use eyre::{Context, Result};
+use gl::types::GLenum;
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4};
+use gltf::material::AlphaMode;
+use image::RgbaImage;
+use log::{error, info, warn};
 
 use crate::{
     camera::Camera,
-    gui::Gui,
-    model::{
-        AnimationControl, AnimationTransform, Joint, Mesh, Model, Node, Primitive, PrimitiveTexture,
+    model::{pose, Aabb, AnimationTransform, Joint, Joints, Mesh, Model, Node, PrimitiveMaterial},
+    ogl::{
+        self, framebuffer::Framebuffer, gl_check, shader::Shader, uniform_buffer::UniformBuffer,
+        FullscreenTriangle, GpuTimer, Texture2D,
     },
-    ogl::{shader::Shader, uniform_buffer::UniformBuffer},
-    window::MyWindow,
 };
 
+mod environment;
+mod frustum;
+mod hdr_target;
+mod joint_dual_quats;
 mod joint_transforms;
 mod lighting;
 mod material;
+mod options;
 mod settings;
 mod skeleton_mesh;
 mod transforms;
 
 use self::{
-    joint_transforms::JointTransforms, lighting::Lighting, material::Material, settings::Settings,
-    transforms::Transforms,
+    environment::Environment, frustum::Frustum, hdr_target::HdrTarget,
+    joint_dual_quats::JointDualQuats, joint_transforms::JointTransforms, lighting::Lighting,
+    material::Material, settings::Settings, transforms::Transforms,
 };
 
+pub use self::{
+    lighting::{Light, LightType, MAX_LIGHTS},
+    options::{Background, Outline, RenderOptions, ToneMapping, Winding, Wireframe},
+};
+
+/// Shader source files
+const VERTEX_SHADER: &str = "shaders/vs_combined.vert";
+const FRAGMENT_SHADER: &str = "shaders/fs_combined.frag";
+const SKINNING_SHADER: &str = "shaders/cs_skinning.comp";
+const SKYBOX_VERTEX_SHADER: &str = "shaders/skybox.vert";
+const SKYBOX_FRAGMENT_SHADER: &str = "shaders/skybox.frag";
+const FULLSCREEN_VERTEX_SHADER: &str = "shaders/fullscreen.vert";
+const TONEMAP_FRAGMENT_SHADER: &str = "shaders/tonemap.frag";
+const OBJECT_ID_FRAGMENT_SHADER: &str = "shaders/object_id.frag";
+
+/// Shader sources embedded into the binary, used when the files are missing
+const VERTEX_SHADER_SRC: &str = include_str!("../shaders/vs_combined.vert");
+const FRAGMENT_SHADER_SRC: &str = include_str!("../shaders/fs_combined.frag");
+const SKINNING_SHADER_SRC: &str = include_str!("../shaders/cs_skinning.comp");
+const SKYBOX_VERTEX_SHADER_SRC: &str = include_str!("../shaders/skybox.vert");
+const SKYBOX_FRAGMENT_SHADER_SRC: &str = include_str!("../shaders/skybox.frag");
+const FULLSCREEN_VERTEX_SHADER_SRC: &str = include_str!("../shaders/fullscreen.vert");
+const TONEMAP_FRAGMENT_SHADER_SRC: &str = include_str!("../shaders/tonemap.frag");
+const OBJECT_ID_FRAGMENT_SHADER_SRC: &str = include_str!("../shaders/object_id.frag");
+
+/// All of the shader source files (watched for changes)
+pub const SHADER_FILES: [&str; 8] = [
+    VERTEX_SHADER,
+    FRAGMENT_SHADER,
+    SKINNING_SHADER,
+    SKYBOX_VERTEX_SHADER,
+    SKYBOX_FRAGMENT_SHADER,
+    FULLSCREEN_VERTEX_SHADER,
+    TONEMAP_FRAGMENT_SHADER,
+    OBJECT_ID_FRAGMENT_SHADER,
+];
+
+/// Diameter of the skeleton overlay's joints relative to the median bone length
+const JOINT_SIZE: f32 = 0.25;
+/// Thickness of the skeleton overlay's bones relative to the median bone length
+const BONE_THICKNESS: f32 = 0.05;
+
 /// A component responsible for rendering the scene.
 pub struct Renderer {
-    /// Shader for meshes containing texture data
-    texture_shader: Shader,
-    /// Shader for meshes without textures
-    color_shader: Shader,
+    /// Shader for all meshes (the skeleton overlay as well)
+    shader: Shader,
+    /// Compute shader that skins the vertices into the vertex buffers
+    /// (None if compute shaders aren't supported, skinning is then done in the vertex shader)
+    skinning_shader: Option<Shader>,
+    /// Shader drawing the environment map behind the models
+    skybox_shader: Shader,
+    /// Shader mapping the HDR colors to the screen
+    tonemap_shader: Shader,
+    /// Writes the node ids of the meshes for picking (uses the mesh vertex shader)
+    object_id_shader: Shader,
+    /// If the draw calls use the object id shader (set by `pick_node`)
+    picking: bool,
+    /// Used by the skybox and the tone mapping pass
+    fullscreen_triangle: FullscreenTriangle,
+    /// The scene is rendered into it, recreated when the window is resized
+    /// (None before the first frame and if it couldn't be created)
+    hdr_target: Option<HdrTarget>,
+    /// If creating the HDR framebuffer failed, the scene is then rendered directly to the screen
+    hdr_failed: bool,
+    /// MSAA samples of the HDR framebuffer (the same as the window has)
+    msaa_samples: u32,
+    /// The loaded environment map (the skybox and the ambient light)
+    environment: Option<Environment>,
     /// Current MVP transformation matrices
     transforms: UniformBuffer<Transforms>,
     /// Joint transformation matrices
     joint_transforms: UniformBuffer<JointTransforms>,
+    /// Joint transforms as dual quaternions (only updated when dual quaternion skinning is on)
+    joint_dual_quats: UniformBuffer<JointDualQuats>,
     /// Rendering settings
     settings: UniformBuffer<Settings>,
     /// Current mesh material
     material: UniformBuffer<Material>,
-    #[allow(unused)]
     /// Current lighting settings
     lighting: UniformBuffer<Lighting>,
+    /// A 1x1 white texture bound in place of missing base color / emissive / occlusion textures
+    default_texture: Texture2D,
     /// Current joint / node transforms
-    node_animation_transforms: Vec<NodeAnimationTransform>,
+    node_animation_transforms: Vec<pose::NodeAnimationTransform>,
+    /// View frustum of the current frame
+    frustum: Frustum,
+    /// If the skeleton debug view is active (it abuses face culling, so culling can't be changed per material)
+    skeleton_x_ray: bool,
+    /// If face culling is enabled for the meshes
+    face_culling: bool,
+    /// If the opaque draw calls are recorded and sorted (set from the GUI every frame)
+    sort_draws: bool,
+    /// Opaque primitives of the current frame, drawn after the node traversal sorted by textures
+    opaque_draws: Vec<DrawCall>,
+    /// Transparent primitives of the current frame, drawn after the opaque ones
+    transparent_draws: Vec<DrawCall>,
+    /// Primitives of the current frame that are outlined, drawn again after the transparent ones
+    outline_draws: Vec<DrawCall>,
+    /// Copies of joint matrices needed by the skinned transparent primitives
+    frame_skins: Vec<Vec<Mat4>>,
+    /// Statistics of the last rendered frame
+    pub stats: FrameStats,
+    /// World-space positions of the joints in the last rendered frame
+    pub joint_positions: Vec<Vec3>,
+    /// Positions of the joints of the compared model relative to the model, by joint name
+    compared_joints: HashMap<String, Vec3>,
+    /// If the joint positions of the skins are stored in `compared_joints`
+    recording_joints: bool,
+    /// Inverse of the transform of the model that is being rendered
+    model_inverse: Mat4,
+    /// Maximum number of joint matrices that fit into a uniform block
+    max_joints: usize,
+    /// Maximum anisotropic filtering level, None if it isn't supported
+    max_anisotropy: Option<f32>,
+    /// Maximum width of the skeleton overlay's bones
+    max_line_width: f32,
+    /// GPU times of the render passes (the GUI pass is timed by the main loop)
+    pub gpu_timer: GpuTimer,
 }
 
 impl Renderer {
     /// Create a new renderer
+    ///
+    /// Returns an error if the OpenGL context doesn't support the required features.
     pub fn new() -> Result<Self> {
-        let texture_shader =
-            Shader::from_file("shaders/vs_combined.vert", "shaders/fs_texture.frag")?;
-        let color_shader = Shader::from_file("shaders/vs_combined.vert", "shaders/fs_color.frag")?;
+        let capabilities = ogl::Capabilities::query();
+        capabilities.check(JointTransforms::new().size())?;
+
+        let mut shader = Self::load_shader(
+            (VERTEX_SHADER, VERTEX_SHADER_SRC),
+            (FRAGMENT_SHADER, FRAGMENT_SHADER_SRC),
+        )?;
+
+        Self::bind_uniform_blocks(&mut shader)?;
+        Self::bind_samplers(&mut shader);
+        shader.set_label("Mesh shader");
+
+        let mut skybox_shader = Self::load_shader(
+            (SKYBOX_VERTEX_SHADER, SKYBOX_VERTEX_SHADER_SRC),
+            (SKYBOX_FRAGMENT_SHADER, SKYBOX_FRAGMENT_SHADER_SRC),
+        )?;
+        skybox_shader.bind_sampler("skybox", ogl::ENVIRONMENT_TEXTURE_UNIT);
+        skybox_shader.set_label("Skybox shader");
+
+        let mut tonemap_shader = Self::load_shader(
+            (FULLSCREEN_VERTEX_SHADER, FULLSCREEN_VERTEX_SHADER_SRC),
+            (TONEMAP_FRAGMENT_SHADER, TONEMAP_FRAGMENT_SHADER_SRC),
+        )?;
+        tonemap_shader.bind_sampler("hdrColor", ogl::HDR_COLOR_TEXTURE_UNIT);
+        tonemap_shader.set_label("Tone mapping shader");
+
+        let mut object_id_shader = Self::load_shader(
+            (VERTEX_SHADER, VERTEX_SHADER_SRC),
+            (OBJECT_ID_FRAGMENT_SHADER, OBJECT_ID_FRAGMENT_SHADER_SRC),
+        )?;
+        object_id_shader.bind_uniform_block::<Transforms>()?;
+        object_id_shader.bind_uniform_block::<JointTransforms>()?;
+        object_id_shader.bind_uniform_block::<JointDualQuats>()?;
+        object_id_shader.bind_uniform_block::<Settings>()?;
+        object_id_shader.set_label("Object id shader");
 
         Ok(Self {
-            texture_shader,
-            color_shader,
+            shader,
+            skinning_shader: Self::load_skinning_shader(&capabilities),
+            skybox_shader,
+            tonemap_shader,
+            object_id_shader,
+            picking: false,
+            fullscreen_triangle: FullscreenTriangle::new(),
+            hdr_target: None,
+            hdr_failed: false,
+            msaa_samples: capabilities.samples,
+            environment: None,
             transforms: UniformBuffer::new(Transforms::new_indentity()),
             joint_transforms: UniformBuffer::new(JointTransforms::new()),
+            joint_dual_quats: UniformBuffer::new(JointDualQuats::new()),
             settings: UniformBuffer::new(Settings::new()),
             material: UniformBuffer::new(Material::new()),
-            lighting: UniformBuffer::new(Lighting::new(Vec3::new(400., 1000., 400.))),
+            lighting: UniformBuffer::new(Lighting::new(
+                vec![Light::new_point(Vec3::new(400., 1000., 400.))],
+                Vec3::splat(0.4),
+            )),
+            default_texture: ogl::create_1x1_texture([255, 255, 255, 255]),
             node_animation_transforms: Vec::new(),
+            frustum: Frustum::from_matrix(Mat4::IDENTITY),
+            skeleton_x_ray: false,
+            face_culling: true,
+            sort_draws: true,
+            opaque_draws: Vec::new(),
+            transparent_draws: Vec::new(),
+            outline_draws: Vec::new(),
+            frame_skins: Vec::new(),
+            stats: FrameStats::default(),
+            joint_positions: Vec::new(),
+            compared_joints: HashMap::new(),
+            recording_joints: false,
+            model_inverse: Mat4::IDENTITY,
+            max_joints: capabilities.max_joints(),
+            max_anisotropy: capabilities.max_anisotropy,
+            max_line_width: capabilities.max_line_width,
+            gpu_timer: GpuTimer::new(),
         })
     }
 
+    /// Creates the shader from the files on disk (so it can be hot-reloaded),
+    /// or from the embedded sources if the files are missing.
+    ///
+    /// 'vs' and 'fs' are pairs of (path, embedded source).
+    fn load_shader(vs: (&str, &str), fs: (&str, &str)) -> Result<Shader> {
+        let (vs_path, vs_src) = vs;
+        let (fs_path, fs_src) = fs;
+
+        if Path::new(vs_path).exists() && Path::new(fs_path).exists() {
+            Shader::from_file(vs_path, fs_path)
+        } else {
+            warn!("Shader files '{vs_path}' + '{fs_path}' not found, using the embedded sources");
+
+            Shader::from_source(vs_src, fs_src)
+                .wrap_err_with(|| format!("Embedded shaders '{vs_path}' + '{fs_path}'"))
+        }
+    }
+
+    /// Creates the skinning compute shader if the context supports compute shaders (OpenGL 4.3).
+    ///
+    /// Errors aren't fatal, the vertex shader skinning is used instead.
+    fn load_skinning_shader(capabilities: &ogl::Capabilities) -> Option<Shader> {
+        let (major, minor) = capabilities.version;
+        if !capabilities.compute_shaders {
+            warn!("Compute shaders need OpenGL 4.3 (the context is {major}.{minor}), compute skinning is disabled");
+            return None;
+        }
+
+        let shader = if Path::new(SKINNING_SHADER).exists() {
+            Shader::compute_from_file(SKINNING_SHADER)
+        } else {
+            Shader::compute_from_source(SKINNING_SHADER_SRC)
+                .wrap_err_with(|| format!("Embedded shader '{SKINNING_SHADER}'"))
+        };
+
+        let shader = shader.and_then(|mut shader| {
+            shader.bind_uniform_block::<JointTransforms>()?;
+            shader.set_label("Skinning compute shader");
+            Ok(shader)
+        });
+
+        match shader {
+            Ok(shader) => Some(shader),
+            Err(e) => {
+                error!("Couldn't create the skinning compute shader: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Sets the texture units of the material textures
+    fn bind_samplers(shader: &mut Shader) {
+        shader.bind_sampler("baseColorTexture", ogl::BASE_COLOR_TEXTURE_UNIT);
+        shader.bind_sampler("normalTexture", ogl::NORMAL_TEXTURE_UNIT);
+        shader.bind_sampler("emissiveTexture", ogl::EMISSIVE_TEXTURE_UNIT);
+        shader.bind_sampler("occlusionTexture", ogl::OCCLUSION_TEXTURE_UNIT);
+        shader.bind_sampler("environmentMap", ogl::ENVIRONMENT_TEXTURE_UNIT);
+        shader.bind_sampler("irradianceMap", ogl::IRRADIANCE_TEXTURE_UNIT);
+    }
+
+    /// Binds the uniform blocks to the binding points of the uniform buffers.
+    ///
+    /// The shader uses all of the blocks, a missing block is a startup error.
+    fn bind_uniform_blocks(shader: &mut Shader) -> Result<()> {
+        shader.bind_uniform_block::<Transforms>()?;
+        shader.bind_uniform_block::<JointTransforms>()?;
+        shader.bind_uniform_block::<JointDualQuats>()?;
+        shader.bind_uniform_block::<Settings>()?;
+        shader.bind_uniform_block::<Material>()?;
+        shader.bind_uniform_block::<Lighting>()?;
+
+        Ok(())
+    }
+
+    /// Recompiles the shaders from the source files.
+    ///
+    /// If the shaders fail to compile, the old program stays in use.
+    pub fn reload_shaders(&mut self) -> Result<()> {
+        self.shader.reload()?;
+        self.skybox_shader.reload()?;
+        self.tonemap_shader.reload()?;
+        self.object_id_shader.reload()?;
+
+        if let Some(shader) = &mut self.skinning_shader {
+            shader.reload()?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads the environment map (the skybox and the ambient light) from an equirectangular image.
+    ///
+    /// The previous environment stays if the loading fails.
+    pub fn load_environment(&mut self, path: &Path) -> Result<()> {
+        let environment = Environment::load(path)?;
+
+        environment
+            .skybox
+            .bind_to_unit(ogl::ENVIRONMENT_TEXTURE_UNIT);
+        environment
+            .irradiance
+            .bind_to_unit(ogl::IRRADIANCE_TEXTURE_UNIT);
+
+        info!("Loaded the environment '{}'", environment.path);
+        self.environment = Some(environment);
+        Ok(())
+    }
+
+    /// Path of the loaded environment map
+    pub fn environment_path(&self) -> Option<&str> {
+        self.environment.as_ref().map(|env| env.path.as_str())
+    }
+
     /// Render a new frame
     pub fn render(
         &mut self,
         models: &mut [Model],
         camera: &mut Camera,
-        window: &MyWindow,
-        gui_state: &Gui,
+        width: u32,
+        height: u32,
+        options: &RenderOptions,
     ) {
+        let resized = self
+            .hdr_target
+            .as_ref()
+            .is_none_or(|target| target.size() != (width, height));
+
+        // A minimized window has zero size
+        if resized && !self.hdr_failed && width > 0 && height > 0 {
+            self.hdr_target = match HdrTarget::new(width, height, self.msaa_samples) {
+                Ok(target) => Some(target),
+                Err(e) => {
+                    error!(
+                        "Couldn't create the HDR framebuffer, rendering without tone mapping: {e}"
+                    );
+                    self.hdr_failed = true;
+                    None
+                }
+            };
+        }
+
+        match self.hdr_target.take() {
+            Some(target) if target.size() == (width, height) => {
+                self.render_hdr(models, camera, &target, 0, options);
+                self.hdr_target = Some(target);
+            }
+            target => {
+                self.hdr_target = target;
+                self.render_viewport(models, camera, width, height, options);
+            }
+        }
+    }
+
+    /// Renders the scene into an offscreen framebuffer and reads the result back.
+    ///
+    /// Doesn't need a visible window, only a current OpenGL context.
+    pub fn render_to_image(
+        &mut self,
+        models: &mut [Model],
+        camera: &mut Camera,
+        options: &RenderOptions,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbaImage> {
+        let target = HdrTarget::new(width, height, self.msaa_samples)?;
+        let framebuffer = Framebuffer::new(width, height)?;
+
+        self.render_hdr(models, camera, &target, framebuffer.id, options);
+
+        framebuffer.bind();
         unsafe {
-            gl::Viewport(0, 0, window.width as i32, window.height as i32);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+        let image = Self::read_pixels(width, height);
+
+        framebuffer.unbind();
+
+        Ok(image)
+    }
+
+    /// Returns the index of the selected model's node whose mesh is at the pixel
+    /// ('x' and 'y' are from the top-left corner), None if there's no mesh.
+    ///
+    /// The node ids of the recorded draw calls are rendered into an integer framebuffer,
+    /// only the pixel itself is rasterized. Uses the node transforms of the last rendered frame.
+    pub fn pick_node(
+        &mut self,
+        models: &mut [Model],
+        camera: &mut Camera,
+        options: &RenderOptions,
+        (width, height): (u32, u32),
+        (x, y): (u32, u32),
+    ) -> Result<Option<usize>> {
+        let model = match models.get_mut(options.selected_model) {
+            Some(model) => model,
+            None => return Ok(None),
+        };
+
+        if x >= width || y >= height {
+            return Ok(None);
+        }
+
+        let framebuffer = Framebuffer::new_object_id(width, height)?;
+        // OpenGL has the rows bottom-to-top
+        let pixel_y = height - 1 - y;
+
+        framebuffer.bind();
+        unsafe {
+            gl::Viewport(0, 0, width as i32, height as i32);
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(x as i32, pixel_y as i32, 1, 1);
+
             gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
 
-            gl::Enable(gl::CULL_FACE);
-            gl::FrontFace(gl::CCW);
+            let background = 0u32;
+            gl::ClearBufferuiv(gl::COLOR, 0, &background);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
 
-            if gui_state.draw_skeleton {
-                gl::CullFace(gl::FRONT);
-                gl::PolygonMode(gl::BACK, gl::LINE);
-            } else {
-                gl::CullFace(gl::BACK);
-                gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
-            }
+        let persp = camera.projection_mat(width as f32 / height as f32);
+        let view = camera.view_mat();
+        self.frustum = Frustum::from_matrix(persp * view);
 
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        self.transforms.inner.projection = persp;
+        self.transforms.inner.view = view;
+        self.transforms.inner.model = model.transform;
+        self.transforms.update();
+
+        // The traversal counts the primitives and draws the skeleton, keep the frame's state
+        let stats = std::mem::take(&mut self.stats);
+        let sort_draws = std::mem::replace(&mut self.sort_draws, true);
+        let skeleton_x_ray = std::mem::replace(&mut self.skeleton_x_ray, false);
+        let pick_options = RenderOptions {
+            draw_skeleton: false,
+            outline: None,
+            wireframe: None,
+            ..options.clone()
+        };
+
+        self.picking = true;
+        ogl::debug_group("Picking", || {
+            let transform = model.transform;
+            self.render_node(&mut model.root, transform, &pick_options);
+
+            let mut draws = std::mem::take(&mut self.opaque_draws);
+            draws.append(&mut self.transparent_draws);
+            self.outline_draws.clear();
+
+            // The same culling and depth testing as in the view, but without the x-ray
+            self.begin_mesh_state(&pick_options);
+            self.submit_draws(&draws);
+            self.end_mesh_state();
+            self.frame_skins.clear();
+
+            // Keep the allocation for the next frame
+            draws.clear();
+            self.opaque_draws = draws;
+        });
+        self.picking = false;
+
+        self.stats = stats;
+        self.sort_draws = sort_draws;
+        self.skeleton_x_ray = skeleton_x_ray;
+
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+
+        let id = framebuffer.read_object_id(x, pixel_y);
+        framebuffer.unbind();
+
+        Ok(id.checked_sub(1).map(|node| node as usize))
+    }
+
+    /// Draws only the skeleton of the selected model into a rectangle of the default framebuffer,
+    /// on top of the rendered frame.
+    ///
+    /// 'rect' is (x, y, width, height) in pixels from the top-left corner. The camera is the same
+    /// as in the main view, only the aspect ratio is taken from the rectangle.
+    pub fn render_skeleton_inset(
+        &mut self,
+        models: &mut [Model],
+        camera: &mut Camera,
+        (x, y, width, height): (u32, u32, u32, u32),
+        (window_width, window_height): (u32, u32),
+        options: &RenderOptions,
+    ) {
+        let model = match models.get_mut(options.selected_model) {
+            Some(model) => model,
+            None => return,
+        };
+
+        if width == 0 || height == 0 || x + width > window_width || y + height > window_height {
+            return;
+        }
+
+        // OpenGL has the rows bottom-to-top
+        let bottom = (window_height - y - height) as i32;
 
-            gl::ClearColor(0.15, 0.15, 0.15, 1.0);
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(x as i32, bottom, width as i32, height as i32);
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(x as i32, bottom, width as i32, height as i32);
+
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+
+            let [r, g, b] = (options.background_color * 0.5).to_array();
+            gl::ClearColor(r, g, b, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         }
 
+        let persp = camera.projection_mat(width as f32 / height as f32);
+        let view = camera.view_mat();
+        self.frustum = Frustum::from_matrix(persp * view);
+
+        self.transforms.inner.projection = persp;
+        self.transforms.inner.view = view;
+        self.transforms.inner.model = model.transform;
+        self.transforms.update();
+        self.settings.inner.viewport_size = Vec2::new(width as f32, height as f32);
+
+        // The traversal counts the primitives, keep the frame's statistics
+        let stats = std::mem::take(&mut self.stats);
+        let skeleton_options = RenderOptions {
+            draw_skeleton: true,
+            mesh_visible: false,
+            outline: None,
+            ..options.clone()
+        };
+
+        self.gpu_timer.start("Skeleton inset");
+        ogl::debug_group("Skeleton inset", || {
+            let transform = model.transform;
+            self.render_node(&mut model.root, transform, &skeleton_options);
+            self.frame_skins.clear();
+        });
+        self.gpu_timer.stop();
+
+        self.stats = stats;
+
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::Viewport(0, 0, window_width as i32, window_height as i32);
+        }
+    }
+
+    /// Renders the scene into the HDR target and tone maps it into the 'output' framebuffer
+    fn render_hdr(
+        &mut self,
+        models: &mut [Model],
+        camera: &mut Camera,
+        target: &HdrTarget,
+        output: u32,
+        options: &RenderOptions,
+    ) {
+        let (width, height) = target.size();
+
+        target.bind();
+        self.render_viewport(models, camera, width, height, options);
+
+        self.gpu_timer.start("Tone mapping");
+        ogl::debug_group("Tone mapping", || {
+            let color = target.resolve();
+
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, output);
+                gl::Disable(gl::DEPTH_TEST);
+                gl::Disable(gl::CULL_FACE);
+
+                gl::ActiveTexture(gl::TEXTURE0 + ogl::HDR_COLOR_TEXTURE_UNIT);
+                color.bind();
+                // Egui expects unit 0 to be active
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+
+            self.tonemap_shader.render(|| {
+                let tone_mapping = match options.tone_mapping {
+                    ToneMapping::Clamp => 0,
+                    ToneMapping::Reinhard => 1,
+                    ToneMapping::Aces => 2,
+                };
+
+                self.tonemap_shader.set_f32(options.exposure, "exposure");
+                self.tonemap_shader.set_i32(tone_mapping, "toneMapping");
+                self.tonemap_shader.set_bool(options.srgb, "srgb");
+
+                self.fullscreen_triangle.draw();
+            });
+        });
+        self.gpu_timer.stop();
+    }
+
+    /// Renders the scene into the currently bound framebuffer.
+    ///
+    /// The colors are linear and unclamped, `render_hdr` tone maps them.
+    fn render_viewport(
+        &mut self,
+        models: &mut [Model],
+        camera: &mut Camera,
+        width: u32,
+        height: u32,
+        options: &RenderOptions,
+    ) {
+        unsafe {
+            gl::Viewport(0, 0, width as i32, height as i32);
+            gl::Enable(gl::DEPTH_TEST);
+
+            // The culling, winding and polygon mode options only apply to the meshes,
+            // the other passes expect the defaults (see 'end_mesh_state')
+            gl::Enable(gl::CULL_FACE);
+            gl::FrontFace(gl::CCW);
+            gl::CullFace(gl::BACK);
+
+            // Blending is only enabled for transparent primitives
+            gl::Disable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+
+        let use_environment =
+            options.background == Background::Environment && self.environment.is_some();
+
+        self.gpu_timer.start("Clear");
+        ogl::debug_group("Clear", || unsafe {
+            let [r, g, b] = options.background_color.to_array();
+            gl::ClearColor(r, g, b, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+        });
+        self.gpu_timer.stop();
+
         self.node_animation_transforms.clear();
 
-        // TODO: možná glu perspective
-        let persp = Mat4::perspective_rh(
-            f32::to_radians(60.),
-            window.width as f32 / window.height as f32,
-            0.1,
-            3000.,
-        );
+        let persp = camera.projection_mat(width as f32 / height as f32);
 
-        let model = &mut models[gui_state.selected_model];
+        let view = camera.view_mat();
+
+        if use_environment {
+            self.gpu_timer.start("Skybox");
+            ogl::debug_group("Skybox", || self.draw_skybox(persp, view, options));
+            self.gpu_timer.stop();
+        }
+
+        self.frustum = Frustum::from_matrix(persp * view);
+        self.stats = FrameStats::default();
 
         self.transforms.inner.projection = persp;
-        self.transforms.inner.view = camera.view_mat();
+        self.transforms.inner.view = view;
+
+        self.settings.inner.srgb = options.srgb;
+        self.settings.inner.dual_quaternions = options.dual_quaternion_skinning;
+        self.settings.inner.viewport_size = Vec2::new(width as f32, height as f32);
+
+        self.lighting.inner.lights.clone_from(&options.lights);
+        self.lighting.inner.ambient = options.ambient_light;
+        self.lighting.inner.camera_pos = camera.pos();
+        self.lighting.inner.use_environment = use_environment;
+        self.lighting.inner.environment_intensity = options.environment_intensity;
+        self.lighting.update();
+
+        self.sort_draws = options.sort_draws;
+
+        // The compared model is drawn first, so the selected model's joints can be compared
+        // with its joints (and the joint positions of the GUI are the selected model's)
+        self.compared_joints.clear();
+        let compared = options
+            .compare_model
+            .filter(|&i| i != options.selected_model);
+
+        if let Some(model) = compared.and_then(|i| models.get_mut(i)) {
+            let compare_options = RenderOptions {
+                outline: None,
+                ..options.clone()
+            };
+
+            self.recording_joints = true;
+            self.render_model(model, width, height, &compare_options);
+            self.recording_joints = false;
+        }
+
+        // Empty scene, only clear the screen
+        if let Some(model) = models.get_mut(options.selected_model) {
+            self.render_model(model, width, height, options);
+        }
+    }
+
+    /// Animates and draws a single model
+    fn render_model(
+        &mut self,
+        model: &mut Model,
+        width: u32,
+        height: u32,
+        options: &RenderOptions,
+    ) {
         self.transforms.inner.model = model.transform;
         self.transforms.update();
+        self.model_inverse = model.transform.inverse();
 
         self.recalculate_animation(model);
 
-        let transform = model.transform;
-        self.render_node(&mut model.root, transform, gui_state);
+        let anisotropy = self
+            .max_anisotropy
+            .map(|max| options.anisotropy.clamp(1., max));
+        model.set_texture_filtering(options.texture_filtering, anisotropy);
+
+        self.gpu_timer.start(&format!("Meshes ({})", model.name));
+
+        ogl::debug_group(&format!("Model '{}'", model.name), || {
+            let transform = model.transform;
+            self.render_node(&mut model.root, transform, options);
+
+            self.render_meshes(options);
+
+            if let Some(outline) = &options.outline {
+                self.render_outline(outline, width, height);
+            }
+
+            self.frame_skins.clear();
+        });
+
+        self.gpu_timer.stop();
+    }
+
+    /// Draws the environment map behind everything (without writing the depth)
+    fn draw_skybox(&self, projection: Mat4, view: Mat4, options: &RenderOptions) {
+        // Only the rotation of the camera matters
+        let view_rotation = Mat4::from_mat3(Mat3::from_mat4(view));
+        let inverse = (projection * view_rotation).inverse();
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::DepthMask(gl::FALSE);
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+
+        self.skybox_shader.render(|| {
+            self.skybox_shader.set_mat4(inverse, "invViewProjection");
+            self.skybox_shader
+                .set_f32(options.environment_intensity, "intensity");
+
+            self.fullscreen_triangle.draw();
+        });
+
+        unsafe {
+            gl::DepthMask(gl::TRUE);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    /// Reads the contents of the back buffer into an image
+    pub fn capture_frame(&self, width: u32, height: u32) -> RgbaImage {
+        unsafe {
+            gl::ReadBuffer(gl::BACK);
+        }
+
+        Self::read_pixels(width, height)
+    }
+
+    /// Reads the pixels of the current read buffer into an image.
+    ///
+    /// OpenGL stores the rows bottom-to-top, so the image is flipped vertically.
+    fn read_pixels(width: u32, height: u32) -> RgbaImage {
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as _,
+            );
+        }
+
+        // The alpha channel of the framebuffer is garbage after blending
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+
+        let mut image = RgbaImage::from_raw(width, height, pixels)
+            .expect("The buffer has the right size for the image");
+        image::imageops::flip_vertical_in_place(&mut image);
+
+        image
     }
 
     /// Recursive - traverses the node hierarchy and handles each node.
-    fn render_node(&mut self, node: &mut Node, outer_transform: Mat4, gui_state: &Gui) {
+    fn render_node(&mut self, node: &mut Node, outer_transform: Mat4, options: &RenderOptions) {
         let next_level_transform = outer_transform * node.transform;
 
+        let mut joint_bounds = None;
         if let Some(joints) = &mut node.joints {
-            self.recalc_skin_matrices(&mut joints.joints, next_level_transform, gui_state);
+            joint_bounds =
+                Some(self.recalc_skin_matrices(&mut joints.joints, next_level_transform, options));
         }
 
-        if gui_state.mesh_visible {
-            if let Some(mesh) = &node.mesh {
-                let do_skinning = node.joints.is_some();
-                self.settings.inner.do_skinning = do_skinning;
-                self.settings.update();
+        if options.mesh_visible {
+            let skinned = node.joints.is_some();
+            if let Some(mut mesh) = node.mesh.as_mut() {
+                let mesh_bounds = mesh.bounds.transform(&next_level_transform);
+
+                // Skinned vertices can be anywhere around the joints, so inflate the joints' bounds
+                // by the whole extent of the mesh
+                let bounds = match joint_bounds {
+                    Some(joint_bounds) => joint_bounds.inflate(2. * mesh_bounds.radius()),
+                    None => mesh_bounds,
+                };
+
+                if let Some(lods) = &mut node.lods {
+                    let level = lods.select(self.screen_size(&bounds), options.forced_lod);
+                    if let Some(lod_mesh) = lods.mesh_mut(level) {
+                        mesh = lod_mesh;
+                    }
+                }
+
+                if options.frustum_culling && self.frustum.culls_aabb(&bounds) {
+                    self.stats.culled_primitives += mesh.primitives.len();
+                } else {
+                    // Vertices skinned on the CPU or by the compute shader are already in world space
+                    let cpu_skinning = skinned && options.cpu_skinning;
+                    let compute_skinning = match &self.skinning_shader {
+                        Some(shader) if skinned && options.compute_skinning => Some(shader),
+                        _ => None,
+                    };
+
+                    for prim in &mut mesh.primitives {
+                        if cpu_skinning {
+                            prim.upload_cpu_skinned(&self.joint_transforms.inner.matrices);
+                        } else if let Some(shader) = compute_skinning {
+                            prim.skin_on_gpu(shader);
+                        } else {
+                            prim.restore_bind_pose();
+                        }
+                    }
+
+                    let pre_skinned = cpu_skinning || compute_skinning.is_some();
+                    let (transform, do_skinning) = if pre_skinned {
+                        (Mat4::IDENTITY, false)
+                    } else {
+                        (next_level_transform, skinned)
+                    };
 
-                self.render_mesh(mesh, next_level_transform);
+                    let outlined = match options.outline {
+                        Some(Outline {
+                            node: Some(index), ..
+                        }) => index == node.index,
+                        Some(Outline { node: None, .. }) => true,
+                        None => false,
+                    };
+
+                    self.render_mesh(
+                        mesh,
+                        node.index,
+                        transform,
+                        do_skinning,
+                        bounds.center(),
+                        outlined,
+                    );
+                    self.stats.drawn_primitives += mesh.primitives.len();
+                    self.stats.drawn_instances += mesh.instances.len();
+                    self.stats.drawn_triangles +=
+                        mesh.stats.triangles * mesh.instances.len().max(1);
+                }
             }
         }
 
         for node in &mut node.children {
-            self.render_node(node, next_level_transform, gui_state);
+            self.render_node(node, next_level_transform, options);
         }
     }
 
-    /// Renders the mesh of a node
-    fn render_mesh(&mut self, mesh: &Mesh, node_transform: Mat4) {
-        self.transforms.inner.model = node_transform;
-        self.transforms.update();
+    /// Projected diameter of the bounding sphere of the box divided by the viewport height
+    fn screen_size(&self, bounds: &Aabb) -> f32 {
+        let distance = bounds
+            .center()
+            .distance(self.lighting.inner.camera_pos)
+            .max(f32::EPSILON);
 
-        let draw_mesh = |vao: u32, prim: &Primitive| unsafe {
-            gl::BindVertexArray(vao);
+        // projection[1][1] is 1 / tan(fov_y / 2)
+        bounds.radius() * self.transforms.inner.projection.y_axis.y / distance
+    }
 
-            gl::DrawElements(
-                gl::TRIANGLES,
-                prim.indices.len() as i32,
-                prim.indices.gl_type(),
-                ptr::null(),
-            );
+    /// Size in pixels of a world-space length at the position
+    fn projected_pixels(&self, size: f32, pos: Vec3) -> f32 {
+        let distance = pos
+            .distance(self.lighting.inner.camera_pos)
+            .max(f32::EPSILON);
 
-            gl::BindVertexArray(0);
+        size * self.transforms.inner.projection.y_axis.y * self.settings.inner.viewport_size.y
+            / (2. * distance)
+    }
+
+    /// Updates only the model matrix in the uniform buffer (projection and view stay the same)
+    fn update_model_transform(&self) {
+        self.transforms
+            .update_range(Transforms::MODEL_OFFSET, size_of::<Mat4>());
+    }
+
+    /// Renders the mesh of a node.
+    ///
+    /// The primitives are only recorded, opaque ones are drawn later by `render_opaque`
+    /// and the ones with the BLEND alpha mode by `render_transparent`.
+    /// If sorting is disabled, the opaque primitives are drawn immediately.
+    /// 'center' is the world-space center of the mesh, used for sorting the transparent primitives.
+    /// Primitives of 'outlined' meshes are also recorded for `render_outline`.
+    fn render_mesh(
+        &mut self,
+        mesh: &Mesh,
+        node: usize,
+        node_transform: Mat4,
+        skinned: bool,
+        center: Vec3,
+        outlined: bool,
+    ) {
+        if !self.sort_draws {
+            self.transforms.inner.model = node_transform;
+            self.update_model_transform();
+
+            self.settings.inner.do_skinning = skinned;
+            self.settings.update();
+        }
+
+        let distance = center.distance(self.lighting.inner.camera_pos);
+        let mut skin = None;
+
+        let instances = match mesh.instances.len() {
+            0 => None,
+            count => Some(count as i32),
         };
 
         for prim in &mesh.primitives {
-            match prim.texture_info {
-                PrimitiveTexture::None { base_color_factor } => {
-                    self.material.inner.base_color_factor = base_color_factor;
-                    self.material.update();
-
-                    self.color_shader.render(|| {
-                        draw_mesh(prim.vao, prim);
-                    });
+            let vao = match &prim.vao {
+                Some(vao) if prim.visible => vao.id,
+                // Hidden or not uploaded to the GPU
+                _ => continue,
+            };
+
+            let transparent = prim.material.alpha_mode == AlphaMode::Blend;
+            let immediate = !transparent && !self.sort_draws;
+
+            if immediate {
+                let eight_influences = skinned && prim.has_eight_influences();
+                if self.settings.inner.eight_influences != eight_influences {
+                    self.settings.inner.eight_influences = eight_influences;
+                    self.settings.update();
                 }
-                PrimitiveTexture::Some {
-                    gl_id,
-                    base_color_factor,
-                } => {
-                    self.material.inner.base_color_factor = base_color_factor;
-                    self.material.update();
-
-                    unsafe {
-                        gl::BindTexture(gl::TEXTURE_2D, gl_id);
-                    }
 
-                    self.texture_shader.render(|| {
-                        draw_mesh(prim.vao, prim);
-                    });
+                self.draw_primitive(
+                    vao,
+                    prim.indices.len() as i32,
+                    prim.indices.gl_type(),
+                    instances,
+                    &prim.material,
+                    true,
+                );
+
+                if !outlined {
+                    continue;
                 }
+            }
+
+            // Joint matrices of the current skin are overwritten by the next skinned node
+            if skinned && skin.is_none() {
+                self.frame_skins
+                    .push(self.joint_transforms.inner.matrices.clone());
+                skin = Some(self.frame_skins.len() - 1);
+            }
+
+            let draw = DrawCall {
+                vao,
+                index_count: prim.indices.len() as i32,
+                index_type: prim.indices.gl_type(),
+                material: prim.material.clone(),
+                instances,
+                transform: node_transform,
+                node,
+                skin,
+                eight_influences: skinned && prim.has_eight_influences(),
+                distance,
+                cull_face: None,
             };
+
+            if outlined {
+                self.outline_draws.push(draw.clone());
+            }
+
+            if immediate {
+                continue;
+            }
+
+            if transparent {
+                self.transparent_draws.push(draw);
+            } else {
+                self.opaque_draws.push(draw);
+            }
         }
     }
 
-    /// Recalculates the skin matrices for each joint
+    /// Sets up the culling, winding, depth testing and polygon mode of the mesh passes.
+    ///
+    /// The skeleton view draws the back faces as lines (the front faces are culled),
+    /// so the joints are visible through the mesh.
+    fn begin_mesh_state(&mut self, options: &RenderOptions) {
+        self.skeleton_x_ray = options.draw_skeleton;
+        self.face_culling = options.face_culling;
+
+        unsafe {
+            gl::FrontFace(options.front_face.gl_enum());
+
+            if options.face_culling {
+                gl::Enable(gl::CULL_FACE);
+            } else {
+                gl::Disable(gl::CULL_FACE);
+            }
+
+            if self.skeleton_x_ray {
+                gl::CullFace(gl::FRONT);
+                gl::PolygonMode(gl::BACK, gl::LINE);
+            } else {
+                gl::CullFace(gl::BACK);
+            }
+
+            if !options.depth_test {
+                gl::Disable(gl::DEPTH_TEST);
+            }
+        }
+    }
+
+    /// Restores the state the other passes (skeleton, outline, tone mapping, GUI) expect
+    fn end_mesh_state(&self) {
+        unsafe {
+            gl::FrontFace(gl::CCW);
+            gl::Enable(gl::CULL_FACE);
+            gl::CullFace(gl::BACK);
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    /// Draws the recorded primitives, shaded and / or as a wireframe
+    fn render_meshes(&mut self, options: &RenderOptions) {
+        let wireframe = options.wireframe.as_ref();
+        let wireframe_draws = wireframe.map(|wireframe| {
+            let mut draws = self.opaque_draws.clone();
+            draws.extend_from_slice(&self.transparent_draws);
+
+            // Without the shaded mesh the hidden edges are drawn too
+            if !wireframe.overlay {
+                for draw in &mut draws {
+                    draw.material.double_sided = true;
+                }
+            }
+
+            draws
+        });
+
+        self.begin_mesh_state(options);
+
+        match wireframe {
+            Some(wireframe) if !wireframe.overlay => {
+                self.opaque_draws.clear();
+                self.transparent_draws.clear();
+            }
+            _ => {
+                self.render_opaque();
+                self.render_transparent();
+            }
+        }
+
+        if let (Some(wireframe), Some(draws)) = (wireframe, wireframe_draws) {
+            self.render_wireframe(wireframe, &draws);
+        }
+
+        self.end_mesh_state();
+    }
+
+    /// Draws the edges of the primitives with the flat color of the wireframe.
+    ///
+    /// Over the shaded mesh the lines are pulled towards the camera (polygon offset), so they
+    /// don't z-fight with the faces.
+    fn render_wireframe(&mut self, wireframe: &Wireframe, draws: &[DrawCall]) {
+        ogl::debug_group("Wireframe", || {
+            unsafe {
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+                gl::LineWidth(wireframe.width.clamp(1., self.max_line_width));
+
+                if wireframe.overlay {
+                    gl::Enable(gl::POLYGON_OFFSET_LINE);
+                    gl::PolygonOffset(-1., -1.);
+                }
+            }
+
+            // The flat color of the outline, without the extrusion
+            self.settings.inner.outline = true;
+            self.settings.inner.outline_width = 0.;
+            self.settings.inner.outline_color = wireframe.color;
+            self.settings.update();
+
+            self.submit_draws(draws);
+
+            self.settings.inner.outline = false;
+            self.settings.update();
+
+            unsafe {
+                gl::LineWidth(1.);
+                gl::Disable(gl::POLYGON_OFFSET_LINE);
+            }
+        });
+    }
+
+    /// Draws the recorded opaque primitives sorted by their textures (and skins)
+    fn render_opaque(&mut self) {
+        let mut draws = std::mem::take(&mut self.opaque_draws);
+        draws.sort_by_key(|draw| (draw.textures(), draw.skin));
+
+        ogl::debug_group("Opaque", || self.submit_draws(&draws));
+
+        // Keep the allocation for the next frame
+        draws.clear();
+        self.opaque_draws = draws;
+    }
+
+    /// Draws the recorded transparent primitives sorted back-to-front
+    fn render_transparent(&mut self) {
+        let mut draws = std::mem::take(&mut self.transparent_draws);
+        draws.sort_by(|a, b| {
+            b.distance
+                .partial_cmp(&a.distance)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        // Double-sided primitives are drawn twice, the back faces first, so that their faces
+        // are blended in the right order from both sides (the skeleton view culls the front faces)
+        let split_double_sided = self.face_culling && !self.skeleton_x_ray;
+        if split_double_sided && draws.iter().any(|draw| draw.material.double_sided) {
+            draws = draws
+                .into_iter()
+                .flat_map(|mut draw| {
+                    if !draw.material.double_sided {
+                        return vec![draw];
+                    }
+
+                    draw.material.double_sided = false;
+                    let mut back = draw.clone();
+                    back.cull_face = Some(gl::FRONT);
+                    draw.cull_face = Some(gl::BACK);
+
+                    vec![back, draw]
+                })
+                .collect();
+        }
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::DepthMask(gl::FALSE);
+        }
+
+        ogl::debug_group("Transparent", || self.submit_draws(&draws));
+
+        unsafe {
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
+
+            if !self.skeleton_x_ray {
+                gl::CullFace(gl::BACK);
+            }
+        }
+
+        // Keep the allocation for the next frame
+        draws.clear();
+        self.transparent_draws = draws;
+    }
+
+    /// Draws the outline around the recorded outline primitives.
+    ///
+    /// The primitives are drawn into the stencil buffer first, then again extruded
+    /// along their normals with a flat color, only outside of the stencil.
+    /// The outline is visible through the other meshes.
+    fn render_outline(&mut self, outline: &Outline, width: u32, height: u32) {
+        let mut draws = std::mem::take(&mut self.outline_draws);
+
+        ogl::debug_group("Outline", || {
+            unsafe {
+                gl::Enable(gl::STENCIL_TEST);
+                gl::Disable(gl::DEPTH_TEST);
+                gl::DepthMask(gl::FALSE);
+
+                gl::StencilMask(0xFF);
+                gl::StencilFunc(gl::ALWAYS, 1, 0xFF);
+                gl::StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+                gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            }
+
+            self.submit_draws(&draws);
+
+            unsafe {
+                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+                gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
+                gl::StencilMask(0);
+            }
+
+            self.settings.inner.outline = true;
+            self.settings.inner.outline_width = outline.width;
+            self.settings.inner.outline_color = outline.color;
+            self.settings.inner.viewport_size = Vec2::new(width as f32, height as f32);
+            self.settings.update();
+
+            self.submit_draws(&draws);
+
+            self.settings.inner.outline = false;
+            self.settings.update();
+
+            unsafe {
+                gl::StencilMask(0xFF);
+                gl::Disable(gl::STENCIL_TEST);
+                gl::DepthMask(gl::TRUE);
+                gl::Enable(gl::DEPTH_TEST);
+            }
+        });
+
+        // Keep the allocation for the next frame
+        draws.clear();
+        self.outline_draws = draws;
+    }
+
+    /// Draws the recorded primitives in order.
+    ///
+    /// Textures and joint matrices are only bound when they differ from the previous draw.
+    fn submit_draws(&mut self, draws: &[DrawCall]) {
+        let mut bound_textures = None;
+        let mut bound_skin = None;
+
+        for draw in draws {
+            self.transforms.inner.model = draw.transform;
+            self.update_model_transform();
+
+            if bound_skin != Some((draw.skin, draw.eight_influences)) {
+                self.settings.inner.do_skinning = draw.skin.is_some();
+                self.settings.inner.eight_influences = draw.eight_influences;
+                self.settings.update();
+
+                if let Some(skin) = draw.skin {
+                    self.joint_transforms
+                        .inner
+                        .matrices
+                        .clone_from(&self.frame_skins[skin]);
+                    self.update_joint_transforms();
+                }
+
+                bound_skin = Some((draw.skin, draw.eight_influences));
+            }
+
+            if self.picking {
+                // 0 is the background
+                let id = draw.node as u32 + 1;
+                self.object_id_shader
+                    .render(|| self.object_id_shader.set_u32(id, "objectId"));
+            }
+
+            if let Some(cull_face) = draw.cull_face {
+                unsafe {
+                    gl::CullFace(cull_face);
+                }
+            }
+
+            let textures = draw.textures();
+            let bind_textures = bound_textures != Some(textures);
+            bound_textures = Some(textures);
+
+            self.draw_primitive(
+                draw.vao,
+                draw.index_count,
+                draw.index_type,
+                draw.instances,
+                &draw.material,
+                bind_textures,
+            );
+        }
+    }
+
+    /// Sets up the material and issues the draw call of a single primitive.
+    ///
+    /// The textures of the material are only bound if 'bind_textures' is set.
+    /// 'instances' is the instance count of instanced meshes.
+    fn draw_primitive(
+        &mut self,
+        vao: u32,
+        index_count: i32,
+        index_type: GLenum,
+        instances: Option<i32>,
+        material: &PrimitiveMaterial,
+        bind_textures: bool,
+    ) {
+        if self.settings.inner.instanced != instances.is_some() {
+            self.settings.inner.instanced = instances.is_some();
+            self.settings.update();
+        }
+
+        self.material.inner.base_color_factor = material.base_color_factor;
+        self.material.inner.normal_scale = material.normal_scale;
+        self.material.inner.has_normal_texture = material.normal_texture.is_some();
+        self.material.inner.occlusion_strength = material.occlusion_strength;
+        self.material.inner.emissive_factor = material.emissive_factor;
+        self.material.inner.alpha_mode = material.alpha_mode;
+        self.material.inner.alpha_cutoff = material.alpha_cutoff;
+        self.material.inner.unlit = material.unlit;
+        self.material.inner.base_color_uv_transform = material.base_color_uv_transform.matrix();
+        self.material.update();
+
+        if bind_textures {
+            self.bind_textures(material);
+        }
+
+        let disable_culling = material.double_sided && self.face_culling && !self.skeleton_x_ray;
+
+        self.stats.draw_calls += 1;
+        self.stats.submitted_triangles +=
+            index_count as usize / 3 * instances.unwrap_or(1) as usize;
+
+        let draw = || unsafe {
+            if disable_culling {
+                gl::Disable(gl::CULL_FACE);
+            }
+
+            gl::BindVertexArray(vao);
+            match instances {
+                Some(count) => gl_check!(gl::DrawElementsInstanced(
+                    gl::TRIANGLES,
+                    index_count,
+                    index_type,
+                    ptr::null(),
+                    count
+                )),
+                None => gl_check!(gl::DrawElements(
+                    gl::TRIANGLES,
+                    index_count,
+                    index_type,
+                    ptr::null()
+                )),
+            }
+            gl::BindVertexArray(0);
+
+            if disable_culling {
+                gl::Enable(gl::CULL_FACE);
+            }
+        };
+
+        if self.picking {
+            self.object_id_shader.render(draw);
+        } else {
+            self.shader.render(draw);
+        }
+    }
+
+    /// Binds the textures of the material, missing ones are replaced with the default texture
+    fn bind_textures(&self, material: &PrimitiveMaterial) {
+        let base_color_texture = material
+            .base_color_texture
+            .unwrap_or(self.default_texture.id);
+        let emissive_texture = material.emissive_texture.unwrap_or(self.default_texture.id);
+        let occlusion_texture = material
+            .occlusion_texture
+            .unwrap_or(self.default_texture.id);
+
+        unsafe {
+            if let Some(normal_texture) = material.normal_texture {
+                gl::ActiveTexture(gl::TEXTURE0 + ogl::NORMAL_TEXTURE_UNIT);
+                gl::BindTexture(gl::TEXTURE_2D, normal_texture);
+            }
+
+            gl::ActiveTexture(gl::TEXTURE0 + ogl::BASE_COLOR_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, base_color_texture);
+
+            gl::ActiveTexture(gl::TEXTURE0 + ogl::EMISSIVE_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, emissive_texture);
+
+            gl::ActiveTexture(gl::TEXTURE0 + ogl::OCCLUSION_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, occlusion_texture);
+
+            // Egui expects unit 0 to be active
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    /// Recalculates the skin matrices for each joint.
+    ///
+    /// Returns the world-space bounding box of the joint positions.
     pub fn recalc_skin_matrices(
         &mut self,
         joints: &mut [Joint],
         outer_transform: Mat4,
-        gui_state: &Gui,
-    ) {
-        self.apply_joint_transforms(joints);
-
+        options: &RenderOptions,
+    ) -> Aabb {
         // world transforms of each joint after applying the animation for the current frame
-        let mut world_transforms = vec![Mat4::IDENTITY; joints.len()];
-
-        // Cascade transformation from parent joints to child joints.
-        // Parent joints are always placed before child joints in the buffer, so they are processed first.
-        for i in 0..joints.len() {
-            let transform = match joints[i].parent {
-                Some(parent_index) => world_transforms[parent_index] * joints[i].transform.matrix(),
-                None => outer_transform * joints[i].transform.matrix(),
-            };
+        let world_transforms = if options.bind_pose {
+            // The animated pose isn't touched, so it's shown again when the bind pose is turned off
+            Joints::bind_world_transforms(joints, outer_transform)
+        } else {
+            pose::apply_animation(joints, &self.node_animation_transforms);
+            pose::compute_world_transforms(joints, outer_transform)
+        };
 
-            world_transforms[i] = transform;
+        if self.recording_joints {
+            let inverse = self.model_inverse;
+            self.compared_joints
+                .extend(joints.iter().zip(&world_transforms).map(|(joint, trans)| {
+                    (
+                        joint.name.clone(),
+                        inverse.transform_point3(trans.w_axis.truncate()),
+                    )
+                }));
         }
 
-        if gui_state.draw_skeleton {
-            self.debug_joints(&world_transforms, joints);
+        if options.draw_skeleton {
+            self.gpu_timer.start("Skeleton");
+            ogl::debug_group("Skeleton", || {
+                self.debug_joints(&world_transforms, joints, options.skeleton_scale);
+
+                if let Some(threshold) = options.divergence_threshold {
+                    if !self.recording_joints && !self.compared_joints.is_empty() {
+                        self.debug_diverging_joints(
+                            &world_transforms,
+                            joints,
+                            threshold,
+                            options.skeleton_scale,
+                        );
+                    }
+                }
+            });
+            self.gpu_timer.stop();
         }
 
+        self.ensure_joint_capacity(joints.len());
+
         let joint_matrices = &mut self.joint_transforms.inner.matrices;
-        joint_matrices.clear();
+        *joint_matrices = pose::compute_skin_matrices(joints, &world_transforms);
+
+        self.update_joint_transforms();
+
+        self.joint_positions.clear();
+        self.joint_positions
+            .extend(world_transforms.iter().map(|trans| trans.w_axis.truncate()));
+
+        Aabb::from_points(&self.joint_positions)
+    }
+
+    /// Grows the joint matrix buffer and recompiles the shaders with a bigger MAX_JOINTS
+    /// if the skin has more joints than the buffer can hold.
+    ///
+    /// The size is limited by the maximum uniform block size, skins over the limit are
+    /// reported when the model is uploaded (see `Model::upload_to_gpu`).
+    fn ensure_joint_capacity(&mut self, joint_count: usize) {
+        let capacity = self.joint_transforms.inner.capacity;
+        let new_capacity =
+            match joint_transforms::grown_capacity(joint_count, capacity, self.max_joints) {
+                Some(new_capacity) => new_capacity,
+                None => return,
+            };
+
+        info!("Growing the joint matrix buffer to {new_capacity} joints (skin with {joint_count} joints)");
+
+        let value = new_capacity.to_string();
+        let recompiled = self
+            .shader
+            .set_define("MAX_JOINTS", &value)
+            .and_then(|_| self.object_id_shader.set_define("MAX_JOINTS", &value));
 
-        for (i, joint) in joints.iter().enumerate() {
-            let mat = world_transforms[i] * joint.inverse_bind_matrix;
-            joint_matrices.push(mat);
+        if let Err(e) = recompiled {
+            error!("Couldn't recompile the shaders for {new_capacity} joints: {e:?}");
+            return;
         }
 
+        if let Some(shader) = &mut self.skinning_shader {
+            if let Err(e) = shader.set_define("MAX_JOINTS", &value) {
+                error!("Couldn't recompile the skinning shader for {new_capacity} joints: {e:?}");
+                self.skinning_shader = None;
+            }
+        }
+
+        self.joint_transforms.inner.capacity = new_capacity;
+        self.joint_transforms.reallocate();
+        self.joint_dual_quats.inner.capacity = new_capacity;
+        self.joint_dual_quats.reallocate();
+    }
+
+    /// Uploads the joint matrices, and their dual quaternions if dual quaternion skinning is on
+    fn update_joint_transforms(&mut self) {
         self.joint_transforms.update();
+
+        if self.settings.inner.dual_quaternions {
+            self.joint_dual_quats
+                .inner
+                .set_from_matrices(&self.joint_transforms.inner.matrices);
+            self.joint_dual_quats.update();
+        }
     }
 
-    /// Draws a debug view of the skeleton
-    fn debug_joints(&mut self, world_transforms: &[Mat4], joints: &[Joint]) {
+    /// Draws a debug view of the skeleton.
+    ///
+    /// The joints and bones are sized by the median bone length (times 'scale'), so the overlay
+    /// is readable on both tiny and huge models.
+    fn debug_joints(&mut self, world_transforms: &[Mat4], joints: &[Joint], scale: f32) {
+        let bone_length = skeleton_mesh::median_bone_length(world_transforms, joints);
+
         self.settings.inner.do_skinning = false;
+        self.settings.inner.instanced = false;
+        self.settings.inner.joint_size = JOINT_SIZE * bone_length * scale;
         self.settings.update();
 
         self.material.inner.base_color_factor = Vec4::new(0.85, 0.08, 0.7, 1.0);
+        self.material.inner.has_normal_texture = false;
         self.material.update();
 
+        // The skeleton isn't textured
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + ogl::BASE_COLOR_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, self.default_texture.id);
+        }
+
         let tmp = self.transforms.inner.model;
         self.transforms.inner.model = Mat4::IDENTITY;
-        self.transforms.update();
+        self.update_model_transform();
+
+        skeleton_mesh::draw_joints(world_transforms, &self.shader);
+        self.stats.draw_calls += 1;
 
-        skeleton_mesh::draw_joints(world_transforms, &self.color_shader);
+        self.settings.inner.joint_size = 0.;
+        self.settings.update();
 
         self.material.inner.base_color_factor = Vec4::new(0.1, 0.3, 0.7, 1.0);
         self.material.update();
 
-        skeleton_mesh::draw_bones(world_transforms, joints, &self.color_shader);
+        // Lines can't be sized in the shader, so the width is computed for the skeleton's center
+        let center = world_transforms
+            .iter()
+            .fold(Vec3::ZERO, |sum, trans| sum + trans.w_axis.truncate())
+            / world_transforms.len().max(1) as f32;
+        let line_width = self
+            .projected_pixels(BONE_THICKNESS * bone_length * scale, center)
+            .clamp(1., self.max_line_width);
+
+        skeleton_mesh::draw_bones(world_transforms, joints, line_width, &self.shader);
+        self.stats.draw_calls += 1;
 
         self.transforms.inner.model = tmp;
-        self.transforms.update();
+        self.update_model_transform();
     }
 
-    /// Recalculates the animation transform for the current time / animation
-    fn recalculate_animation(&mut self, model: &mut Model) {
-        let active_animation = match model.animations.animation_control {
-            AnimationControl::Loop {
-                active_animation,
-                start_time,
-            } => {
-                let anim = &mut model.animations.animations[active_animation];
-
-                // Calculate current time inside the animation
-                let mut since_start = Instant::now().duration_since(start_time).as_secs_f32();
-                if since_start > anim.end_time {
-                    since_start %= anim.end_time;
-                }
+    /// Draws the joints that are further than 'threshold' bone lengths from the joints
+    /// of the compared model with the same names (relative to their models) in red,
+    /// over the debug view of the skeleton
+    fn debug_diverging_joints(
+        &mut self,
+        world_transforms: &[Mat4],
+        joints: &[Joint],
+        threshold: f32,
+        scale: f32,
+    ) {
+        let bone_length = skeleton_mesh::median_bone_length(world_transforms, joints);
+        let inverse = self.model_inverse;
 
-                anim.current_time = since_start;
-                active_animation
-            }
-            AnimationControl::Controllable { active_animation } => active_animation,
-            AnimationControl::Static => return,
-        };
+        let diverging: Vec<Mat4> = joints
+            .iter()
+            .zip(world_transforms)
+            .filter(
+                |(joint, trans)| match self.compared_joints.get(&joint.name) {
+                    Some(other) => {
+                        let pos = inverse.transform_point3(trans.w_axis.truncate());
+                        pos.distance(*other) > threshold * bone_length
+                    }
+                    None => false,
+                },
+            )
+            .map(|(_, trans)| *trans)
+            .collect();
 
-        self.node_animation_transforms.clear();
-        let anim = &model.animations.animations[active_animation];
-        let current_time = anim.current_time;
+        if diverging.is_empty() {
+            return;
+        }
+
+        self.settings.inner.do_skinning = false;
+        self.settings.inner.instanced = false;
+        self.settings.inner.joint_size = JOINT_SIZE * bone_length * scale * 1.5;
+        self.settings.update();
 
-        // Interpolate the animation transforms
-        for channel in &anim.channels {
-            let keyframe_times = &channel.keyframe_times;
+        self.material.inner.base_color_factor = Vec4::new(1.0, 0.1, 0.05, 1.0);
+        self.material.inner.has_normal_texture = false;
+        self.material.update();
 
-            'inner: for i in 0..keyframe_times.len() {
-                let start_time = keyframe_times[i];
+        let tmp = self.transforms.inner.model;
+        self.transforms.inner.model = Mat4::IDENTITY;
+        self.update_model_transform();
 
-                // If the current time is before the start time of this specific channel, take the first transform.
-                if (i == keyframe_times.len() - 1) || (i == 0 && current_time < start_time) {
-                    let transform = channel.get_fixed_transform(i);
-                    self.node_animation_transforms
-                        .push(NodeAnimationTransform::new(channel.node, transform));
-                    break 'inner;
-                }
+        skeleton_mesh::draw_joints(&diverging, &self.shader);
+        self.stats.draw_calls += 1;
 
-                let end_time = keyframe_times[i + 1];
+        self.settings.inner.joint_size = 0.;
+        self.settings.update();
 
-                if start_time <= current_time && end_time > current_time {
-                    let coeff = (current_time - start_time) / (end_time - start_time);
+        self.transforms.inner.model = tmp;
+        self.update_model_transform();
+    }
 
-                    let transform = channel.interpolate_transforms(i, coeff);
+    /// Recalculates the animation transform for the current time / animation.
+    ///
+    /// Channels of the nodes masked out of the animation are skipped.
+    fn recalculate_animation(&mut self, model: &mut Model) {
+        let active_animation = match model.animations.active_animation() {
+            Some(active_animation) => active_animation,
+            None => return,
+        };
 
-                    self.node_animation_transforms
-                        .push(NodeAnimationTransform::new(channel.node, transform));
-                    break 'inner;
-                }
-            }
-        }
+        let anim = &model.animations.animations[active_animation];
+        self.node_animation_transforms = pose::sample_animation(anim, anim.current_time);
+
+        self.extract_root_motion(model, active_animation);
     }
 
-    /// Appplies the current animatoin transforms to the joints
-    fn apply_joint_transforms(&self, joints: &mut [Joint]) {
-        for joint in joints {
-            for nat in &self.node_animation_transforms {
-                if joint.node_index == nat.node {
-                    match nat.transform {
-                        AnimationTransform::Translation(trans) => {
-                            joint.transform.translation = trans;
-                        }
-                        AnimationTransform::Rotation(rot) => {
-                            joint.transform.rotation = rot;
-                        }
-                        AnimationTransform::Scale(scale) => joint.transform.scale = scale,
-                    }
-                }
+    /// Extracts the motion of the root joint from the sampled animation transforms
+    /// (removing it when the animation is played in place)
+    fn extract_root_motion(&mut self, model: &mut Model, active_animation: usize) {
+        let root = model.root.joints_mut().and_then(|joints| {
+            joints
+                .joints
+                .iter()
+                .find(|joint| joint.parent.is_none())
+                .map(|joint| joint.node_index)
+        });
+
+        let root = match root {
+            Some(root) => root,
+            None => return,
+        };
+
+        let to_world = model
+            .root
+            .skin_transform(model.transform)
+            .unwrap_or(model.transform);
+        let anim = &mut model.animations.animations[active_animation];
+
+        for nat in &mut self.node_animation_transforms {
+            if nat.node != root {
+                continue;
+            }
+
+            if let AnimationTransform::Translation(translation) = &mut nat.transform {
+                anim.extract_root_motion(root, translation, to_world);
             }
         }
     }
 }
 
-/// A struct that holds which transforms should be aplied to which nodes for the current frame
-struct NodeAnimationTransform {
-    /// Index of the node
+/// A recorded draw call of a primitive
+#[derive(Clone)]
+struct DrawCall {
+    vao: u32,
+    index_count: i32,
+    index_type: GLenum,
+    material: PrimitiveMaterial,
+    /// Instance count of an instanced mesh
+    instances: Option<i32>,
+    /// Node transform of the primitive
+    transform: Mat4,
+    /// Index of the node (written by the object id shader)
     node: usize,
-    /// Transform that should overwrite the node's current transform
-    transform: AnimationTransform,
+    /// Index into the renderer's frame_skins, if the primitive is skinned
+    skin: Option<usize>,
+    /// If the vertices have 8 joint influences
+    eight_influences: bool,
+    /// Distance from the camera
+    distance: f32,
+    /// Faces that are culled (glCullFace), None keeps the current setting
+    cull_face: Option<GLenum>,
 }
 
-impl NodeAnimationTransform {
-    fn new(node: usize, transform: AnimationTransform) -> Self {
-        Self { node, transform }
+impl DrawCall {
+    /// Textures of the material (0 if missing), used as the sorting key of the opaque draws
+    fn textures(&self) -> [u32; 4] {
+        let material = &self.material;
+
+        [
+            material.base_color_texture.unwrap_or(0),
+            material.normal_texture.unwrap_or(0),
+            material.emissive_texture.unwrap_or(0),
+            material.occlusion_texture.unwrap_or(0),
+        ]
     }
 }
+
+/// Statistics about the rendered frame that can be shown in the GUI
+#[derive(Clone, Default)]
+pub struct FrameStats {
+    /// Number of primitives that were drawn
+    pub drawn_primitives: usize,
+    /// Number of primitives that were skipped by frustum culling
+    pub culled_primitives: usize,
+    /// Number of drawn instances of the instanced meshes
+    pub drawn_instances: usize,
+    /// Number of drawn triangles (of all instances and the selected levels of detail)
+    pub drawn_triangles: usize,
+    /// Number of draw calls of the meshes and the skeletons
+    pub draw_calls: usize,
+    /// Number of triangles sent to the GPU, meshes drawn by multiple passes (outlines,
+    /// wireframe overlay) are counted for each pass
+    pub submitted_triangles: usize,
+}
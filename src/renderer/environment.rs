@@ -0,0 +1,328 @@
+use std::{f32::consts::PI, fs, path::Path};
+
+use eyre::{eyre, Context, Result};
+use glam::{Vec2, Vec3};
+
+use crate::ogl::TextureCube;
+
+/// Largest size of the skybox faces
+const MAX_SKYBOX_SIZE: u32 = 1024;
+/// Size of the irradiance faces (the irradiance changes slowly)
+const IRRADIANCE_SIZE: u32 = 16;
+/// Size of the downsampled image the irradiance is integrated from
+const IRRADIANCE_SOURCE: (u32, u32) = (64, 32);
+
+/// An environment map - the skybox and the ambient light of the scene
+pub struct Environment {
+    /// The environment as a mipmapped cube map (the blurry mip levels are used for reflections)
+    pub skybox: TextureCube,
+    /// Cosine-weighted irradiance for each normal direction (divided by pi)
+    pub irradiance: TextureCube,
+    /// Path of the image the environment was loaded from
+    pub path: String,
+}
+
+impl Environment {
+    /// Loads an equirectangular image - Radiance HDR (.hdr) or an LDR image (decoded from sRGB).
+    ///
+    /// Has to be called on the thread with the OpenGL context.
+    pub fn load(path: &Path) -> Result<Self> {
+        let image = EquirectImage::load(path)
+            .wrap_err_with(|| format!("Couldn't load the environment '{}'", path.display()))?;
+
+        let size = (image.width / 4)
+            .next_power_of_two()
+            .clamp(IRRADIANCE_SIZE, MAX_SKYBOX_SIZE);
+        let skybox = cube_faces(size, |dir| image.sample(dir));
+
+        let (width, height) = IRRADIANCE_SOURCE;
+        let small = image.downsample(width, height);
+        let irradiance = cube_faces(IRRADIANCE_SIZE, |normal| small.irradiance(normal));
+
+        Ok(Self {
+            skybox: TextureCube::new(&skybox, size, true),
+            irradiance: TextureCube::new(&irradiance, IRRADIANCE_SIZE, false),
+            path: path.display().to_string(),
+        })
+    }
+}
+
+/// Evaluates 'f' for the direction of every texel of the 6 faces (+X, -X, +Y, -Y, +Z, -Z).
+///
+/// The directions follow the OpenGL cube map convention (the first row is at t = -1).
+fn cube_faces(size: u32, f: impl Fn(Vec3) -> Vec3) -> [Vec<Vec3>; 6] {
+    let face = |face: usize| {
+        let mut texels = Vec::with_capacity((size * size) as usize);
+
+        for y in 0..size {
+            for x in 0..size {
+                let s = 2. * (x as f32 + 0.5) / size as f32 - 1.;
+                let t = 2. * (y as f32 + 0.5) / size as f32 - 1.;
+
+                let dir = match face {
+                    0 => Vec3::new(1., -t, -s),
+                    1 => Vec3::new(-1., -t, s),
+                    2 => Vec3::new(s, 1., t),
+                    3 => Vec3::new(s, -1., -t),
+                    4 => Vec3::new(s, -t, 1.),
+                    _ => Vec3::new(-s, -t, -1.),
+                };
+
+                texels.push(f(dir.normalize()));
+            }
+        }
+
+        texels
+    };
+
+    [face(0), face(1), face(2), face(3), face(4), face(5)]
+}
+
+/// A linear-color image in the equirectangular (latitude-longitude) projection, +Y is up
+struct EquirectImage {
+    width: u32,
+    height: u32,
+    /// Rows from the top (+Y)
+    pixels: Vec<Vec3>,
+}
+
+impl EquirectImage {
+    fn load(path: &Path) -> Result<Self> {
+        let is_hdr = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+
+        if is_hdr {
+            return read_hdr(&fs::read(path)?);
+        }
+
+        let image = image::open(path)?.to_rgb8();
+        let pixels = image
+            .pixels()
+            .map(|p| Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32) / 255.)
+            // The same approximation of sRGB as in the shaders
+            .map(|color| color.powf(2.2))
+            .collect();
+
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Bilinear sample of the image in the direction (wraps around horizontally)
+    fn sample(&self, dir: Vec3) -> Vec3 {
+        let uv = direction_to_uv(dir);
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (uv.y * self.height as f32 - 0.5).clamp(0., (self.height - 1) as f32);
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (fx, fy) = (x - x0, y - y0);
+
+        let wrap = |x: f32| (x as i64).rem_euclid(self.width as i64) as u32;
+        let (x0, x1) = (wrap(x0), wrap(x0 + 1.));
+        let (y0, y1) = (y0 as u32, (y0 as u32 + 1).min(self.height - 1));
+
+        let top = self.pixel(x0, y0).lerp(self.pixel(x1, y0), fx);
+        let bottom = self.pixel(x0, y1).lerp(self.pixel(x1, y1), fx);
+        top.lerp(bottom, fy)
+    }
+
+    /// Box-filtered copy of the image
+    fn downsample(&self, width: u32, height: u32) -> Self {
+        let mut pixels = vec![Vec3::ZERO; (width * height) as usize];
+        let mut counts = vec![0u32; pixels.len()];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = (y * height / self.height * width + x * width / self.width) as usize;
+                pixels[i] += self.pixel(x, y);
+                counts[i] += 1;
+            }
+        }
+
+        for (pixel, count) in pixels.iter_mut().zip(counts) {
+            *pixel /= count.max(1) as f32;
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Cosine-weighted integral of the light coming from the hemisphere around the normal,
+    /// divided by pi (so a white environment gives 1)
+    fn irradiance(&self, normal: Vec3) -> Vec3 {
+        let mut sum = Vec3::ZERO;
+
+        for y in 0..self.height {
+            let theta = (y as f32 + 0.5) / self.height as f32 * PI;
+            // Texels near the poles cover a smaller solid angle
+            let solid_angle =
+                (2. * PI / self.width as f32) * (PI / self.height as f32) * theta.sin();
+
+            for x in 0..self.width {
+                let uv = Vec2::new(
+                    (x as f32 + 0.5) / self.width as f32,
+                    (y as f32 + 0.5) / self.height as f32,
+                );
+                let cos = normal.dot(uv_to_direction(uv));
+
+                if cos > 0. {
+                    sum += self.pixel(x, y) * cos * solid_angle;
+                }
+            }
+        }
+
+        sum / PI
+    }
+}
+
+/// Equirectangular texture coordinates of the direction (v = 0 is +Y)
+fn direction_to_uv(dir: Vec3) -> Vec2 {
+    Vec2::new(
+        0.5 + dir.z.atan2(dir.x) / (2. * PI),
+        dir.y.clamp(-1., 1.).acos() / PI,
+    )
+}
+
+fn uv_to_direction(uv: Vec2) -> Vec3 {
+    let phi = (uv.x - 0.5) * 2. * PI;
+    let theta = uv.y * PI;
+
+    Vec3::new(
+        theta.sin() * phi.cos(),
+        theta.cos(),
+        theta.sin() * phi.sin(),
+    )
+}
+
+/// Reads a Radiance RGBE image (.hdr), both flat and run-length encoded scanlines
+fn read_hdr(data: &[u8]) -> Result<EquirectImage> {
+    let mut pos = 0;
+    let mut read_line = || -> Result<String> {
+        let end = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| eyre!("Unexpected end of the header"))?;
+        let line = String::from_utf8_lossy(&data[pos..pos + end]).to_string();
+        pos += end + 1;
+        Ok(line)
+    };
+
+    let magic = read_line()?;
+    if !magic.starts_with("#?") {
+        return Err(eyre!("Not a Radiance HDR file"));
+    }
+
+    // The header ends with an empty line
+    loop {
+        let line = read_line()?;
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(eyre!("Unsupported HDR format: '{format}'"));
+            }
+        }
+    }
+
+    let resolution = read_line()?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<u32>()?, width.parse::<u32>()?),
+        _ => return Err(eyre!("Unsupported HDR orientation: '{resolution}'")),
+    };
+
+    if width == 0 || height == 0 {
+        return Err(eyre!("The HDR image is empty"));
+    }
+
+    let mut data = &data[pos..];
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    let mut scanline = vec![[0u8; 4]; width as usize];
+
+    for _ in 0..height {
+        data = read_scanline(data, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_float(rgbe)));
+    }
+
+    Ok(EquirectImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Reads one scanline, returns the rest of the data
+fn read_scanline<'d>(mut data: &'d [u8], scanline: &mut [[u8; 4]]) -> Result<&'d [u8]> {
+    let width = scanline.len();
+    let truncated = || eyre!("The HDR data is truncated");
+
+    let is_rle = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+
+    if !is_rle {
+        let bytes = data.get(..width * 4).ok_or_else(truncated)?;
+        for (pixel, rgbe) in scanline.iter_mut().zip(bytes.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(&data[width * 4..]);
+    }
+
+    data = &data[4..];
+
+    // The channels are stored one after another, each as runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first().ok_or_else(truncated)?;
+            data = rest;
+
+            if count > 128 {
+                let count = (count - 128) as usize;
+                let (&value, rest) = data.split_first().ok_or_else(truncated)?;
+                data = rest;
+
+                for pixel in scanline.iter_mut().skip(x).take(count) {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else if count > 0 {
+                let count = count as usize;
+                let values = data.get(..count).ok_or_else(truncated)?;
+
+                for (pixel, &value) in scanline.iter_mut().skip(x).zip(values) {
+                    pixel[channel] = value;
+                }
+                data = &data[count..];
+                x += count;
+            } else {
+                return Err(eyre!("Invalid run length in the HDR data"));
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+fn rgbe_to_float([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        return Vec3::ZERO;
+    }
+
+    let scale = 2f32.powi(e as i32 - 136);
+    Vec3::new(r as f32, g as f32, b as f32) * scale
+}
@@ -0,0 +1,42 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::model::Aabb;
+
+/// The 6 planes of the view frustum, used for culling.
+///
+/// Each plane is stored as (normal, distance), normals point inside the frustum.
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum planes from a (projection * view) matrix.
+    /// <https://www.gamedevs.org/uploads/fast-extraction-viewing-frustum-planes-from-world-view-projection-matrix.pdf>
+    pub fn from_matrix(mat: Mat4) -> Self {
+        let (r0, r1, r2, r3) = (mat.row(0), mat.row(1), mat.row(2), mat.row(3));
+
+        // The near plane uses the OpenGL (-1, 1) depth range, which is conservative
+        // for the (0, 1) range produced by glam's 'perspective_rh' / 'orthographic_rh'.
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2].map(|p| {
+            let len = p.truncate().length();
+            p / len
+        });
+
+        Self { planes }
+    }
+
+    /// Returns true if the box is completely outside of the frustum
+    pub fn culls_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+
+        self.planes.iter().any(|plane| {
+            let normal = plane.truncate();
+
+            // The corner that is furthest along the plane normal
+            let positive = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(positive) + plane.w < 0.
+        })
+    }
+}
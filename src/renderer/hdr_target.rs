@@ -0,0 +1,46 @@
+use eyre::Result;
+
+use crate::ogl::{framebuffer::Framebuffer, Texture2D};
+
+/// The RGBA16F framebuffer the scene is rendered into before the tone mapping
+pub struct HdrTarget {
+    /// The multisampled framebuffer (None without MSAA)
+    multisampled: Option<Framebuffer>,
+    /// Single-sampled copy of the color, read by the tone mapping pass
+    resolved: Framebuffer,
+}
+
+impl HdrTarget {
+    /// Creates the framebuffers, multisampled ones if 'samples' is larger than 0
+    pub fn new(width: u32, height: u32, samples: u32) -> Result<Self> {
+        let multisampled = match samples {
+            0 => None,
+            samples => Some(Framebuffer::new_hdr(width, height, samples)?),
+        };
+
+        Ok(Self {
+            multisampled,
+            resolved: Framebuffer::new_hdr(width, height, 0)?,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.resolved.width, self.resolved.height)
+    }
+
+    /// Binds the framebuffer the scene is rendered into
+    pub fn bind(&self) {
+        self.multisampled.as_ref().unwrap_or(&self.resolved).bind();
+    }
+
+    /// Resolves the multisampled color and returns the texture with the rendered scene
+    pub fn resolve(&self) -> &Texture2D {
+        if let Some(multisampled) = &self.multisampled {
+            multisampled.blit_to(&self.resolved);
+        }
+
+        self.resolved
+            .color_texture()
+            .expect("The resolved framebuffer has a color texture")
+    }
+}
@@ -0,0 +1,106 @@
+use std::{mem::size_of, ptr, slice};
+
+use glam::{Mat3, Mat4, Quat, Vec4};
+
+use crate::ogl::uniform_buffer::UniformBufferElement;
+
+use super::joint_transforms::MAX_JOINT_TRANSFORMS;
+
+/// Axes shorter than this are treated as a zero scale
+const MIN_AXIS_LENGTH: f32 = 1e-6;
+
+// std140 layout:
+// vec4 jointDualQuats[MAX_JOINTS * 2] - offset 0, stride 16
+// (the real part of joint i at index 2i, the dual part at 2i + 1)
+
+/// Uniform buffer element that stores the joint transforms as dual quaternions
+/// (for dual quaternion skinning).
+///
+/// The scale of the joints isn't representable, the shader reads it from the joint matrices.
+pub struct JointDualQuats {
+    pub dual_quats: Vec<Vec4>,
+    /// Number of joints the buffer has space for (the same as JointTransforms).
+    ///
+    /// The buffer has to be reallocated after changing it.
+    pub capacity: usize,
+}
+
+impl JointDualQuats {
+    pub fn new() -> Self {
+        Self {
+            dual_quats: Vec::new(),
+            capacity: MAX_JOINT_TRANSFORMS,
+        }
+    }
+
+    /// Converts the rotation and translation of the joint matrices
+    pub fn set_from_matrices(&mut self, matrices: &[Mat4]) {
+        self.dual_quats.clear();
+
+        for matrix in matrices {
+            let real = Self::rotation(matrix);
+            let t = matrix.w_axis;
+            let dual = Quat::from_xyzw(t.x, t.y, t.z, 0.) * real * 0.5;
+
+            self.dual_quats.push(Vec4::from(real));
+            self.dual_quats.push(Vec4::from(dual));
+        }
+    }
+
+    /// The rotation of the matrix with the scale removed.
+    ///
+    /// Unlike Mat4::to_scale_rotation_translation this doesn't panic on a zero scale.
+    fn rotation(matrix: &Mat4) -> Quat {
+        let axes = [
+            matrix.x_axis.truncate(),
+            matrix.y_axis.truncate(),
+            matrix.z_axis.truncate(),
+        ];
+
+        if axes.iter().any(|axis| axis.length() < MIN_AXIS_LENGTH) {
+            return Quat::IDENTITY;
+        }
+
+        let rotation = Mat3::from_cols(
+            axes[0].normalize(),
+            axes[1].normalize(),
+            axes[2].normalize(),
+        );
+
+        Quat::from_mat3(&rotation).normalize()
+    }
+}
+
+impl UniformBufferElement for JointDualQuats {
+    fn update(&self) {
+        // Only the used part of the array is updated
+        let count = self.dual_quats.len().min(self.capacity * 2);
+        let used = &self.dual_quats[..count];
+
+        unsafe {
+            let bytes =
+                slice::from_raw_parts(used.as_ptr() as *const u8, count * size_of::<Vec4>());
+
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                bytes.len() as isize,
+                bytes.as_ptr() as _,
+            );
+        }
+    }
+
+    fn init_buffer(&self) {
+        unsafe {
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                (self.capacity * 2 * size_of::<Vec4>()) as isize,
+                ptr::null() as _,
+                gl::DYNAMIC_DRAW,
+            );
+        }
+    }
+
+    const BINDING: u32 = 6;
+    const NAME: &'static str = "JointDualQuats";
+}
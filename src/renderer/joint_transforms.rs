@@ -1,53 +1,71 @@
-use std::{mem::size_of, ptr};
+use std::ptr;
 
 use glam::Mat4;
 
-use crate::ogl::uniform_buffer::UniformBufferElement;
+use crate::ogl::uniform_buffer::{self, UniformBufferElement, STD140_MAT4_SIZE};
 
-const MAX_JOINT_TRANSFORMS: usize = 256;
+/// Initial capacity of the buffer, the same as the default MAX_JOINTS in the vertex shader
+pub const MAX_JOINT_TRANSFORMS: usize = 256;
+
+// std140 layout:
+// mat4 jointMatrices[MAX_JOINTS] - offset 0, stride 64
 
 /// Uniform buffer element that stores the vertex joint transforms
 pub struct JointTransforms {
     pub matrices: Vec<Mat4>,
+    /// Number of matrices the buffer has space for (MAX_JOINTS in the vertex shader).
+    ///
+    /// The buffer has to be reallocated after changing it.
+    pub capacity: usize,
 }
 
 impl JointTransforms {
     pub fn new() -> Self {
         Self {
             matrices: Vec::new(),
+            capacity: MAX_JOINT_TRANSFORMS,
         }
     }
+
+    /// Size of the uniform block in bytes
+    pub fn size(&self) -> usize {
+        self.capacity * STD140_MAT4_SIZE
+    }
+}
+
+/// The capacity the buffer has to grow to for a skin with 'joint_count' joints,
+/// None if the current capacity is enough (or can't grow past 'max_joints').
+///
+/// The capacity grows to the next power of two, limited by 'max_joints'.
+pub fn grown_capacity(joint_count: usize, capacity: usize, max_joints: usize) -> Option<usize> {
+    if joint_count <= capacity || capacity >= max_joints {
+        return None;
+    }
+
+    Some(joint_count.next_power_of_two().min(max_joints))
 }
 
 impl UniformBufferElement for JointTransforms {
     fn update(&self) {
-        if self.matrices.len() > MAX_JOINT_TRANSFORMS {
-            todo!("Support models with more than 256 joints");
-        }
-
-        let buf: Vec<f32> = self
-            .matrices
-            .iter()
-            .flat_map(|mat| mat.to_cols_array())
-            .collect();
+        // Only the used part of the array is updated
+        let count = self.matrices.len().min(self.capacity);
+        let bytes = uniform_buffer::mat4_bytes(&self.matrices[..count]);
 
         unsafe {
             gl::BufferSubData(
                 gl::UNIFORM_BUFFER,
                 0,
-                (buf.len() * size_of::<f32>()) as isize,
-                buf.as_ptr() as _,
+                bytes.len() as isize,
+                bytes.as_ptr() as _,
             );
         }
     }
 
     fn init_buffer(&self) {
-        let size = MAX_JOINT_TRANSFORMS * size_of::<[f32; 16]>();
-
         unsafe {
             gl::BufferData(
                 gl::UNIFORM_BUFFER,
-                size as isize,
+                self.size() as isize,
                 ptr::null() as _,
                 gl::DYNAMIC_DRAW,
             );
@@ -55,4 +73,46 @@ impl UniformBufferElement for JointTransforms {
     }
 
     const BINDING: u32 = 2;
+    const NAME: &'static str = "JointTransforms";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialized_size_matches_allocation() {
+        let mut transforms = JointTransforms::new();
+        transforms.matrices = vec![Mat4::IDENTITY; transforms.capacity];
+
+        assert_eq!(
+            uniform_buffer::mat4_bytes(&transforms.matrices).len(),
+            transforms.size()
+        );
+
+        transforms.capacity = 512;
+        transforms.matrices.resize(512, Mat4::IDENTITY);
+        assert_eq!(
+            uniform_buffer::mat4_bytes(&transforms.matrices).len(),
+            transforms.size()
+        );
+    }
+
+    #[test]
+    fn capacity_grows_for_300_joints() {
+        assert_eq!(grown_capacity(300, MAX_JOINT_TRANSFORMS, 1024), Some(512));
+    }
+
+    #[test]
+    fn capacity_is_limited_by_the_gpu() {
+        assert_eq!(grown_capacity(300, MAX_JOINT_TRANSFORMS, 400), Some(400));
+        // Already at the limit, the extra joints are reported while loading
+        assert_eq!(grown_capacity(600, 512, 512), None);
+    }
+
+    #[test]
+    fn capacity_is_kept_when_it_fits() {
+        assert_eq!(grown_capacity(200, MAX_JOINT_TRANSFORMS, 1024), None);
+        assert_eq!(grown_capacity(300, 512, 1024), None);
+    }
 }
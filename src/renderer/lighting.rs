@@ -1,38 +1,138 @@
 use std::{mem::size_of, ptr};
 
 use glam::Vec3;
+use serde::{Deserialize, Serialize};
 
 use crate::ogl::uniform_buffer::UniformBufferElement;
 
+/// The type of a light source
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightType {
+    /// Light coming from a single direction (like the sun)
+    Directional,
+    /// Light emitted from a single point in all directions
+    Point,
+}
+
+/// A single light source
+#[derive(Clone, Copy)]
+pub struct Light {
+    pub typ: LightType,
+    /// Position of a point light
+    pub position: Vec3,
+    /// Direction of a directional light (the direction the light travels in)
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn new_point(position: Vec3) -> Self {
+        Self {
+            typ: LightType::Point,
+            position,
+            direction: Vec3::new(0., -1., 0.),
+            color: Vec3::ONE,
+            intensity: 1.,
+        }
+    }
+}
+
+/// Maximum number of lights, has to be the same as MAX_LIGHTS in the shaders
+pub const MAX_LIGHTS: usize = 8;
+
 /// Uniform buffer element that stores the lighing data
 pub struct Lighting {
-    pub light_pos: Vec3,
+    /// Active lights (at most MAX_LIGHTS)
+    pub lights: Vec<Light>,
+    /// Color of the ambient light
+    pub ambient: Vec3,
+    /// Position of the camera, needed for specular highlights
+    pub camera_pos: Vec3,
+    /// If the ambient light and the reflections come from the environment map
+    pub use_environment: bool,
+    /// Multiplies the environment map
+    pub environment_intensity: f32,
 }
 
 impl Lighting {
-    pub fn new(light_pos: Vec3) -> Self {
-        Self { light_pos }
+    pub fn new(lights: Vec<Light>, ambient: Vec3) -> Self {
+        Self {
+            lights,
+            ambient,
+            camera_pos: Vec3::ZERO,
+            use_environment: false,
+            environment_intensity: 1.,
+        }
     }
 }
 
+// std140 layout:
+//
+// struct Light {
+//     vec3 position   - offset 0
+//     float intensity - offset 12
+//     vec3 direction  - offset 16
+//     int lightType   - offset 28
+//     vec3 color      - offset 32
+// }                   - size 48 (rounded up to a multiple of vec4)
+//
+// Light lights[MAX_LIGHTS]   - offset 0
+// vec3 ambientColor          - offset MAX_LIGHTS * 48
+// int lightCount             - offset MAX_LIGHTS * 48 + 12
+// vec3 cameraPos             - offset MAX_LIGHTS * 48 + 16
+// int useEnvironment         - offset MAX_LIGHTS * 48 + 28
+// float environmentIntensity - offset MAX_LIGHTS * 48 + 32 (padded to + 48)
+const LIGHT_FLOATS: usize = 12;
+const LIGHTING_FLOATS: usize = MAX_LIGHTS * LIGHT_FLOATS + 12;
+const LIGHTING_SIZE: usize = LIGHTING_FLOATS * size_of::<f32>();
+
 impl UniformBufferElement for Lighting {
     fn update(&self) {
-        // GLSL vec3 has an alignment of 16 bytes (4 floats)
-        let size = 4 * size_of::<f32>();
-        let buf = self.light_pos.extend(0.).to_array();
+        let mut buf = [0f32; LIGHTING_FLOATS];
+
+        let light_count = self.lights.len().min(MAX_LIGHTS);
+        for (light, chunk) in self
+            .lights
+            .iter()
+            .take(light_count)
+            .zip(buf.chunks_exact_mut(LIGHT_FLOATS))
+        {
+            let typ = match light.typ {
+                LightType::Directional => 0i32,
+                LightType::Point => 1i32,
+            };
+
+            chunk[0..3].copy_from_slice(&light.position.to_array());
+            chunk[3] = light.intensity;
+            chunk[4..7].copy_from_slice(&light.direction.normalize_or_zero().to_array());
+            // The type is an int in GLSL, so copy the bits
+            chunk[7] = f32::from_bits(typ as u32);
+            chunk[8..11].copy_from_slice(&light.color.to_array());
+        }
+
+        let offset = MAX_LIGHTS * LIGHT_FLOATS;
+        buf[offset..offset + 3].copy_from_slice(&self.ambient.to_array());
+        buf[offset + 3] = f32::from_bits(light_count as u32);
+        buf[offset + 4..offset + 7].copy_from_slice(&self.camera_pos.to_array());
+        buf[offset + 7] = f32::from_bits(self.use_environment as u32);
+        buf[offset + 8] = self.environment_intensity;
 
         unsafe {
-            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size as isize, buf.as_ptr() as _);
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                LIGHTING_SIZE as isize,
+                buf.as_ptr() as _,
+            );
         }
     }
 
     fn init_buffer(&self) {
-        let size = 4 * size_of::<f32>();
-
         unsafe {
             gl::BufferData(
                 gl::UNIFORM_BUFFER,
-                size as isize,
+                LIGHTING_SIZE as isize,
                 ptr::null() as _,
                 gl::DYNAMIC_DRAW,
             );
@@ -40,4 +140,5 @@ impl UniformBufferElement for Lighting {
     }
 
     const BINDING: u32 = 5;
+    const NAME: &'static str = "Lighting";
 }
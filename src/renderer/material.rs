@@ -1,39 +1,107 @@
 use std::{mem::size_of, ptr};
 
-use glam::Vec4;
+use glam::{Mat3, Vec3, Vec4};
+use gltf::material::AlphaMode;
 
 use crate::ogl::uniform_buffer::UniformBufferElement;
 
 /// Uniform buffer element that stores the material settings
 pub struct Material {
     pub base_color_factor: Vec4,
+    /// Scale of the normal map x and y components
+    pub normal_scale: f32,
+    /// If a normal map is bound
+    pub has_normal_texture: bool,
+    /// How much the occlusion texture affects the ambient light
+    pub occlusion_strength: f32,
+    /// Multiplies the emissive texture
+    pub emissive_factor: Vec3,
+    /// How the alpha value of the base color is interpreted
+    pub alpha_mode: AlphaMode,
+    /// Fragments with a lower alpha value are discarded in the MASK alpha mode
+    pub alpha_cutoff: f32,
+    /// Lighting is skipped, the base color is the output color
+    pub unlit: bool,
+    /// Transform of the base color texture coordinates
+    pub base_color_uv_transform: Mat3,
 }
 
 impl Material {
     pub fn new() -> Self {
         Self {
             base_color_factor: Vec4::splat(1.),
+            normal_scale: 1.,
+            has_normal_texture: false,
+            occlusion_strength: 1.,
+            emissive_factor: Vec3::ZERO,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            unlit: false,
+            base_color_uv_transform: Mat3::IDENTITY,
         }
     }
 }
 
+// std140 layout:
+// vec4 texBaseColorFactor - offset 0
+// float normalScale       - offset 16
+// int hasNormalTexture    - offset 20
+// float occlusionStrength - offset 24
+// float alphaCutoff       - offset 28
+// vec3 emissiveFactor     - offset 32
+// int alphaMode           - offset 44
+// int unlit               - offset 48
+// mat3 baseColorUvTransform - offset 64 (3 columns padded to vec4)
+const MATERIAL_FLOATS: usize = 28;
+const MATERIAL_SIZE: usize = MATERIAL_FLOATS * size_of::<f32>();
+
 impl UniformBufferElement for Material {
     fn update(&self) {
-        let size = 4 * size_of::<f32>();
-        let buf = self.base_color_factor.to_array();
+        let mut buf = [0f32; MATERIAL_FLOATS];
+        buf[0..4].copy_from_slice(&self.base_color_factor.to_array());
+        buf[4] = self.normal_scale;
+        // The flag is an int in GLSL, so copy the bits
+        buf[5] = f32::from_bits(self.has_normal_texture as u32);
+        buf[6] = self.occlusion_strength;
+        buf[7] = self.alpha_cutoff;
+        buf[8..11].copy_from_slice(&self.emissive_factor.to_array());
+
+        let alpha_mode = match self.alpha_mode {
+            AlphaMode::Opaque => 0u32,
+            AlphaMode::Mask => 1u32,
+            AlphaMode::Blend => 2u32,
+        };
+        buf[11] = f32::from_bits(alpha_mode);
+        buf[12] = f32::from_bits(self.unlit as u32);
+
+        let uv_transform = self.base_color_uv_transform;
+        for (i, col) in [
+            uv_transform.x_axis,
+            uv_transform.y_axis,
+            uv_transform.z_axis,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let start = 16 + i * 4;
+            buf[start..start + 3].copy_from_slice(&col.to_array());
+        }
 
         unsafe {
-            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size as isize, buf.as_ptr() as _);
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                MATERIAL_SIZE as isize,
+                buf.as_ptr() as _,
+            );
         }
     }
 
     fn init_buffer(&self) {
-        let size = 4 * size_of::<f32>();
-
         unsafe {
             gl::BufferData(
                 gl::UNIFORM_BUFFER,
-                size as isize,
+                MATERIAL_SIZE as isize,
                 ptr::null() as _,
                 gl::DYNAMIC_DRAW,
             );
@@ -41,4 +109,5 @@ impl UniformBufferElement for Material {
     }
 
     const BINDING: u32 = 4;
+    const NAME: &'static str = "Material";
 }
@@ -0,0 +1,254 @@
+use gl::types::GLenum;
+use glam::Vec3;
+
+use crate::ogl::TextureFiltering;
+
+use super::Light;
+
+/// What is drawn behind the models
+#[derive(Clone, Copy, PartialEq)]
+pub enum Background {
+    /// A solid color, the ambient light color is used for the ambient lighting
+    Color,
+    /// The environment map, it's used for the ambient lighting as well
+    /// (falls back to the color if no environment is loaded)
+    Environment,
+}
+
+/// How the HDR colors are mapped to the [0, 1] range of the screen
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    /// The colors are clipped
+    Clamp,
+    Reinhard,
+    /// Approximation of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMapping {
+    pub const ALL: [Self; 3] = [Self::Clamp, Self::Reinhard, Self::Aces];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clamp => "Clamp",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES",
+        }
+    }
+}
+
+/// A flat-colored outline around the selected model (or the meshes of one of its nodes)
+#[derive(Clone, Copy)]
+pub struct Outline {
+    pub color: Vec3,
+    /// Width in pixels
+    pub width: f32,
+    /// Index of the node whose meshes are outlined, the whole model is outlined if it's None
+    pub node: Option<usize>,
+}
+
+/// Winding of the front faces of the triangles
+#[derive(Clone, Copy, PartialEq)]
+pub enum Winding {
+    /// Counter-clockwise, the glTF convention
+    Ccw,
+    Cw,
+}
+
+impl Winding {
+    pub const ALL: [Self; 2] = [Self::Ccw, Self::Cw];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ccw => "Counter-clockwise",
+            Self::Cw => "Clockwise",
+        }
+    }
+
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Self::Ccw => gl::CCW,
+            Self::Cw => gl::CW,
+        }
+    }
+}
+
+/// Edges of the mesh triangles drawn as flat-colored lines
+#[derive(Clone, Copy)]
+pub struct Wireframe {
+    pub color: Vec3,
+    /// Line width in pixels, limited by the driver
+    pub width: f32,
+    /// If the lines are drawn over the shaded mesh, otherwise only the lines are drawn
+    pub overlay: bool,
+}
+
+/// What and how the renderer draws.
+///
+/// A plain value, so the renderer can be used without the GUI (the viewer creates it
+/// from the GUI state every frame).
+#[derive(Clone)]
+pub struct RenderOptions {
+    /// Index of the model that is rendered, nothing is rendered when it's out of range
+    pub selected_model: usize,
+    /// If joints should be visible inside of the mesh
+    pub draw_skeleton: bool,
+    /// Multiplies the size of the skeleton overlay (relative to the bone lengths)
+    pub skeleton_scale: f32,
+    /// If the mesh should be visible
+    pub mesh_visible: bool,
+    /// If skinned meshes and the skeleton are shown in the bind pose instead of the animated pose
+    pub bind_pose: bool,
+    /// If nodes outside of the view frustum should be skipped
+    pub frustum_culling: bool,
+    /// If the opaque draw calls should be recorded and sorted by textures before drawing
+    pub sort_draws: bool,
+    /// If rendering should be sRGB-correct (textures decoded from sRGB, output gamma-corrected)
+    pub srgb: bool,
+    /// If skinned meshes blend the joint transforms as dual quaternions instead of matrices
+    pub dual_quaternion_skinning: bool,
+    /// If skinned meshes are skinned on the CPU and drawn without skinning in the shader
+    pub cpu_skinning: bool,
+    /// If skinned meshes are skinned by a compute shader once per frame
+    pub compute_skinning: bool,
+    /// Lights illuminating the scene (at most MAX_LIGHTS)
+    pub lights: Vec<Light>,
+    /// Color of the ambient light
+    pub ambient_light: Vec3,
+    /// Filtering of the model textures
+    pub texture_filtering: TextureFiltering,
+    /// Anisotropic filtering level, clamped to the maximum supported by the driver
+    pub anisotropy: f32,
+    pub background: Background,
+    /// Clear color of the solid background
+    pub background_color: Vec3,
+    /// Multiplies the environment map (the skybox and the ambient light)
+    pub environment_intensity: f32,
+    pub tone_mapping: ToneMapping,
+    /// Multiplies the HDR colors before the tone mapping
+    pub exposure: f32,
+    /// Highlights the selected model or node, None disables the outline
+    pub outline: Option<Outline>,
+    /// Draws the edges of the triangles, None disables the wireframe
+    pub wireframe: Option<Wireframe>,
+    /// If the back faces of the meshes are culled (double-sided materials are never culled)
+    pub face_culling: bool,
+    /// Winding of the front faces of the meshes
+    pub front_face: Winding,
+    /// If the meshes are depth tested, otherwise the hidden geometry is visible
+    pub depth_test: bool,
+    /// Draws this level of detail (or the coarsest one) instead of selecting it by the screen size
+    pub forced_lod: Option<usize>,
+    /// A second model drawn together with the selected one (comparison mode)
+    pub compare_model: Option<usize>,
+    /// Highlights the joints of the selected model that are further than this (in bone lengths)
+    /// from the joints of the compared model with the same names, None disables the highlighting
+    pub divergence_threshold: Option<f32>,
+}
+
+impl Default for RenderOptions {
+    /// The same settings the viewer starts with
+    fn default() -> Self {
+        Self {
+            selected_model: 0,
+            draw_skeleton: false,
+            skeleton_scale: 1.,
+            mesh_visible: true,
+            bind_pose: false,
+            frustum_culling: true,
+            sort_draws: true,
+            srgb: true,
+            dual_quaternion_skinning: false,
+            cpu_skinning: false,
+            compute_skinning: false,
+            lights: vec![Light::new_point(Vec3::new(400., 1000., 400.))],
+            ambient_light: Vec3::splat(0.4),
+            texture_filtering: TextureFiltering::Sampler,
+            anisotropy: 1.,
+            background: Background::Color,
+            background_color: Vec3::splat(0.15),
+            environment_intensity: 1.,
+            tone_mapping: ToneMapping::Aces,
+            exposure: 1.,
+            outline: Some(Outline {
+                color: Vec3::new(1., 0.5, 0.1),
+                width: 3.,
+                node: None,
+            }),
+            forced_lod: None,
+            compare_model: None,
+            divergence_threshold: None,
+            wireframe: None,
+            face_culling: true,
+            front_face: Winding::Ccw,
+            depth_test: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::LightType;
+
+    #[test]
+    fn default_options_match_the_viewer_defaults() {
+        let options = RenderOptions::default();
+
+        assert_eq!(options.selected_model, 0);
+        assert!(options.mesh_visible);
+        assert!(!options.draw_skeleton);
+        assert!(options.srgb);
+        assert!(options.frustum_culling);
+        assert!(options.face_culling && options.depth_test);
+        assert!(options.front_face == Winding::Ccw);
+        assert!(options.tone_mapping == ToneMapping::Aces);
+        assert!(options.background == Background::Color);
+        assert_eq!(options.texture_filtering, TextureFiltering::Sampler);
+
+        assert_eq!(options.lights.len(), 1);
+        assert!(options.lights[0].typ == LightType::Point);
+        assert_eq!(options.lights[0].position, Vec3::new(400., 1000., 400.));
+
+        assert!(options
+            .outline
+            .is_some_and(|outline| outline.node.is_none()));
+        assert!(options.wireframe.is_none());
+        assert!(options.forced_lod.is_none() && options.compare_model.is_none());
+    }
+
+    #[test]
+    fn modified_options_are_independent_values() {
+        let options = RenderOptions {
+            selected_model: 2,
+            draw_skeleton: true,
+            lights: Vec::new(),
+            outline: None,
+            wireframe: Some(Wireframe {
+                color: Vec3::ONE,
+                width: 2.,
+                overlay: true,
+            }),
+            forced_lod: Some(1),
+            tone_mapping: ToneMapping::Reinhard,
+            ..RenderOptions::default()
+        };
+
+        let mut copy = options.clone();
+        copy.selected_model = 3;
+        copy.lights.push(Light::new_point(Vec3::ZERO));
+
+        assert_eq!(options.selected_model, 2);
+        assert!(options.lights.is_empty());
+        assert_eq!(copy.lights.len(), 1);
+
+        assert!(options.draw_skeleton);
+        assert!(options.outline.is_none());
+        assert!(options.wireframe.is_some_and(|wireframe| wireframe.overlay));
+        assert_eq!(options.forced_lod, Some(1));
+        assert!(options.tone_mapping == ToneMapping::Reinhard);
+        // The rest stays at the defaults
+        assert!(options.mesh_visible && options.srgb);
+        assert_eq!(options.exposure, 1.);
+    }
+}
@@ -1,40 +1,94 @@
 use std::{mem::size_of, ptr};
 
+use glam::{Vec2, Vec3};
+
 use crate::ogl::uniform_buffer::UniformBufferElement;
 
 /// Uniform buffer element that stores the rendering 'settings' (controls)
 pub struct Settings {
     pub do_skinning: bool,
+    /// If the skinned vertices have 8 joint influences instead of 4
+    pub eight_influences: bool,
+    /// Decode sRGB textures and encode the output to sRGB
+    pub srgb: bool,
+    /// Blend the joint transforms as dual quaternions instead of matrices
+    pub dual_quaternions: bool,
+    /// Apply the per-instance transforms (EXT_mesh_gpu_instancing)
+    pub instanced: bool,
+    /// Extrude the vertices along the normals and draw them with the outline color
+    pub outline: bool,
+    /// Outline width in pixels
+    pub outline_width: f32,
+    /// Size of the viewport in pixels, needed for the outline width
+    pub viewport_size: Vec2,
+    pub outline_color: Vec3,
+    /// World-space diameter of the skeleton overlay's joint points, 0 outside of the overlay
+    pub joint_size: f32,
 }
 
 impl Settings {
     pub fn new() -> Self {
-        Self { do_skinning: false }
+        Self {
+            do_skinning: false,
+            eight_influences: false,
+            srgb: true,
+            dual_quaternions: false,
+            instanced: false,
+            outline: false,
+            outline_width: 1.,
+            viewport_size: Vec2::ONE,
+            outline_color: Vec3::ONE,
+            joint_size: 0.,
+        }
     }
 }
 
+// std140 layout:
+// int doSkinning       - offset 0
+// int srgb             - offset 4
+// int eightInfluences  - offset 8
+// int dualQuaternions  - offset 12
+// int instanced        - offset 16
+// int outline          - offset 20
+// float outlineWidth   - offset 24
+// vec2 viewportSize    - offset 32
+// vec3 outlineColor    - offset 48
+// float jointSize      - offset 60
+const SETTINGS_INTS: usize = 16;
+const SETTINGS_SIZE: usize = SETTINGS_INTS * size_of::<i32>();
+
 impl UniformBufferElement for Settings {
     fn update(&self) {
-        let size = size_of::<i32>();
-        let num = if self.do_skinning { 1 } else { 0 };
+        let mut buf = [0i32; SETTINGS_INTS];
+        buf[0] = self.do_skinning as i32;
+        buf[1] = self.srgb as i32;
+        buf[2] = self.eight_influences as i32;
+        buf[3] = self.dual_quaternions as i32;
+        buf[4] = self.instanced as i32;
+        buf[5] = self.outline as i32;
+        buf[6] = self.outline_width.to_bits() as i32;
+        buf[8] = self.viewport_size.x.to_bits() as i32;
+        buf[9] = self.viewport_size.y.to_bits() as i32;
+        buf[12] = self.outline_color.x.to_bits() as i32;
+        buf[13] = self.outline_color.y.to_bits() as i32;
+        buf[14] = self.outline_color.z.to_bits() as i32;
+        buf[15] = self.joint_size.to_bits() as i32;
 
         unsafe {
             gl::BufferSubData(
                 gl::UNIFORM_BUFFER,
                 0,
-                size as isize,
-                &num as *const i32 as _,
+                SETTINGS_SIZE as isize,
+                buf.as_ptr() as _,
             );
         }
     }
 
     fn init_buffer(&self) {
-        let size = size_of::<i32>();
-
         unsafe {
             gl::BufferData(
                 gl::UNIFORM_BUFFER,
-                size as isize,
+                SETTINGS_SIZE as isize,
                 ptr::null() as _,
                 gl::DYNAMIC_DRAW,
             );
@@ -42,4 +96,5 @@ impl UniformBufferElement for Settings {
     }
 
     const BINDING: u32 = 3;
+    const NAME: &'static str = "Settings";
 }
@@ -2,11 +2,38 @@ use glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
 
 use crate::{
     model::Joint,
-    ogl::{self, shader::Shader},
+    ogl::{self, shader::Shader, Vao},
 };
 
+/// Median length of the bones, the reference scale of the skeleton overlay
+pub fn median_bone_length(world_transforms: &[Mat4], joints: &[Joint]) -> f32 {
+    let mut lengths: Vec<f32> = joints
+        .iter()
+        .enumerate()
+        .filter_map(|(i, joint)| {
+            let parent = joint.parent?;
+            Some(
+                world_transforms[i]
+                    .w_axis
+                    .truncate()
+                    .distance(world_transforms[parent].w_axis.truncate()),
+            )
+        })
+        .filter(|&length| length > 0.)
+        .collect();
+
+    if lengths.is_empty() {
+        return 0.;
+    }
+
+    lengths.sort_by(f32::total_cmp);
+    lengths[lengths.len() / 2]
+}
+
 // TODO: do not create a new buffer every frame
-/// Draws the joints by their current world transforms
+/// Draws the joints by their current world transforms.
+///
+/// The point size is computed by the vertex shader from the jointSize setting.
 pub fn draw_joints(world_transforms: &[Mat4], shader: &Shader) {
     let mut positions = Vec::new();
     let texcoords = vec![Vec2::ZERO; world_transforms.len()];
@@ -17,35 +44,28 @@ pub fn draw_joints(world_transforms: &[Mat4], shader: &Shader) {
         positions.push(pos.xyz());
     }
 
-    let mut vao = 0;
-
-    unsafe {
-        gl::GenVertexArrays(1, &mut vao);
-        gl::BindVertexArray(vao);
-
-        let _positions = ogl::create_float_buf(&positions, 3, ogl::POS_INDEX, gl::FLOAT);
-        let _texcoords = ogl::create_float_buf(&texcoords, 2, ogl::TEXCOORDS_INDEX, gl::FLOAT);
-        let _normals = ogl::create_float_buf(&normals, 3, ogl::NORMALS_INDEX, gl::FLOAT);
+    // The buffers and the VAO are deleted at the end of the scope
+    let vao = Vao::new();
+    vao.bind();
 
-        gl::BindVertexArray(0);
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    let _positions = ogl::create_float_buf(&positions, 3, ogl::POS_INDEX, gl::FLOAT);
+    let _texcoords = ogl::create_float_buf(&texcoords, 2, ogl::TEXCOORDS_INDEX, gl::FLOAT);
+    let _normals = ogl::create_float_buf(&normals, 3, ogl::NORMALS_INDEX, gl::FLOAT);
 
-        shader.render(|| {
-            gl::BindVertexArray(vao);
-            gl::PointSize(4.);
-            gl::DrawArrays(gl::POINTS, 0, positions.len() as i32);
-            gl::BindVertexArray(0);
-        });
+    vao.unbind();
+    _normals.unbind();
 
-        gl::DeleteVertexArrays(1, &vao);
-
-        let bufs = [_positions, _texcoords, _normals];
-        gl::DeleteBuffers(bufs.len() as _, bufs.as_ptr());
-    }
+    shader.render(|| unsafe {
+        vao.bind();
+        gl::Enable(gl::PROGRAM_POINT_SIZE);
+        gl::DrawArrays(gl::POINTS, 0, positions.len() as i32);
+        gl::Disable(gl::PROGRAM_POINT_SIZE);
+        vao.unbind();
+    });
 }
 
 /// Drwas the bones of the joints specified by the joints array and their world_transforms array
-pub fn draw_bones(world_transforms: &[Mat4], joints: &[Joint], shader: &Shader) {
+pub fn draw_bones(world_transforms: &[Mat4], joints: &[Joint], line_width: f32, shader: &Shader) {
     let mut positions = Vec::new();
 
     for (i, joint) in joints.iter().enumerate() {
@@ -61,28 +81,21 @@ pub fn draw_bones(world_transforms: &[Mat4], joints: &[Joint], shader: &Shader)
     let texcoords = vec![Vec2::ZERO; positions.len()];
     let normals = vec![Vec3::ZERO; positions.len()];
 
-    let mut vao = 0;
-
-    unsafe {
-        gl::GenVertexArrays(1, &mut vao);
-        gl::BindVertexArray(vao);
+    let vao = Vao::new();
+    vao.bind();
 
-        let _positions = ogl::create_float_buf(&positions, 3, ogl::POS_INDEX, gl::FLOAT);
-        let _texcoords = ogl::create_float_buf(&texcoords, 2, ogl::TEXCOORDS_INDEX, gl::FLOAT);
-        let _normals = ogl::create_float_buf(&normals, 3, ogl::NORMALS_INDEX, gl::FLOAT);
+    let _positions = ogl::create_float_buf(&positions, 3, ogl::POS_INDEX, gl::FLOAT);
+    let _texcoords = ogl::create_float_buf(&texcoords, 2, ogl::TEXCOORDS_INDEX, gl::FLOAT);
+    let _normals = ogl::create_float_buf(&normals, 3, ogl::NORMALS_INDEX, gl::FLOAT);
 
-        gl::BindVertexArray(0);
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    vao.unbind();
+    _normals.unbind();
 
-        shader.render(|| {
-            gl::BindVertexArray(vao);
-            gl::DrawArrays(gl::LINES, 0, positions.len() as i32);
-            gl::BindVertexArray(0);
-        });
-
-        gl::DeleteVertexArrays(1, &vao);
-
-        let bufs = [_positions, _texcoords, _normals];
-        gl::DeleteBuffers(bufs.len() as _, bufs.as_ptr());
-    }
+    shader.render(|| unsafe {
+        vao.bind();
+        gl::LineWidth(line_width);
+        gl::DrawArrays(gl::LINES, 0, positions.len() as i32);
+        gl::LineWidth(1.);
+        vao.unbind();
+    });
 }
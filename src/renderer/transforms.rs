@@ -1,8 +1,8 @@
-use std::{mem::size_of, ptr};
+use std::ptr;
 
 use glam::Mat4;
 
-use crate::ogl::uniform_buffer::UniformBufferElement;
+use crate::ogl::uniform_buffer::{self, UniformBufferElement, STD140_MAT4_SIZE};
 
 /// Uniform buffer element that stores the transformation matrices
 pub struct Transforms {
@@ -19,21 +19,40 @@ impl Transforms {
             model: Mat4::IDENTITY,
         }
     }
+
+    /// Byte offset of the model matrix, used for updating only the model matrix
+    pub const MODEL_OFFSET: usize = 2 * STD140_MAT4_SIZE;
+
+    /// Size of the uniform block in bytes
+    pub const SIZE: usize = 3 * STD140_MAT4_SIZE;
+
+    /// The matrices in the order of the uniform block
+    fn matrices(&self) -> [Mat4; 3] {
+        [self.projection, self.view, self.model]
+    }
 }
 
+// std140 layout:
+// mat4 projection - offset 0
+// mat4 view       - offset 64
+// mat4 model      - offset 128
+
 impl UniformBufferElement for Transforms {
     fn update(&self) {
-        let buf: Vec<f32> = [self.projection, self.view, self.model]
-            .iter()
-            .flat_map(|mat| mat.to_cols_array())
-            .collect();
+        self.update_range(0, Self::SIZE);
+    }
+
+    fn update_range(&self, offset: usize, len: usize) {
+        // Copied on the stack, the matrices are already in the std140 layout
+        let mats = self.matrices();
+        let bytes = &uniform_buffer::mat4_bytes(&mats)[offset..offset + len];
 
         unsafe {
             gl::BufferSubData(
                 gl::UNIFORM_BUFFER,
-                0,
-                (buf.len() * size_of::<f32>()) as isize,
-                buf.as_ptr() as _,
+                offset as isize,
+                len as isize,
+                bytes.as_ptr() as _,
             );
         }
     }
@@ -42,7 +61,7 @@ impl UniformBufferElement for Transforms {
         unsafe {
             gl::BufferData(
                 gl::UNIFORM_BUFFER,
-                3 * size_of::<[f32; 16]>() as isize,
+                Self::SIZE as isize,
                 ptr::null() as _,
                 gl::DYNAMIC_DRAW,
             );
@@ -50,4 +69,36 @@ impl UniformBufferElement for Transforms {
     }
 
     const BINDING: u32 = 1;
+    const NAME: &'static str = "Transforms";
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn serialized_size_matches_allocation() {
+        let transforms = Transforms::new_indentity();
+
+        assert_eq!(
+            uniform_buffer::mat4_bytes(&transforms.matrices()).len(),
+            Transforms::SIZE
+        );
+    }
+
+    #[test]
+    fn model_matrix_is_at_model_offset() {
+        let mut transforms = Transforms::new_indentity();
+        transforms.model = Mat4::from_translation(Vec3::new(1., 2., 3.));
+
+        let mats = transforms.matrices();
+        let bytes = uniform_buffer::mat4_bytes(&mats);
+
+        assert_eq!(
+            &bytes[Transforms::MODEL_OFFSET..],
+            uniform_buffer::mat4_bytes(&[transforms.model])
+        );
+    }
 }
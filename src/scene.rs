@@ -0,0 +1,179 @@
+use std::{fs, path::Path};
+
+use eyre::{Context, Result};
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{Camera, CameraPose, Projection},
+    model::{AnimationControl, Model},
+    renderer::{Light, LightType, RenderOptions},
+};
+
+/// A model of a saved scene
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneModel {
+    /// Path of the gltf file
+    pub path: String,
+    /// Model transform (column-major)
+    pub transform: [f32; 16],
+    /// Name of the active animation (None if the model is static)
+    pub animation: Option<String>,
+    /// Current time of the active animation
+    pub time: f32,
+    /// If the active animation is playing (otherwise it's controlled from the GUI)
+    pub looping: bool,
+}
+
+impl SceneModel {
+    /// Captures the transform and the animation state of the model
+    pub fn capture(model: &Model) -> Self {
+        let animations = &model.animations;
+        let active = animations
+            .active_animation()
+            .map(|i| &animations.animations[i]);
+
+        Self {
+            path: model.path.clone(),
+            transform: model.transform.to_cols_array(),
+            animation: active.and_then(|anim| anim.name.clone()),
+            time: active.map(|anim| anim.current_time).unwrap_or(0.),
+            looping: matches!(animations.animation_control, AnimationControl::Loop { .. }),
+        }
+    }
+
+    /// Restores the transform and the animation state of the model.
+    ///
+    /// Animations are matched by name, returns a warning if the animation doesn't exist.
+    pub fn apply(&self, model: &mut Model) -> Option<String> {
+        model.transform = Mat4::from_cols_array(&self.transform);
+
+        let name = match &self.animation {
+            Some(name) => name,
+            None => {
+                model.animations.animation_control = AnimationControl::Static;
+                return None;
+            }
+        };
+
+        let animations = &mut model.animations;
+        let i = match animations
+            .animations
+            .iter()
+            .position(|a| a.name.as_ref() == Some(name))
+        {
+            Some(i) => i,
+            None => {
+                return Some(format!(
+                    "Animation '{name}' of '{}' doesn't exist",
+                    self.path
+                ))
+            }
+        };
+
+        let anim = &mut animations.animations[i];
+        anim.current_time = self.time.min(anim.end_time);
+
+        animations.animation_control = if self.looping {
+            AnimationControl::Loop {
+                active_animation: i,
+            }
+        } else {
+            AnimationControl::Controllable {
+                active_animation: i,
+            }
+        };
+
+        None
+    }
+}
+
+/// A light of a saved scene
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneLight {
+    pub typ: LightType,
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl From<&Light> for SceneLight {
+    fn from(light: &Light) -> Self {
+        Self {
+            typ: light.typ,
+            position: light.position.to_array(),
+            direction: light.direction.to_array(),
+            color: light.color.to_array(),
+            intensity: light.intensity,
+        }
+    }
+}
+
+impl From<&SceneLight> for Light {
+    fn from(light: &SceneLight) -> Self {
+        Self {
+            typ: light.typ,
+            position: Vec3::from(light.position),
+            direction: Vec3::from(light.direction),
+            color: Vec3::from(light.color),
+            intensity: light.intensity,
+        }
+    }
+}
+
+/// A whole viewer session - the models, the camera and the lighting, saved as JSON
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub models: Vec<SceneModel>,
+    /// Index into `models`
+    pub selected_model: usize,
+    pub camera: CameraPose,
+    pub projection: Projection,
+    /// If joints should be visible inside of the mesh
+    pub draw_skeleton: bool,
+    /// If the mesh should be visible
+    pub mesh_visible: bool,
+    pub lights: Vec<SceneLight>,
+    pub ambient_light: [f32; 3],
+}
+
+impl SceneFile {
+    /// Captures the current state of the scene
+    pub fn capture(models: &[Model], camera: &Camera, options: &RenderOptions) -> Self {
+        Self {
+            models: models.iter().map(SceneModel::capture).collect(),
+            selected_model: options.selected_model,
+            camera: camera.pose(),
+            projection: camera.projection,
+            draw_skeleton: options.draw_skeleton,
+            mesh_visible: options.mesh_visible,
+            lights: options.lights.iter().map(SceneLight::from).collect(),
+            ambient_light: options.ambient_light.to_array(),
+        }
+    }
+
+    /// Restores the camera and the render settings of the scene (the models have to be loaded separately)
+    pub fn apply(&self, camera: &mut Camera, options: &mut RenderOptions) {
+        camera.projection = self.projection;
+        camera.set_pose(&self.camera);
+
+        options.draw_skeleton = self.draw_skeleton;
+        options.mesh_visible = self.mesh_visible;
+        options.lights = self.lights.iter().map(Light::from).collect();
+        options.ambient_light = Vec3::from(self.ambient_light);
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Couldn't read '{}'", path.display()))?;
+
+        serde_json::from_str(&json).wrap_err_with(|| format!("Couldn't parse '{}'", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fs, path::PathBuf};
+
+use eyre::Result;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use leoric::{
+    camera::Camera,
+    renderer::{Light, RenderOptions},
+    scene::SceneLight,
+};
+
+use crate::{gamepad::GamepadSettings, gui::Gui, key_bindings::KeyBindings, window::MyWindow};
+
+/// Camera move speed of a new camera
+pub const DEFAULT_CAMERA_SPEED: f32 = 10.;
+
+/// Viewer settings kept between runs, persisted to 'settings.json' next to the executable
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Size of the window when it isn't fullscreen, None is a part of the screen
+    pub window_size: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    /// Scale of the GUI relative to the scale of the display
+    pub ui_scale: f32,
+    /// Path of the selected model, it's selected again once it's loaded
+    pub selected_model: Option<String>,
+    /// If joints should be visible inside of the mesh
+    pub draw_skeleton: bool,
+    /// If the mesh should be visible
+    pub mesh_visible: bool,
+    pub camera_speed: f32,
+    pub lights: Vec<SceneLight>,
+    pub ambient_light: [f32; 3],
+    pub key_bindings: KeyBindings,
+    pub gamepad: GamepadSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let options = RenderOptions::default();
+
+        Self {
+            window_size: None,
+            fullscreen: false,
+            ui_scale: 1.,
+            selected_model: None,
+            draw_skeleton: options.draw_skeleton,
+            mesh_visible: options.mesh_visible,
+            camera_speed: DEFAULT_CAMERA_SPEED,
+            lights: options.lights.iter().map(SceneLight::from).collect(),
+            ambient_light: options.ambient_light.to_array(),
+            key_bindings: KeyBindings::default(),
+            gamepad: GamepadSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings from the JSON file.
+    ///
+    /// A missing or invalid file isn't reported, the defaults are used instead.
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the settings to the JSON file
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(Self::path(), json)?;
+        Ok(())
+    }
+
+    /// Captures the current state of the viewer
+    pub fn capture(gui: &Gui, camera: &Camera, scene_paths: &[&str], window: &MyWindow) -> Self {
+        let options = gui.render_options();
+
+        Self {
+            window_size: Some(window.windowed_size()),
+            fullscreen: window.is_fullscreen(),
+            ui_scale: gui.ui_scale,
+            selected_model: scene_paths
+                .get(gui.selected_model)
+                .map(|path| path.to_string()),
+            draw_skeleton: options.draw_skeleton,
+            mesh_visible: options.mesh_visible,
+            camera_speed: camera.move_speed,
+            lights: options.lights.iter().map(SceneLight::from).collect(),
+            ambient_light: options.ambient_light.to_array(),
+            key_bindings: gui.key_bindings.clone(),
+            gamepad: gui.gamepad,
+        }
+    }
+
+    /// Restores the settings of the GUI and the camera (the window is created with the settings)
+    pub fn apply(&self, gui: &mut Gui, camera: &mut Camera) {
+        let mut options = gui.render_options();
+        options.draw_skeleton = self.draw_skeleton;
+        options.mesh_visible = self.mesh_visible;
+        options.lights = self.lights.iter().map(Light::from).collect();
+        options.ambient_light = Vec3::from(self.ambient_light);
+        gui.set_render_options(options);
+        gui.key_bindings = self.key_bindings.clone();
+        gui.gamepad = self.gamepad;
+        gui.ui_scale = self.ui_scale;
+
+        camera.move_speed = self.camera_speed;
+    }
+
+    /// 'settings.json' in the directory of the executable (or the working directory)
+    fn path() -> PathBuf {
+        let dir = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+            .unwrap_or_default();
+
+        dir.join("settings.json")
+    }
+}
@@ -0,0 +1,913 @@
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::Result;
+use glam::{Vec2, Vec3};
+use leoric::{
+    camera::Camera,
+    comparison::{self, Comparison},
+    export::Export,
+    joint_export,
+    loader::{LoadTarget, LoadedModel, Loader},
+    model::{AnimationControl, Model, TimeSource},
+    obj, ogl,
+    renderer::{Renderer, SHADER_FILES},
+    scene::SceneFile,
+};
+use log::{error, info, warn};
+use sdl2::video::SwapInterval;
+
+use crate::{
+    gui::{Gui, ReloadStatus},
+    input::{handle_inputs, InputState},
+    settings::{Settings, DEFAULT_CAMERA_SPEED},
+    watcher::FileWatcher,
+    window::MyWindow,
+    Args, ModelArg,
+};
+
+/// Size of the images rendered in the offscreen mode
+const OFFSCREEN_WIDTH: u32 = 1280;
+const OFFSCREEN_HEIGHT: u32 = 720;
+
+/// Clicks in the skeleton view select the closest joint within this distance in pixels
+const JOINT_PICK_DISTANCE: f32 = 12.;
+
+/// The window, the scene and everything else the render loop works with
+pub struct Viewer {
+    window: MyWindow,
+    gui: Gui,
+    scene: Vec<Model>,
+    camera: Camera,
+    renderer: Renderer,
+    /// Loads the models in the background
+    loader: Loader,
+    input: InputState,
+    /// Reloads the models when their files change
+    model_watcher: FileWatcher,
+    /// Reloads the shaders when their files change
+    shader_watcher: FileWatcher,
+    /// The running GIF / PNG sequence export
+    export: Option<Export>,
+    /// The swap interval the window currently uses
+    swap_interval: SwapInterval,
+    comparison: Comparison,
+    /// The models load in the background, the last selected one is selected when it's added
+    restore_selection: Option<String>,
+}
+
+impl Viewer {
+    /// Creates the window, configures OpenGL and starts loading the models (or opens the scene)
+    pub fn new(args: &Args) -> Result<Self> {
+        // Offscreen renders don't depend on the last session
+        let settings = match args.offscreen_dir {
+            Some(_) => Settings::default(),
+            None => Settings::load(),
+        };
+
+        let mut window = MyWindow::new(
+            "PGRF2 Projekt - Skeletální Animace - Tomáš Král",
+            args.msaa_samples,
+            args.offscreen_dir.is_some(),
+            args.gl_version,
+            settings.window_size,
+        )?;
+
+        if settings.fullscreen && args.offscreen_dir.is_none() {
+            if let Err(e) = window.toggle_fullscreen() {
+                error!("Couldn't switch to fullscreen: {e}");
+            }
+        }
+
+        ogl::init_debug();
+
+        let mut loader = Loader::new(args.strict);
+        // The models of the scene file replace the ones found in 'resources'
+        if args.scene.is_none() || !args.models.is_empty() {
+            start_loading(&mut loader, &args.models);
+        }
+
+        let mut scene = Vec::new();
+        let mut gui = Gui::new();
+        let renderer = Renderer::new()?;
+        let mut camera = Camera::new(
+            Vec3::new(0.2, 3., 7.5),
+            DEFAULT_CAMERA_SPEED,
+            0.05,
+            window.width,
+            window.height,
+        );
+        settings.apply(&mut gui, &mut camera);
+
+        let restore_selection = settings.selected_model.filter(|_| args.scene.is_none());
+
+        if let Some(path) = &args.scene {
+            // The viewer still starts (with an empty scene) if the scene file can't be opened
+            if let Err(e) = open_scene(path, &mut loader, &mut scene, &mut camera, &mut gui) {
+                error!("Couldn't open the scene: {e:?}");
+                gui.errors.push(format!("Couldn't open the scene: {e}"));
+            }
+        }
+
+        Ok(Self {
+            window,
+            swap_interval: gui.swap_interval,
+            gui,
+            scene,
+            camera,
+            renderer,
+            loader,
+            input: InputState::new(),
+            model_watcher: FileWatcher::new(),
+            shader_watcher: FileWatcher::new(),
+            export: None,
+            comparison: Comparison::new(),
+            restore_selection,
+        })
+    }
+
+    /// Renders the first animation of every model at a fixed time into '<dir>/<model name>.png'.
+    ///
+    /// Waits for the models to load. The output is deterministic, so it can be compared
+    /// against reference images.
+    pub fn render_offscreen(&mut self, dir: &str, time: f32) -> Result<()> {
+        add_loaded_models(
+            self.loader.wait(),
+            &mut self.scene,
+            &mut self.camera,
+            &mut self.gui,
+        );
+
+        fs::create_dir_all(dir)?;
+
+        let mut options = self.gui.render_options();
+        // The animations advance from the start by a single fixed step, so the images
+        // are the same in every run
+        let time_source = TimeSource::FixedStep(time);
+
+        for i in 0..self.scene.len() {
+            let animations = &mut self.scene[i].animations;
+            if !animations.animations.is_empty() {
+                animations.animations[0].current_time = 0.;
+                animations.animation_control = AnimationControl::Loop {
+                    active_animation: 0,
+                };
+                animations.advance(time_source.step(0.));
+                animations.animation_control = AnimationControl::Controllable {
+                    active_animation: 0,
+                };
+            }
+
+            options.selected_model = i;
+            let image = self.renderer.render_to_image(
+                &mut self.scene,
+                &mut self.camera,
+                &options,
+                OFFSCREEN_WIDTH,
+                OFFSCREEN_HEIGHT,
+            )?;
+
+            let path = format!("{dir}/{}.png", self.scene[i].name);
+            image.save(&path)?;
+            info!("Rendered '{path}'");
+        }
+
+        Ok(())
+    }
+
+    /// Runs the render loop until the window is closed and saves the settings
+    pub fn run(&mut self) {
+        let mut last_frame = Instant::now();
+
+        loop {
+            let now = Instant::now();
+            let dt = now.duration_since(last_frame).as_secs_f32();
+            last_frame = now;
+
+            let should_quit = self.frame(now, dt);
+            if should_quit {
+                break;
+            }
+
+            if self.gui.limit_fps && self.gui.max_fps > 0 {
+                let frame_duration = Duration::from_secs_f32(1. / self.gui.max_fps as f32);
+                sleep_until(last_frame + frame_duration);
+            }
+        }
+
+        let paths: Vec<&str> = self.scene.iter().map(|model| model.path.as_str()).collect();
+        if let Err(e) = Settings::capture(&self.gui, &self.camera, &paths, &self.window).save() {
+            error!("Couldn't save the settings: {e}");
+        }
+    }
+
+    /// Updates, renders and presents a single frame.
+    ///
+    /// 'start' is when the frame started and 'dt' is the duration of the last frame in seconds.
+    /// Returns true when the window was closed.
+    fn frame(&mut self, start: Instant, dt: f32) -> bool {
+        // Smooth the displayed frame time, so it's readable
+        self.gui.frame_time += (dt - self.gui.frame_time) * 0.05;
+        self.gui.status_bar.record_frame_time(dt);
+
+        self.apply_display_settings();
+
+        self.window.begin_frame();
+        self.renderer.gpu_timer.begin_frame();
+
+        self.update_models();
+
+        handle_inputs(
+            &mut self.window,
+            &mut self.scene,
+            &mut self.camera,
+            &mut self.gui,
+            &mut self.input,
+            dt,
+        );
+        self.camera.update(dt);
+
+        self.edit_pose();
+        self.animate(dt);
+        self.handle_requests();
+        self.step_export();
+        self.render();
+        self.draw_gui();
+
+        // Without the buffer swap (it waits for VSync) and the FPS limit
+        let cpu_time = start.elapsed().as_secs_f32();
+        self.gui.cpu_frame_time += (cpu_time - self.gui.cpu_frame_time) * 0.05;
+
+        // Nothing changes by itself, so the next frame is drawn after an input event
+        let idle = !self.window.needs_repaint()
+            && self.camera.is_settled()
+            && self.export.is_none()
+            && self.gui.loading.is_empty()
+            && self.scene.iter().all(|model| {
+                !matches!(
+                    model.animations.animation_control,
+                    AnimationControl::Loop { .. }
+                )
+            });
+
+        self.window.end_frame(idle)
+    }
+
+    /// Applies the settings of the window changed in the GUI
+    fn apply_display_settings(&mut self) {
+        let gui = &mut self.gui;
+        let window = &mut self.window;
+
+        if gui.reset_settings {
+            gui.reset_settings = false;
+            Settings::default().apply(gui, &mut self.camera);
+        }
+
+        if gui.toggle_fullscreen {
+            gui.toggle_fullscreen = false;
+            if let Err(e) = window.toggle_fullscreen() {
+                error!("Couldn't toggle fullscreen: {e}");
+            }
+        }
+
+        // The scale is applied after the slider is released, the GUI would move under the cursor
+        if gui.ui_scale != window.ui_scale() && !window.egui_ctx.is_using_pointer() {
+            window.set_ui_scale(gui.ui_scale);
+        }
+
+        if gui.swap_interval != self.swap_interval {
+            match window.set_swap_interval(gui.swap_interval) {
+                Ok(_) => self.swap_interval = gui.swap_interval,
+                Err(e) => {
+                    error!("Couldn't change the swap interval: {e}");
+                    gui.swap_interval = self.swap_interval;
+                }
+            }
+        }
+    }
+
+    /// Adds the loaded models, removes and reloads models and reloads the shaders
+    fn update_models(&mut self) {
+        let gui = &mut self.gui;
+        let scene = &mut self.scene;
+        let loader = &mut self.loader;
+
+        // Dropped files are loaded in the background like the rest
+        for path in self.window.take_dropped_files() {
+            loader.load(path, 1., LoadTarget::Add { select: true });
+        }
+
+        add_loaded_models(loader.finished(), scene, &mut self.camera, gui);
+
+        if let Some(path) = &self.restore_selection {
+            if let Some(i) = scene.iter().position(|model| &model.path == path) {
+                gui.selected_model = i;
+                self.camera.set_target(scene[i].bounds().center());
+                self.restore_selection = None;
+            } else if loader.pending().is_empty() {
+                self.restore_selection = None;
+            }
+        }
+
+        if let Some(i) = gui.remove_model.take() {
+            // The indices of the models change, the comparison ends
+            gui.compare_model = None;
+            self.comparison.update(scene, gui.selected_model, None);
+            remove_model(scene, gui, i);
+        }
+
+        // The file watcher and the "Reload" buttons both reload in the background
+        let model_paths: Vec<&str> = scene.iter().map(|m| m.path.as_str()).collect();
+        let mut reload = self.model_watcher.changed(&model_paths);
+        if let Some(model) = gui.reload_model.take().and_then(|i| scene.get(i)) {
+            reload.push(model.path.clone());
+        }
+
+        for path in reload {
+            // The transform is kept from the current model
+            loader.load(path, 1., LoadTarget::Replace);
+        }
+
+        if !self.shader_watcher.changed(&SHADER_FILES).is_empty() {
+            gui.reload_shaders = true;
+        }
+
+        if gui.reload_shaders {
+            gui.reload_shaders = false;
+            // The old shaders stay in use if the new ones don't compile
+            gui.shader_error = self
+                .renderer
+                .reload_shaders()
+                .err()
+                .map(|e| format!("{e:?}"));
+        }
+        if let Some(id) = gui.cancel_load.take() {
+            loader.cancel(id);
+        }
+        gui.loading = loader.pending().to_vec();
+    }
+
+    /// Undoes or redoes the pose edits of the selected model
+    fn edit_pose(&mut self) {
+        if let Some(model) = self.scene.get_mut(self.gui.selected_model) {
+            if std::mem::take(&mut self.gui.undo_pose) {
+                model.undo_pose_edit();
+            }
+
+            if std::mem::take(&mut self.gui.redo_pose) {
+                model.redo_pose_edit();
+            }
+        }
+    }
+
+    /// Advances the animations of the models by the frame time ('dt' in seconds)
+    fn animate(&mut self, dt: f32) {
+        let gui = &self.gui;
+        let scene = &mut self.scene;
+
+        self.comparison
+            .update(scene, gui.selected_model, gui.compare_model);
+
+        // Only the selected model is rendered, the others are paused (unless they should keep
+        // playing in the background), so they continue from the same pose when selected again.
+        // Models in the sync group of the selected model follow its clock instead.
+        let sync_group = scene
+            .get(gui.selected_model)
+            .and_then(|model| model.animations.sync_group);
+
+        // The export always steps the time by its frame rate
+        let time_source = self
+            .export
+            .as_ref()
+            .map_or(gui.time_source, Export::time_source);
+        let animation_dt = time_source.step(dt);
+
+        for (i, model) in scene.iter_mut().enumerate() {
+            let follows = sync_group.is_some() && model.animations.sync_group == sync_group;
+            if i == gui.selected_model || (gui.animate_unselected && !follows) {
+                model.animations.advance(animation_dt);
+            }
+        }
+
+        comparison::sync_animations(scene, gui.selected_model);
+    }
+
+    /// Handles the actions requested in the GUI (exports, scene files, the environment map)
+    fn handle_requests(&mut self) {
+        let gui = &mut self.gui;
+        let scene = &mut self.scene;
+
+        if gui.focus_selected {
+            gui.focus_selected = false;
+            focus_selected(scene, &self.renderer, gui, &mut self.camera);
+        }
+
+        if gui.start_export {
+            gui.start_export = false;
+            if self.export.is_none() {
+                match Export::new(
+                    gui.export_settings,
+                    scene,
+                    &self.camera,
+                    gui.render_options(),
+                ) {
+                    Ok(e) => self.export = Some(e),
+                    Err(e) => error!("Couldn't start the export: {e}"),
+                }
+            }
+        }
+
+        if std::mem::take(&mut gui.save_scene) {
+            let file = SceneFile::capture(scene, &self.camera, &gui.render_options());
+            match file.save(Path::new(&gui.scene_file)) {
+                Ok(()) => info!("Saved the scene to '{}'", gui.scene_file),
+                Err(e) => gui.errors.push(format!(
+                    "Couldn't save the scene to '{}': {e}",
+                    gui.scene_file
+                )),
+            }
+        }
+
+        if std::mem::take(&mut gui.open_scene) {
+            let path = gui.scene_file.clone();
+            // The compared model was replaced with the scene
+            gui.compare_model = None;
+            self.comparison.clear();
+
+            if let Err(e) = open_scene(&path, &mut self.loader, scene, &mut self.camera, gui) {
+                gui.errors.push(format!("Couldn't open the scene: {e:?}"));
+            }
+        }
+
+        if std::mem::take(&mut gui.load_environment) {
+            if let Err(e) = self
+                .renderer
+                .load_environment(Path::new(&gui.environment_file))
+            {
+                gui.errors.push(format!("{e:?}"));
+            }
+        }
+
+        if std::mem::take(&mut gui.export_posed_mesh) {
+            export_posed_mesh(scene, gui);
+        }
+
+        if std::mem::take(&mut gui.export_joint_positions) {
+            export_joint_positions(scene, gui);
+        }
+    }
+
+    /// Renders the next frame of the running export
+    fn step_export(&mut self) {
+        let running = match &mut self.export {
+            Some(running) => running,
+            None => return,
+        };
+
+        let finished = match running.render_frame(&mut self.renderer, &mut self.scene) {
+            Ok(finished) => finished,
+            Err(e) => {
+                let error = format!("The export failed: {e}");
+                error!("{error}");
+                self.gui.errors.push(error);
+                true
+            }
+        };
+        self.gui.export_progress = Some(running.progress());
+
+        if finished {
+            self.export = None;
+            self.gui.export_progress = None;
+        }
+    }
+
+    /// Renders the scene and the skeleton view and handles the clicks into them
+    fn render(&mut self) {
+        let gui = &mut self.gui;
+        let scene = &mut self.scene;
+        let camera = &mut self.camera;
+        let renderer = &mut self.renderer;
+        let window_size = (self.window.width, self.window.height);
+
+        renderer.render(
+            scene,
+            camera,
+            window_size.0,
+            window_size.1,
+            &gui.render_options(),
+        );
+
+        let inset = gui.skeleton_inset(window_size);
+        if let Some(rect) = inset {
+            let options = gui.render_options();
+            renderer.render_skeleton_inset(scene, camera, rect, window_size, &options);
+        }
+
+        if let Some(pixel) = gui.pick.take() {
+            let (x, y) = pixel;
+            let in_inset = inset.filter(|&(left, top, width, height)| {
+                (left..left + width).contains(&x) && (top..top + height).contains(&y)
+            });
+
+            match in_inset {
+                Some(rect) => {
+                    if let Some(joint) = pick_joint(camera, &renderer.joint_positions, rect, pixel)
+                    {
+                        gui.selected_joint = Some(joint);
+                    }
+                }
+                None => {
+                    let options = gui.render_options();
+                    match renderer.pick_node(scene, camera, &options, window_size, pixel) {
+                        Ok(node) => gui.select_node(node),
+                        Err(e) => error!("Couldn't pick the node: {e}"),
+                    }
+                }
+            }
+        }
+
+        if gui.take_screenshot && gui.screenshot_without_ui {
+            gui.take_screenshot = false;
+            if let Err(e) = save_screenshot(renderer, &self.window) {
+                error!("Couldn't save the screenshot: {e}");
+            }
+        }
+    }
+
+    /// Creates and draws the GUI over the rendered scene
+    fn draw_gui(&mut self) {
+        let gui = &mut self.gui;
+        let renderer = &mut self.renderer;
+        let window = &mut self.window;
+
+        gui.create_gui(
+            &mut self.scene,
+            &mut self.camera,
+            &renderer.stats,
+            renderer.gpu_timer.results(),
+            &renderer.joint_positions,
+            (window.width, window.height),
+            &mut window.egui_ctx,
+        );
+
+        renderer.gpu_timer.start("GUI");
+        ogl::debug_group("GUI", || window.draw_gui());
+        renderer.gpu_timer.stop();
+        renderer.gpu_timer.end_frame();
+
+        if gui.take_screenshot {
+            gui.take_screenshot = false;
+            if let Err(e) = save_screenshot(renderer, window) {
+                error!("Couldn't save the screenshot: {e}");
+            }
+        }
+    }
+}
+
+/// Starts loading the models from the command-line arguments, or every glTF file found
+/// in the 'resources' directory if there are none
+fn start_loading(loader: &mut Loader, models: &[ModelArg]) {
+    let found;
+    let models = if models.is_empty() {
+        found = find_models(Path::new("resources"));
+        &found
+    } else {
+        models
+    };
+
+    for model_arg in models {
+        loader.load(
+            model_arg.path.clone(),
+            model_arg.scale,
+            LoadTarget::Add { select: false },
+        );
+    }
+}
+
+/// Uploads the loaded models to the GPU and adds them to the scene.
+///
+/// The first model of an empty scene and dropped models are selected.
+/// Models that failed to load are skipped and the errors are shown in the GUI.
+fn add_loaded_models(
+    loaded: Vec<LoadedModel>,
+    scene: &mut Vec<Model>,
+    camera: &mut Camera,
+    gui: &mut Gui,
+) {
+    for loaded in loaded {
+        let model = loaded.model.and_then(|mut model| {
+            model.upload_to_gpu()?;
+            Ok(model)
+        });
+
+        match (model, loaded.target) {
+            (Ok(model), LoadTarget::Add { select }) => {
+                if select || scene.is_empty() {
+                    camera.set_target(model.bounds().center());
+                    gui.selected_model = scene.len();
+                    gui.selected_joint = None;
+                }
+
+                scene.push(model);
+            }
+            (Ok(model), LoadTarget::Replace) => {
+                replace_model(scene, gui, model);
+            }
+            (Err(e), LoadTarget::Add { .. }) => {
+                let error = format!("Couldn't load '{}': {e}", loaded.path);
+                error!("{error}");
+                gui.errors.push(error);
+            }
+            (Err(e), LoadTarget::Replace) => {
+                error!("Couldn't reload '{}': {e}", loaded.path);
+                gui.reload_status
+                    .insert(loaded.path, ReloadStatus::Failed(e.to_string()));
+            }
+            // Waited for by `open_scene`
+            (_, LoadTarget::Scene { .. }) => {}
+        }
+    }
+}
+
+/// Replaces the scene by the models of the scene file and restores the camera and the settings.
+///
+/// Blocks until the models are loaded. Models that couldn't be loaded are skipped
+/// and reported in the GUI.
+fn open_scene(
+    path: &str,
+    loader: &mut Loader,
+    scene: &mut Vec<Model>,
+    camera: &mut Camera,
+    gui: &mut Gui,
+) -> Result<()> {
+    let file = SceneFile::load(Path::new(path))?;
+
+    for (entry, model) in file.models.iter().enumerate() {
+        loader.load(model.path.clone(), 1., LoadTarget::Scene { entry });
+    }
+
+    let mut models: Vec<Option<Model>> = file.models.iter().map(|_| None).collect();
+    let mut other = Vec::new();
+
+    for loaded in loader.wait() {
+        let entry = match loaded.target {
+            LoadTarget::Scene { entry } => entry,
+            _ => {
+                other.push(loaded);
+                continue;
+            }
+        };
+
+        let model = loaded.model.and_then(|mut model| {
+            model.upload_to_gpu()?;
+            Ok(model)
+        });
+
+        match model {
+            Ok(model) => models[entry] = Some(model),
+            Err(e) => {
+                let error = format!("Scene entry {entry}: couldn't load '{}': {e}", loaded.path);
+                error!("{error}");
+                gui.errors.push(error);
+            }
+        }
+    }
+
+    scene.clear();
+    gui.selected_joint = None;
+
+    let mut options = gui.render_options();
+    options.selected_model = 0;
+    file.apply(camera, &mut options);
+
+    for (entry, (saved, model)) in file.models.iter().zip(models).enumerate() {
+        let mut model = match model {
+            Some(model) => model,
+            None => continue,
+        };
+
+        if let Some(warning) = saved.apply(&mut model) {
+            warn!("{warning}");
+            gui.errors.push(warning);
+        }
+
+        if entry == file.selected_model {
+            options.selected_model = scene.len();
+        }
+
+        scene.push(model);
+    }
+
+    gui.set_render_options(options);
+
+    // Models that were loading before the scene was opened are added to it
+    add_loaded_models(other, scene, camera, gui);
+    Ok(())
+}
+
+/// Replaces the model loaded from the same path by the reloaded one.
+///
+/// Keeps the transform and the active animation (if it still exists), the old GPU resources are freed.
+fn replace_model(scene: &mut [Model], gui: &mut Gui, mut model: Model) {
+    // The model might have been removed in the meantime
+    let old = match scene.iter_mut().find(|m| m.path == model.path) {
+        Some(old) => old,
+        None => return,
+    };
+
+    model.transform = old.transform;
+    model.animations.sync_group = old.animations.sync_group;
+    model.animations.continue_from(&old.animations);
+    model.animations.keep_masks(&old.animations);
+    model.animations.keep_authored(&mut old.animations);
+
+    gui.reload_status
+        .insert(model.path.clone(), ReloadStatus::Reloaded(Instant::now()));
+
+    *old = model;
+}
+
+/// Removes the model from the scene (freeing its GPU resources) and fixes the selection
+fn remove_model(scene: &mut Vec<Model>, gui: &mut Gui, index: usize) {
+    if index >= scene.len() {
+        return;
+    }
+
+    scene.remove(index);
+
+    if gui.selected_model == index {
+        gui.selected_joint = None;
+    }
+
+    if gui.selected_model > index || gui.selected_model >= scene.len() {
+        gui.selected_model = gui.selected_model.saturating_sub(1);
+    }
+}
+
+/// Recursively finds all .gltf and .glb files in the directory (sorted by path)
+fn find_models(dir: &Path) -> Vec<ModelArg> {
+    fn visit(dir: &Path, paths: &mut Vec<String>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                visit(&path, paths);
+                continue;
+            }
+
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase());
+
+            if matches!(extension.as_deref(), Some("gltf" | "glb")) {
+                paths.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    visit(dir, &mut paths);
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| ModelArg { path, scale: 1. })
+        .collect()
+}
+
+/// Sleeps until the deadline.
+///
+/// `thread::sleep` is imprecise (it can oversleep by a millisecond or more),
+/// so it only sleeps most of the time and spins for the rest.
+fn sleep_until(deadline: Instant) {
+    const SPIN_DURATION: Duration = Duration::from_millis(2);
+
+    let now = Instant::now();
+    if deadline <= now {
+        return;
+    }
+
+    let remaining = deadline - now;
+    if remaining > SPIN_DURATION {
+        thread::sleep(remaining - SPIN_DURATION);
+    }
+
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Exports the selected model in its current pose into the 'exports' directory
+fn export_posed_mesh(scene: &[Model], gui: &mut Gui) {
+    let model = match scene.get(gui.selected_model) {
+        Some(model) => model,
+        None => return,
+    };
+
+    let path = Path::new("exports").join(&gui.posed_mesh_file);
+
+    match obj::export_posed_model(model, &path) {
+        Ok(()) => info!("Exported the posed mesh to '{}'", path.display()),
+        Err(e) => gui.errors.push(format!(
+            "Couldn't export the posed mesh to '{}': {e}",
+            path.display()
+        )),
+    }
+}
+
+/// Writes the positions of the checked joints of the selected model into the 'exports' directory
+fn export_joint_positions(scene: &[Model], gui: &mut Gui) {
+    let model = match scene.get(gui.selected_model) {
+        Some(model) => model,
+        None => return,
+    };
+
+    let joints: Vec<usize> = match model.root.joints() {
+        Some(joints) => (0..joints.joints.len())
+            .filter(|&i| gui.joint_export_joints.contains(&joints.joints[i].name))
+            .collect(),
+        None => return,
+    };
+
+    let format = gui.joint_export_format;
+    let path = Path::new("exports")
+        .join(&gui.joint_export_file)
+        .with_extension(format.extension());
+
+    match joint_export::export_joint_positions(
+        model,
+        gui.joint_export_animation,
+        gui.joint_export_rate,
+        &joints,
+        format,
+        &path,
+    ) {
+        Ok(()) => info!("Exported the joint positions to '{}'", path.display()),
+        Err(e) => gui.errors.push(format!(
+            "Couldn't export the joint positions to '{}': {e}",
+            path.display()
+        )),
+    }
+}
+
+/// Captures the back buffer and saves it to the 'screenshots' directory
+fn save_screenshot(renderer: &Renderer, window: &MyWindow) -> Result<()> {
+    let image = renderer.capture_frame(window.width, window.height);
+
+    fs::create_dir_all("screenshots")?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = format!("screenshots/screenshot_{timestamp}.png");
+    image.save(&path)?;
+
+    info!("Saved screenshot '{path}'");
+    Ok(())
+}
+
+/// Returns the joint closest to the pixel clicked in the skeleton view ('rect' of the view
+/// and the pixel are from the top-left corner of the window)
+fn pick_joint(
+    camera: &mut Camera,
+    joint_positions: &[Vec3],
+    (left, top, width, height): (u32, u32, u32, u32),
+    (x, y): (u32, u32),
+) -> Option<usize> {
+    let click = Vec2::new((x - left) as f32, (y - top) as f32);
+
+    joint_positions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &pos)| {
+            let pixel = camera.world_to_pixel(pos, (width, height))?;
+            Some((i, pixel.distance(click)))
+        })
+        .filter(|&(_, distance)| distance <= JOINT_PICK_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(joint, _)| joint)
+}
+
+/// Moves the camera so the selected joint (or the whole selected model) is in view
+fn focus_selected(scene: &[Model], renderer: &Renderer, gui: &Gui, camera: &mut Camera) {
+    let bounds = gui.selected_bounds(scene);
+    if bounds.is_empty() {
+        return;
+    }
+
+    let joint = gui
+        .selected_joint
+        .and_then(|i| renderer.joint_positions.get(i));
+
+    match joint {
+        // A small sphere around the joint
+        Some(pos) => camera.frame_bounds(*pos, bounds.radius() * 0.1),
+        None => camera.frame_bounds(bounds.center(), bounds.radius()),
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Detects changes of files by polling their modification times
+pub struct FileWatcher {
+    /// Last seen modification times of the files
+    modified: HashMap<String, SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    /// How often are the files checked
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Self {
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns the paths of the files that changed since the last poll.
+    ///
+    /// Files seen for the first time are only remembered, files that are no longer
+    /// in 'paths' are forgotten.
+    pub fn changed(&mut self, paths: &[&str]) -> Vec<String> {
+        if self.last_poll.elapsed() < Self::POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        self.modified
+            .retain(|path, _| paths.contains(&path.as_str()));
+
+        let mut changed = Vec::new();
+
+        for path in paths {
+            let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+                Ok(modified) => modified,
+                // The file might be in the middle of being written
+                Err(_) => continue,
+            };
+
+            match self.modified.insert(path.to_string(), modified) {
+                Some(previous) if previous != modified && !changed.iter().any(|c| c == path) => {
+                    changed.push(path.to_string())
+                }
+                _ => {}
+            }
+        }
+
+        changed
+    }
+}
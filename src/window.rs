@@ -4,19 +4,24 @@ use egui::CtxRef;
 use egui_backend::{painter::Painter, DpiScaling, EguiStateHandler};
 use egui_sdl2_gl::ShaderVersion;
 use eyre::{eyre, Result};
+use log::{info, warn};
 use sdl2::{
-    event::{Event, WindowEvent},
-    video::Window,
+    controller::{Axis, Button, GameController},
+    event::{Event, EventType, WindowEvent},
+    mouse::MouseButton,
+    video::{FullscreenType, Window, WindowPos},
     video::{GLContext, GLProfile, SwapInterval},
-    EventPump, Sdl, VideoSubsystem,
+    EventPump, GameControllerSubsystem, Sdl, VideoSubsystem,
 };
 
 use egui_sdl2_gl as egui_backend;
 
+use leoric::ogl;
+
 /// A component that handles the window creation and egui drawing
 pub struct MyWindow {
     /// SDL2 context
-    _sdl_context: Sdl,
+    sdl_context: Sdl,
     /// SDL2 video subsystem
     _video_subsystem: VideoSubsystem,
     /// SDL2 window
@@ -33,43 +38,122 @@ pub struct MyWindow {
     painter: Painter,
     /// Time when the window was creaetd
     start_time: Instant,
+    /// If the GUI was repainted and the buffers should be swapped
+    needs_swap: bool,
     /// Width of the window
     pub width: u32,
     /// Height of the window
     pub height: u32,
+    /// Mouse wheel movement since the last call to `take_scroll`
+    scroll: f32,
+    /// If the mouse is captured for looking around (relative mouse mode)
+    mouse_look: bool,
+    /// Position and size of the window before entering fullscreen
+    windowed_rect: Option<(i32, i32, u32, u32)>,
+    /// If the next frame has to be presented even if the GUI didn't change
+    /// (the window contents were lost, eg. after alt-tab)
+    force_repaint: bool,
+    /// Paths of the files dropped onto the window since the last call to `take_dropped_files`
+    dropped_files: Vec<String>,
+    /// Where the left mouse button was pressed (in window coordinates)
+    press_pos: Option<(i32, i32)>,
+    /// Position of the last left click in pixels since the last call to `take_click`
+    click: Option<(u32, u32)>,
+    /// SDL2 game controller subsystem (None if it couldn't be initialized)
+    game_controller: Option<GameControllerSubsystem>,
+    /// The connected game controllers
+    controllers: Vec<GameController>,
+    /// Controller buttons pressed since the last call to `take_controller_buttons`
+    controller_buttons: Vec<Button>,
+    /// Scale of the GUI chosen by the user, multiplies the scale of the display
+    ui_scale: f32,
+    /// Scale of the display the window is on (from its DPI)
+    display_scale: f32,
+    /// Index of the display the window was on when the scale was computed
+    display_index: i32,
 }
 
+/// How long an idle viewer waits for an event before drawing the next frame (in milliseconds)
+const IDLE_TIMEOUT_MS: u32 = 100;
+
+/// DPI of a display with 100% scaling
+const BASE_DPI: f32 = 96.;
+
+/// How far the mouse can move between the press and the release of a click (in window coordinates)
+const CLICK_DISTANCE: i32 = 4;
+
 impl MyWindow {
     /// Create the window with the specific title.
     ///
     /// Initializes the OpenGL context, the egui renderer and the SDL2 video subsystem.
-    pub fn new(title: &str) -> Result<Self> {
+    /// The default framebuffer is multisampled with 'msaa_samples' samples (0 disables MSAA).
+    /// If the requested sample count isn't available, the window is created without MSAA.
+    /// A hidden window only provides the OpenGL context (for offscreen rendering).
+    /// 'gl_version' is the requested (major, minor) version of the core profile context,
+    /// an error is returned if the driver doesn't provide at least that version.
+    /// Without a 'size', the window covers a part of the screen.
+    pub fn new(
+        title: &str,
+        msaa_samples: u8,
+        hidden: bool,
+        gl_version: (u8, u8),
+        size: Option<(u32, u32)>,
+    ) -> Result<Self> {
         let sdl_context = sdl2::init().map_err(|e| eyre!("{e}"))?;
         let video_subsystem = sdl_context.video().map_err(|e| eyre!("{e}"))?;
 
-        let size = video_subsystem
-            .display_bounds(0)
-            .map_err(|e| eyre!("{e}"))?;
+        let (width, height) = match size {
+            Some((width, height)) if width > 0 && height > 0 => (width, height),
+            _ => {
+                let bounds = video_subsystem
+                    .display_bounds(0)
+                    .map_err(|e| eyre!("{e}"))?;
 
-        let width = (size.width() as f32 * 0.7) as u32;
-        let height = (size.height() as f32 * 0.7) as u32;
-
-        let window = video_subsystem
-            .window(title, width, height)
-            .opengl()
-            .resizable()
-            .position_centered()
-            .allow_highdpi()
-            .build()?;
+                (
+                    (bounds.width() as f32 * 0.7) as u32,
+                    (bounds.height() as f32 * 0.7) as u32,
+                )
+            }
+        };
 
-        // Init OpenGL
-        let gl_ctx = window.gl_create_context().map_err(|e| eyre!("{e}"))?;
+        // The context attributes have to be set before the window and the context are created
         let gl_attr = video_subsystem.gl_attr();
-        gl_attr.set_context_major_version(4);
-        gl_attr.set_context_minor_version(2);
+        let (major, minor) = gl_version;
+        gl_attr.set_context_major_version(major);
+        gl_attr.set_context_minor_version(minor);
         gl_attr.set_context_profile(GLProfile::Core);
         gl_attr.set_context_flags().debug().set();
         gl_attr.set_double_buffer(true);
+        // The selection outline is drawn with the stencil buffer
+        gl_attr.set_stencil_size(8);
+
+        if msaa_samples > 0 {
+            gl_attr.set_multisample_buffers(1);
+            gl_attr.set_multisample_samples(msaa_samples);
+        }
+
+        let (window, gl_ctx) = match Self::create_window(
+            &video_subsystem,
+            title,
+            width,
+            height,
+            hidden,
+        ) {
+            Ok(res) => res,
+            Err(e) if msaa_samples > 0 => {
+                warn!(
+                    "Couldn't create a window with {msaa_samples}x MSAA ({e}), continuing without it"
+                );
+                gl_attr.set_multisample_buffers(0);
+                gl_attr.set_multisample_samples(0);
+                Self::create_window(&video_subsystem, title, width, height, hidden)?
+            }
+            Err(e) => {
+                return Err(e.wrap_err(format!(
+                    "Couldn't create an OpenGL {major}.{minor} core context (an older version can be requested with '--gl <major>.<minor>')"
+                )))
+            }
+        };
 
         window
             .subsystem()
@@ -78,24 +162,50 @@ impl MyWindow {
 
         let shader_ver = ShaderVersion::Default;
 
-        // It's better if we calculate this ourselves
-        let custom_dpi = {
-            if width <= 1280 && height <= 720 {
-                1.0
-            } else if width <= 1920 && height <= 1080 {
-                1.5
-            } else {
-                2.5
-            }
-        };
+        let display_scale = Self::display_scale(&window);
+        let display_index = window.display_index().unwrap_or(0);
 
         let (painter, egui_state) =
-            egui_backend::with_sdl2(&window, shader_ver, DpiScaling::Custom(custom_dpi));
+            egui_backend::with_sdl2(&window, shader_ver, DpiScaling::Custom(display_scale));
         let egui_ctx = egui::CtxRef::default();
-        let event_pump = sdl_context.event_pump().map_err(|e| eyre!("{e}"))?;
+
+        // The GL functions are loaded by now, some drivers silently create an older context
+        let actual_version = ogl::gl_version();
+        if actual_version < (major as i32, minor as i32) {
+            return Err(eyre!(
+                "Requested an OpenGL {major}.{minor} context, but got {}.{}",
+                actual_version.0,
+                actual_version.1
+            ));
+        }
+
+        // The GL functions are loaded by now, check how many samples we actually got
+        if msaa_samples > 0 {
+            let mut samples = 0;
+            unsafe {
+                gl::GetIntegerv(gl::SAMPLES, &mut samples);
+                gl::Enable(gl::MULTISAMPLE);
+            }
+
+            if samples < msaa_samples as i32 {
+                warn!("Requested {msaa_samples}x MSAA, but got {samples}x");
+            }
+        }
+
+        // Controllers that are already connected are reported by 'ControllerDeviceAdded' events
+        let game_controller = match sdl_context.game_controller() {
+            Ok(subsystem) => Some(subsystem),
+            Err(e) => {
+                warn!("Couldn't initialize the game controller subsystem: {e}");
+                None
+            }
+        };
+
+        let mut event_pump = sdl_context.event_pump().map_err(|e| eyre!("{e}"))?;
+        event_pump.enable_event(EventType::DropFile);
 
         Ok(Self {
-            _sdl_context: sdl_context,
+            sdl_context,
             _video_subsystem: video_subsystem,
             window,
             _gl_ctx: gl_ctx,
@@ -104,19 +214,256 @@ impl MyWindow {
             egui_state,
             painter,
             start_time: Instant::now(),
+            needs_swap: false,
             width,
             height,
+            scroll: 0.,
+            mouse_look: false,
+            windowed_rect: None,
+            force_repaint: false,
+            dropped_files: Vec::new(),
+            press_pos: None,
+            click: None,
+            game_controller,
+            controllers: Vec::new(),
+            controller_buttons: Vec::new(),
+            ui_scale: 1.,
+            display_scale,
+            display_index,
         })
     }
 
+    /// Creates the window and its OpenGL context
+    fn create_window(
+        video_subsystem: &VideoSubsystem,
+        title: &str,
+        width: u32,
+        height: u32,
+        hidden: bool,
+    ) -> Result<(Window, GLContext)> {
+        let mut builder = video_subsystem.window(title, width, height);
+        builder
+            .opengl()
+            .resizable()
+            .position_centered()
+            .allow_highdpi();
+
+        if hidden {
+            builder.hidden();
+        }
+
+        let window = builder.build()?;
+
+        let gl_ctx = window.gl_create_context().map_err(|e| eyre!("{e}"))?;
+
+        Ok((window, gl_ctx))
+    }
+
+    /// Returns the mouse wheel movement since the last call and resets it
+    pub fn take_scroll(&mut self) -> f32 {
+        std::mem::take(&mut self.scroll)
+    }
+
+    /// Returns the position of the last left click since the last call (in pixels from the top-left corner).
+    ///
+    /// Pressing and releasing the button is a click only if the mouse didn't move (dragging).
+    pub fn take_click(&mut self) -> Option<(u32, u32)> {
+        self.click.take()
+    }
+
+    /// Returns the controller buttons pressed since the last call (in the pressed order)
+    pub fn take_controller_buttons(&mut self) -> Vec<Button> {
+        std::mem::take(&mut self.controller_buttons)
+    }
+
+    /// Value of the axis from -1 to 1 (0 to 1 for the triggers).
+    ///
+    /// With multiple controllers, the one with the largest deflection of the axis is used.
+    pub fn controller_axis(&self, axis: Axis) -> f32 {
+        self.controllers
+            .iter()
+            .map(|controller| controller.axis(axis) as f32 / i16::MAX as f32)
+            .fold(
+                0.,
+                |value: f32, v| if v.abs() > value.abs() { v } else { value },
+            )
+            .clamp(-1., 1.)
+    }
+
+    /// Returns the files dropped onto the window since the last call (in the dropped order)
+    pub fn take_dropped_files(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.dropped_files)
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.windowed_rect.is_some()
+    }
+
+    /// Size of the window outside of fullscreen
+    pub fn windowed_size(&self) -> (u32, u32) {
+        match self.windowed_rect {
+            Some((_, _, width, height)) => (width, height),
+            None => self.window.size(),
+        }
+    }
+
+    /// Switches between windowed and borderless fullscreen (desktop resolution).
+    ///
+    /// The size change itself is picked up by `begin_frame` like any other resize.
+    pub fn toggle_fullscreen(&mut self) -> Result<()> {
+        match self.windowed_rect.take() {
+            Some((x, y, width, height)) => {
+                self.window
+                    .set_fullscreen(FullscreenType::Off)
+                    .map_err(|e| eyre!("{e}"))?;
+
+                self.window.set_size(width, height)?;
+                self.window
+                    .set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+            }
+            None => {
+                let (x, y) = self.window.position();
+                let (width, height) = self.window.size();
+
+                self.window
+                    .set_fullscreen(FullscreenType::Desktop)
+                    .map_err(|e| eyre!("{e}"))?;
+
+                self.windowed_rect = Some((x, y, width, height));
+            }
+        }
+
+        self.force_repaint = true;
+        Ok(())
+    }
+
+    /// Switches between immediate presentation, VSync and adaptive VSync
+    pub fn set_swap_interval(&self, interval: SwapInterval) -> Result<()> {
+        self.window
+            .subsystem()
+            .gl_set_swap_interval(interval)
+            .map_err(|e| eyre!("{e}"))
+    }
+
+    /// If the mouse is captured for looking around
+    pub fn mouse_look(&self) -> bool {
+        self.mouse_look
+    }
+
+    /// Captures (or releases) the mouse for looking around.
+    ///
+    /// SDL relative mouse mode hides the cursor and reports relative movement even when
+    /// the cursor would hit the edge of the window.
+    pub fn set_mouse_look(&mut self, enabled: bool) {
+        if enabled == self.mouse_look {
+            return;
+        }
+
+        let mouse = self.sdl_context.mouse();
+        mouse.set_relative_mouse_mode(enabled);
+        mouse.show_cursor(!enabled);
+
+        // Discard the movement accumulated before the mode changed
+        self.event_pump.relative_mouse_state();
+        self.mouse_look = enabled;
+    }
+
     /// Resets state at the beginning of a frame
     pub fn begin_frame(&mut self) {
+        // Pick up resizes before anything is drawn, so the scene and the GUI use the same size.
+        // Checking the size directly handles both 'Resized' and 'SizeChanged' (maximizing).
+        let (width, height) = self.window.drawable_size();
+        if width != self.width || height != self.height {
+            self.handle_resize(width, height);
+        }
+
         self.egui_state.input.time = Some(self.start_time.elapsed().as_secs_f64());
         self.egui_ctx.begin_frame(self.egui_state.input.take());
     }
 
-    /// Finalizes the frame and returns if the render loop should terminate
-    pub fn end_frame(&mut self) -> bool {
+    /// Converts window coordinates to pixels of the drawable (they differ on high-DPI displays)
+    fn to_pixels(window: &Window, x: i32, y: i32) -> (u32, u32) {
+        let (window_width, window_height) = window.size();
+        let (width, height) = window.drawable_size();
+        let scale_x = width as f32 / window_width.max(1) as f32;
+        let scale_y = height as f32 / window_height.max(1) as f32;
+
+        (
+            (x.max(0) as f32 * scale_x) as u32,
+            (y.max(0) as f32 * scale_y) as u32,
+        )
+    }
+
+    /// Updates the size of the window, the egui painter and the DPI scaling after a resize
+    fn handle_resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+
+        self.apply_pixels_per_point();
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    /// Sets the scale of the GUI relative to the scale of the display
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale;
+        self.apply_pixels_per_point();
+        self.force_repaint = true;
+    }
+
+    /// Updates the egui painter and input state after the scale or the size changed
+    fn apply_pixels_per_point(&mut self) {
+        let pixels_per_point = self.display_scale * self.ui_scale;
+        self.painter.pixels_per_point = pixels_per_point;
+        self.egui_state.input.pixels_per_point = Some(pixels_per_point);
+
+        self.painter.update_screen_rect((self.width, self.height));
+        self.egui_state.input.screen_rect = Some(self.painter.screen_rect);
+    }
+
+    /// Recomputes the display scale if the window was moved to another display
+    fn check_display_change(&mut self) {
+        let display_index = match self.window.display_index() {
+            Ok(index) => index,
+            Err(_) => return,
+        };
+
+        if display_index != self.display_index {
+            self.display_index = display_index;
+            self.display_scale = Self::display_scale(&self.window);
+            self.apply_pixels_per_point();
+            self.force_repaint = true;
+        }
+    }
+
+    /// The GUI scale of the display the window is on.
+    ///
+    /// High-DPI windows (macOS, Wayland) have a drawable larger than the window, the OS scaling
+    /// is the ratio of the sizes. Otherwise the scaling is read from the DPI of the display.
+    fn display_scale(window: &Window) -> f32 {
+        let (window_width, _) = window.size();
+        let (drawable_width, _) = window.drawable_size();
+        let ratio = drawable_width as f32 / window_width.max(1) as f32;
+
+        if ratio > 1. {
+            return ratio;
+        }
+
+        let dpi = window
+            .display_index()
+            .and_then(|index| window.subsystem().display_dpi(index))
+            .map(|(diagonal_dpi, _, _)| diagonal_dpi);
+
+        match dpi {
+            Ok(dpi) if dpi > 0. => (dpi / BASE_DPI).clamp(0.5, 4.),
+            _ => 1.,
+        }
+    }
+
+    /// Draws the GUI into the back buffer
+    pub fn draw_gui(&mut self) {
         unsafe {
             // Reset gl properties so Egui can render properly
             gl::Disable(gl::DEPTH_TEST);
@@ -130,32 +477,108 @@ impl MyWindow {
 
         let paint_jobs = self.egui_ctx.tessellate(paint_cmds);
 
-        if !egui_output.needs_repaint {
-            // TODO: check egui_backend needs_repaint
-            /* if let Some(event) = self.event_pump.wait_event_timeout(5) {
-                match event {
-                    Event::Quit { .. } => return true,
-                    _ => {
-                        self.egui_state
-                            .process_input(&self.window, event, &mut self.painter);
-                    }
-                }
-            } */
-        } else {
+        self.needs_swap = egui_output.needs_repaint || self.force_repaint;
+        self.force_repaint = false;
+        if self.needs_swap {
             self.painter
                 .paint_jobs(None, paint_jobs, &self.egui_ctx.font_image());
+        }
+    }
+
+    /// If egui requested another frame (input was handled or it's animating)
+    pub fn needs_repaint(&self) -> bool {
+        self.needs_swap
+    }
+
+    /// Finalizes the frame and returns if the render loop should terminate.
+    ///
+    /// If the viewer is 'idle', it waits for an event (up to `IDLE_TIMEOUT_MS`) instead
+    /// of polling, so an idle viewer doesn't use the CPU.
+    pub fn end_frame(&mut self, idle: bool) -> bool {
+        if self.needs_swap {
             self.window.gl_swap_window();
         }
-        for event in self.event_pump.poll_iter() {
+
+        let mut events = Vec::new();
+        if idle {
+            events.extend(self.event_pump.wait_event_timeout(IDLE_TIMEOUT_MS));
+        }
+        events.extend(self.event_pump.poll_iter());
+
+        let mut moved = false;
+
+        for event in events {
             match event {
                 Event::Quit { .. } => return true,
+                // SDL 2.0.18 'DisplayChanged' isn't exposed by the sdl2 crate, so the display
+                // is checked whenever the window moves
                 Event::Window {
-                    timestamp: _,
-                    window_id: _,
-                    win_event: WindowEvent::Resized(new_width, new_height),
+                    win_event: WindowEvent::Moved(..),
+                    ..
+                } => moved = true,
+                Event::Window {
+                    win_event:
+                        WindowEvent::Exposed
+                        | WindowEvent::FocusGained
+                        | WindowEvent::Restored
+                        | WindowEvent::Shown,
+                    ..
                 } => {
-                    self.width = new_width as u32;
-                    self.height = new_height as u32;
+                    self.force_repaint = true;
+                    self.egui_state
+                        .process_input(&self.window, event, &mut self.painter);
+                }
+                Event::DropFile { filename, .. } => self.dropped_files.push(filename),
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(subsystem) = &self.game_controller {
+                        match subsystem.open(which) {
+                            Ok(controller) => {
+                                info!("Game controller connected: {}", controller.name());
+                                self.controllers.push(controller);
+                            }
+                            Err(e) => warn!("Couldn't open the game controller {which}: {e}"),
+                        }
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    // 'which' is the instance id here, not the device index
+                    self.controllers
+                        .retain(|controller| controller.instance_id() != which);
+                }
+                Event::ControllerButtonDown { button, .. } => self.controller_buttons.push(button),
+                // The cursor position is meaningless for egui while looking around
+                Event::MouseMotion { .. } if self.mouse_look => {}
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    self.press_pos = Some((x, y));
+                    self.egui_state
+                        .process_input(&self.window, event, &mut self.painter);
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some((press_x, press_y)) = self.press_pos.take() {
+                        if (x - press_x).abs() <= CLICK_DISTANCE
+                            && (y - press_y).abs() <= CLICK_DISTANCE
+                        {
+                            self.click = Some(Self::to_pixels(&self.window, x, y));
+                        }
+                    }
+
+                    self.egui_state
+                        .process_input(&self.window, event, &mut self.painter);
+                }
+                Event::MouseWheel { y, .. } => {
+                    self.scroll += y as f32;
+                    self.egui_state
+                        .process_input(&self.window, event, &mut self.painter);
                 }
                 _ => {
                     self.egui_state
@@ -164,6 +587,10 @@ impl MyWindow {
             }
         }
 
+        if moved {
+            self.check_display_change();
+        }
+
         false
     }
 }
@@ -0,0 +1,152 @@
+//! Loads small glTF files with `Model::load` (doesn't need OpenGL).
+//!
+//! The files in 'tests/data' embed their buffers and images as data URIs.
+
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+use leoric::model::{Model, Primitive};
+
+/// The primitives of the only mesh of the model
+fn primitives(model: &Model) -> &[Primitive] {
+    let node = &model.root.children[0];
+    &node
+        .mesh
+        .as_ref()
+        .expect("The node doesn't have a mesh")
+        .primitives
+}
+
+#[test]
+fn missing_texture_coordinates_are_filled_in() {
+    let model = Model::load("tests/data/missing_uvs.gltf", false).unwrap();
+    let primitives = primitives(&model);
+
+    assert_eq!(
+        model.warnings,
+        vec![
+            "Mesh 'Malformed', primitive 0: the material has textures, but there are no texture coordinates, the textures are ignored",
+            "Mesh 'Malformed', primitive 1: 2 texture coordinates for 4 vertices, truncated / padded with zeros",
+        ]
+    );
+
+    // The texture of the first primitive is ignored, the second one keeps it
+    assert_eq!(primitives[0].texcoords, vec![Vec2::ZERO; 4]);
+    assert_eq!(
+        primitives[1].texcoords,
+        vec![Vec2::new(0., 1.), Vec2::new(1., 1.), Vec2::ZERO, Vec2::ZERO]
+    );
+}
+
+#[test]
+fn missing_texture_coordinates_are_errors_in_strict_mode() {
+    assert!(Model::load("tests/data/missing_uvs.gltf", true).is_err());
+}
+
+#[test]
+fn texture_transform_selects_atlas_region() {
+    let model = Model::load("tests/data/uv_atlas.gltf", true).unwrap();
+    let transform = primitives(&model)[0].material.base_color_uv_transform;
+
+    assert_eq!(transform.offset, Vec2::new(0.5, 0.));
+    assert_eq!(transform.rotation, 0.);
+    assert_eq!(transform.scale, Vec2::new(0.5, 0.5));
+
+    // The whole quad maps to the top right quadrant of the atlas
+    let matrix = transform.matrix();
+    let corners = [Vec2::ZERO, Vec2::ONE].map(|uv| matrix * Vec3::new(uv.x, uv.y, 1.));
+    assert_eq!(corners, [Vec3::new(0.5, 0., 1.), Vec3::new(1., 0.5, 1.)]);
+}
+
+#[test]
+fn instances_are_read() {
+    let model = Model::load("tests/data/instanced.gltf", true).unwrap();
+    let mesh = model.root.children[0].mesh.as_ref().unwrap();
+
+    let rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    let expected = [
+        Mat4::IDENTITY,
+        Mat4::from_translation(Vec3::new(2., 0., 0.)),
+        Mat4::from_scale_rotation_translation(Vec3::splat(2.), rotation, Vec3::new(4., 0., 0.)),
+    ];
+
+    assert_eq!(mesh.instances.len(), 3);
+    for (instance, expected) in mesh.instances.iter().zip(expected) {
+        assert!(instance.abs_diff_eq(expected, 1e-4), "{instance}");
+    }
+
+    assert_eq!(model.stats.instances, 3);
+
+    // The bounds cover all of the instances, the last one reaches from x = 2 to x = 4
+    let bounds = model.bounds();
+    assert!(bounds.min.abs_diff_eq(Vec3::new(0., 0., 0.), 1e-4));
+    assert!(bounds.max.abs_diff_eq(Vec3::new(4., 2., 0.), 1e-4));
+}
+
+#[test]
+fn quantized_attributes_are_dequantized() {
+    let model = Model::load("tests/data/quantized.gltf", true).unwrap();
+    let node = &model.root.children[0];
+    let primitive = &primitives(&model)[0];
+
+    // The shorts aren't normalized, the node scales them down
+    assert_eq!(
+        primitive.positions,
+        vec![
+            Vec3::new(-1000., -1000., 0.),
+            Vec3::new(1000., -1000., 0.),
+            Vec3::new(1000., 1000., 0.),
+            Vec3::new(-1000., 1000., 0.),
+        ]
+    );
+    assert!(node
+        .transform
+        .abs_diff_eq(Mat4::from_scale(Vec3::splat(0.001)), 1e-6));
+
+    // Normalized bytes and shorts
+    assert_eq!(primitive.normals, vec![Vec3::Z; 4]);
+    assert_eq!(
+        primitive.texcoords,
+        vec![
+            Vec2::new(0., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 0.),
+            Vec2::new(0., 0.),
+        ]
+    );
+
+    let bounds = model.bounds();
+    assert!(bounds.min.abs_diff_eq(Vec3::new(-1., -1., 0.), 1e-6));
+    assert!(bounds.max.abs_diff_eq(Vec3::new(1., 1., 0.), 1e-6));
+}
+
+#[test]
+fn shared_image_keeps_material_factors() {
+    let model = Model::load("tests/data/shared_texture.gltf", true).unwrap();
+    let factors: Vec<_> = primitives(&model)
+        .iter()
+        .map(|primitive| primitive.material.base_color_factor)
+        .collect();
+
+    assert_eq!(
+        factors,
+        vec![
+            Vec4::new(1., 0., 0., 1.),
+            Vec4::new(0., 1., 0., 1.),
+            Vec4::new(0., 0., 1., 1.),
+        ]
+    );
+
+    // The 2x2 image is decoded once, for all of the textures
+    assert_eq!(model.stats.texture_bytes, 2 * 2 * 4 * 4 / 3);
+}
+
+#[test]
+fn unlit_materials_are_read() {
+    let model = Model::load("tests/data/unlit.gltf", true).unwrap();
+    let unlit: Vec<_> = primitives(&model)
+        .iter()
+        .map(|primitive| primitive.material.unlit)
+        .collect();
+
+    assert_eq!(unlit, vec![true, false]);
+}
@@ -0,0 +1,66 @@
+//! Loads an OBJ file with `Model::load` (doesn't need OpenGL).
+
+use glam::{Vec3, Vec4};
+use gltf::material::AlphaMode;
+
+use leoric::model::{Model, Primitive};
+
+fn primitive<'a>(model: &'a Model, name: &str) -> &'a Primitive {
+    let node = model
+        .root
+        .children
+        .iter()
+        .find(|node| node.name == name)
+        .unwrap_or_else(|| panic!("No node '{name}'"));
+
+    let mesh = node.mesh.as_ref().unwrap();
+    assert_eq!(mesh.primitives.len(), 1);
+    &mesh.primitives[0]
+}
+
+#[test]
+fn obj_objects_become_nodes() {
+    // Strict, so a missing material would be an error
+    let model = Model::load("tests/data/shapes.obj", true).unwrap();
+
+    assert_eq!(model.name, "shapes.obj");
+    assert!(model.warnings.is_empty());
+    assert_eq!(model.root.children.len(), 2);
+    assert!(model.animations.animations.is_empty());
+
+    assert_eq!(model.stats.vertices, 3 + 4);
+    assert_eq!(model.stats.triangles, 1 + 2);
+    assert_eq!(model.stats.primitives, 2);
+}
+
+#[test]
+fn obj_quad_is_triangulated_with_calculated_normals() {
+    let model = Model::load("tests/data/shapes.obj", true).unwrap();
+    let quad = primitive(&model, "Quad");
+
+    assert_eq!(quad.positions.len(), 4);
+    assert_eq!(quad.indices.len(), 6);
+    assert_eq!(quad.texcoords.len(), 4);
+
+    // The quad faces +Z
+    for normal in &quad.normals {
+        assert!(normal.abs_diff_eq(Vec3::Z, 1e-6), "{normal}");
+    }
+
+    assert_eq!(quad.material.base_color_factor, Vec4::new(1., 0., 0., 0.5));
+    assert!(quad.material.alpha_mode == AlphaMode::Blend);
+}
+
+#[test]
+fn obj_normals_are_read_from_the_file() {
+    let model = Model::load("tests/data/shapes.obj", true).unwrap();
+    let floor = primitive(&model, "Floor");
+
+    assert_eq!(floor.positions.len(), 3);
+    assert_eq!(floor.indices.len(), 3);
+    assert_eq!(floor.normals, vec![Vec3::Y; 3]);
+
+    // Without a material
+    assert_eq!(floor.material.base_color_factor, Vec4::ONE);
+    assert!(floor.material.alpha_mode == AlphaMode::Opaque);
+}
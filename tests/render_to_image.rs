@@ -0,0 +1,201 @@
+//! Renders models offscreen with `Renderer::render_to_image`.
+//!
+//! Needs a display with OpenGL 4.2, the tests are skipped (pass) if no context can be created.
+
+use std::sync::Mutex;
+
+use glam::Vec3;
+use image::RgbaImage;
+use sdl2::video::{GLContext, GLProfile, Window};
+
+use leoric::{
+    camera::Camera,
+    model::Model,
+    renderer::{RenderOptions, Renderer, Winding},
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/// SDL can only be initialized once at a time, so the tests render one after another
+static GL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Keeps the hidden window and its context alive
+struct GlContext {
+    _window: Window,
+    _context: GLContext,
+}
+
+/// Creates a hidden window with an OpenGL context and loads the functions
+fn create_context() -> Result<GlContext, String> {
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+
+    let gl_attr = video.gl_attr();
+    gl_attr.set_context_major_version(4);
+    gl_attr.set_context_minor_version(2);
+    gl_attr.set_context_profile(GLProfile::Core);
+    gl_attr.set_stencil_size(8);
+
+    let window = video
+        .window("render_to_image", WIDTH, HEIGHT)
+        .opengl()
+        .hidden()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let context = window.gl_create_context()?;
+    gl::load_with(|name| video.gl_get_proc_address(name) as _);
+
+    Ok(GlContext {
+        _window: window,
+        _context: context,
+    })
+}
+
+/// Calls 'f' with an OpenGL context.
+///
+/// Returns None if there is no OpenGL context.
+fn with_context<T>(f: impl FnOnce() -> T) -> Option<T> {
+    let _lock = GL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let _context = match create_context() {
+        Ok(context) => context,
+        Err(e) => {
+            eprintln!("Skipping the test, couldn't create an OpenGL context: {e}");
+            return None;
+        }
+    };
+
+    Some(f())
+}
+
+/// Renders the model from the camera position (looking at the origin).
+///
+/// Returns None if there is no OpenGL context.
+fn render(path: &str, camera_pos: Vec3, options: &RenderOptions) -> Option<RgbaImage> {
+    with_context(|| {
+        let mut model = Model::load(path, true).unwrap();
+        model.upload_to_gpu().unwrap();
+        let mut scene = vec![model];
+
+        let mut renderer = Renderer::new().unwrap();
+        let mut camera = Camera::new(camera_pos, 1., 0.05, WIDTH, HEIGHT);
+        camera.look_at(Vec3::ZERO);
+
+        let image = renderer
+            .render_to_image(&mut scene, &mut camera, options, WIDTH, HEIGHT)
+            .unwrap();
+
+        assert_eq!(image.dimensions(), (WIDTH, HEIGHT));
+        image
+    })
+}
+
+/// If the center of the image differs from the background in the corner
+fn center_is_covered(image: &RgbaImage) -> bool {
+    image.get_pixel(WIDTH / 2, HEIGHT / 2) != image.get_pixel(0, 0)
+}
+
+#[test]
+fn render_to_image_draws_triangle() {
+    let options = RenderOptions::default();
+    let image = match render("tests/data/triangle.obj", Vec3::new(0., 0., 3.), &options) {
+        Some(image) => image,
+        None => return,
+    };
+
+    // The corner is outside of the triangle, the center is inside
+    assert!(center_is_covered(&image));
+}
+
+#[test]
+fn inverted_winding_is_visible_with_flipped_front_face() {
+    let path = "tests/data/inverted.obj";
+    let camera_pos = Vec3::new(0., 0., 3.);
+
+    let culled = match render(path, camera_pos, &RenderOptions::default()) {
+        Some(image) => image,
+        None => return,
+    };
+    assert!(!center_is_covered(&culled), "The back face wasn't culled");
+
+    let flipped = RenderOptions {
+        front_face: Winding::Cw,
+        ..RenderOptions::default()
+    };
+    let image = render(path, camera_pos, &flipped).unwrap();
+    assert!(center_is_covered(&image));
+
+    let no_culling = RenderOptions {
+        face_culling: false,
+        ..RenderOptions::default()
+    };
+    let image = render(path, camera_pos, &no_culling).unwrap();
+    assert!(center_is_covered(&image));
+}
+
+#[test]
+fn double_sided_plane_is_visible_from_behind() {
+    // Culling is enabled, the plane faces +Z and is seen from -Z
+    let options = RenderOptions::default();
+    let behind = Vec3::new(0., 0., -3.);
+
+    let image = match render("tests/data/double_sided.gltf", behind, &options) {
+        Some(image) => image,
+        None => return,
+    };
+    assert!(center_is_covered(&image));
+
+    // A single-sided triangle is still culled
+    let image = render("tests/data/triangle.obj", behind, &options).unwrap();
+    assert!(!center_is_covered(&image), "The back face wasn't culled");
+}
+
+#[test]
+fn transparent_quads_composite_from_both_sides() {
+    let path = "tests/data/overlapping.gltf";
+    // The quads are double-sided, so they are visible from -Z with culling enabled
+    let options = RenderOptions::default();
+
+    // The closer quad is drawn last, so its color dominates
+    let front = match render(path, Vec3::new(0., 0., 3.), &options) {
+        Some(image) => image,
+        None => return,
+    };
+    let [r, g, ..] = front.get_pixel(WIDTH / 2, HEIGHT / 2).0;
+    assert!(
+        g > r,
+        "The red quad was drawn over the green one ({r}, {g})"
+    );
+
+    let back = render(path, Vec3::new(0., 0., -3.), &options).unwrap();
+    let [r, g, ..] = back.get_pixel(WIDTH / 2, HEIGHT / 2).0;
+    assert!(
+        r > g,
+        "The green quad was drawn over the red one ({r}, {g})"
+    );
+}
+
+#[test]
+fn shared_image_keeps_per_texture_samplers() {
+    let textures = with_context(|| {
+        let mut model = Model::load("tests/data/shared_texture.gltf", true).unwrap();
+        model.upload_to_gpu().unwrap();
+
+        let mesh = model.root.children[0].mesh.as_ref().unwrap();
+        mesh.primitives
+            .iter()
+            .map(|primitive| primitive.material.base_color_texture.unwrap())
+            .collect::<Vec<_>>()
+    });
+    let textures = match textures {
+        Some(textures) => textures,
+        None => return,
+    };
+
+    // The first two primitives use the same sampler, so they share the texture,
+    // the last one has its own sampler
+    assert_eq!(textures[0], textures[1]);
+    assert_ne!(textures[0], textures[2]);
+}